@@ -0,0 +1,168 @@
+use std::{borrow::Borrow, cell::Cell, collections::HashMap, hash::Hash};
+
+// The cache is the only owner of what it holds, so evicting an entry drops
+// it and frees its GPU resource, unless something like a bind group built
+// from it still holds on to it. Pinned entries are never evicted.
+pub struct AssetCache<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+    capacity: usize,
+    // Lookups only borrow the cache, but still count as a use.
+    tick: Cell<u64>,
+    loads: u64,
+}
+
+struct CacheEntry<V> {
+    value: V,
+    last_used: Cell<u64>,
+    pinned: bool,
+}
+
+impl<K: Hash + Eq + Clone, V> AssetCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            tick: Cell::new(0),
+            loads: 0,
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.get(key)?;
+        entry.last_used.set(self.next_tick());
+        Some(&entry.value)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.contains_key(key)
+    }
+
+    pub fn get_or_load(&mut self, key: &K, loader: impl FnOnce(&K) -> V) -> &V {
+        if !self.entries.contains_key(key) {
+            self.loads += 1;
+            let value = loader(key);
+            self.insert(key.clone(), value);
+        }
+        self.get(key).expect("the entry was just loaded")
+    }
+
+    // Replaces whatever is cached under `key`, dropping the old value.
+    pub fn insert(&mut self, key: K, value: V) {
+        let pinned = self.entries.remove(&key).is_some_and(|entry| entry.pinned);
+        while self.entries.len() >= self.capacity {
+            if !self.evict_least_recently_used() {
+                break;
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                last_used: Cell::new(self.next_tick()),
+                pinned,
+            },
+        );
+    }
+
+    // Keeps the entry under `key` from ever being evicted, though inserting
+    // over it still replaces it.
+    pub fn pin<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.pinned = true;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Everything cached, without counting as a use.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|entry| &entry.value)
+    }
+
+    pub fn loads(&self) -> u64 {
+        self.loads
+    }
+
+    fn next_tick(&self) -> u64 {
+        self.tick.set(self.tick.get() + 1);
+        self.tick.get()
+    }
+
+    // Returns false when everything left is pinned.
+    fn evict_least_recently_used(&mut self) -> bool {
+        let oldest = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| !entry.pinned)
+            .min_by_key(|(_, entry)| entry.last_used.get())
+            .map(|(key, _)| key.clone());
+
+        match oldest {
+            Some(key) => {
+                self.entries.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_key_loads_once() {
+        let mut cache = AssetCache::new(4);
+        assert_eq!(*cache.get_or_load(&"a.png", |_| 1), 1);
+        assert_eq!(
+            *cache.get_or_load(&"a.png", |_| panic!("should be cached")),
+            1
+        );
+
+        assert_eq!(cache.loads(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = AssetCache::new(2);
+        cache.get_or_load(&"a.png", |_| 1);
+        cache.get_or_load(&"b.png", |_| 2);
+        // Touching `a` leaves `b` as the least recently used entry.
+        cache.get(&"a.png");
+        cache.get_or_load(&"c.png", |_| 3);
+
+        assert!(cache.get(&"a.png").is_some());
+        assert!(cache.get(&"b.png").is_none());
+        assert!(cache.get(&"c.png").is_some());
+        assert_eq!(cache.loads(), 3);
+    }
+
+    #[test]
+    fn never_evicts_pinned_entries() {
+        let mut cache = AssetCache::new(2);
+        cache.get_or_load(&"a.png", |_| 1);
+        cache.pin(&"a.png");
+        cache.get_or_load(&"b.png", |_| 2);
+        cache.get_or_load(&"c.png", |_| 3);
+
+        assert!(cache.get(&"a.png").is_some());
+        assert!(cache.get(&"b.png").is_none());
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+use crate::model::{LoadError, ModelData};
+
+pub struct LoadedModel {
+    pub job_id: u64,
+    pub path: PathBuf,
+    pub result: Result<ModelData, LoadError>,
+}
+
+// Reads and decodes model files on a background thread. Only CPU work happens
+// there; the results are uploaded on the main thread once `poll` hands them
+// back.
+pub struct AssetLoader {
+    request_sender: Option<Sender<(u64, PathBuf)>>,
+    result_receiver: Receiver<LoadedModel>,
+    worker: Option<JoinHandle<()>>,
+    next_id: u64,
+}
+
+impl AssetLoader {
+    // `features` are the device's, so only texture formats it can sample are
+    // picked.
+    pub fn new(features: wgpu::Features) -> Self {
+        let (request_sender, request_receiver) = mpsc::channel::<(u64, PathBuf)>();
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        let worker = std::thread::spawn(move || {
+            for (job_id, path) in request_receiver {
+                let result = ModelData::read(&path, features);
+                let loaded = LoadedModel {
+                    job_id,
+                    path,
+                    result,
+                };
+                if result_sender.send(loaded).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            request_sender: Some(request_sender),
+            result_receiver,
+            worker: Some(worker),
+            next_id: 0,
+        }
+    }
+
+    pub fn load_model(&mut self, path: PathBuf) -> u64 {
+        self.next_id += 1;
+        if let Some(sender) = &self.request_sender {
+            let _ = sender.send((self.next_id, path));
+        }
+        self.next_id
+    }
+
+    pub fn poll(&self) -> Vec<LoadedModel> {
+        self.result_receiver.try_iter().collect()
+    }
+
+    pub fn wait(&self) -> Option<LoadedModel> {
+        self.result_receiver.recv().ok()
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        self.request_sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
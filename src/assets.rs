@@ -0,0 +1,483 @@
+use std::{collections::HashMap, fmt, hash, marker::PhantomData, sync::Arc};
+
+use crate::{
+    asset_cache::AssetCache,
+    material::Material,
+    mipmap::MipmapGenerator,
+    model::Model,
+    shader_source::{specialise_wgsl, ShaderLoadError, ShaderSource},
+    texture::{texture_bytes, SamplerCache, SamplerConfig, Texture},
+};
+
+// Built into the binary so there is always something valid to bind when an
+// asset can't be loaded.
+const CHECKERBOARD_PNG: &[u8] = include_bytes!("checkerboard.png");
+const ERROR_SHADER: &str = include_str!("error_shader.wgsl");
+
+// A typed index into `Assets`. Handles are only meaningful for the registry
+// that issued them.
+pub struct Handle<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> hash::Hash for Handle<T> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+// Assets of one type. `cache` is the only owner of what's loaded, so a handle
+// only names an asset: `get` resolves it through the cache and falls back to
+// the pinned stand-in once its entry has been evicted. Lookups by name use a
+// file's path or a `<...>` name for generated assets, so loading something
+// that's still cached reuses it without another upload, and loading it after
+// eviction uploads it again under a fresh handle. Handles are never reused,
+// and names whose asset has been evicted are forgotten on the next insert.
+struct Storage<T> {
+    cache: AssetCache<Handle<T>, T>,
+    by_name: HashMap<String, Handle<T>>,
+    next_index: usize,
+    fallback: Option<Handle<T>>,
+}
+
+impl<T> Storage<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            cache: AssetCache::new(capacity),
+            by_name: HashMap::new(),
+            next_index: 0,
+            fallback: None,
+        }
+    }
+
+    fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.cache
+            .get(&handle)
+            .or_else(|| self.cache.get(&self.fallback?))
+    }
+
+    fn find(&self, name: &str) -> Option<Handle<T>> {
+        let handle = *self.by_name.get(name)?;
+        self.cache.contains(&handle).then_some(handle)
+    }
+
+    // Replaces the asset stored under `name`, keeping its handle while the
+    // old one is still cached.
+    fn insert(&mut self, name: &str, item: T) -> Handle<T> {
+        let handle = match self.find(name) {
+            Some(handle) => handle,
+            None => self.new_handle(name),
+        };
+        self.cache.insert(handle, item);
+        self.forget_evicted();
+        handle
+    }
+
+    fn get_or_load(&mut self, name: &str, loader: impl FnOnce() -> T) -> Handle<T> {
+        if let Some(handle) = self.find(name) {
+            return handle;
+        }
+
+        let handle = self.new_handle(name);
+        self.cache.get_or_load(&handle, |_| loader());
+        self.forget_evicted();
+        handle
+    }
+
+    // Keeps `handle` cached for good, and resolves evicted handles to it.
+    fn set_fallback(&mut self, handle: Handle<T>) {
+        self.cache.pin(&handle);
+        self.fallback = Some(handle);
+    }
+
+    fn new_handle(&mut self, name: &str) -> Handle<T> {
+        let handle = Handle {
+            index: self.next_index,
+            _marker: PhantomData,
+        };
+        self.next_index += 1;
+        self.by_name.insert(name.to_string(), handle);
+        handle
+    }
+
+    fn forget_evicted(&mut self) {
+        let cache = &self.cache;
+        self.by_name.retain(|_, handle| cache.contains(handle));
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cache.values()
+    }
+}
+
+pub struct Assets {
+    textures: Storage<Texture>,
+    materials: Storage<Material>,
+    models: Storage<Model>,
+    shaders: Storage<wgpu::ShaderModule>,
+    samplers: SamplerCache,
+    mipmaps: Option<MipmapGenerator>,
+}
+
+impl Assets {
+    // `capacity` applies to each kind of asset's cache separately.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            textures: Storage::new(capacity),
+            materials: Storage::new(capacity),
+            models: Storage::new(capacity),
+            shaders: Storage::new(capacity),
+            samplers: SamplerCache::default(),
+            mipmaps: None,
+        }
+    }
+
+    // Falls back to the checkerboard once `handle` has been evicted.
+    pub fn texture(&self, handle: Handle<Texture>) -> &Texture {
+        self.textures
+            .get(handle)
+            .expect("an evicted texture needs the checkerboard to stand in for it")
+    }
+
+    // Textures are stored with their mip chain filled in, so every texture
+    // handed out by `Assets` is ready to sample at any distance.
+    pub fn insert_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: &str,
+        texture: Texture,
+    ) -> Handle<Texture> {
+        self.mipmaps
+            .get_or_insert_with(|| MipmapGenerator::new(device))
+            .generate(device, queue, &texture.texture);
+        self.textures.insert(name, texture)
+    }
+
+    pub fn sampler(&mut self, device: &wgpu::Device, config: SamplerConfig) -> Arc<wgpu::Sampler> {
+        self.samplers.get(device, config)
+    }
+
+    pub fn texture_or_insert_with(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: &str,
+        create: impl FnOnce() -> Texture,
+    ) -> Handle<Texture> {
+        let mipmaps = self
+            .mipmaps
+            .get_or_insert_with(|| MipmapGenerator::new(device));
+        self.textures.get_or_load(name, || {
+            let texture = create();
+            mipmaps.generate(device, queue, &texture.texture);
+            texture
+        })
+    }
+
+    // A 1x1 texture of `colour`, shared by everything that asks for the same
+    // colour.
+    pub fn solid_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        colour: [u8; 4],
+    ) -> Handle<Texture> {
+        let name = format!("<solid {colour:?}>");
+        let sampler = self.sampler(device, SamplerConfig::default());
+        self.texture_or_insert_with(device, queue, &name, || {
+            Texture::from_colour(device, queue, colour, &name, sampler)
+        })
+    }
+
+    // A 1x1 normal map pointing straight out of the surface, for materials
+    // without one.
+    pub fn flat_normal_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Handle<Texture> {
+        let sampler = self.sampler(device, SamplerConfig::default());
+        self.texture_or_insert_with(device, queue, "<flat normal>", || {
+            let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]));
+            Texture::from_linear_image(
+                device,
+                queue,
+                &image::DynamicImage::ImageRgba8(img),
+                Some("<flat normal>"),
+                sampler,
+            )
+        })
+    }
+
+    // Stands in for textures that couldn't be loaded.
+    pub fn checkerboard_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Handle<Texture> {
+        let sampler = self.sampler(
+            device,
+            SamplerConfig {
+                address_mode: wgpu::AddressMode::Repeat,
+                mag_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            },
+        );
+        let handle = self.texture_or_insert_with(device, queue, "<checkerboard>", || {
+            Texture::from_bytes(device, queue, CHECKERBOARD_PNG, "<checkerboard>", sampler)
+                .expect("the embedded checkerboard is a valid PNG")
+        });
+        self.textures.set_fallback(handle);
+        handle
+    }
+
+    // None once `handle` has been evicted, for the caller to draw with a
+    // default material instead.
+    pub fn material(&self, handle: Handle<Material>) -> Option<&Material> {
+        self.materials.get(handle)
+    }
+
+    pub fn insert_material(&mut self, name: &str, material: Material) -> Handle<Material> {
+        self.materials.insert(name, material)
+    }
+
+    // Falls back to the placeholder once `handle` has been evicted.
+    pub fn model(&self, handle: Handle<Model>) -> &Model {
+        self.models
+            .get(handle)
+            .expect("an evicted model needs the placeholder to stand in for it")
+    }
+
+    pub fn insert_model(&mut self, name: &str, model: Model) -> Handle<Model> {
+        self.models.insert(name, model)
+    }
+
+    // Never evicted, and drawn in place of models that have been.
+    pub fn insert_fallback_model(&mut self, name: &str, model: Model) -> Handle<Model> {
+        let handle = self.models.insert(name, model);
+        self.models.set_fallback(handle);
+        handle
+    }
+
+    pub fn find_model(&self, name: &str) -> Option<Handle<Model>> {
+        self.models.find(name)
+    }
+
+    // Falls back to the error shader once `handle` has been evicted.
+    pub fn shader(&self, handle: Handle<wgpu::ShaderModule>) -> &wgpu::ShaderModule {
+        self.shaders
+            .get(handle)
+            .expect("an evicted shader needs the error shader to stand in for it")
+    }
+
+    // Keeps a shader that pipelines are rebuilt from cached for good.
+    pub fn pin_shader(&mut self, handle: Handle<wgpu::ShaderModule>) {
+        self.shaders.cache.pin(&handle);
+    }
+
+    pub fn insert_shader(
+        &mut self,
+        name: &str,
+        shader: wgpu::ShaderModule,
+    ) -> Handle<wgpu::ShaderModule> {
+        self.shaders.insert(name, shader)
+    }
+
+    // Stands in for shaders that couldn't be loaded. It draws a magenta
+    // triangle without any vertex buffers.
+    pub fn error_shader(&mut self, device: &wgpu::Device) -> Handle<wgpu::ShaderModule> {
+        let handle = self.shaders.get_or_load("<error>", || {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Error Shader"),
+                source: wgpu::ShaderSource::Wgsl(ERROR_SHADER.into()),
+            })
+        });
+        self.shaders.set_fallback(handle);
+        handle
+    }
+
+    pub fn load_shader(
+        &mut self,
+        device: &wgpu::Device,
+        source: &ShaderSource,
+    ) -> Result<Handle<wgpu::ShaderModule>, ShaderLoadError> {
+        let name = source.path().to_string_lossy();
+        if let Some(handle) = self.shaders.find(&name) {
+            return Ok(handle);
+        }
+
+        let shader = source.load(device)?;
+        Ok(self.shaders.insert(&name, shader))
+    }
+
+    // Keyed by `name` and the constants, so each combination is only
+    // specialised and compiled once while it stays cached, and variants that
+    // fall out of use are evicted like any other shader.
+    pub fn specialised_shader(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        source: &str,
+        constants: &HashMap<String, f64>,
+    ) -> Handle<wgpu::ShaderModule> {
+        self.shaders
+            .get_or_load(&specialised_shader_key(name, constants), || {
+                device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("Specialised Shader"),
+                    source: wgpu::ShaderSource::Wgsl(specialise_wgsl(source, constants).into()),
+                })
+            })
+    }
+
+    pub fn shaders_compiled(&self) -> u64 {
+        self.shaders.cache.loads()
+    }
+
+    // An estimate of the video memory held by cached textures and model
+    // meshes. Materials only add small uniform buffers, and shaders don't
+    // count.
+    pub fn gpu_bytes(&self) -> u64 {
+        let textures = self
+            .textures
+            .iter()
+            .map(|texture| texture_bytes(&texture.texture))
+            .sum::<u64>();
+        let meshes = self
+            .models
+            .iter()
+            .flat_map(|model| &model.meshes)
+            .map(|mesh| mesh.vertex_buffer.size() + mesh.index_buffer.size())
+            .sum::<u64>();
+        textures + meshes
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} textures, {} samplers, {} materials, {} models, {} shaders",
+            self.textures.len(),
+            self.samplers.count(),
+            self.materials.len(),
+            self.models.len(),
+            self.shaders.len()
+        )
+    }
+}
+
+// `name` followed by the constants in sorted order, like
+// "shader.wgsl [BLUR=2, SAMPLES=16]".
+fn specialised_shader_key(name: &str, constants: &HashMap<String, f64>) -> String {
+    let mut constants = constants
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>();
+    constants.sort();
+    format!("{name} [{}]", constants.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    // Counts its drops, standing in for a GPU resource being freed.
+    struct Upload {
+        drops: Rc<Cell<u32>>,
+    }
+
+    impl Drop for Upload {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn eviction_frees_assets_and_reloading_uploads_them_again() {
+        let mut storage = Storage::new(1);
+        let uploads = Cell::new(0);
+        let a_drops = Rc::new(Cell::new(0));
+        let b_drops = Rc::new(Cell::new(0));
+        let mut load = |name, drops: &Rc<Cell<u32>>| {
+            storage.get_or_load(name, || {
+                uploads.set(uploads.get() + 1);
+                Upload {
+                    drops: drops.clone(),
+                }
+            })
+        };
+
+        let first_a = load("a.png", &a_drops);
+        load("b.png", &b_drops);
+        assert_eq!(a_drops.get(), 1, "loading b.png should evict a.png");
+
+        let second_a = load("a.png", &a_drops);
+        assert_eq!(uploads.get(), 3, "a.png should be uploaded again");
+        assert_eq!(b_drops.get(), 1, "reloading a.png should evict b.png");
+        assert_eq!(a_drops.get(), 1);
+
+        assert_ne!(first_a, second_a);
+        assert!(storage.get(first_a).is_none());
+        assert!(storage.get(second_a).is_some());
+        assert_eq!(storage.find("a.png"), Some(second_a));
+        assert_eq!(storage.find("b.png"), None);
+        assert_eq!(storage.by_name.len(), 1);
+    }
+
+    #[test]
+    fn evicted_handles_resolve_to_the_fallback() {
+        let mut storage = Storage::new(2);
+        let fallback = storage.insert("<fallback>", 0);
+        storage.set_fallback(fallback);
+        let a = storage.insert("a", 1);
+        storage.insert("b", 2);
+
+        assert_eq!(storage.get(a), Some(&0));
+        assert_eq!(storage.get(fallback), Some(&0));
+    }
+
+    #[test]
+    fn specialised_shader_keys_ignore_constant_order() {
+        let constants = |pairs: &[(&str, f64)]| {
+            pairs
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value))
+                .collect::<HashMap<_, _>>()
+        };
+
+        assert_eq!(
+            specialised_shader_key("shader.wgsl", &constants(&[("B", 2.0), ("A", 1.0)])),
+            "shader.wgsl [A=1, B=2]"
+        );
+        assert_eq!(
+            specialised_shader_key("shader.wgsl", &constants(&[])),
+            "shader.wgsl []"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    hdr::SCENE_FORMAT,
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    uniform::UniformBuffer,
+};
+
+// The chain stops at this many levels, or sooner if the next would be under
+// `MIN_MIP_SIZE` texels across.
+const MAX_MIPS: usize = 6;
+const MIN_MIP_SIZE: u32 = 8;
+
+// Matches `BloomUniform` in bloom.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    _padding: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomSettings {
+    // How much of the blurred bright parts is added back to the frame.
+    pub intensity: f32,
+    // Pixels brighter than this, by their brightest channel, start to glow.
+    pub threshold: f32,
+    // How far below `threshold` the glow fades in rather than starting
+    // abruptly. 0 gives a hard cut-off.
+    pub knee: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.05,
+            threshold: 1.0,
+            knee: 0.5,
+        }
+    }
+}
+
+// One level of the blur chain, with a bind group for reading it as group 2.
+struct BloomMip {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// Glow around whatever's brighter than the threshold. The bright parts are
+// pulled out into a half-resolution copy, which is repeatedly halved and then
+// built back up level by level with the dual filter, so the blur is wide but
+// each pass only takes a few taps. The result is added to the frame.
+pub struct Bloom {
+    prefilter: FullscreenPass,
+    downsample: FullscreenPass,
+    upsample: FullscreenPass,
+    composite: FullscreenPass,
+    uniform_buffer: UniformBuffer<BloomUniform>,
+    mip_layout: wgpu::BindGroupLayout,
+    // `down[0]` is half the frame's size; `up[i]` matches `down[i]` and holds
+    // every level from `i` down, blurred.
+    down: Vec<BloomMip>,
+    up: Vec<BloomMip>,
+}
+
+impl Bloom {
+    pub fn new(device: &wgpu::Device, settings: BloomSettings, width: u32, height: u32) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &BloomUniform {
+                threshold: settings.threshold,
+                knee: settings.knee,
+                intensity: settings.intensity,
+                _padding: 0.0,
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "Bloom Uniform",
+        );
+        let mip_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Mip Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("bloom.wgsl"));
+        let uniform_layout = uniform_buffer.layout();
+        let prefilter = FullscreenPass::new(
+            device,
+            "Bloom Prefilter",
+            &shader,
+            "fs_prefilter",
+            &[uniform_layout],
+        );
+        let downsample =
+            FullscreenPass::new(device, "Bloom Downsample", &shader, "fs_downsample", &[]);
+        let upsample = FullscreenPass::new(
+            device,
+            "Bloom Upsample",
+            &shader,
+            "fs_upsample",
+            &[uniform_layout, &mip_layout],
+        );
+        let composite = FullscreenPass::new(
+            device,
+            "Bloom Composite",
+            &shader,
+            "fs_composite",
+            &[uniform_layout, &mip_layout],
+        );
+
+        let (down, up) = create_mips(device, &mip_layout, width, height);
+        Self {
+            prefilter,
+            downsample,
+            upsample,
+            composite,
+            uniform_buffer,
+            mip_layout,
+            down,
+            up,
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.down, self.up) = create_mips(device, &self.mip_layout, width, height);
+    }
+
+    // Every pass samples by uv, so a frame of a different size to the chain,
+    // e.g. a capture, still works at the chain's resolution.
+    fn draw(&self, frame: PostFrame) {
+        let PostFrame {
+            device,
+            encoder,
+            input,
+            output,
+            depth,
+        } = frame;
+        let uniform = self.uniform_buffer.bind_group();
+
+        self.prefilter.draw(
+            PostFrame {
+                device,
+                encoder,
+                input,
+                output: &self.down[0].view,
+                depth,
+            },
+            &[uniform],
+        );
+        for pair in self.down.windows(2) {
+            self.downsample.draw(
+                PostFrame {
+                    device,
+                    encoder,
+                    input: &pair[0].view,
+                    output: &pair[1].view,
+                    depth,
+                },
+                &[],
+            );
+        }
+
+        // Each level is the one above it upsampled, plus its own downsample.
+        let mut blurred = &self.down[self.down.len() - 1];
+        for (mip, up) in self.down.iter().zip(&self.up).rev() {
+            self.upsample.draw(
+                PostFrame {
+                    device,
+                    encoder,
+                    input: &blurred.view,
+                    output: &up.view,
+                    depth,
+                },
+                &[uniform, &mip.bind_group],
+            );
+            blurred = up;
+        }
+
+        self.composite.draw(
+            PostFrame {
+                device,
+                encoder,
+                input,
+                output,
+                depth,
+            },
+            &[uniform, &blurred.bind_group],
+        );
+    }
+}
+
+fn create_mips(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+) -> (Vec<BloomMip>, Vec<BloomMip>) {
+    let mut sizes = vec![((width / 2).max(1), (height / 2).max(1))];
+    while sizes.len() < MAX_MIPS {
+        let (width, height) = sizes[sizes.len() - 1];
+        if width.min(height) / 2 < MIN_MIP_SIZE {
+            break;
+        }
+        sizes.push((width / 2, height / 2));
+    }
+
+    let create_mip = |&(width, height): &(u32, u32)| {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bloom Mip Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SCENE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Mip Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+        BloomMip {
+            _texture: texture,
+            view,
+            bind_group,
+        }
+    };
+
+    let down = sizes.iter().map(create_mip).collect();
+    // The smallest level is never upsampled into, so it has no `up`.
+    let up = sizes[..sizes.len() - 1].iter().map(create_mip).collect();
+    (down, up)
+}
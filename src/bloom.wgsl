@@ -0,0 +1,73 @@
+// Bloom's passes, drawn with post_process.wgsl's vertex stage. See bloom.rs.
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+};
+@group(1) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+// A level of the chain: the matching downsample when upsampling, or the
+// finished blur when compositing. Read with `s_input`.
+@group(2) @binding(0)
+var t_mip: texture_2d<f32>;
+
+// Half a texel of the input, which the filters offset their taps by so each
+// bilinear sample averages four texels.
+fn half_texel() -> vec2<f32> {
+    return 0.5 / vec2<f32>(textureDimensions(t_input));
+}
+
+// Keeps what's above the threshold, with a quadratic ramp across the knee
+// so the glow doesn't switch on abruptly.
+@fragment
+fn fs_prefilter(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let colour = textureSample(t_input, s_input, uv).rgb;
+    let brightness = max(colour.r, max(colour.g, colour.b));
+    let soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    let ramp = soft * soft / (4.0 * bloom.knee + 0.0001);
+    let contribution = max(ramp, brightness - bloom.threshold) / max(brightness, 0.0001);
+    return vec4<f32>(colour * contribution, 1.0);
+}
+
+// The dual filter's downsample: the centre plus the four diagonals.
+@fragment
+fn fs_downsample(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let offset = half_texel();
+    var sum = textureSample(t_input, s_input, uv).rgb * 4.0;
+    sum += textureSample(t_input, s_input, uv - offset).rgb;
+    sum += textureSample(t_input, s_input, uv + offset).rgb;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(offset.x, -offset.y)).rgb;
+    sum += textureSample(t_input, s_input, uv - vec2<f32>(offset.x, -offset.y)).rgb;
+    return vec4<f32>(sum / 8.0, 1.0);
+}
+
+// The dual filter's upsample of the level below, a ring of eight taps,
+// added to this level's own downsample.
+@fragment
+fn fs_upsample(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let offset = half_texel();
+    var sum = textureSample(t_input, s_input, uv + vec2<f32>(-offset.x * 2.0, 0.0)).rgb;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(-offset.x, offset.y)).rgb * 2.0;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(0.0, offset.y * 2.0)).rgb;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(offset.x, offset.y)).rgb * 2.0;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(offset.x * 2.0, 0.0)).rgb;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(offset.x, -offset.y)).rgb * 2.0;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(0.0, -offset.y * 2.0)).rgb;
+    sum += textureSample(t_input, s_input, uv + vec2<f32>(-offset.x, -offset.y)).rgb * 2.0;
+    let own = textureSample(t_mip, s_input, uv).rgb;
+    return vec4<f32>(sum / 12.0 + own, 1.0);
+}
+
+@fragment
+fn fs_composite(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let scene = textureSample(t_input, s_input, uv);
+    let glow = textureSample(t_mip, s_input, uv).rgb;
+    return vec4<f32>(scene.rgb + glow * bloom.intensity, scene.a);
+}
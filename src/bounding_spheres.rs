@@ -0,0 +1,157 @@
+use wgpu::util::DeviceExt;
+
+use crate::{picking::BoundingSphere, texture::Texture};
+
+const RING_SEGMENTS: u32 = 64;
+
+// Debug view of the spheres around each mesh instance: three orthogonal
+// great circles per sphere, drawn as lines over the scene. The rings are built
+// once at unit radius and instanced onto every sphere.
+pub struct BoundingSphereDebug {
+    pipeline: wgpu::RenderPipeline,
+    ring_buffer: wgpu::Buffer,
+    ring_vertex_count: u32,
+    sphere_buffer: wgpu::Buffer,
+    sphere_capacity: usize,
+    sphere_count: u32,
+}
+
+impl BoundingSphereDebug {
+    // `layout` is the scene's pipeline layout, so the camera is at
+    // `CAMERA_GROUP` and the other groups the scene pass has set stay valid.
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        colour_target: wgpu::ColorTargetState,
+        sample_count: u32,
+    ) -> Self {
+        let ring = ring_vertices();
+        let ring_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bounding Sphere Ring Buffer"),
+            contents: bytemuck::cast_slice(&ring),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("bounding_spheres.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Bounding Sphere Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![1 => Float32x4],
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(colour_target)],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // Tested against the scene so rings pass behind what's in front of
+            // them, but never written, so they don't hide each other.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            ring_buffer,
+            ring_vertex_count: ring.len() as u32,
+            sphere_buffer: create_sphere_buffer(device, 1),
+            sphere_capacity: 1,
+            sphere_count: 0,
+        }
+    }
+
+    pub fn ring_vertex_count(&self) -> u32 {
+        self.ring_vertex_count
+    }
+
+    pub fn sphere_count(&self) -> u32 {
+        self.sphere_count
+    }
+
+    // The buffer only grows, to the next power of two that fits.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        spheres: &[BoundingSphere],
+    ) {
+        let data = spheres
+            .iter()
+            .map(|sphere| {
+                let [x, y, z]: [f32; 3] = sphere.centre.into();
+                [x, y, z, sphere.radius]
+            })
+            .collect::<Vec<_>>();
+        if data.len() > self.sphere_capacity {
+            self.sphere_capacity = data.len().next_power_of_two();
+            self.sphere_buffer = create_sphere_buffer(device, self.sphere_capacity);
+        }
+        queue.write_buffer(&self.sphere_buffer, 0, bytemuck::cast_slice(&data));
+        self.sphere_count = data.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.sphere_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.ring_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.sphere_buffer.slice(..));
+        render_pass.draw(0..self.ring_vertex_count, 0..self.sphere_count);
+    }
+}
+
+fn create_sphere_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Bounding Sphere Buffer"),
+        size: (capacity * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Unit circles in the XY, YZ and ZX planes, as pairs of line endpoints.
+fn ring_vertices() -> Vec<[f32; 3]> {
+    let point = |plane: usize, step: u32| {
+        let angle = step as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        let mut point = [0.0; 3];
+        point[plane] = cos;
+        point[(plane + 1) % 3] = sin;
+        point
+    };
+
+    (0..3)
+        .flat_map(|plane| {
+            (0..RING_SEGMENTS).flat_map(move |step| [point(plane, step), point(plane, step + 1)])
+        })
+        .collect()
+}
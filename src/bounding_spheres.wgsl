@@ -0,0 +1,29 @@
+// Draws each bounding sphere as three unit great circles, scaled and moved
+// onto the sphere per instance. See bounding_spheres.rs.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+struct SphereInput {
+    // xyz is the centre, w the radius.
+    @location(1) sphere: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: SphereInput) -> @builtin(position) vec4<f32> {
+    let world_position = instance.sphere.xyz + vertex.position * instance.sphere.w;
+    return camera.view_proj * vec4<f32>(world_position, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.2, 1.0, 0.4, 1.0);
+}
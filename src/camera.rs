@@ -0,0 +1,404 @@
+use std::time::Duration;
+
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix, Transform, VectorSpace};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+impl Projection {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Perspective => Self::Orthographic,
+            Self::Orthographic => Self::Perspective,
+        }
+    }
+}
+
+// The fixed directions a CAD tool snaps to, each named for the side of the
+// scene it shows. Isometric looks down from the front right.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PresetView {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    Isometric,
+}
+
+impl PresetView {
+    pub const ALL: [Self; 7] = [
+        Self::Front,
+        Self::Back,
+        Self::Left,
+        Self::Right,
+        Self::Top,
+        Self::Bottom,
+        Self::Isometric,
+    ];
+
+    // Which way the camera looks.
+    pub fn direction(self) -> cgmath::Vector3<f32> {
+        match self {
+            Self::Front => -cgmath::Vector3::unit_z(),
+            Self::Back => cgmath::Vector3::unit_z(),
+            Self::Left => cgmath::Vector3::unit_x(),
+            Self::Right => -cgmath::Vector3::unit_x(),
+            Self::Top => -cgmath::Vector3::unit_y(),
+            Self::Bottom => cgmath::Vector3::unit_y(),
+            Self::Isometric => cgmath::Vector3::new(-1.0, -1.0, -1.0).normalize(),
+        }
+    }
+}
+
+// Where a camera is and what it looks at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewpoint {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+}
+
+impl Viewpoint {
+    fn lerp(self, other: Self, amount: f32) -> Self {
+        let lerp = |a: cgmath::Point3<f32>, b: cgmath::Point3<f32>| {
+            cgmath::Point3::from_vec(a.to_vec().lerp(b.to_vec(), amount))
+        };
+        Self {
+            eye: lerp(self.eye, other.eye),
+            target: lerp(self.target, other.target),
+        }
+    }
+}
+
+struct Flight {
+    from: Viewpoint,
+    to: Viewpoint,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+pub struct Camera {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    pub up: cgmath::Vector3<f32>,
+    pub aspect: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub projection: Projection,
+    flight: Option<Flight>,
+}
+
+impl Camera {
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            eye: (0.0, 6.0, 12.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+            flight: None,
+        }
+    }
+
+    pub fn viewpoint(&self) -> Viewpoint {
+        Viewpoint {
+            eye: self.eye,
+            target: self.target,
+        }
+    }
+
+    pub fn set_viewpoint(&mut self, viewpoint: Viewpoint) {
+        self.eye = viewpoint.eye;
+        self.target = viewpoint.target;
+    }
+
+    // Where to stand, looking along `direction`, for a sphere to just fit in
+    // the narrower of the two fields of view.
+    pub fn framing(
+        &self,
+        centre: cgmath::Point3<f32>,
+        radius: f32,
+        direction: cgmath::Vector3<f32>,
+    ) -> Viewpoint {
+        let half_fovy = (self.fovy / 2.0).to_radians();
+        let half_fovx = (half_fovy.tan() * self.aspect).atan();
+        let distance = radius / half_fovy.min(half_fovx).sin();
+        Viewpoint {
+            eye: centre - direction.normalize() * distance,
+            target: centre,
+        }
+    }
+
+    // Starts moving smoothly from the current viewpoint to `to`, easing in
+    // and out. Call `update_flight` every frame to advance it.
+    pub fn fly_to(&mut self, to: Viewpoint, duration: Duration) {
+        self.flight = Some(Flight {
+            from: self.viewpoint(),
+            to,
+            elapsed: Duration::ZERO,
+            duration,
+        });
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.flight.is_some()
+    }
+
+    // Returns true on the frame the flight arrives.
+    pub fn update_flight(&mut self, dt: Duration) -> bool {
+        let Some(flight) = &mut self.flight else {
+            return false;
+        };
+
+        flight.elapsed += dt;
+        let t = if flight.duration.is_zero() {
+            1.0
+        } else {
+            (flight.elapsed.as_secs_f32() / flight.duration.as_secs_f32()).min(1.0)
+        };
+        let eased = t * t * (3.0 - 2.0 * t);
+        let viewpoint = flight.from.lerp(flight.to, eased);
+        if t >= 1.0 {
+            self.flight = None;
+        }
+        self.set_viewpoint(viewpoint);
+        t >= 1.0
+    }
+
+    // Exponential damping of the viewpoint and field of view towards `goal`:
+    // after `time_constant` the camera has covered about 63% of the remaining
+    // distance, whatever the frame rate. A zero time constant snaps straight
+    // to the goal.
+    pub fn smooth_towards(&mut self, goal: &Camera, time_constant: Duration, dt: Duration) {
+        let amount = if time_constant.is_zero() {
+            1.0
+        } else {
+            1.0 - (-dt.as_secs_f32() / time_constant.as_secs_f32()).exp()
+        };
+
+        self.set_viewpoint(self.viewpoint().lerp(goal.viewpoint(), amount));
+        self.fovy += (goal.fovy - self.fovy) * amount;
+    }
+
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.view_projection_between(self.znear, self.zfar)
+    }
+
+    pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.view_up())
+    }
+
+    // `up`, unless the camera is looking straight along it, as the top and
+    // bottom views do, where it says nothing about which way up the view is.
+    // Then the back of the scene goes at the top of the view when looking
+    // down, and the front when looking up, as the view flying in from the
+    // front would have it.
+    fn view_up(&self) -> cgmath::Vector3<f32> {
+        let forward = (self.target - self.eye).normalize();
+        if forward.cross(self.up).magnitude2() > 1e-6 {
+            return self.up;
+        }
+        cgmath::Vector3::unit_z() * forward.dot(self.up).signum()
+    }
+
+    pub fn projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        self.projection_between(self.znear, self.zfar)
+    }
+
+    // The view-projection matrix with the depth range limited to `znear` to
+    // `zfar`, for looking at a slice of the view.
+    fn view_projection_between(&self, znear: f32, zfar: f32) -> cgmath::Matrix4<f32> {
+        self.projection_between(znear, zfar) * self.view_matrix()
+    }
+
+    fn projection_between(&self, znear: f32, zfar: f32) -> cgmath::Matrix4<f32> {
+        let proj = match self.projection {
+            Projection::Perspective => {
+                cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, znear, zfar)
+            }
+            Projection::Orthographic => {
+                // Sized to match the perspective view at the depth of the
+                // scene origin, so switching projections keeps the scene
+                // framed the same however far ahead `target` is.
+                let forward = (self.target - self.eye).normalize();
+                let distance = forward
+                    .dot(cgmath::Point3::new(0.0, 0.0, 0.0) - self.eye)
+                    .max(self.znear);
+                let half_height = distance * (self.fovy / 2.0).to_radians().tan();
+                let half_width = half_height * self.aspect;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    znear,
+                    zfar,
+                )
+            }
+        };
+        OPENGL_TO_WGPU_MATRIX * proj
+    }
+
+    // The eight corners of the part of the view between distances `near` and
+    // `far` in front of the camera: the near corners first, then the far.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [cgmath::Point3<f32>; 8] {
+        let inverse = self
+            .view_projection_between(near, far)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        let mut corners = [cgmath::Point3::new(0.0, 0.0, 0.0); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+            *corner = inverse.transform_point(cgmath::Point3::new(x, y, z));
+        }
+        corners
+    }
+
+    // Where `point` lands on a surface of `size`, in physical pixels from the
+    // top-left corner. None when it's behind the camera or outside the depth
+    // range; points off the sides of the screen are still returned.
+    pub fn world_to_screen(
+        &self,
+        point: cgmath::Point3<f32>,
+        size: PhysicalSize<u32>,
+    ) -> Option<PhysicalPosition<f64>> {
+        let clip = self.build_view_projection_matrix() * point.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        if !(0.0..=1.0).contains(&ndc.z) {
+            return None;
+        }
+
+        Some(PhysicalPosition::new(
+            (ndc.x as f64 + 1.0) / 2.0 * size.width as f64,
+            (1.0 - ndc.y as f64) / 2.0 * size.height as f64,
+        ))
+    }
+
+    // The world point under a screen position at the given depth, where 0 is
+    // the near plane and 1 the far plane, as stored in the depth buffer.
+    pub fn screen_to_world(
+        &self,
+        position: PhysicalPosition<f64>,
+        depth: f32,
+        size: PhysicalSize<u32>,
+    ) -> Option<cgmath::Point3<f32>> {
+        let (x, y) = screen_to_ndc(position, size);
+        let inverse = self.build_view_projection_matrix().invert()?;
+        Some(inverse.transform_point(cgmath::Point3::new(x as f32, y as f32, depth)))
+    }
+}
+
+// Maps a window position in physical pixels to normalised device coordinates,
+// with (-1, -1) at the bottom-left corner and (1, 1) at the top-right.
+pub fn screen_to_ndc(position: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> (f64, f64) {
+    (
+        2.0 * position.x / size.width as f64 - 1.0,
+        1.0 - 2.0 * position.y / size.height as f64,
+    )
+}
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+pub struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    // w is unused; vec3 would be padded to 16 bytes anyway.
+    view_position: [f32; 4],
+    // Takes clip space back to world space, e.g. to find the direction a
+    // pixel looks in.
+    inverse_view_proj: [[f32; 4]; 4],
+}
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        Self {
+            view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0; 4],
+            inverse_view_proj: cgmath::Matrix4::identity().into(),
+        }
+    }
+
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        let view_proj = camera.build_view_projection_matrix();
+        self.view_proj = view_proj.into();
+        self.view_position = camera.eye.to_homogeneous().into();
+        self.inverse_view_proj = view_proj
+            .invert()
+            .unwrap_or(cgmath::Matrix4::identity())
+            .into();
+    }
+
+    // Looks through `view_proj` instead of a `Camera`, from the middle of
+    // its near plane.
+    pub fn set_view_proj(&mut self, view_proj: cgmath::Matrix4<f32>) {
+        let inverse_view_proj = view_proj.invert().unwrap_or(cgmath::Matrix4::identity());
+        let eye = inverse_view_proj * cgmath::Vector4::unit_w();
+        self.view_proj = view_proj.into();
+        self.view_position = (eye / eye.w).into();
+        self.inverse_view_proj = inverse_view_proj.into();
+    }
+
+    // Shifts the projection by `offset` in NDC, after `update_view_proj`.
+    pub fn jitter(&mut self, offset: cgmath::Vector2<f32>) {
+        let view_proj = cgmath::Matrix4::from_translation(offset.extend(0.0))
+            * cgmath::Matrix4::from(self.view_proj);
+        self.view_proj = view_proj.into();
+        self.inverse_view_proj = view_proj
+            .invert()
+            .unwrap_or(cgmath::Matrix4::identity())
+            .into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_views_frame_the_scene_along_their_axis() {
+        let mut camera = Camera::new(16.0 / 9.0);
+        let centre = cgmath::Point3::new(1.0, 2.0, 3.0);
+        for view in PresetView::ALL {
+            camera.set_viewpoint(camera.framing(centre, 2.0, view.direction()));
+            let forward = (camera.target - camera.eye).normalize();
+            assert!((forward - view.direction()).magnitude() < 1e-5, "{view:?}");
+            assert_eq!(camera.target, centre);
+
+            // The scene's centre lands in the middle of the view, and nothing
+            // degenerates looking straight up or down.
+            let clip = camera.build_view_projection_matrix() * centre.to_homogeneous();
+            assert!(clip.x.abs() < 1e-4 && clip.y.abs() < 1e-4, "{view:?}");
+        }
+    }
+
+    #[test]
+    fn screen_corners_and_centre_map_to_ndc() {
+        let size = PhysicalSize::new(800, 600);
+        let ndc = |x, y| screen_to_ndc(PhysicalPosition::new(x, y), size);
+
+        assert_eq!(ndc(0.0, 0.0), (-1.0, 1.0));
+        assert_eq!(ndc(800.0, 600.0), (1.0, -1.0));
+        assert_eq!(ndc(400.0, 300.0), (0.0, 0.0));
+    }
+}
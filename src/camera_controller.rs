@@ -0,0 +1,362 @@
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta},
+    keyboard::{Key, NamedKey},
+};
+
+use crate::{camera::Camera, input_recording::InputEvent};
+
+// Keeps the camera from flipping over when looking straight up or down.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+
+// Turns input into camera movement. `process_input` returns true when the
+// controller consumed the event.
+pub trait CameraController {
+    fn process_input(&mut self, input: &InputEvent) -> bool;
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration);
+    // While the pointer is locked every mouse movement is meant for the
+    // camera, without holding a button.
+    fn set_pointer_locked(&mut self, _locked: bool) {}
+    // The point the next drag should rotate around, or `None` for the
+    // controller's own target.
+    fn set_orbit_pivot(&mut self, _pivot: Option<Point3<f32>>) {}
+    // Whether the mouse is steering the camera right now.
+    fn is_dragging(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    FirstPerson,
+    Orbit,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::FirstPerson => Self::Orbit,
+            Self::Orbit => Self::FirstPerson,
+        }
+    }
+
+    // The new controller picks up from wherever `camera` is, so switching
+    // doesn't move it.
+    pub fn controller(
+        self,
+        camera: &Camera,
+        speed: f32,
+        sensitivity: f32,
+    ) -> Box<dyn CameraController> {
+        match self {
+            Self::FirstPerson => Box::new(FirstPersonController::new(camera, speed, sensitivity)),
+            Self::Orbit => Box::new(OrbitController::new(camera, sensitivity)),
+        }
+    }
+}
+
+// Scroll distance in lines, treating 20 pixels of a touchpad scroll as a line.
+pub fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+    }
+}
+
+// Yaw and pitch of the direction `from` -> `to`.
+fn yaw_pitch(from: Point3<f32>, to: Point3<f32>) -> (f32, f32) {
+    let direction = (to - from).normalize();
+    (direction.z.atan2(direction.x), direction.y.asin())
+}
+
+fn direction(yaw: f32, pitch: f32) -> Vector3<f32> {
+    Vector3::new(
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+        yaw.sin() * pitch.cos(),
+    )
+}
+
+// `direction(yaw, pitch)` with the right and up axes around it.
+fn axes(yaw: f32, pitch: f32) -> [Vector3<f32>; 3] {
+    let offset = direction(yaw, pitch);
+    let right = Vector3::unit_y().cross(offset).normalize();
+    let up = offset.cross(right);
+    [offset, right, up]
+}
+
+// First-person fly camera, active while the right mouse button is held or the
+// pointer is locked: mouse to look, WASD to move, Q/E to go down/up. Shift
+// speeds movement up and Control slows it down. Keys only reach the camera
+// while flying, so the usual single-key toggles keep working otherwise.
+pub struct FirstPersonController {
+    speed: f32,
+    sensitivity: f32,
+    yaw: f32,
+    pitch: f32,
+    mouselook_held: bool,
+    pointer_locked: bool,
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    fast: bool,
+    slow: bool,
+    mouse_delta: (f64, f64),
+}
+
+impl FirstPersonController {
+    // Starts out looking the same way as `camera`.
+    pub fn new(camera: &Camera, speed: f32, sensitivity: f32) -> Self {
+        let (yaw, pitch) = yaw_pitch(camera.eye, camera.target);
+
+        Self {
+            speed,
+            sensitivity,
+            yaw,
+            pitch,
+            mouselook_held: false,
+            pointer_locked: false,
+            forward: false,
+            backward: false,
+            left: false,
+            right: false,
+            up: false,
+            down: false,
+            fast: false,
+            slow: false,
+            mouse_delta: (0.0, 0.0),
+        }
+    }
+
+    fn flying(&self) -> bool {
+        self.mouselook_held || self.pointer_locked
+    }
+
+    fn stop(&mut self) {
+        self.forward = false;
+        self.backward = false;
+        self.left = false;
+        self.right = false;
+        self.up = false;
+        self.down = false;
+        self.mouse_delta = (0.0, 0.0);
+    }
+}
+
+impl CameraController for FirstPersonController {
+    fn process_input(&mut self, input: &InputEvent) -> bool {
+        match input {
+            InputEvent::MouseInput {
+                button: MouseButton::Right,
+                state,
+            } => {
+                self.mouselook_held = *state == ElementState::Pressed;
+                if !self.flying() {
+                    self.stop();
+                }
+                true
+            }
+            InputEvent::MouseMotion { dx, dy } if self.flying() => {
+                self.mouse_delta.0 += dx;
+                self.mouse_delta.1 += dy;
+                true
+            }
+            // Modifiers are tracked but never consumed.
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Shift),
+                state,
+            } => {
+                self.fast = *state == ElementState::Pressed;
+                false
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Control),
+                state,
+            } => {
+                self.slow = *state == ElementState::Pressed;
+                false
+            }
+            InputEvent::Key {
+                key: Key::Character(ch),
+                state,
+            } if self.flying() => {
+                let pressed = *state == ElementState::Pressed;
+                let direction = match ch.to_lowercase().as_str() {
+                    "w" => &mut self.forward,
+                    "s" => &mut self.backward,
+                    "a" => &mut self.left,
+                    "d" => &mut self.right,
+                    "e" => &mut self.up,
+                    "q" => &mut self.down,
+                    _ => return false,
+                };
+                *direction = pressed;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let (dx, dy) = std::mem::take(&mut self.mouse_delta);
+        self.yaw += dx as f32 * self.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let forward = direction(self.yaw, self.pitch);
+        let right = forward.cross(Vector3::unit_y()).normalize();
+
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let movement = forward * axis(self.forward, self.backward)
+            + right * axis(self.right, self.left)
+            + Vector3::unit_y() * axis(self.up, self.down);
+
+        let mut speed = self.speed;
+        if self.fast {
+            speed *= 4.0;
+        }
+        if self.slow {
+            speed *= 0.25;
+        }
+
+        if movement.magnitude2() > 0.0 {
+            camera.eye += movement.normalize() * speed * dt.as_secs_f32();
+        }
+        camera.target = camera.eye + forward;
+    }
+
+    fn set_pointer_locked(&mut self, locked: bool) {
+        self.pointer_locked = locked;
+        if !self.flying() {
+            self.stop();
+        }
+    }
+
+    fn is_dragging(&self) -> bool {
+        self.flying()
+    }
+}
+
+// Orbits a target point for inspecting models: left-drag to rotate around it,
+// middle-drag to pan it and scroll to zoom. A drag can instead rotate around
+// a pivot elsewhere, such as the point under the cursor, in which case the
+// target swings around the pivot along with the eye.
+pub struct OrbitController {
+    sensitivity: f32,
+    target: Point3<f32>,
+    pivot: Option<Point3<f32>>,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+    rotating: bool,
+    panning: bool,
+    rotate_delta: (f64, f64),
+    pan_delta: (f64, f64),
+    zoom_delta: f32,
+}
+
+impl OrbitController {
+    // Orbits `camera.target` from where the camera currently is.
+    pub fn new(camera: &Camera, sensitivity: f32) -> Self {
+        // Yaw and pitch point from the target out to the eye.
+        let (yaw, pitch) = yaw_pitch(camera.target, camera.eye);
+
+        Self {
+            sensitivity,
+            target: camera.target,
+            pivot: None,
+            distance: (camera.eye - camera.target)
+                .magnitude()
+                .max(MIN_ORBIT_DISTANCE),
+            yaw,
+            pitch,
+            rotating: false,
+            panning: false,
+            rotate_delta: (0.0, 0.0),
+            pan_delta: (0.0, 0.0),
+            zoom_delta: 0.0,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn process_input(&mut self, input: &InputEvent) -> bool {
+        match input {
+            InputEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+            } => {
+                self.rotating = *state == ElementState::Pressed;
+                true
+            }
+            InputEvent::MouseInput {
+                button: MouseButton::Middle,
+                state,
+            } => {
+                self.panning = *state == ElementState::Pressed;
+                true
+            }
+            InputEvent::MouseMotion { dx, dy } if self.rotating || self.panning => {
+                let delta = if self.rotating {
+                    &mut self.rotate_delta
+                } else {
+                    &mut self.pan_delta
+                };
+                delta.0 += dx;
+                delta.1 += dy;
+                true
+            }
+            InputEvent::MouseWheel(delta) => {
+                self.zoom_delta += scroll_lines(delta);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, _dt: Duration) {
+        let before = axes(self.yaw, self.pitch);
+        let (dx, dy) = std::mem::take(&mut self.rotate_delta);
+        self.yaw += dx as f32 * self.sensitivity;
+        self.pitch = (self.pitch + dy as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        let [offset, right, up] = axes(self.yaw, self.pitch);
+
+        // Turning the target the same way as the view keeps the pivot still
+        // relative to the camera, so it stays put on screen.
+        if let Some(pivot) = self.pivot {
+            let from_pivot = self.target - pivot;
+            self.target = pivot
+                + [offset, right, up]
+                    .iter()
+                    .zip(before)
+                    .map(|(after, before)| after * from_pivot.dot(before))
+                    .sum::<Vector3<f32>>();
+        }
+
+        // Each step zooms by 10%, so zooming feels the same at any distance.
+        let zoom = std::mem::take(&mut self.zoom_delta);
+        self.distance = (self.distance * 0.9f32.powf(zoom)).max(MIN_ORBIT_DISTANCE);
+
+        // Panning scales with distance so the target keeps up with the cursor.
+        let (dx, dy) = std::mem::take(&mut self.pan_delta);
+        let pan = self.distance * self.sensitivity;
+        self.target += (right * -dx as f32 + up * dy as f32) * pan;
+
+        camera.target = self.target;
+        camera.eye = self.target + offset * self.distance;
+    }
+
+    fn set_orbit_pivot(&mut self, pivot: Option<Point3<f32>>) {
+        self.pivot = pivot;
+    }
+
+    fn is_dragging(&self) -> bool {
+        self.rotating || self.panning
+    }
+}
@@ -0,0 +1,213 @@
+use std::{fmt, path::PathBuf};
+
+#[derive(Debug)]
+pub enum DumpTargetError {
+    UnknownTarget(String),
+    Image(PathBuf, image::ImageError),
+}
+
+impl fmt::Display for DumpTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownTarget(name) => write!(f, "no render target called {name:?}"),
+            Self::Image(path, error) => write!(f, "failed to save {}: {error}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for DumpTargetError {}
+
+// Besides 8-bit colour, float colour and depth can be read back for
+// debugging: see `to_rgba8` for how they're made viewable.
+pub fn can_read_rgba8(format: wgpu::TextureFormat) -> bool {
+    bytes_per_texel(format).is_some()
+}
+
+fn bytes_per_texel(format: wgpu::TextureFormat) -> Option<u32> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm
+        | wgpu::TextureFormat::Rgba8UnormSrgb
+        | wgpu::TextureFormat::Bgra8Unorm
+        | wgpu::TextureFormat::Bgra8UnormSrgb
+        | wgpu::TextureFormat::R32Float
+        | wgpu::TextureFormat::Depth32Float => Some(4),
+        wgpu::TextureFormat::Rgba16Float => Some(8),
+        _ => None,
+    }
+}
+
+// Copies the first layer of a texture back to the CPU as tightly packed
+// RGBA8 rows. The texture must have been created with `COPY_SRC`.
+pub fn read_texture_rgba8(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Vec<u8> {
+    let format = texture.format();
+    let Some(bytes_per_texel) = bytes_per_texel(format) else {
+        panic!("Can't read back texture format {format:?} as RGBA8");
+    };
+
+    let width = texture.width();
+    let height = texture.height();
+    let unpadded_bytes_per_row = width * bytes_per_texel;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Capture Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: if format.has_depth_aspect() {
+                wgpu::TextureAspect::DepthOnly
+            } else {
+                wgpu::TextureAspect::All
+            },
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &output_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let buffer_slice = output_buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().unwrap().unwrap();
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let data = buffer_slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    output_buffer.unmap();
+
+    to_rgba8(format, pixels)
+}
+
+// 8-bit colour is passed through, swizzled to RGBA. Float colour is clamped
+// to 0..1 and sRGB encoded, with no tonemapping. Single-channel floats,
+// depth included, are shown as grey stretched between the nearest and
+// farthest values below 1, so the scene's depth range fills the image rather
+// than crowding up against the far plane. Anything but 8-bit colour comes
+// out opaque, since those alphas are rarely coverage.
+fn to_rgba8(format: wgpu::TextureFormat, mut data: Vec<u8>) -> Vec<u8> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => data,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            data
+        }
+        wgpu::TextureFormat::Rgba16Float => data
+            .chunks_exact(8)
+            .flat_map(|texel| {
+                let channel = |i: usize| {
+                    let value = half::f16::from_le_bytes([texel[i * 2], texel[i * 2 + 1]]);
+                    unorm8(linear_to_srgb(value.to_f32()))
+                };
+                [channel(0), channel(1), channel(2), 255]
+            })
+            .collect(),
+        _ => {
+            let values = data
+                .chunks_exact(4)
+                .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+                .collect::<Vec<_>>();
+            let (min, max) = values
+                .iter()
+                .filter(|value| **value < 1.0)
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+                    (min.min(*value), max.max(*value))
+                });
+            let range = (max - min).max(f32::EPSILON);
+            values
+                .into_iter()
+                .flat_map(|value| {
+                    let grey = if value < 1.0 {
+                        unorm8((value - min) / range)
+                    } else {
+                        255
+                    };
+                    [grey, grey, grey, 255]
+                })
+                .collect()
+        }
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth_bytes(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn stretches_depth_between_nearest_and_farthest() {
+        let pixels = to_rgba8(
+            wgpu::TextureFormat::Depth32Float,
+            depth_bytes(&[0.9, 0.95, 1.0]),
+        );
+        assert_eq!(
+            pixels,
+            [0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn clamps_and_encodes_float_colour() {
+        let texel = [2.0, 0.5, -1.0, 0.0]
+            .iter()
+            .flat_map(|value| half::f16::from_f32(*value).to_le_bytes())
+            .collect();
+        assert_eq!(
+            to_rgba8(wgpu::TextureFormat::Rgba16Float, texel),
+            [255, 188, 0, 255]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// Clustered light culling: one invocation per froxel, listing the point and
+// spot lights whose range reaches it. See light_clusters.rs.
+
+const CLUSTER_X: u32 = 16u;
+const CLUSTER_Y: u32 = 9u;
+const CLUSTER_Z: u32 = 24u;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 32u;
+
+struct Light {
+    direction: vec3<f32>,
+    intensity: f32,
+    colour: vec3<f32>,
+    point_light_count: u32,
+    spot_light_count: u32,
+};
+@group(0) @binding(0)
+var<uniform> light: Light;
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    colour: vec3<f32>,
+    intensity: f32,
+    attenuation: vec2<f32>,
+    casts_shadows: u32,
+    shadow_index: i32,
+};
+@group(0) @binding(1)
+var<storage, read> point_lights: array<PointLight>;
+
+struct SpotLight {
+    position: vec3<f32>,
+    radius: f32,
+    direction: vec3<f32>,
+    intensity: f32,
+    colour: vec3<f32>,
+    inner_cos: f32,
+    attenuation: vec2<f32>,
+    outer_cos: f32,
+};
+@group(0) @binding(2)
+var<storage, read> spot_lights: array<SpotLight>;
+
+struct ClusterView {
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inverse_proj: mat4x4<f32>,
+    znear: f32,
+    zfar: f32,
+};
+@group(0) @binding(3)
+var<uniform> cluster_view: ClusterView;
+
+struct Cluster {
+    point_count: u32,
+    spot_count: u32,
+};
+@group(0) @binding(4)
+var<storage, read_write> clusters: array<Cluster>;
+@group(0) @binding(5)
+var<storage, read_write> light_indices: array<u32>;
+
+// Depth of the near side of slice `slice`. Slices are spaced exponentially.
+fn slice_depth(slice: u32) -> f32 {
+    let t = f32(slice) / f32(CLUSTER_Z);
+    return cluster_view.znear * pow(cluster_view.zfar / cluster_view.znear, t);
+}
+
+// Where the line through `ndc` from the near to the far plane reaches view
+// space depth `depth`. Works for perspective and orthographic projections.
+fn point_at_depth(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let near = cluster_view.inverse_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = cluster_view.inverse_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let a = near.xyz / near.w;
+    let b = far.xyz / far.w;
+    // View space looks down -z.
+    let t = (-depth - a.z) / (b.z - a.z);
+    return a + (b - a) * t;
+}
+
+fn sphere_touches_box(centre: vec3<f32>, radius: f32, box_min: vec3<f32>, box_max: vec3<f32>) -> bool {
+    let closest = clamp(centre, box_min, box_max);
+    let offset = closest - centre;
+    return dot(offset, offset) <= radius * radius;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= CLUSTER_X * CLUSTER_Y * CLUSTER_Z {
+        return;
+    }
+    let x = index % CLUSTER_X;
+    let y = (index / CLUSTER_X) % CLUSTER_Y;
+    let z = index / (CLUSTER_X * CLUSTER_Y);
+
+    // The view space box around the froxel.
+    let tile = vec2<f32>(2.0 / f32(CLUSTER_X), 2.0 / f32(CLUSTER_Y));
+    let ndc_min = vec2<f32>(-1.0) + vec2<f32>(f32(x), f32(y)) * tile;
+    let ndc_max = ndc_min + tile;
+    let near_depth = slice_depth(z);
+    let far_depth = slice_depth(z + 1u);
+    var box_min = vec3<f32>(1e30);
+    var box_max = vec3<f32>(-1e30);
+    for (var corner = 0u; corner < 8u; corner++) {
+        let ndc = vec2<f32>(
+            select(ndc_min.x, ndc_max.x, (corner & 1u) != 0u),
+            select(ndc_min.y, ndc_max.y, (corner & 2u) != 0u),
+        );
+        let depth = select(near_depth, far_depth, (corner & 4u) != 0u);
+        let point = point_at_depth(ndc, depth);
+        box_min = min(box_min, point);
+        box_max = max(box_max, point);
+    }
+
+    let first = index * MAX_LIGHTS_PER_CLUSTER;
+    var count = 0u;
+    for (var i = 0u; i < light.point_light_count && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let point = point_lights[i];
+        let centre = (cluster_view.view * vec4<f32>(point.position, 1.0)).xyz;
+        if sphere_touches_box(centre, point.radius, box_min, box_max) {
+            light_indices[first + count] = i;
+            count++;
+        }
+    }
+    let point_count = count;
+
+    // Spot lights are tested by the sphere their cone fits in.
+    for (var i = 0u; i < light.spot_light_count && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let spot = spot_lights[i];
+        let centre = (cluster_view.view * vec4<f32>(spot.position, 1.0)).xyz;
+        if sphere_touches_box(centre, spot.radius, box_min, box_max) {
+            light_indices[first + count] = i;
+            count++;
+        }
+    }
+
+    clusters[index] = Cluster(point_count, count - point_count);
+}
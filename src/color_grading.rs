@@ -0,0 +1,249 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::post_process::{FullscreenPass, PostEffect, PostFrame};
+
+const LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+#[derive(Debug)]
+pub enum LutLoadError {
+    Io(PathBuf, io::Error),
+    Image(PathBuf, image::ImageError),
+    Invalid(PathBuf, &'static str),
+}
+
+impl fmt::Display for LutLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, error) => write!(f, "failed to read {}: {error}", path.display()),
+            Self::Image(path, error) => write!(f, "failed to load {}: {error}", path.display()),
+            Self::Invalid(path, reason) => {
+                write!(f, "{} is not a usable LUT: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LutLoadError {}
+
+// A cube of `size`^3 colours, red varying fastest, then green, then blue.
+struct LutData {
+    size: u32,
+    texels: Vec<[u8; 4]>,
+}
+
+struct Lut {
+    name: String,
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Remaps the tonemapped frame's colours through a 3D lookup table, the way
+// grading tools export a look. LUTs come from Adobe .cube files or from PNG
+// strips of `size` squares side by side, each `size` pixels across, with red
+// along each square, green down it and blue from square to square. Both are
+// taken to map sRGB-encoded colours, as they're usually authored.
+pub struct ColorGrading {
+    pass: FullscreenPass,
+    luts: Vec<Lut>,
+    current: Option<usize>,
+}
+
+impl ColorGrading {
+    // LUTs that fail to load are reported and left out. The first one that
+    // loads is used to start with.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, paths: &[PathBuf]) -> Self {
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("LUT Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            }],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("color_grading.wgsl"));
+        let pass = FullscreenPass::new(device, "Color Grading", &shader, "fs_main", &[&lut_layout]);
+
+        let luts = paths
+            .iter()
+            .filter_map(|path| match load_lut(path) {
+                Ok(data) => Some(create_lut(device, queue, &lut_layout, path, &data)),
+                Err(e) => {
+                    eprintln!("{e}, skipping the LUT");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let current = (!luts.is_empty()).then_some(0);
+
+        Self {
+            pass,
+            luts,
+            current,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.current.is_some()
+    }
+
+    // Steps through each LUT and then no grading, returning the name of the
+    // LUT now in use.
+    pub fn next_lut(&mut self) -> Option<&str> {
+        self.current = match self.current {
+            None if !self.luts.is_empty() => Some(0),
+            Some(index) if index + 1 < self.luts.len() => Some(index + 1),
+            _ => None,
+        };
+        self.current.map(|index| self.luts[index].name.as_str())
+    }
+
+    pub fn reset(&mut self) {
+        self.current = (!self.luts.is_empty()).then_some(0);
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn draw(&self, frame: PostFrame) {
+        let Some(index) = self.current else {
+            return;
+        };
+        self.pass.draw(frame, &[&self.luts[index].bind_group]);
+    }
+}
+
+fn load_lut(path: &Path) -> Result<LutData, LutLoadError> {
+    let is_cube = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cube"));
+    if is_cube {
+        let text = fs::read_to_string(path).map_err(|e| LutLoadError::Io(path.to_path_buf(), e))?;
+        parse_cube(&text).map_err(|reason| LutLoadError::Invalid(path.to_path_buf(), reason))
+    } else {
+        let image = image::open(path)
+            .map_err(|e| LutLoadError::Image(path.to_path_buf(), e))?
+            .to_rgba8();
+        read_strip(&image).map_err(|reason| LutLoadError::Invalid(path.to_path_buf(), reason))
+    }
+}
+
+fn parse_cube(text: &str) -> Result<LutData, &'static str> {
+    let mut size = None;
+    let mut texels = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("TITLE") => {}
+            Some("LUT_3D_SIZE") => {
+                size = words.next().and_then(|word| word.parse::<u32>().ok());
+                if size.is_none() {
+                    return Err("bad LUT_3D_SIZE");
+                }
+            }
+            Some("LUT_1D_SIZE") => return Err("1D LUTs aren't supported"),
+            Some("DOMAIN_MIN") if words.all(|word| word.parse::<f32>() == Ok(0.0)) => {}
+            Some("DOMAIN_MAX") if words.all(|word| word.parse::<f32>() == Ok(1.0)) => {}
+            Some("DOMAIN_MIN" | "DOMAIN_MAX") => return Err("only a 0-1 domain is supported"),
+            _ => {
+                let values = line
+                    .split_whitespace()
+                    .map(|word| word.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| "unrecognised line")?;
+                let [r, g, b] = values[..] else {
+                    return Err("entries need three values");
+                };
+                texels.push(
+                    [r, g, b, 1.0].map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8),
+                );
+            }
+        }
+    }
+
+    let size = size.ok_or("missing LUT_3D_SIZE")?;
+    if size < 2 || texels.len() != size.pow(3) as usize {
+        return Err("entry count doesn't match LUT_3D_SIZE");
+    }
+    Ok(LutData { size, texels })
+}
+
+fn read_strip(image: &image::RgbaImage) -> Result<LutData, &'static str> {
+    let size = image.height();
+    if size < 2 || image.width() != size * size {
+        return Err("a strip must be as wide as its height squared");
+    }
+    let mut texels = Vec::with_capacity(size.pow(3) as usize);
+    for blue in 0..size {
+        for green in 0..size {
+            for red in 0..size {
+                texels.push(image.get_pixel(blue * size + red, green).0);
+            }
+        }
+    }
+    Ok(LutData { size, texels })
+}
+
+fn create_lut(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    path: &Path,
+    data: &LutData,
+) -> Lut {
+    let size = wgpu::Extent3d {
+        width: data.size,
+        height: data.size,
+        depth_or_array_layers: data.size,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("LUT Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: LUT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&data.texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * data.size),
+            rows_per_image: Some(data.size),
+        },
+        size,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("LUT Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+
+    Lut {
+        name: path.display().to_string(),
+        _texture: texture,
+        bind_group,
+    }
+}
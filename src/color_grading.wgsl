@@ -0,0 +1,30 @@
+// Grades the tonemapped frame through a 3D LUT. Drawn with post_process.wgsl's
+// vertex stage; see color_grading.rs.
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+// Indexed by sRGB-encoded colour, and holds sRGB-encoded colours.
+@group(1) @binding(0)
+var t_lut: texture_3d<f32>;
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let colour = textureSampleLevel(t_input, s_input, uv, 0.0);
+    let encoded = linear_to_srgb(clamp(colour.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    // The outermost texel centres sit half a texel in from each face.
+    let size = f32(textureDimensions(t_lut).x);
+    let coords = encoded * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSampleLevel(t_lut, s_input, coords, 0.0).rgb;
+    return vec4<f32>(srgb_to_linear(graded), colour.a);
+}
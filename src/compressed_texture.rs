@@ -0,0 +1,135 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+// Compressed variants of a texture are looked for next to it, e.g.
+// `brick.png` -> `brick.bc.ktx2`, in this order of preference. Only families
+// the device supports are considered.
+const VARIANTS: &[(&str, wgpu::Features)] = &[
+    ("bc", wgpu::Features::TEXTURE_COMPRESSION_BC),
+    ("astc", wgpu::Features::TEXTURE_COMPRESSION_ASTC),
+    ("etc2", wgpu::Features::TEXTURE_COMPRESSION_ETC2),
+];
+
+pub const FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2);
+
+pub fn compressed_variant(path: &Path, features: wgpu::Features) -> Option<PathBuf> {
+    VARIANTS
+        .iter()
+        .filter(|(_, required)| features.contains(*required))
+        .map(|(family, _)| path.with_extension(format!("{family}.ktx2")))
+        .find(|variant| variant.is_file())
+}
+
+#[derive(Debug)]
+pub enum Ktx2Error {
+    Parse(ktx2::ParseError),
+    Supercompressed(ktx2::SupercompressionScheme),
+    UnsupportedFormat(Option<ktx2::Format>),
+    MissingFeatures(wgpu::TextureFormat, wgpu::Features),
+    NotTwoDimensional,
+    UnalignedSize(u32, u32),
+}
+
+impl fmt::Display for Ktx2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(error) => write!(f, "invalid KTX2 file: {error:?}"),
+            Self::Supercompressed(scheme) => {
+                write!(f, "supercompressed KTX2 ({scheme:?}) is not supported")
+            }
+            Self::UnsupportedFormat(format) => write!(f, "unsupported KTX2 format {format:?}"),
+            Self::MissingFeatures(format, features) => {
+                write!(f, "{format:?} needs device features {features:?}")
+            }
+            Self::NotTwoDimensional => write!(f, "only single 2D textures are supported"),
+            Self::UnalignedSize(width, height) => {
+                write!(f, "{width}x{height} is not a multiple of the block size")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Ktx2Error {}
+
+// Every mip level of a KTX2 texture, ready to upload as is.
+pub struct CompressedImage {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub mip_level_count: u32,
+    pub data: Vec<u8>,
+}
+
+impl CompressedImage {
+    pub fn from_ktx2(bytes: &[u8], features: wgpu::Features) -> Result<Self, Ktx2Error> {
+        let reader = ktx2::Reader::new(bytes).map_err(Ktx2Error::Parse)?;
+        let header = reader.header();
+
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(Ktx2Error::Supercompressed(scheme));
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            return Err(Ktx2Error::NotTwoDimensional);
+        }
+
+        let format = header
+            .format
+            .and_then(wgpu_format)
+            .ok_or(Ktx2Error::UnsupportedFormat(header.format))?;
+        let missing = format.required_features().difference(features);
+        if !missing.is_empty() {
+            return Err(Ktx2Error::MissingFeatures(format, missing));
+        }
+
+        let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+        let (block_width, block_height) = format.block_dimensions();
+        if width % block_width != 0 || height % block_height != 0 {
+            return Err(Ktx2Error::UnalignedSize(width, height));
+        }
+
+        // Levels come out largest first, which is the order wgpu expects.
+        let data = reader.levels().flatten().copied().collect();
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            mip_level_count: header.level_count.max(1),
+            data,
+        })
+    }
+}
+
+fn wgpu_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+    Some(match format {
+        ktx2::Format::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        ktx2::Format::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        ktx2::Format::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        ktx2::Format::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        ktx2::Format::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        ktx2::Format::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        ktx2::Format::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        ktx2::Format::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        ktx2::Format::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        ktx2::Format::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        ktx2::Format::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        ktx2::Format::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        ktx2::Format::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        ktx2::Format::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        ktx2::Format::ASTC_4x4_UNORM_BLOCK => TextureFormat::Astc {
+            block: AstcBlock::B4x4,
+            channel: AstcChannel::Unorm,
+        },
+        ktx2::Format::ASTC_4x4_SRGB_BLOCK => TextureFormat::Astc {
+            block: AstcBlock::B4x4,
+            channel: AstcChannel::UnormSrgb,
+        },
+        _ => return None,
+    })
+}
@@ -0,0 +1,36 @@
+struct CrossfadeUniform {
+    factor: f32,
+};
+
+@group(0) @binding(0)
+var from_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var to_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var crossfade_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> crossfade: CrossfadeUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle that covers the whole screen.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let from_colour = textureSample(from_texture, crossfade_sampler, in.uv);
+    let to_colour = textureSample(to_texture, crossfade_sampler, in.uv);
+    return mix(from_colour, to_colour, crossfade.factor);
+}
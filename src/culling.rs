@@ -0,0 +1,261 @@
+use std::{collections::HashMap, ops::Range};
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+
+use crate::{
+    instance::Instance,
+    picking::{Aabb, BoundingSphere, PickMesh},
+};
+
+// The six planes bounding what a camera can see, each facing inwards as
+// (normal, distance).
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Extracts the planes from a view-projection matrix (Gribb & Hartmann).
+    // wgpu clip space has depth running from 0 to 1, so the near plane is the
+    // z row on its own.
+    pub fn from_matrix(view_projection: Matrix4<f32>) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| view_projection.row(i));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
+
+    // The planes aren't normalised, so the radius is scaled by each normal's
+    // length rather than the distance divided by it.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| {
+            let centre = sphere.centre;
+            let distance = plane.x * centre.x + plane.y * centre.y + plane.z * centre.z + plane.w;
+            distance >= -sphere.radius * plane.truncate().magnitude()
+        })
+    }
+
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal is enough: if it is
+            // behind the plane, the whole box is.
+            let corner = [0, 1, 2].map(|axis| {
+                if plane[axis] >= 0.0 {
+                    aabb.max[axis]
+                } else {
+                    aabb.min[axis]
+                }
+            });
+            plane.x * corner[0] + plane.y * corner[1] + plane.z * corner[2] + plane.w >= 0.0
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CullStats {
+    pub tested: u32,
+    pub culled: u32,
+    // Chunks of `ChunkedInstances`, whose instances are only tested one by
+    // one when the chunk itself is in view.
+    pub chunks_tested: u32,
+    pub chunks_culled: u32,
+}
+
+struct Chunk {
+    // Around the positions of the chunk's instances, not their meshes, so
+    // the same chunks serve every mesh.
+    bounds: Aabb,
+    // In ascending order.
+    instances: Vec<u32>,
+}
+
+// Instances sorted into the cells of a uniform grid by position, so a whole
+// cell out of view can be skipped without testing what's in it. Hidden
+// instances are kept, so hiding one doesn't mean rebuilding the chunks, but
+// moving or adding one does.
+pub struct ChunkedInstances {
+    chunks: Vec<Chunk>,
+}
+
+impl ChunkedInstances {
+    pub fn new(instances: &[Instance], chunk_size: f32) -> Self {
+        let mut cells: HashMap<[i32; 3], Chunk> = HashMap::new();
+        for (index, instance) in instances.iter().enumerate() {
+            let position = cgmath::Point3::from_vec(instance.position);
+            let cell = [0, 1, 2].map(|axis| (position[axis] / chunk_size).floor() as i32);
+            let chunk = cells.entry(cell).or_insert_with(|| Chunk {
+                bounds: Aabb {
+                    min: position,
+                    max: position,
+                },
+                instances: Vec::new(),
+            });
+            chunk.bounds = chunk.bounds.union(&Aabb {
+                min: position,
+                max: position,
+            });
+            chunk.instances.push(index as u32);
+        }
+
+        let mut cells = cells.into_iter().collect::<Vec<_>>();
+        cells.sort_by_key(|(cell, _)| *cell);
+        Self {
+            chunks: cells.into_iter().map(|(_, chunk)| chunk).collect(),
+        }
+    }
+
+    // The instances in chunks that `frustum` may see some of `sphere` in, in
+    // ascending order. Instances only rotate and translate, so each one's
+    // copy of the sphere stays within `reach` of its position.
+    fn candidates(
+        &self,
+        frustum: &Frustum,
+        sphere: &BoundingSphere,
+        instances: &[Instance],
+        stats: &mut CullStats,
+    ) -> Vec<u32> {
+        let reach =
+            Vector3::new(1.0, 1.0, 1.0) * (sphere.centre.to_vec().magnitude() + sphere.radius);
+        let mut candidates = Vec::new();
+        for chunk in &self.chunks {
+            stats.chunks_tested += 1;
+            let bounds = Aabb {
+                min: chunk.bounds.min - reach,
+                max: chunk.bounds.max + reach,
+            };
+            if frustum.intersects(&bounds) {
+                candidates.extend_from_slice(&chunk.instances);
+                continue;
+            }
+            stats.chunks_culled += 1;
+            stats.culled += chunk
+                .instances
+                .iter()
+                .filter(|&&index| instances[index as usize].visible)
+                .count() as u32;
+        }
+        candidates.sort_unstable();
+        candidates
+    }
+}
+
+// The visible instances of `mesh` that `frustum` can see, merged into runs
+// of neighbouring instances so each run is a single draw. With `chunks`,
+// instances in chunks out of view are culled without being tested, which
+// gives the same runs. The bounding sphere is the cheaper test, so it
+// rejects what it can before the box is tried. Hidden instances aren't
+// tested at all.
+pub fn visible_instances(
+    frustum: &Frustum,
+    mesh: &PickMesh,
+    instances: &[Instance],
+    chunks: Option<&ChunkedInstances>,
+    stats: &mut CullStats,
+) -> Vec<Range<u32>> {
+    let mut ranges: Vec<Range<u32>> = Vec::new();
+    let shown = instances.iter().filter(|instance| instance.visible).count() as u32;
+    stats.tested += shown;
+
+    // Meshes without vertices have nothing to draw.
+    let (Some(bounds), Some(sphere)) = (mesh.bounds(), mesh.sphere()) else {
+        stats.culled += shown;
+        return ranges;
+    };
+
+    let candidates = match chunks {
+        Some(chunks) => chunks.candidates(frustum, &sphere, instances, stats),
+        None => (0..instances.len() as u32).collect(),
+    };
+    for index in candidates {
+        let instance = &instances[index as usize];
+        if !instance.visible {
+            continue;
+        }
+        let model = instance.model_matrix();
+        if !frustum.intersects_sphere(&sphere.transformed(&model))
+            || !frustum.intersects(&bounds.transformed(&model))
+        {
+            stats.culled += 1;
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Point3, Rotation3};
+
+    use super::*;
+    use crate::{camera::Camera, mesh::MeshData};
+
+    // A long row of spheres along X, seen by a camera at one end.
+    fn scene() -> (PickMesh, Vec<Instance>) {
+        let sphere = MeshData::uv_sphere(8, 4);
+        let mesh = PickMesh::new(&sphere.vertices, &sphere.indices);
+        let instances = (0..200)
+            .map(|i| Instance {
+                position: Vector3::new(i as f32 * 1.5, 0.0, (i % 7) as f32 - 3.0),
+                rotation: cgmath::Quaternion::from_angle_y(Deg(i as f32 * 10.0)),
+                visible: i % 5 != 0,
+            })
+            .collect();
+        (mesh, instances)
+    }
+
+    fn frustum(target: Point3<f32>) -> Frustum {
+        let mut camera = Camera::new(1.0);
+        camera.eye = Point3::new(-5.0, 2.0, 0.0);
+        camera.target = target;
+        Frustum::from_matrix(camera.build_view_projection_matrix())
+    }
+
+    #[test]
+    fn chunks_cull_the_same_instances() {
+        let (mesh, instances) = scene();
+        let chunks = ChunkedInstances::new(&instances, 8.0);
+        for target in [
+            Point3::new(10.0, 0.0, 0.0),
+            Point3::new(-5.0, 2.0, 10.0),
+            Point3::new(0.0, 0.0, 3.0),
+        ] {
+            let frustum = frustum(target);
+            let mut flat = CullStats::default();
+            let mut chunked = CullStats::default();
+            assert_eq!(
+                visible_instances(&frustum, &mesh, &instances, None, &mut flat),
+                visible_instances(&frustum, &mesh, &instances, Some(&chunks), &mut chunked),
+            );
+            assert_eq!((flat.tested, flat.culled), (chunked.tested, chunked.culled));
+        }
+    }
+
+    #[test]
+    fn looking_away_culls_whole_chunks() {
+        let (mesh, instances) = scene();
+        let chunks = ChunkedInstances::new(&instances, 8.0);
+        let mut towards = CullStats::default();
+        visible_instances(
+            &frustum(Point3::new(10.0, 0.0, 0.0)),
+            &mesh,
+            &instances,
+            Some(&chunks),
+            &mut towards,
+        );
+        let mut away = CullStats::default();
+        visible_instances(
+            &frustum(Point3::new(-20.0, 2.0, 0.0)),
+            &mesh,
+            &instances,
+            Some(&chunks),
+            &mut away,
+        );
+        assert!(towards.chunks_culled < towards.chunks_tested);
+        assert_eq!(away.chunks_culled, away.chunks_tested);
+        assert_eq!(away.culled, away.tested);
+    }
+}
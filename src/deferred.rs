@@ -0,0 +1,311 @@
+use crate::{hdr::SCENE_FORMAT, instance::InstanceRaw, texture::Texture, Vertex};
+
+// Match the G-buffer layout described in deferred.wgsl.
+const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const EMISSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const GBUFFER_FORMATS: [wgpu::TextureFormat; 4] = [
+    ALBEDO_FORMAT,
+    NORMAL_FORMAT,
+    MATERIAL_FORMAT,
+    EMISSION_FORMAT,
+];
+// The G-buffer's colour textures start here in group 1, after the material's.
+const FIRST_GBUFFER_BINDING: u32 = 8;
+
+// Per-pixel surface attributes for one frame, the size of the target they're
+// resolved into.
+pub struct GBuffer {
+    textures: Vec<wgpu::Texture>,
+    views: Vec<wgpu::TextureView>,
+    depth: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GBuffer {
+    // Albedo, normal, material and emission, in that order.
+    pub fn textures(&self) -> &[wgpu::Texture] {
+        &self.textures
+    }
+}
+
+// The alternative to drawing lit meshes straight into the scene. Meshes write
+// what their materials give into a G-buffer, then a fullscreen pass lights
+// each pixel once, so lighting costs the same however much overdraw there is
+// and later passes can read the surfaces back. Only opaque surfaces are
+// supported, and the G-buffer isn't multisampled.
+pub struct Deferred {
+    geometry_pipeline: wgpu::RenderPipeline,
+    double_sided_geometry_pipeline: wgpu::RenderPipeline,
+    resolve_pipeline: wgpu::RenderPipeline,
+    resolve_pipeline_layout: wgpu::PipelineLayout,
+    gbuffer_layout: wgpu::BindGroupLayout,
+}
+
+impl Deferred {
+    // `shader` is shader.wgsl with deferred.wgsl appended, and
+    // `geometry_layout` the forward pipeline's layout.
+    pub fn new(
+        device: &wgpu::Device,
+        geometry_layout: &wgpu::PipelineLayout,
+        user_uniform_layout: &wgpu::BindGroupLayout,
+        camera_layout: &wgpu::BindGroupLayout,
+        light_layout: &wgpu::BindGroupLayout,
+        shader: &wgpu::ShaderModule,
+    ) -> Self {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let mut entries = (0..GBUFFER_FORMATS.len() as u32)
+            .map(|i| {
+                texture_entry(
+                    FIRST_GBUFFER_BINDING + i,
+                    wgpu::TextureSampleType::Float { filterable: false },
+                )
+            })
+            .collect::<Vec<_>>();
+        entries.push(texture_entry(
+            FIRST_GBUFFER_BINDING + GBUFFER_FORMATS.len() as u32,
+            wgpu::TextureSampleType::Depth,
+        ));
+        let gbuffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Bind Group Layout"),
+            entries: &entries,
+        });
+        let resolve_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Deferred Resolve Pipeline Layout"),
+                bind_group_layouts: &[
+                    user_uniform_layout,
+                    &gbuffer_layout,
+                    camera_layout,
+                    light_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        Self {
+            geometry_pipeline: create_geometry_pipeline(
+                device,
+                geometry_layout,
+                shader,
+                Some(wgpu::Face::Back),
+            ),
+            double_sided_geometry_pipeline: create_geometry_pipeline(
+                device,
+                geometry_layout,
+                shader,
+                None,
+            ),
+            resolve_pipeline: create_resolve_pipeline(device, &resolve_pipeline_layout, shader),
+            resolve_pipeline_layout,
+            gbuffer_layout,
+        }
+    }
+
+    // Rebuilds the pipelines, e.g. after the shader's constants change.
+    pub fn set_shader(
+        &mut self,
+        device: &wgpu::Device,
+        geometry_layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+    ) {
+        self.geometry_pipeline =
+            create_geometry_pipeline(device, geometry_layout, shader, Some(wgpu::Face::Back));
+        self.double_sided_geometry_pipeline =
+            create_geometry_pipeline(device, geometry_layout, shader, None);
+        self.resolve_pipeline =
+            create_resolve_pipeline(device, &self.resolve_pipeline_layout, shader);
+    }
+
+    pub fn create_gbuffer(&self, device: &wgpu::Device, width: u32, height: u32) -> GBuffer {
+        let textures = GBUFFER_FORMATS
+            .iter()
+            .map(|&format| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("G-Buffer Texture"),
+                    size: wgpu::Extent3d {
+                        width: width.max(1),
+                        height: height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                })
+            })
+            .collect::<Vec<_>>();
+        let views = textures
+            .iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect::<Vec<_>>();
+        let depth = Texture::create_depth_texture(device, width, height, 1, "G-Buffer Depth");
+
+        let mut entries = views
+            .iter()
+            .zip(FIRST_GBUFFER_BINDING..)
+            .map(|(view, binding)| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect::<Vec<_>>();
+        entries.push(wgpu::BindGroupEntry {
+            binding: FIRST_GBUFFER_BINDING + views.len() as u32,
+            resource: wgpu::BindingResource::TextureView(&depth.view),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer Bind Group"),
+            layout: &self.gbuffer_layout,
+            entries: &entries,
+        });
+
+        GBuffer {
+            textures,
+            views,
+            depth,
+            bind_group,
+        }
+    }
+
+    // Clears `gbuffer` and leaves the pass ready to draw meshes into it, once
+    // the forward pipeline's bind groups are set.
+    pub fn begin_geometry_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        gbuffer: &'a GBuffer,
+    ) -> wgpu::RenderPass<'a> {
+        let color_attachments = gbuffer
+            .views
+            .iter()
+            .map(|view| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &gbuffer.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.geometry_pipeline);
+        render_pass
+    }
+
+    // For meshes with double-sided materials, in place of the geometry pass's
+    // own pipeline.
+    pub fn double_sided_geometry_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.double_sided_geometry_pipeline
+    }
+
+    // Lights the current viewport from `gbuffer`. The pass must have the
+    // forward pipeline's user uniform, camera and light bind groups set, and
+    // a depth attachment to write the G-buffer's depth into.
+    pub fn resolve<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, gbuffer: &'a GBuffer) {
+        render_pass.set_pipeline(&self.resolve_pipeline);
+        render_pass.set_bind_group(1, &gbuffer.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_geometry_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    cull_mode: Option<wgpu::Face>,
+) -> wgpu::RenderPipeline {
+    let targets = GBUFFER_FORMATS
+        .iter()
+        .map(|&format| Some(format.into()))
+        .collect::<Vec<_>>();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("G-Buffer Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc(), InstanceRaw::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_gbuffer",
+            targets: &targets,
+        }),
+        primitive: wgpu::PrimitiveState {
+            cull_mode,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn create_resolve_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Deferred Resolve Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_resolve",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_resolve",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: SCENE_FORMAT,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        // Every pixel the G-buffer covers is written, with its depth.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
@@ -0,0 +1,99 @@
+// The deferred path's entry points, appended to shader.wgsl so they share its
+// bindings, material sampling and lighting. See deferred.rs.
+
+// The G-buffer, at group 1 after the material's bindings so the two never
+// collide. Albedo is sRGB with material occlusion in alpha; the normal is
+// after normal mapping, with reflectivity in w; metallic and roughness are in
+// red and green.
+@group(1) @binding(8)
+var t_gbuffer_albedo: texture_2d<f32>;
+@group(1) @binding(9)
+var t_gbuffer_normal: texture_2d<f32>;
+@group(1) @binding(10)
+var t_gbuffer_material: texture_2d<f32>;
+@group(1) @binding(11)
+var t_gbuffer_emission: texture_2d<f32>;
+@group(1) @binding(12)
+var t_gbuffer_depth: texture_depth_2d;
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) material: vec4<f32>,
+    @location(3) emission: vec4<f32>,
+};
+
+// Drawn with shader.wgsl's `vs_main`. Opacity is dropped: the deferred path
+// only handles opaque surfaces.
+@fragment
+fn fs_gbuffer(
+    vertex: VertexOutput,
+    @builtin(front_facing) front_facing: bool,
+) -> GBufferOutput {
+    let in = facing_viewer(vertex, front_facing);
+    let sampled = sample_material(in);
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(sampled.albedo, sampled.occlusion);
+    out.normal = vec4<f32>(sampled.normal, sampled.reflectivity);
+    out.material = vec4<f32>(sampled.metallic, sampled.roughness, 0.0, 0.0);
+    out.emission = vec4<f32>(sampled.emission, 1.0);
+    return out;
+}
+
+struct ResolveVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// A single triangle that covers the viewport.
+@vertex
+fn vs_resolve(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> ResolveVertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: ResolveVertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+struct ResolveOutput {
+    @location(0) colour: vec4<f32>,
+    // The G-buffer's depth, so what's drawn after the resolve in the same
+    // pass is hidden behind the lit surfaces.
+    @builtin(frag_depth) depth: f32,
+};
+
+@fragment
+fn fs_resolve(in: ResolveVertexOutput) -> ResolveOutput {
+    let texel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_gbuffer_depth, texel, 0);
+    // Nothing was drawn here; leave it for the clear colour or skybox.
+    if depth >= 1.0 {
+        discard;
+    }
+
+    let albedo = textureLoad(t_gbuffer_albedo, texel, 0);
+    let normal = textureLoad(t_gbuffer_normal, texel, 0);
+    let params = textureLoad(t_gbuffer_material, texel, 0);
+    var sampled: MaterialSample;
+    sampled.albedo = albedo.rgb;
+    sampled.opacity = 1.0;
+    sampled.normal = normalize(normal.xyz);
+    sampled.metallic = params.r;
+    sampled.roughness = params.g;
+    sampled.occlusion = albedo.a;
+    sampled.emission = textureLoad(t_gbuffer_emission, texel, 0).rgb;
+    sampled.reflectivity = normal.w;
+
+    let position = camera.inverse_view_proj * vec4<f32>(in.ndc, depth, 1.0);
+    let world_position = position.xyz / position.w;
+
+    var out: ResolveOutput;
+    // The unmapped normal isn't kept, so shadow lookups are offset along the
+    // mapped one.
+    out.colour = vec4<f32>(shade(sampled, world_position, sampled.normal), 1.0);
+    out.depth = depth;
+    return out;
+}
@@ -0,0 +1,279 @@
+use std::sync::mpsc;
+
+use cgmath::SquareMatrix;
+
+use crate::{
+    camera::Camera,
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    texture::Texture,
+    uniform::UniformBuffer,
+};
+
+// Matches `DepthOfFieldUniform` in depth_of_field.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthOfFieldUniform {
+    inverse_proj: [[f32; 4]; 4],
+    focus_distance: f32,
+    aperture: f32,
+    max_blur: f32,
+    focus_range: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthOfFieldSettings {
+    // How far in front of the camera, in world units, is perfectly sharp.
+    pub focus_distance: f32,
+    // The depth, in world units, of the sharp band centred on
+    // `focus_distance`. Blur starts at its edges.
+    pub focus_range: f32,
+    // How quickly the blur grows away from the focus distance. At 1, things
+    // infinitely far away get the full `max_blur`.
+    pub aperture: f32,
+    // The widest blur, in pixels.
+    pub max_blur: f32,
+    // Keeps `focus_distance` on whatever is under the cursor.
+    pub autofocus: bool,
+}
+
+impl Default for DepthOfFieldSettings {
+    fn default() -> Self {
+        Self {
+            focus_distance: 5.0,
+            focus_range: 1.0,
+            aperture: 1.0,
+            max_blur: 12.0,
+            autofocus: false,
+        }
+    }
+}
+
+enum ProbeState {
+    Idle,
+    Copied,
+    Mapping(mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+// Reads one depth texel back to the CPU without stalling: the copy is
+// recorded with a frame, mapped once that frame is submitted, and picked up
+// by a later `poll` whenever the GPU gets to it.
+pub struct DepthProbe {
+    buffer: wgpu::Buffer,
+    state: ProbeState,
+}
+
+impl DepthProbe {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Depth Probe Buffer"),
+                size: std::mem::size_of::<f32>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            state: ProbeState::Idle,
+        }
+    }
+
+    // Does nothing, returning false, while the last read is still in flight.
+    pub fn copy(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &Texture,
+        x: u32,
+        y: u32,
+    ) -> bool {
+        if !matches!(self.state, ProbeState::Idle) {
+            return false;
+        }
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &depth.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout::default(),
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.state = ProbeState::Copied;
+        true
+    }
+
+    // Call once the encoder given to `copy` has been submitted.
+    pub fn map(&mut self) {
+        if !matches!(self.state, ProbeState::Copied) {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.state = ProbeState::Mapping(receiver);
+    }
+
+    // The depth, once a read has finished.
+    pub fn poll(&mut self) -> Option<f32> {
+        let ProbeState::Mapping(receiver) = &self.state else {
+            return None;
+        };
+        let result = receiver.try_recv().ok()?;
+        self.state = ProbeState::Idle;
+        if let Err(e) = result {
+            eprintln!("Failed to read back depth: {e}");
+            return None;
+        }
+        let depth = bytemuck::pod_read_unaligned(&self.buffer.slice(..).get_mapped_range());
+        self.buffer.unmap();
+        Some(depth)
+    }
+}
+
+// Blurs what's in front of and behind the focus distance, like a camera lens
+// with a wide aperture. Each pixel gathers from a disc of neighbours on a
+// spiral, and a neighbour counts when its own blur is wide enough to reach
+// this pixel, so out-of-focus shapes spread into discs. Sharp things behind a
+// pixel can't blur over it, which keeps the background from bleeding onto
+// what's in focus. Depth comes from the scene pass, resolved to the nearest
+// sample of each pixel under MSAA.
+pub struct DepthOfField {
+    pass: FullscreenPass,
+    uniform_buffer: UniformBuffer<DepthOfFieldUniform>,
+    depth_layout: wgpu::BindGroupLayout,
+    settings: DepthOfFieldSettings,
+    probe: DepthProbe,
+}
+
+impl DepthOfField {
+    pub fn new(device: &wgpu::Device, settings: DepthOfFieldSettings) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &DepthOfFieldUniform {
+                inverse_proj: cgmath::Matrix4::identity().into(),
+                focus_distance: settings.focus_distance,
+                aperture: settings.aperture,
+                max_blur: settings.max_blur,
+                focus_range: settings.focus_range,
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "Depth of Field Uniform",
+        );
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth of Field Depth Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("depth_of_field.wgsl"));
+        let pass = FullscreenPass::new(
+            device,
+            "Depth of Field",
+            &shader,
+            "fs_main",
+            &[uniform_buffer.layout(), &depth_layout],
+        );
+
+        Self {
+            pass,
+            uniform_buffer,
+            depth_layout,
+            settings,
+            probe: DepthProbe::new(device),
+        }
+    }
+
+    pub fn settings(&self) -> DepthOfFieldSettings {
+        self.settings
+    }
+
+    // Takes effect at the next `update`.
+    pub fn set_settings(&mut self, settings: DepthOfFieldSettings) {
+        self.settings = settings;
+    }
+
+    // Moves the focus by `scale` and stops autofocus from moving it back.
+    pub fn adjust_focus(&mut self, scale: f32) -> f32 {
+        self.settings.autofocus = false;
+        self.settings.focus_distance = (self.settings.focus_distance * scale).clamp(0.1, 1000.0);
+        self.settings.focus_distance
+    }
+
+    // Records a read of the depth at `texel` if autofocus is on. `depth` is
+    // the one passed to the post chain as `PostFrame::depth`.
+    pub fn probe_focus(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &Texture,
+        texel: (u32, u32),
+    ) {
+        if self.settings.autofocus {
+            self.probe.copy(encoder, depth, texel.0, texel.1);
+        }
+    }
+
+    // Call once the encoder given to `probe_focus` has been submitted.
+    pub fn after_submit(&mut self) {
+        self.probe.map();
+    }
+
+    // Picks up any finished focus read and uploads the settings for
+    // `camera`'s projection.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let inverse_proj = camera
+            .projection_matrix()
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        if let Some(depth) = self.probe.poll() {
+            if self.settings.autofocus {
+                // Only depth matters for how far in front of the camera a
+                // point is, whatever the projection.
+                let position = inverse_proj * cgmath::Vector4::new(0.0, 0.0, depth, 1.0);
+                self.settings.focus_distance = -position.z / position.w;
+            }
+        }
+        self.uniform_buffer.write(
+            queue,
+            &DepthOfFieldUniform {
+                inverse_proj: inverse_proj.into(),
+                focus_distance: self.settings.focus_distance,
+                aperture: self.settings.aperture,
+                max_blur: self.settings.max_blur,
+                focus_range: self.settings.focus_range,
+            },
+        );
+    }
+}
+
+impl PostEffect for DepthOfField {
+    fn draw(&self, frame: PostFrame) {
+        let depth_bind_group = frame.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Depth of Field Depth Bind Group"),
+            layout: &self.depth_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(frame.depth),
+            }],
+        });
+        self.pass.draw(
+            frame,
+            &[self.uniform_buffer.bind_group(), &depth_bind_group],
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// Gathered depth of field, drawn with post_process.wgsl's vertex stage. See
+// depth_of_field.rs.
+
+const SAMPLE_COUNT: u32 = 48u;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct DepthOfFieldUniform {
+    inverse_proj: mat4x4<f32>,
+    focus_distance: f32,
+    aperture: f32,
+    max_blur: f32,
+    focus_range: f32,
+};
+@group(1) @binding(0)
+var<uniform> dof: DepthOfFieldUniform;
+
+// The main camera's depth, which may be a different size to the frame.
+@group(2) @binding(0)
+var t_depth: texture_depth_2d;
+
+// How far in front of the camera the surface at `uv` is.
+fn distance_at(uv: vec2<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let texel = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let depth = textureLoad(t_depth, texel, 0);
+    let position = dof.inverse_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return -position.z / position.w;
+}
+
+// The radius, in pixels, a point this far away is blurred over. Anything
+// within the focus range stays sharp.
+fn blur_radius(distance: f32) -> f32 {
+    let defocus = max(abs(distance - dof.focus_distance) - dof.focus_range * 0.5, 0.0);
+    let spread = dof.aperture * defocus / max(distance, 0.0001);
+    return clamp(spread, 0.0, 1.0) * dof.max_blur;
+}
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let texel_size = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let centre = textureSampleLevel(t_input, s_input, uv, 0.0);
+    let centre_distance = distance_at(uv);
+    let centre_radius = blur_radius(centre_distance);
+
+    var colour = centre.rgb;
+    var total = 1.0;
+    for (var i = 1u; i < SAMPLE_COUNT; i++) {
+        // Evenly spread over the disc of the widest blur.
+        let radius = dof.max_blur * sqrt(f32(i) / f32(SAMPLE_COUNT));
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let sample_uv = uv + vec2<f32>(cos(angle), sin(angle)) * radius * texel_size;
+        let sample_distance = distance_at(sample_uv);
+        var sample_radius = blur_radius(sample_distance);
+        if sample_distance > centre_distance {
+            sample_radius = min(sample_radius, centre_radius);
+        }
+        let weight = smoothstep(radius - 0.5, radius + 0.5, sample_radius);
+        colour += textureSampleLevel(t_input, s_input, sample_uv, 0.0).rgb * weight;
+        total += weight;
+    }
+    return vec4<f32>(colour / total, centre.a);
+}
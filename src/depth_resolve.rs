@@ -0,0 +1,128 @@
+use crate::texture::Texture;
+
+// Resolves a multisampled scene depth to one sample per pixel, for the passes
+// that read the scene's depth after it's drawn: depth of field, its focus
+// probe and the cursor readout. Each pixel keeps its nearest sample, so an
+// edge pixel reads as the surface in front of it rather than a depth between
+// it and whatever is behind.
+pub struct DepthResolve {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+}
+
+// The resolved copy of one scene depth texture, rebuilt along with it.
+pub struct ResolvedDepth {
+    depth: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl DepthResolve {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Resolve Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: true,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("depth_resolve.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth Resolve Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth Resolve Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self { pipeline, layout }
+    }
+
+    // None when `scene_depth` is single-sampled and can be read as it is.
+    pub fn target(&self, device: &wgpu::Device, scene_depth: &Texture) -> Option<ResolvedDepth> {
+        if scene_depth.texture.sample_count() == 1 {
+            return None;
+        }
+
+        let depth = Texture::create_depth_texture(
+            device,
+            scene_depth.texture.width(),
+            scene_depth.texture.height(),
+            1,
+            "Resolved Depth Texture",
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Depth Resolve Bind Group"),
+            layout: &self.layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&scene_depth.view),
+            }],
+        });
+        Some(ResolvedDepth { depth, bind_group })
+    }
+
+    // Must run after the scene is drawn and before anything reads `target`.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder, target: &ResolvedDepth) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Resolve Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &target.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl ResolvedDepth {
+    pub fn depth(&self) -> &Texture {
+        &self.depth
+    }
+}
+
+// `resolved` when the scene depth needed resolving, otherwise `scene_depth`
+// itself.
+pub fn readable_depth<'a>(
+    resolved: &'a Option<ResolvedDepth>,
+    scene_depth: &'a Texture,
+) -> &'a Texture {
+    resolved.as_ref().map_or(scene_depth, ResolvedDepth::depth)
+}
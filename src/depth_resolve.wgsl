@@ -0,0 +1,23 @@
+@group(0) @binding(0)
+var t_depth: texture_depth_multisampled_2d;
+
+// A single triangle that covers the whole screen.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The nearest of the pixel's samples. Averaging them would put an edge pixel
+// at a depth between the two surfaces, where nothing actually is.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    let coords = vec2<i32>(position.xy);
+    var depth = 1.0;
+    for (var i = 0; i < i32(textureNumSamples(t_depth)); i++) {
+        depth = min(depth, textureLoad(t_depth, coords, i));
+    }
+    return depth;
+}
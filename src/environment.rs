@@ -0,0 +1,391 @@
+use std::path::Path;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::Assets,
+    texture::{SamplerConfig, Texture},
+};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+// Matches `PREFILTERED_MIP_COUNT` in shader.wgsl. Each level is blurred for a
+// roughness from 0 at the top to 1 at the bottom.
+const PREFILTERED_MIP_COUNT: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+// The unblurred cube drawn behind the scene. It's seen directly, so it needs
+// more detail than the lighting maps.
+const SKY_SIZE: u32 = 512;
+// Used when no environment is given or it can't be loaded.
+const SKY_WIDTH: u32 = 256;
+const SKY_HEIGHT: u32 = 128;
+
+// Matches `Bake` in environment.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeUniform {
+    face: u32,
+    roughness: f32,
+    _padding: [f32; 2],
+}
+
+// Image-based lighting, baked once at startup from an equirectangular HDR
+// image: a diffuse irradiance cubemap, a specular cubemap whose mip levels
+// are prefiltered for increasing roughness, and the lookup table for the
+// split-sum approximation of the specular BRDF. The image itself is also
+// kept as a cubemap for the skybox. Cube faces follow the +x, -x, +y, -y, +z,
+// -z layer order.
+pub struct Environment {
+    _sky: wgpu::Texture,
+    sky_view: wgpu::TextureView,
+    _irradiance: wgpu::Texture,
+    irradiance_view: wgpu::TextureView,
+    _prefiltered: wgpu::Texture,
+    prefiltered_view: wgpu::TextureView,
+    _brdf_lut: wgpu::Texture,
+    brdf_lut_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl Environment {
+    // `path` is an .hdr or .exr image. Without one, a plain sky gradient is
+    // used instead.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut Assets,
+        path: Option<&Path>,
+    ) -> Self {
+        let (name, image) = match path {
+            Some(path) => match image::open(path) {
+                Ok(image) => (path.display().to_string(), image),
+                Err(error) => {
+                    eprintln!("Failed to load environment {}: {error}", path.display());
+                    ("<sky>".to_string(), sky_image())
+                }
+            },
+            None => ("<sky>".to_string(), sky_image()),
+        };
+        let sampler = assets.sampler(
+            device,
+            SamplerConfig {
+                address_mode: wgpu::AddressMode::Repeat,
+                ..Default::default()
+            },
+        );
+        let source = Texture::from_image(device, queue, &image, Some(&name), sampler);
+        let source = assets.insert_texture(device, queue, &name, source);
+
+        let sky = create_cube_texture(device, "Sky Map", SKY_SIZE, 1);
+        let irradiance = create_cube_texture(device, "Irradiance Map", IRRADIANCE_SIZE, 1);
+        let prefiltered = create_cube_texture(
+            device,
+            "Prefiltered Environment Map",
+            PREFILTERED_SIZE,
+            PREFILTERED_MIP_COUNT,
+        );
+        let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF Lookup Table"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BRDF_LUT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let baker = Baker::new(device, assets.texture(source));
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Bake Encoder"),
+        });
+        for face in 0..6 {
+            // At roughness 0 the prefilter just resamples the source.
+            baker.draw(
+                device,
+                &mut encoder,
+                &baker.prefilter_pipeline,
+                &face_view(&sky, face, 0),
+                BakeUniform {
+                    face,
+                    roughness: 0.0,
+                    _padding: [0.0; 2],
+                },
+            );
+            baker.draw(
+                device,
+                &mut encoder,
+                &baker.irradiance_pipeline,
+                &face_view(&irradiance, face, 0),
+                BakeUniform {
+                    face,
+                    roughness: 1.0,
+                    _padding: [0.0; 2],
+                },
+            );
+        }
+        for mip in 0..PREFILTERED_MIP_COUNT {
+            for face in 0..6 {
+                baker.draw(
+                    device,
+                    &mut encoder,
+                    &baker.prefilter_pipeline,
+                    &face_view(&prefiltered, face, mip),
+                    BakeUniform {
+                        face,
+                        roughness: mip as f32 / (PREFILTERED_MIP_COUNT - 1) as f32,
+                        _padding: [0.0; 2],
+                    },
+                );
+            }
+        }
+        baker.draw(
+            device,
+            &mut encoder,
+            &baker.brdf_lut_pipeline,
+            &brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            BakeUniform {
+                face: 0,
+                roughness: 0.0,
+                _padding: [0.0; 2],
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let cube_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            })
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            sky_view: cube_view(&sky),
+            _sky: sky,
+            irradiance_view: cube_view(&irradiance),
+            _irradiance: irradiance,
+            prefiltered_view: cube_view(&prefiltered),
+            _prefiltered: prefiltered,
+            brdf_lut_view: brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            _brdf_lut: brdf_lut,
+            sampler,
+        }
+    }
+
+    pub fn sky_view(&self) -> &wgpu::TextureView {
+        &self.sky_view
+    }
+
+    pub fn irradiance_view(&self) -> &wgpu::TextureView {
+        &self.irradiance_view
+    }
+
+    pub fn prefiltered_view(&self) -> &wgpu::TextureView {
+        &self.prefiltered_view
+    }
+
+    pub fn brdf_lut_view(&self) -> &wgpu::TextureView {
+        &self.brdf_lut_view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+}
+
+// The pipelines that render each face of the baked maps from the source
+// image with a full-screen triangle.
+struct Baker<'a> {
+    source: &'a Texture,
+    layout: wgpu::BindGroupLayout,
+    irradiance_pipeline: wgpu::RenderPipeline,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    brdf_lut_pipeline: wgpu::RenderPipeline,
+}
+
+impl<'a> Baker<'a> {
+    fn new(device: &wgpu::Device, source: &'a Texture) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Bake Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Environment Bake Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("environment.wgsl"));
+        let pipeline = |entry_point: &str, format: wgpu::TextureFormat| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&format!("Environment Bake Pipeline {entry_point}")),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        Self {
+            source,
+            irradiance_pipeline: pipeline("fs_irradiance", FORMAT),
+            prefilter_pipeline: pipeline("fs_prefilter", FORMAT),
+            brdf_lut_pipeline: pipeline("fs_brdf_lut", BRDF_LUT_FORMAT),
+            layout,
+        }
+    }
+
+    fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        target: &wgpu::TextureView,
+        uniform: BakeUniform,
+    ) {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Bake Buffer"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Bake Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.source.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.source.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Environment Bake Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+// A six-layer texture that's sampled as a cube and rendered into one face and
+// mip level at a time.
+fn create_cube_texture(
+    device: &wgpu::Device,
+    label: &str,
+    size: u32,
+    mip_level_count: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn face_view(texture: &wgpu::Texture, face: u32, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+    })
+}
+
+// An equirectangular sky fading from blue overhead to a pale horizon, over
+// dark ground.
+fn sky_image() -> image::DynamicImage {
+    let zenith = [0.1, 0.18, 0.35];
+    let horizon = [0.35, 0.33, 0.3];
+    let ground = [0.08, 0.07, 0.06];
+    let sky = image::Rgb32FImage::from_fn(SKY_WIDTH, SKY_HEIGHT, |_, y| {
+        let elevation = 0.5 - (y as f32 + 0.5) / SKY_HEIGHT as f32;
+        if elevation < 0.0 {
+            return image::Rgb(ground);
+        }
+        let t = (elevation * std::f32::consts::PI).sin().sqrt();
+        image::Rgb([0, 1, 2].map(|i| horizon[i] + (zenith[i] - horizon[i]) * t))
+    });
+    image::DynamicImage::ImageRgb32F(sky)
+}
@@ -0,0 +1,187 @@
+// Bakes the image-based lighting maps from an equirectangular environment.
+// See environment.rs.
+
+const PI: f32 = 3.14159265359;
+const IRRADIANCE_SAMPLES: u32 = 1024u;
+const PREFILTER_SAMPLES: u32 = 512u;
+const BRDF_LUT_SAMPLES: u32 = 1024u;
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+struct Bake {
+    // Which cube face is being rendered, +x, -x, +y, -y, +z, -z.
+    face: u32,
+    roughness: f32,
+};
+@group(0) @binding(2)
+var<uniform> bake: Bake;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle that covers the whole target.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// The direction through `uv` on a cube face, with v running down the face
+// as it does when the cube is sampled.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - 1.0;
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3<f32>(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3<f32>(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3<f32>(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-st.x, -st.y, -1.0)); }
+    }
+}
+
+fn sample_environment(direction: vec3<f32>, lod: f32) -> vec3<f32> {
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+    return textureSampleLevel(source_texture, source_sampler, uv, lod).rgb;
+}
+
+// The solid angle one texel of the source's top level covers, on average.
+fn source_texel_solid_angle() -> f32 {
+    let size = vec2<f32>(textureDimensions(source_texture));
+    return 4.0 * PI / (size.x * size.y);
+}
+
+// The source mip level whose texels cover about as much of the sphere as a
+// sample drawn with probability `pdf` out of `sample_count` does. Reading
+// from it instead of the top level stops a few bright texels turning into
+// speckles.
+fn sample_lod(pdf: f32, sample_count: u32) -> f32 {
+    let sample_solid_angle = 1.0 / (f32(sample_count) * pdf + 0.0001);
+    let max_lod = f32(textureNumLevels(source_texture) - 1u);
+    return clamp(0.5 * log2(sample_solid_angle / source_texel_solid_angle()) + 1.0, 0.0, max_lod);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+fn tangent_to_world(v: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(1.0, 0.0, 0.0), abs(normal.z) > 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return tangent * v.x + bitangent * v.y + normal * v.z;
+}
+
+// A half vector around +z, drawn in proportion to the GGX distribution.
+fn importance_sample_ggx(xi: vec2<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// The average radiance over the hemisphere around the texel's direction,
+// weighted by cosine. Times the diffuse colour, that's the Lambert
+// reflection.
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = face_direction(bake.face, in.uv);
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < IRRADIANCE_SAMPLES; i++) {
+        let xi = hammersley(i, IRRADIANCE_SAMPLES);
+        // Cosine-weighted, so the cosine and the pdf cancel out.
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let local = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+        let lod = sample_lod(cos_theta / PI, IRRADIANCE_SAMPLES);
+        sum += sample_environment(tangent_to_world(local, normal), lod);
+    }
+    return vec4<f32>(sum / f32(IRRADIANCE_SAMPLES), 1.0);
+}
+
+// The environment blurred by the GGX lobe for `bake.roughness`, assuming the
+// view, normal and reflection directions are all the same.
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = face_direction(bake.face, in.uv);
+    if bake.roughness <= 0.0 {
+        return vec4<f32>(sample_environment(normal, 0.0), 1.0);
+    }
+
+    let alpha = bake.roughness * bake.roughness;
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < PREFILTER_SAMPLES; i++) {
+        let half_dir = tangent_to_world(
+            importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), alpha),
+            normal,
+        );
+        let light_dir = reflect(-normal, half_dir);
+        let n_dot_l = dot(normal, light_dir);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        // With the view along the normal the pdf reduces to D / 4.
+        let n_dot_h = saturate(dot(normal, half_dir));
+        let lod = sample_lod(distribution_ggx(n_dot_h, alpha) / 4.0, PREFILTER_SAMPLES);
+        sum += sample_environment(light_dir, lod) * n_dot_l;
+        weight += n_dot_l;
+    }
+    return vec4<f32>(sum / max(weight, 0.0001), 1.0);
+}
+
+fn geometry_schlick_ggx(n_dot: f32, k: f32) -> f32 {
+    return n_dot / (n_dot * (1.0 - k) + k);
+}
+
+// The scale (red) and bias (green) applied to F0 by the specular BRDF
+// integrated over the hemisphere, for n.v across and roughness down.
+@fragment
+fn fs_brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.uv.x, 0.001);
+    let roughness = in.uv.y;
+    let alpha = roughness * roughness;
+    // Image-based lighting uses k = alpha / 2 for the Smith term.
+    let k = alpha / 2.0;
+    let view_dir = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_LUT_SAMPLES; i++) {
+        let half_dir = importance_sample_ggx(hammersley(i, BRDF_LUT_SAMPLES), alpha);
+        let light_dir = reflect(-view_dir, half_dir);
+        let n_dot_l = saturate(light_dir.z);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let n_dot_h = saturate(half_dir.z);
+        let v_dot_h = saturate(dot(view_dir, half_dir));
+        let g = geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k);
+        let g_vis = g * v_dot_h / max(n_dot_h * n_dot_v, 0.0001);
+        let fc = pow(1.0 - v_dot_h, 5.0);
+        scale += (1.0 - fc) * g_vis;
+        bias += fc * g_vis;
+    }
+    return vec4<f32>(scale, bias, 0.0, 0.0) / f32(BRDF_LUT_SAMPLES);
+}
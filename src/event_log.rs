@@ -0,0 +1,277 @@
+use std::{collections::VecDeque, time::Duration};
+
+use winit::event::{Event, WindowEvent};
+
+use crate::text::TextBatch;
+
+// How many events are kept, and how many of those matching the filter are
+// shown.
+const CAPACITY: usize = 64;
+const SHOWN: usize = 16;
+// Longer descriptions are cut short to keep the log narrow.
+const MAX_CHARS: usize = 72;
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+
+const PANEL_COLOUR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const HEADER_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const INPUT_COLOUR: [f32; 4] = [0.5, 0.85, 1.0, 1.0];
+const WINDOW_COLOUR: [f32; 4] = [1.0, 0.85, 0.4, 1.0];
+const DEVICE_COLOUR: [f32; 4] = [0.75, 1.0, 0.6, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventCategory {
+    // Keys, buttons and the cursor as the window sees them.
+    Input,
+    // Everything else that happens to the window.
+    Window,
+    // Raw device input, which arrives whether or not the window has focus.
+    Device,
+}
+
+impl EventCategory {
+    fn colour(self) -> [f32; 4] {
+        match self {
+            EventCategory::Input => INPUT_COLOUR,
+            EventCategory::Window => WINDOW_COLOUR,
+            EventCategory::Device => DEVICE_COLOUR,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EventFilter {
+    #[default]
+    All,
+    Input,
+    Window,
+    Device,
+}
+
+impl EventFilter {
+    pub fn next(self) -> Self {
+        match self {
+            EventFilter::All => EventFilter::Input,
+            EventFilter::Input => EventFilter::Window,
+            EventFilter::Window => EventFilter::Device,
+            EventFilter::Device => EventFilter::All,
+        }
+    }
+
+    fn matches(self, category: EventCategory) -> bool {
+        match self {
+            EventFilter::All => true,
+            EventFilter::Input => category == EventCategory::Input,
+            EventFilter::Window => category == EventCategory::Window,
+            EventFilter::Device => category == EventCategory::Device,
+        }
+    }
+}
+
+struct LogEntry {
+    // Since startup.
+    time: Duration,
+    category: EventCategory,
+    description: String,
+}
+
+// The most recent winit events, drawn in the bottom left of the window. The
+// filter only changes what's shown, so cycling it brings back events of
+// every kind still in the log. Like `PerfOverlay`, it records nothing while
+// hidden.
+#[derive(Default)]
+pub struct EventLog {
+    visible: bool,
+    filter: EventFilter,
+    // The most recent event last.
+    entries: VecDeque<LogEntry>,
+}
+
+impl EventLog {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.entries.clear();
+    }
+
+    pub fn filter(&self) -> EventFilter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: EventFilter) {
+        self.filter = filter;
+    }
+
+    pub fn record_event(&mut self, time: Duration, event: &Event<()>) {
+        if !self.visible {
+            return;
+        }
+        if let Some((category, description)) = describe(event) {
+            self.record(time, category, description);
+        }
+    }
+
+    fn record(&mut self, time: Duration, category: EventCategory, description: String) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry {
+            time,
+            category,
+            description,
+        });
+    }
+
+    // The most recent entries that pass the filter, oldest first.
+    fn shown(&self) -> impl Iterator<Item = &LogEntry> {
+        let mut shown = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| self.filter.matches(entry.category))
+            .take(SHOWN)
+            .collect::<Vec<_>>();
+        shown.reverse();
+        shown.into_iter()
+    }
+
+    // Adds the log to `batch`, if it's shown, above the bottom of a window
+    // `window_height` tall.
+    pub fn build(&self, batch: &mut TextBatch, window_height: f32) {
+        if !self.visible {
+            return;
+        }
+        let header = format!("EVENTS: {:?}", self.filter);
+        let lines = self
+            .shown()
+            .map(|entry| {
+                let line = format!("{:8.3} {}", entry.time.as_secs_f64(), entry.description);
+                (line, entry.category.colour())
+            })
+            .collect::<Vec<_>>();
+
+        let width = lines
+            .iter()
+            .map(|(line, _)| line.chars().count())
+            .fold(header.chars().count(), usize::max) as f32
+            * TextBatch::CHAR_WIDTH;
+        let height = (lines.len() + 1) as f32 * TextBatch::LINE_HEIGHT;
+        let top = window_height - MARGIN - PADDING * 2.0 - height;
+        batch.rect(
+            [MARGIN, top, width + PADDING * 2.0, height + PADDING * 2.0],
+            PANEL_COLOUR,
+        );
+
+        let x = MARGIN + PADDING;
+        let mut y = top + PADDING;
+        batch.text(x, y, &header, HEADER_COLOUR);
+        for (line, colour) in &lines {
+            y += TextBatch::LINE_HEIGHT;
+            batch.text(x, y, line, *colour);
+        }
+    }
+}
+
+// What kind of event this is and a line describing it. Events that arrive
+// every frame whatever happens, like `AboutToWait` and `RedrawRequested`,
+// aren't worth logging and would push everything else out.
+fn describe(event: &Event<()>) -> Option<(EventCategory, String)> {
+    let (category, description) = match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::RedrawRequested => return None,
+            WindowEvent::KeyboardInput { event, .. } => (
+                EventCategory::Input,
+                format!("KeyboardInput {:?} {:?}", event.logical_key, event.state),
+            ),
+            WindowEvent::MouseInput { button, state, .. } => (
+                EventCategory::Input,
+                format!("MouseInput {button:?} {state:?}"),
+            ),
+            WindowEvent::CursorMoved { position, .. } => (
+                EventCategory::Input,
+                format!("CursorMoved ({:.0}, {:.0})", position.x, position.y),
+            ),
+            WindowEvent::MouseWheel { delta, .. } => {
+                (EventCategory::Input, format!("MouseWheel {delta:?}"))
+            }
+            WindowEvent::ModifiersChanged(modifiers) => (
+                EventCategory::Input,
+                format!("ModifiersChanged {:?}", modifiers.state()),
+            ),
+            WindowEvent::CursorEntered { .. } => {
+                (EventCategory::Input, "CursorEntered".to_string())
+            }
+            WindowEvent::CursorLeft { .. } => (EventCategory::Input, "CursorLeft".to_string()),
+            WindowEvent::Ime(_)
+            | WindowEvent::Touch(_)
+            | WindowEvent::TouchpadPressure { .. }
+            | WindowEvent::TouchpadMagnify { .. }
+            | WindowEvent::TouchpadRotate { .. }
+            | WindowEvent::SmartMagnify { .. }
+            | WindowEvent::AxisMotion { .. } => (EventCategory::Input, format!("{event:?}")),
+            _ => (EventCategory::Window, format!("{event:?}")),
+        },
+        Event::DeviceEvent { event, .. } => (EventCategory::Device, format!("{event:?}")),
+        _ => return None,
+    };
+    Some((category, truncate(description)))
+}
+
+fn truncate(mut text: String) -> String {
+    if let Some((cut, _)) = text.char_indices().nth(MAX_CHARS) {
+        text.truncate(cut);
+        text.push_str("...");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_of(categories: &[EventCategory]) -> EventLog {
+        let mut log = EventLog::default();
+        for (i, category) in categories.iter().enumerate() {
+            log.record(Duration::from_secs(i as u64), *category, i.to_string());
+        }
+        log
+    }
+
+    fn shown(log: &EventLog) -> Vec<&str> {
+        log.shown()
+            .map(|entry| entry.description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn filters_without_forgetting() {
+        use EventCategory::*;
+        let mut log = log_of(&[Input, Window, Device, Input]);
+        assert_eq!(shown(&log), ["0", "1", "2", "3"]);
+        log.set_filter(EventFilter::Input);
+        assert_eq!(shown(&log), ["0", "3"]);
+        log.set_filter(log.filter().next());
+        assert_eq!(shown(&log), ["1"]);
+        log.set_filter(log.filter().next().next());
+        assert_eq!(shown(&log), ["0", "1", "2", "3"]);
+    }
+
+    #[test]
+    fn keeps_the_most_recent_events() {
+        let log = log_of(&[EventCategory::Window; CAPACITY + 4]);
+        assert_eq!(log.entries.len(), CAPACITY);
+        let shown = shown(&log);
+        assert_eq!(shown.len(), SHOWN);
+        assert_eq!(shown.last(), Some(&(CAPACITY + 3).to_string().as_str()));
+    }
+
+    #[test]
+    fn cuts_long_descriptions() {
+        assert_eq!(truncate("short".to_string()), "short");
+        let long = truncate("x".repeat(MAX_CHARS + 10));
+        assert_eq!(long.chars().count(), MAX_CHARS + 3);
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use crate::{
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    uniform::UniformBuffer,
+};
+
+// Matches `FilmGrainUniform` in film_grain.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilmGrainUniform {
+    strength: f32,
+    time: f32,
+    _padding: [u32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilmGrainSettings {
+    // How far each pixel's brightness is pushed up or down by the grain, as a
+    // fraction of it.
+    pub strength: f32,
+}
+
+impl Default for FilmGrainSettings {
+    fn default() -> Self {
+        Self { strength: 0.05 }
+    }
+}
+
+// Per-pixel noise that changes every frame. An example of an effect that
+// animates: `update` writes the time into its uniform before each frame.
+pub struct FilmGrain {
+    pass: FullscreenPass,
+    uniform: FilmGrainUniform,
+    uniform_buffer: UniformBuffer<FilmGrainUniform>,
+}
+
+impl FilmGrain {
+    pub fn new(device: &wgpu::Device, settings: FilmGrainSettings) -> Self {
+        let uniform = FilmGrainUniform {
+            strength: settings.strength,
+            time: 0.0,
+            _padding: [0; 2],
+        };
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &uniform,
+            wgpu::ShaderStages::FRAGMENT,
+            "Film Grain Uniform",
+        );
+        let shader = device.create_shader_module(wgpu::include_wgsl!("film_grain.wgsl"));
+        let pass = FullscreenPass::new(
+            device,
+            "Film Grain",
+            &shader,
+            "fs_main",
+            &[uniform_buffer.layout()],
+        );
+
+        Self {
+            pass,
+            uniform,
+            uniform_buffer,
+        }
+    }
+}
+
+impl PostEffect for FilmGrain {
+    fn update(&self, queue: &wgpu::Queue, elapsed: Duration) {
+        self.uniform_buffer.write(
+            queue,
+            &FilmGrainUniform {
+                time: elapsed.as_secs_f32(),
+                ..self.uniform
+            },
+        );
+    }
+
+    fn draw(&self, frame: PostFrame) {
+        self.pass.draw(frame, &[self.uniform_buffer.bind_group()]);
+    }
+}
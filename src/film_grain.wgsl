@@ -0,0 +1,36 @@
+// Animated film grain. Drawn with post_process.wgsl's vertex stage; see
+// film_grain.rs.
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct FilmGrainUniform {
+    strength: f32,
+    // Seconds since startup, so the grain is different every frame.
+    time: f32,
+};
+@group(1) @binding(0)
+var<uniform> film_grain: FilmGrainUniform;
+
+// PCG hash, from "Hash Functions for GPU Rendering" (Jarzynski and Olano).
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+@fragment
+fn fs_main(
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    let colour = textureSampleLevel(t_input, s_input, uv, 0.0);
+    let pixel = vec2<u32>(position.xy);
+    let seed = pcg(bitcast<u32>(film_grain.time));
+    // Evenly spread over -1..1.
+    let noise = f32(pcg(pixel.x + pcg(pixel.y + seed))) / 4294967295.0 * 2.0 - 1.0;
+    let grained = colour.rgb * (1.0 + film_grain.strength * noise);
+    return vec4<f32>(max(grained, vec3<f32>(0.0)), colour.a);
+}
@@ -0,0 +1,24 @@
+use crate::post_process::{FullscreenPass, PostEffect, PostFrame};
+
+// Fast approximate anti-aliasing. Runs on the tonemapped frame, finding
+// edges by the contrast in luma between neighbouring pixels and blurring
+// along them. Much cheaper than MSAA and works with the deferred path, at the
+// cost of some softness and no help with detail smaller than a pixel.
+pub struct Fxaa {
+    pass: FullscreenPass,
+}
+
+impl Fxaa {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("fxaa.wgsl"));
+        Self {
+            pass: FullscreenPass::new(device, "FXAA", &shader, "fs_main", &[]),
+        }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn draw(&self, frame: PostFrame) {
+        self.pass.draw(frame, &[]);
+    }
+}
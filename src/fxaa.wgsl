@@ -0,0 +1,57 @@
+// FXAA on the tonemapped frame, after Timothy Lottes' original. Drawn with
+// post_process.wgsl's vertex stage; see fxaa.rs.
+
+// How far along an edge to blur, in pixels.
+const SPAN_MAX: f32 = 8.0;
+// Keep the blur direction from blowing up in flat or dark areas.
+const REDUCE_MUL: f32 = 0.125;
+const REDUCE_MIN: f32 = 0.0078125;
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+// The frame is linear, so the square root stands in for gamma and keeps
+// contrast in the shadows from reading as too small to be an edge.
+fn luma(colour: vec3<f32>) -> f32 {
+    return sqrt(dot(colour, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn sample_rgb(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_input, s_input, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let centre = textureSampleLevel(t_input, s_input, uv, 0.0);
+    let luma_nw = luma(sample_rgb(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_rgb(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_rgb(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_rgb(uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(centre.rgb);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Perpendicular to the luma gradient, so along the edge.
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    let inner = 0.5 * (sample_rgb(uv + direction * (1.0 / 3.0 - 0.5))
+        + sample_rgb(uv + direction * (2.0 / 3.0 - 0.5)));
+    let outer = inner * 0.5 + 0.25 * (sample_rgb(uv - direction * 0.5)
+        + sample_rgb(uv + direction * 0.5));
+    // The wider blur reached past the edge into something else; fall back to
+    // the narrower one.
+    let luma_outer = luma(outer);
+    if luma_outer < luma_min || luma_outer > luma_max {
+        return vec4<f32>(inner, centre.a);
+    }
+    return vec4<f32>(outer, centre.a);
+}
@@ -0,0 +1,164 @@
+use std::{sync::mpsc, time::Duration};
+
+// How many frames' timestamps can be waiting to be read back at once.
+const READBACK_COUNT: usize = 3;
+// A start and an end timestamp, each a u64.
+const TIMESTAMPS_SIZE: wgpu::BufferAddress = 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+enum ReadbackState {
+    Idle,
+    Written,
+    Mapping(mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    state: ReadbackState,
+}
+
+// Times a frame's GPU work with timestamps at the start and end of its
+// encoder, on adapters with `Features::TIMESTAMP_QUERY`. Each frame's pair is
+// resolved into the next of a ring of readback buffers, mapped once the frame
+// is submitted and picked up by a later `poll`, so nothing waits for the GPU
+// and the time shown is a few frames old. A frame goes untimed when every
+// buffer in the ring is still being read.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readbacks: Vec<Readback>,
+    next: usize,
+    // Whether `begin` wrote a timestamp for `end` to pair up with.
+    timing: bool,
+    // Nanoseconds per timestamp tick.
+    period: f32,
+    last: Option<Duration>,
+}
+
+impl GpuTimer {
+    // None when the device can't write timestamps.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GPU Timer Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Timer Resolve Buffer"),
+            size: TIMESTAMPS_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACK_COUNT)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("GPU Timer Readback Buffer"),
+                    size: TIMESTAMPS_SIZE,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: ReadbackState::Idle,
+            })
+            .collect();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            next: 0,
+            timing: false,
+            period: queue.get_timestamp_period(),
+            last: None,
+        })
+    }
+
+    // Call before anything the frame records.
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.timing = matches!(self.readbacks[self.next].state, ReadbackState::Idle);
+        if self.timing {
+            encoder.write_timestamp(&self.query_set, 0);
+        }
+    }
+
+    // Call with the encoder given to `begin`, after everything it times.
+    pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !std::mem::take(&mut self.timing) {
+            return;
+        }
+        let readback = &mut self.readbacks[self.next];
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            TIMESTAMPS_SIZE,
+        );
+        readback.state = ReadbackState::Written;
+    }
+
+    // Call once the encoder given to `end` has been submitted.
+    pub fn map(&mut self) {
+        let readback = &mut self.readbacks[self.next];
+        if !matches!(readback.state, ReadbackState::Written) {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        readback
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        readback.state = ReadbackState::Mapping(receiver);
+        self.next = (self.next + 1) % self.readbacks.len();
+    }
+
+    // Picks up any reads that have finished, and returns the GPU time of the
+    // latest frame read so far. The ring is walked from the oldest read.
+    pub fn poll(&mut self) -> Option<Duration> {
+        let count = self.readbacks.len();
+        for i in 0..count {
+            let readback = &mut self.readbacks[(self.next + i) % count];
+            let ReadbackState::Mapping(receiver) = &readback.state else {
+                continue;
+            };
+            let Ok(result) = receiver.try_recv() else {
+                continue;
+            };
+            readback.state = ReadbackState::Idle;
+            if let Err(e) = result {
+                eprintln!("Failed to read back GPU timestamps: {e}");
+                continue;
+            }
+            let [start, end]: [u64; 2] =
+                bytemuck::pod_read_unaligned(&readback.buffer.slice(..).get_mapped_range());
+            readback.buffer.unmap();
+            self.last = Some(elapsed(start, end, self.period));
+        }
+        self.last
+    }
+}
+
+// The time between two timestamps `period` nanoseconds a tick apart. A pair
+// that runs backwards, as some drivers report across power state changes,
+// counts as no time.
+fn elapsed(start: u64, end: u64, period: f32) -> Duration {
+    Duration::from_nanos((end.saturating_sub(start) as f64 * period as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_ticks_by_the_timestamp_period() {
+        assert_eq!(elapsed(1_000, 3_000, 1.0), Duration::from_micros(2));
+        assert_eq!(elapsed(1_000, 3_000, 2.5), Duration::from_micros(5));
+        assert_eq!(elapsed(3_000, 1_000, 1.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{sync::Arc, time::Duration};
+
+use wgpu::util::DeviceExt;
+
+use crate::{texture::Texture, uniform::UniformBuffer, CAMERA_GROUP};
+
+// Blade variants side by side in the atlas, and the size of each.
+const ATLAS_COLUMNS: u32 = 4;
+const ATLAS_CELL: (u32, u32) = (32, 64);
+const ATLAS_GROUP: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GrassSettings {
+    // The field is a jittered grid of this many blades a side.
+    pub blades_per_side: u32,
+    // How far across the square field is, centred on the origin.
+    pub extent: f32,
+    pub ground_height: f32,
+    // How far the tips sway, in world units.
+    pub wind_strength: f32,
+    // Sways per second.
+    pub wind_frequency: f32,
+}
+
+impl Default for GrassSettings {
+    fn default() -> Self {
+        Self {
+            blades_per_side: 100,
+            extent: 30.0,
+            ground_height: -0.5,
+            wind_strength: 0.15,
+            wind_frequency: 0.6,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+struct GrassBlade {
+    // Where the bottom of the blade sits.
+    position: [f32; 3],
+    height: f32,
+    // Which column of the atlas to draw.
+    texture_index: u32,
+    // Offsets the blade's sway so neighbours don't move in lockstep.
+    phase: f32,
+}
+
+impl GrassBlade {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32,
+        2 => Uint32,
+        3 => Float32,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GrassBlade>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// Matches `GrassUniform` in grass.wgsl.
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+struct GrassUniform {
+    time: f32,
+    wind_strength: f32,
+    wind_frequency: f32,
+    atlas_columns: u32,
+}
+
+// A field of grass drawn as one instanced quad per blade. Each quad turns
+// about Y to face the camera, so blades stay upright when seen from above,
+// and its top sways in the wind. The blades are alpha-tested cutouts from a
+// small generated atlas, so they write depth like any opaque mesh.
+pub struct GrassRenderer {
+    pipeline: wgpu::RenderPipeline,
+    instance_buffer: wgpu::Buffer,
+    num_instances: u32,
+    uniform: GrassUniform,
+    uniform_buffer: UniformBuffer<GrassUniform>,
+    atlas_bind_group: wgpu::BindGroup,
+}
+
+impl GrassRenderer {
+    pub const VERTICES_PER_BLADE: u32 = 6;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        user_uniform_layout: &wgpu::BindGroupLayout,
+        camera_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+        sampler: Arc<wgpu::Sampler>,
+        settings: GrassSettings,
+    ) -> Self {
+        let uniform = GrassUniform {
+            time: 0.0,
+            wind_strength: settings.wind_strength,
+            wind_frequency: settings.wind_frequency,
+            atlas_columns: ATLAS_COLUMNS,
+        };
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &uniform,
+            wgpu::ShaderStages::VERTEX,
+            "Grass Uniform",
+        );
+
+        let blades = scatter_blades(&settings);
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grass Instance Buffer"),
+            contents: bytemuck::cast_slice(&blades),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let atlas = Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(blade_atlas()),
+            Some("Grass Atlas"),
+            sampler,
+        );
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grass Atlas Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grass Atlas Bind Group"),
+            layout: &atlas_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("grass.wgsl"));
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grass Pipeline Layout"),
+            bind_group_layouts: &[
+                user_uniform_layout,
+                uniform_buffer.layout(),
+                camera_layout,
+                &atlas_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grass Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[GrassBlade::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            // The quads turn to face the camera, but either side may end up
+            // towards it depending on which way the blade leans.
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            // Coverage from alpha smooths the cutout edges under MSAA, where
+            // the discard alone would leave them jagged.
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: sample_count > 1,
+            },
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            instance_buffer,
+            num_instances: blades.len() as u32,
+            uniform,
+            uniform_buffer,
+            atlas_bind_group,
+        }
+    }
+
+    pub fn num_instances(&self) -> u32 {
+        self.num_instances
+    }
+
+    // Moves the wind on to `elapsed` since startup.
+    pub fn update(&mut self, queue: &wgpu::Queue, elapsed: Duration) {
+        self.uniform.time = elapsed.as_secs_f32();
+        self.uniform_buffer.write(queue, &self.uniform);
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, self.uniform_buffer.bind_group(), &[]);
+        render_pass.set_bind_group(CAMERA_GROUP, camera_bind_group, &[]);
+        render_pass.set_bind_group(ATLAS_GROUP, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..Self::VERTICES_PER_BLADE, 0..self.num_instances);
+    }
+}
+
+// A cheap integer hash to 0..1, so the field comes out the same every run.
+fn random(seed: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+fn scatter_blades(settings: &GrassSettings) -> Vec<GrassBlade> {
+    let side = settings.blades_per_side;
+    let spacing = settings.extent / side as f32;
+    let half = settings.extent / 2.0;
+    (0..side * side)
+        .map(|i| {
+            let (x, z) = ((i % side) as f32, (i / side) as f32);
+            let seed = i * 4;
+            GrassBlade {
+                position: [
+                    (x + random(seed)) * spacing - half,
+                    settings.ground_height,
+                    (z + random(seed + 1)) * spacing - half,
+                ],
+                height: 0.3 + random(seed + 2) * 0.5,
+                texture_index: i % ATLAS_COLUMNS,
+                phase: random(seed + 3) * std::f32::consts::TAU,
+            }
+        })
+        .collect()
+}
+
+// Draws a few tapering, curving blades into each column of the atlas, with
+// the shade of green and lean varying between columns.
+fn blade_atlas() -> image::RgbaImage {
+    let (width, height) = ATLAS_CELL;
+    image::RgbaImage::from_fn(width * ATLAS_COLUMNS, height, |x, y| {
+        let column = x / width;
+        // 0 at the root, 1 at the tip.
+        let t = 1.0 - (y as f32 + 0.5) / height as f32;
+        let covered = (0..3).any(|blade| {
+            let seed = column * 3 + blade;
+            let root = width as f32 * (0.3 + 0.4 * random(seed));
+            let lean = (random(seed + 100) - 0.5) * width as f32 * 0.5;
+            let centre = root + lean * t * t;
+            let half_width = 2.5 * (1.0 - t) * (0.6 + 0.4 * random(seed + 200));
+            let tip = 0.7 + 0.3 * random(seed + 300);
+            t <= tip && ((x % width) as f32 + 0.5 - centre).abs() <= half_width
+        });
+        if !covered {
+            return image::Rgba([0, 0, 0, 0]);
+        }
+        let shade = 0.6 + 0.4 * t;
+        let tint = 0.8 + 0.2 * random(column + 400);
+        image::Rgba([
+            (70.0 * shade * tint) as u8,
+            (150.0 * shade) as u8,
+            (40.0 * shade / tint) as u8,
+            255,
+        ])
+    })
+}
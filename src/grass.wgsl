@@ -0,0 +1,99 @@
+// Billboarded grass blades. See grass.rs.
+
+struct GrassUniform {
+    time: f32,
+    wind_strength: f32,
+    wind_frequency: f32,
+    atlas_columns: u32,
+};
+@group(1) @binding(0)
+var<uniform> grass: GrassUniform;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(3) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(3) @binding(1)
+var s_atlas: sampler;
+
+struct InstanceInput {
+    @location(0) position: vec3<f32>,
+    @location(1) height: f32,
+    @location(2) texture_index: u32,
+    @location(3) phase: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    // 0 at the root, 1 at the tip.
+    @location(1) along: f32,
+};
+
+// The atlas cells are twice as tall as they are wide.
+const BLADE_ASPECT: f32 = 0.5;
+const WIND_DIRECTION: vec3<f32> = vec3<f32>(0.8, 0.0, 0.6);
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, 0.0),
+        vec2<f32>(0.5, 0.0),
+        vec2<f32>(0.5, 1.0),
+        vec2<f32>(-0.5, 0.0),
+        vec2<f32>(0.5, 1.0),
+        vec2<f32>(-0.5, 1.0),
+    );
+    let corner = corners[in_vertex_index];
+
+    // Turn only about Y, so the blade stays upright however high the camera
+    // is. Straight overhead there's no direction to turn to, so any will do.
+    var to_camera = camera.view_position.xyz - instance.position;
+    to_camera.y = 0.0;
+    if dot(to_camera, to_camera) < 1e-6 {
+        to_camera = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(vec3<f32>(to_camera.z, 0.0, -to_camera.x));
+
+    // Only the top sways; the root stays planted. Neighbours are a little out
+    // of step so gusts seem to roll across the field.
+    let wave = grass.time * grass.wind_frequency * 6.2831853 + instance.phase
+        + dot(instance.position.xz, vec2<f32>(0.3, 0.2));
+    let sway = WIND_DIRECTION * sin(wave) * grass.wind_strength * corner.y * corner.y;
+
+    let width = instance.height * BLADE_ASPECT;
+    let world_position = instance.position
+        + right * corner.x * width
+        + vec3<f32>(0.0, corner.y * instance.height, 0.0)
+        + sway;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    let columns = f32(grass.atlas_columns);
+    out.uv = vec2<f32>(
+        (f32(instance.texture_index % grass.atlas_columns) + corner.x + 0.5) / columns,
+        1.0 - corner.y,
+    );
+    out.along = corner.y;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let colour = textureSample(t_atlas, s_atlas, in.uv);
+    if colour.a < 0.5 {
+        discard;
+    }
+    // Darker towards the root, where the blades shade each other.
+    let occlusion = mix(0.4, 1.0, in.along);
+    return vec4<f32>(colour.rgb * occlusion, colour.a);
+}
@@ -0,0 +1,30 @@
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+
+// A single triangle that covers the whole screen.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The scene and output are the same size, so each pixel reads its own texel.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(scene_texture, vec2<i32>(position.xy), 0);
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// For surfaces that store whatever they're given, with no sRGB encoding of
+// their own.
+@fragment
+fn fs_encode_srgb(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let colour = textureLoad(scene_texture, vec2<i32>(position.xy), 0);
+    let clamped = clamp(colour.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    return vec4<f32>(linear_to_srgb(clamped), colour.a);
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use winit::{
+    dpi::PhysicalPosition,
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{Key, NamedKey},
+};
+
+// Starts the lines that hold a frame's delta rather than an event.
+const FRAME: &str = "frame";
+
+const NAMED_KEYS: &[NamedKey] = &[
+    NamedKey::Space,
+    NamedKey::Enter,
+    NamedKey::Tab,
+    NamedKey::Backspace,
+    NamedKey::Escape,
+    NamedKey::Shift,
+    NamedKey::Control,
+    NamedKey::Alt,
+    NamedKey::ArrowUp,
+    NamedKey::ArrowDown,
+    NamedKey::ArrowLeft,
+    NamedKey::ArrowRight,
+    NamedKey::PageUp,
+    NamedKey::PageDown,
+    NamedKey::Home,
+    NamedKey::End,
+    NamedKey::Insert,
+    NamedKey::Delete,
+    NamedKey::F1,
+    NamedKey::F2,
+    NamedKey::F3,
+    NamedKey::F4,
+    NamedKey::F5,
+    NamedKey::F6,
+    NamedKey::F7,
+    NamedKey::F8,
+    NamedKey::F9,
+    NamedKey::F10,
+    NamedKey::F11,
+    NamedKey::F12,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key {
+        key: Key,
+        state: ElementState,
+    },
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    MouseInput {
+        button: MouseButton,
+        state: ElementState,
+    },
+    MouseWheel(MouseScrollDelta),
+    // Raw, unaccelerated mouse movement, independent of the cursor.
+    MouseMotion {
+        dx: f64,
+        dy: f64,
+    },
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    Focused(bool),
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    logical_key, state, ..
+                },
+                ..
+            } => Some(Self::Key {
+                key: logical_key.clone(),
+                state: *state,
+            }),
+            WindowEvent::CursorMoved { position, .. } => Some(Self::CursorMoved {
+                x: position.x,
+                y: position.y,
+            }),
+            WindowEvent::MouseInput { state, button, .. } => Some(Self::MouseInput {
+                button: *button,
+                state: *state,
+            }),
+            WindowEvent::MouseWheel { delta, .. } => Some(Self::MouseWheel(*delta)),
+            WindowEvent::Resized(size) => Some(Self::Resized {
+                width: size.width,
+                height: size.height,
+            }),
+            WindowEvent::Focused(focused) => Some(Self::Focused(*focused)),
+            _ => None,
+        }
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta: (dx, dy) } => {
+                Some(Self::MouseMotion { dx: *dx, dy: *dy })
+            }
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> Option<String> {
+        let encoded = match self {
+            Self::Key { key, state } => {
+                let key = match key {
+                    Key::Named(named) => format!("named {named:?}"),
+                    Key::Character(ch) => format!("char {ch}"),
+                    _ => return None,
+                };
+                format!("key {} {key}", encode_state(*state))
+            }
+            Self::CursorMoved { x, y } => format!("cursor {x} {y}"),
+            Self::MouseInput { button, state } => {
+                let button = match button {
+                    MouseButton::Left => "left".to_string(),
+                    MouseButton::Right => "right".to_string(),
+                    MouseButton::Middle => "middle".to_string(),
+                    MouseButton::Back => "back".to_string(),
+                    MouseButton::Forward => "forward".to_string(),
+                    MouseButton::Other(id) => format!("other:{id}"),
+                };
+                format!("mouse {} {button}", encode_state(*state))
+            }
+            Self::MouseWheel(MouseScrollDelta::LineDelta(x, y)) => format!("wheel line {x} {y}"),
+            Self::MouseWheel(MouseScrollDelta::PixelDelta(position)) => {
+                format!("wheel pixel {} {}", position.x, position.y)
+            }
+            Self::MouseMotion { dx, dy } => format!("motion {dx} {dy}"),
+            Self::Resized { width, height } => format!("resize {width} {height}"),
+            Self::Focused(focused) => format!("focus {focused}"),
+        };
+
+        Some(encoded)
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let (kind, args) = line.split_once(' ')?;
+
+        let event = match kind {
+            "key" => {
+                let (state, rest) = args.split_once(' ')?;
+                let (key_kind, value) = rest.split_once(' ')?;
+                let key = match key_kind {
+                    "named" => Key::Named(
+                        NAMED_KEYS
+                            .iter()
+                            .copied()
+                            .find(|named| format!("{named:?}") == value)?,
+                    ),
+                    "char" => Key::Character(value.into()),
+                    _ => return None,
+                };
+                Self::Key {
+                    key,
+                    state: decode_state(state)?,
+                }
+            }
+            "cursor" => {
+                let (x, y) = args.split_once(' ')?;
+                Self::CursorMoved {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                }
+            }
+            "mouse" => {
+                let (state, button) = args.split_once(' ')?;
+                let button = match button {
+                    "left" => MouseButton::Left,
+                    "right" => MouseButton::Right,
+                    "middle" => MouseButton::Middle,
+                    "back" => MouseButton::Back,
+                    "forward" => MouseButton::Forward,
+                    other => MouseButton::Other(other.strip_prefix("other:")?.parse().ok()?),
+                };
+                Self::MouseInput {
+                    button,
+                    state: decode_state(state)?,
+                }
+            }
+            "wheel" => {
+                let (unit, rest) = args.split_once(' ')?;
+                let (x, y) = rest.split_once(' ')?;
+                match unit {
+                    "line" => Self::MouseWheel(MouseScrollDelta::LineDelta(
+                        x.parse().ok()?,
+                        y.parse().ok()?,
+                    )),
+                    "pixel" => Self::MouseWheel(MouseScrollDelta::PixelDelta(
+                        PhysicalPosition::new(x.parse().ok()?, y.parse().ok()?),
+                    )),
+                    _ => return None,
+                }
+            }
+            "motion" => {
+                let (dx, dy) = args.split_once(' ')?;
+                Self::MouseMotion {
+                    dx: dx.parse().ok()?,
+                    dy: dy.parse().ok()?,
+                }
+            }
+            "resize" => {
+                let (width, height) = args.split_once(' ')?;
+                Self::Resized {
+                    width: width.parse().ok()?,
+                    height: height.parse().ok()?,
+                }
+            }
+            "focus" => Self::Focused(args.parse().ok()?),
+            _ => return None,
+        };
+
+        Some(event)
+    }
+}
+
+fn encode_state(state: ElementState) -> &'static str {
+    match state {
+        ElementState::Pressed => "pressed",
+        ElementState::Released => "released",
+    }
+}
+
+fn decode_state(state: &str) -> Option<ElementState> {
+    match state {
+        "pressed" => Some(ElementState::Pressed),
+        "released" => Some(ElementState::Released),
+        _ => None,
+    }
+}
+
+// Each update's frame delta is recorded as a `frame` line, in whole
+// nanoseconds, and events carry the time they arrived by the sum of those
+// deltas. Playback steps the update loop by the recorded deltas instead of
+// live ones, so everything driven by the update's delta (the camera, flights,
+// scrolling textures) sees the same frames as the recording, and each event
+// is replayed on the frame it arrived before, however long frames take to
+// render. Anything timed by the wall clock since startup, such as the grass
+// and post effects' animation, still follows the playback run's own clock.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    elapsed: Duration,
+}
+
+impl InputRecorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            elapsed: Duration::ZERO,
+        })
+    }
+
+    pub fn advance(&mut self, dt: Duration) -> io::Result<()> {
+        self.elapsed += dt;
+        writeln!(self.writer, "{FRAME} {}", dt.as_nanos())
+    }
+
+    pub fn record(&mut self, event: &InputEvent) -> io::Result<()> {
+        match event.encode() {
+            Some(encoded) => writeln!(self.writer, "{} {encoded}", self.elapsed.as_secs_f64()),
+            None => {
+                eprintln!("Not recording unsupported input: {event:?}");
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub struct InputPlayback {
+    events: VecDeque<(Duration, InputEvent)>,
+    // Replayed in place of the live deltas. Recordings made before deltas
+    // were recorded have none, and play back at the live frame rate.
+    deltas: VecDeque<Duration>,
+    elapsed: Duration,
+}
+
+impl InputPlayback {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut events = VecDeque::new();
+        let mut deltas = VecDeque::new();
+        // The sum of the deltas so far, which is exactly the time playback
+        // will have reached when it replays the events that follow. The
+        // times written with them went through a decimal and may be off by a
+        // nanosecond either way.
+        let mut recorded = Duration::ZERO;
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if let Some(nanos) = line
+                .strip_prefix(FRAME)
+                .and_then(|rest| rest.strip_prefix(' '))
+            {
+                match nanos.parse() {
+                    Ok(nanos) => {
+                        let dt = Duration::from_nanos(nanos);
+                        recorded += dt;
+                        deltas.push_back(dt);
+                    }
+                    Err(_) => eprintln!(
+                        "Skipping malformed frame on line {}: {line}",
+                        line_number + 1
+                    ),
+                }
+                continue;
+            }
+
+            let event = line.split_once(' ').and_then(|(time, event)| {
+                let time = if deltas.is_empty() {
+                    Duration::try_from_secs_f64(time.parse().ok()?).ok()?
+                } else {
+                    recorded
+                };
+                Some((time, InputEvent::decode(event)?))
+            });
+
+            match event {
+                Some(event) => events.push_back(event),
+                None => eprintln!(
+                    "Skipping malformed input on line {}: {line}",
+                    line_number + 1
+                ),
+            }
+        }
+
+        Ok(Self {
+            events,
+            deltas,
+            elapsed: Duration::ZERO,
+        })
+    }
+
+    // The delta to step this frame by: the recorded one while there are any
+    // left, otherwise `live`.
+    pub fn next_delta(&mut self, live: Duration) -> Duration {
+        self.deltas.pop_front().unwrap_or(live)
+    }
+
+    // Hands back the events recorded before this frame, then moves playback
+    // on by the frame's delta.
+    pub fn advance(&mut self, dt: Duration) -> Vec<InputEvent> {
+        let mut due = Vec::new();
+
+        while let Some((time, _)) = self.events.front() {
+            if *time > self.elapsed {
+                break;
+            }
+            due.extend(self.events.pop_front().map(|(_, event)| event));
+        }
+        self.elapsed += dt;
+
+        due
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(event: InputEvent) {
+        let encoded = event.encode().expect("event should encode");
+        assert_eq!(InputEvent::decode(&encoded), Some(event), "{encoded}");
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        round_trip(InputEvent::Key {
+            key: Key::Character("w".into()),
+            state: ElementState::Pressed,
+        });
+        round_trip(InputEvent::Key {
+            key: Key::Named(NamedKey::F1),
+            state: ElementState::Released,
+        });
+    }
+
+    #[test]
+    fn mouse_round_trips() {
+        round_trip(InputEvent::CursorMoved { x: 12.5, y: -3.0 });
+        round_trip(InputEvent::MouseInput {
+            button: MouseButton::Other(7),
+            state: ElementState::Pressed,
+        });
+        round_trip(InputEvent::MouseWheel(MouseScrollDelta::LineDelta(
+            0.0, -1.5,
+        )));
+        round_trip(InputEvent::MouseWheel(MouseScrollDelta::PixelDelta(
+            PhysicalPosition::new(4.0, 8.25),
+        )));
+        round_trip(InputEvent::MouseMotion { dx: 0.1, dy: -0.2 });
+    }
+
+    #[test]
+    fn window_events_round_trip() {
+        round_trip(InputEvent::Resized {
+            width: 1280,
+            height: 720,
+        });
+        round_trip(InputEvent::Focused(false));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(InputEvent::decode("key pressed named NotAKey"), None);
+        assert_eq!(InputEvent::decode("key held char w"), None);
+        assert_eq!(InputEvent::decode("cursor 1"), None);
+        assert_eq!(InputEvent::decode("teleport 1 2"), None);
+        assert_eq!(InputEvent::decode(""), None);
+    }
+
+    #[test]
+    fn playback_follows_frame_time() {
+        let recording = "0 focus true\n0.05 resize 10 20\nnonsense\n0.1 focus false\n";
+        let mut playback = InputPlayback::read(recording.as_bytes()).unwrap();
+        let frame = Duration::from_millis(40);
+
+        assert_eq!(playback.advance(frame), vec![InputEvent::Focused(true)]);
+        assert_eq!(playback.advance(frame), vec![]);
+        assert_eq!(
+            playback.advance(frame),
+            vec![InputEvent::Resized {
+                width: 10,
+                height: 20
+            }]
+        );
+        assert!(!playback.is_finished());
+        assert_eq!(playback.advance(frame), vec![InputEvent::Focused(false)]);
+        assert!(playback.is_finished());
+    }
+
+    #[test]
+    fn playback_replays_recorded_frame_deltas() {
+        // Frames of 10ms and 30ms were recorded, with a resize arriving
+        // between them; the event's own time is a nanosecond late.
+        let recording = "0 focus true\nframe 10000000\n0.010000001 resize 10 20\nframe 30000000\n";
+        let mut playback = InputPlayback::read(recording.as_bytes()).unwrap();
+        let live = Duration::from_millis(500);
+
+        let dt = playback.next_delta(live);
+        assert_eq!(dt, Duration::from_millis(10));
+        assert_eq!(playback.advance(dt), vec![InputEvent::Focused(true)]);
+
+        let dt = playback.next_delta(live);
+        assert_eq!(dt, Duration::from_millis(30));
+        assert_eq!(
+            playback.advance(dt),
+            vec![InputEvent::Resized {
+                width: 10,
+                height: 20
+            }]
+        );
+        assert!(playback.is_finished());
+        assert_eq!(playback.next_delta(live), live);
+    }
+}
@@ -0,0 +1,70 @@
+use cgmath::Rotation3;
+
+pub const NUM_INSTANCES_PER_ROW: u32 = 10;
+const INSTANCE_SPACING: f32 = 1.5;
+
+pub struct Instance {
+    pub position: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    // Hidden instances stay in the instance buffer but are left out of every
+    // draw, and can't be picked.
+    pub visible: bool,
+}
+
+impl Instance {
+    pub fn model_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation)
+    }
+
+    pub fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            model: self.model_matrix().into(),
+        }
+    }
+
+    // A grid of copies on the XZ plane, centred on the origin. Each copy is
+    // turned about Y by an amount that varies across the grid, but never far
+    // enough to show its culled back face to a camera in front of it.
+    pub fn grid() -> Vec<Instance> {
+        let half = (NUM_INSTANCES_PER_ROW / 2) as f32;
+        (0..NUM_INSTANCES_PER_ROW)
+            .flat_map(|z| {
+                (0..NUM_INSTANCES_PER_ROW).map(move |x| {
+                    let (x, z) = (x as f32 - half, z as f32 - half);
+                    Instance {
+                        position: cgmath::Vector3::new(x, 0.0, z) * INSTANCE_SPACING,
+                        rotation: cgmath::Quaternion::from_axis_angle(
+                            cgmath::Vector3::unit_y(),
+                            cgmath::Deg((x - z) * 8.0),
+                        ),
+                        visible: true,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+pub struct InstanceRaw {
+    model: [[f32; 4]; 4],
+}
+
+impl InstanceRaw {
+    // Locations 0-4 are left for the per-vertex attributes.
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+    ];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
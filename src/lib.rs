@@ -1,11 +1,127 @@
+mod asset_cache;
+mod asset_loader;
+mod assets;
+mod bloom;
+mod bounding_spheres;
+mod camera;
+mod camera_controller;
+mod capture;
+mod color_grading;
+mod compressed_texture;
+mod crossfade;
+mod culling;
+mod deferred;
+mod depth_of_field;
+mod depth_resolve;
+mod environment;
+mod event_log;
+mod film_grain;
+mod fxaa;
+mod gpu_timer;
+mod grass;
+mod hdr;
+mod input_recording;
+mod instance;
+mod light;
+mod light_clusters;
+mod light_shafts;
+mod lod;
+mod material;
+mod mesh;
+mod mesh_jobs;
+mod mipmap;
+mod model;
+mod motion_blur;
+mod overdraw;
+mod perf_overlay;
+mod picking;
+mod point_sprites;
+mod post_process;
+mod shader_source;
+mod shadow;
+mod skybox;
+mod sprite_batch;
+mod ssao;
+mod taa;
+mod text;
+mod texture;
+mod tonemap;
+mod uniform;
+mod vignette;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use asset_loader::{AssetLoader, LoadedModel};
+use assets::{Assets, Handle};
+use bloom::Bloom;
+pub use bloom::BloomSettings;
+use bounding_spheres::BoundingSphereDebug;
+use camera::{screen_to_ndc, Camera, CameraUniform, PresetView, Viewpoint};
+use camera_controller::{CameraController, CameraMode};
+pub use capture::DumpTargetError;
+use color_grading::ColorGrading;
+use crossfade::{Crossfade, ShaderTransition};
+use culling::{ChunkedInstances, CullStats, Frustum};
+use deferred::{Deferred, GBuffer};
+pub use depth_of_field::DepthOfFieldSettings;
+use depth_of_field::{DepthOfField, DepthProbe};
+use depth_resolve::{readable_depth, DepthResolve, ResolvedDepth};
+use environment::Environment;
+use event_log::{EventFilter, EventLog};
+use film_grain::FilmGrain;
+pub use film_grain::FilmGrainSettings;
+use fxaa::Fxaa;
+use gpu_timer::GpuTimer;
+use grass::GrassRenderer;
+pub use grass::GrassSettings;
+use hdr::{HdrPresenter, HdrTarget, SCENE_FORMAT};
+use input_recording::{InputEvent, InputPlayback, InputRecorder};
+use instance::{Instance, InstanceRaw};
+use light::LightUniform;
+pub use light::{Lights, PointLight, PointLightId, SpotLight, SpotLightId};
+pub use light_shafts::LightShaftSettings;
+use light_shafts::LightShafts;
+use lod::LodMesh;
+use material::{Material, MaterialParams, MaterialTextures, UvScrollUniform};
+use mesh::{DrawMesh, Mesh, MeshData};
+use mesh_jobs::{FinishedMesh, MeshJob, MeshWorkerPool};
+use model::Model;
+pub use motion_blur::MotionBlurSettings;
+use motion_blur::{MotionBlur, MotionBlurGeometry};
+use overdraw::{OverdrawDebug, OverdrawGeometry};
+use perf_overlay::{GpuStats, PerfOverlay};
+use point_sprites::{PointSprite, PointSpriteRenderer};
+use post_process::{PostEffect, PostProcess, PostTargets, ShaderEffect};
+pub use shader_source::{ShaderLoadError, ShaderSource};
+use shadow::{ShadowGeometry, CASCADE_COUNT};
+use skybox::Skybox;
+use sprite_batch::SpriteBatch;
+pub use ssao::SsaoSettings;
+use ssao::{Ssao, SsaoGeometry};
+use taa::Taa;
+use text::{TextBatch, TextRenderer};
+use texture::{texture_bytes, SamplerConfig, Texture};
+use tonemap::Tonemap;
+pub use tonemap::Tonemapper;
+use uniform::UniformBuffer;
+use vignette::Vignette;
+pub use vignette::VignetteSettings;
+
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, One};
 use simple_logger::SimpleLogger;
 use wgpu::util::DeviceExt;
 use winit::{
-    dpi::PhysicalSize,
-    event::{ElementState, Event, KeyEvent, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{DeviceEvent, ElementState, Event, KeyEvent, MouseButton, WindowEvent},
     event_loop::EventLoop,
     keyboard::{Key, NamedKey},
-    window::{Fullscreen, Window, WindowBuilder},
+    window::{CursorGrabMode, CursorIcon, Fullscreen, Window, WindowBuilder},
 };
 
 #[repr(C)]
@@ -13,11 +129,17 @@ use winit::{
 struct Vertex {
     position: [f32; 3],
     colour: [f32; 3],
+    tex_coords: [f32; 2],
+    normal: [f32; 3],
+    // xyz points along increasing u; w is +1 or -1 depending on whether the
+    // bitangent (up the texture image) is normal x tangent or its opposite.
+    tangent: [f32; 4],
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x3, 4 => Float32x4
+    ];
 
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -28,30 +150,624 @@ impl Vertex {
     }
 }
 
-const VERTICES: &[Vertex] = &[
-    Vertex {
+const POINT_SPRITES: &[PointSprite] = &[
+    PointSprite {
         position: [-0.0868241, 0.49240386, 0.0],
-        colour: [0.5, 0.0, 0.5],
+        colour: [1.0, 0.3, 0.3, 1.0],
     },
-    Vertex {
+    PointSprite {
         position: [-0.49513406, 0.06958647, 0.0],
-        colour: [0.5, 0.0, 0.5],
+        colour: [1.0, 0.8, 0.2, 1.0],
     },
-    Vertex {
+    PointSprite {
         position: [-0.21918549, -0.44939706, 0.0],
-        colour: [0.5, 0.0, 0.5],
+        colour: [0.3, 1.0, 0.4, 1.0],
     },
-    Vertex {
+    PointSprite {
         position: [0.35966998, -0.3473291, 0.0],
-        colour: [0.5, 0.0, 0.5],
+        colour: [0.3, 0.6, 1.0, 1.0],
     },
-    Vertex {
+    PointSprite {
         position: [0.44147372, 0.2347359, 0.0],
-        colour: [0.5, 0.0, 0.5],
+        colour: [0.9, 0.4, 1.0, 0.6],
     },
 ];
 
-const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
+const WINDOW_TITLE: &str = "Window!";
+
+const INPUT_RECORDING_PATH: &str = "input_recording.txt";
+
+const THUMBNAIL_PATH: &str = "thumbnail.png";
+const THUMBNAIL_SIZE: PhysicalSize<u32> = PhysicalSize::new(256, 256);
+
+const SHADER_SOURCE: &str = include_str!("shader.wgsl");
+// Entry points for the deferred path, built on top of shader.wgsl.
+const DEFERRED_SOURCE: &str = include_str!("deferred.wgsl");
+
+fn deferred_shader_source() -> String {
+    format!("{SHADER_SOURCE}\n{DEFERRED_SOURCE}")
+}
+
+// Premultiplied output already has alpha folded into the colour, so the
+// source colour is added as-is (`BlendFactor::One`) rather than scaled by
+// alpha a second time. Straight alpha needs the usual `SrcAlpha` scaling.
+// Opaque and inherited modes don't blend at all: their compositors ignore
+// alpha, so only PreMultiplied and PostMultiplied surfaces show a difference,
+// and only where the scene's alpha, which every post pass carries through to
+// the surface, is below one.
+fn blend_state(alpha_mode: wgpu::CompositeAlphaMode) -> wgpu::BlendState {
+    match alpha_mode {
+        wgpu::CompositeAlphaMode::PreMultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        wgpu::CompositeAlphaMode::PostMultiplied => wgpu::BlendState::ALPHA_BLENDING,
+        _ => wgpu::BlendState::REPLACE,
+    }
+}
+
+fn log_model(assets: &Assets, handle: Handle<Model>, path: &Path) {
+    let model = assets.model(handle);
+    println!(
+        "Loaded {}: {} meshes, {} materials",
+        path.display(),
+        model.meshes.len(),
+        model.materials.len()
+    );
+    for &material in &model.materials {
+        let Some(material) = assets.material(material) else {
+            continue;
+        };
+        let texture = &assets.texture(material.textures.base_colour).texture;
+        let params = &material.params;
+        println!(
+            "  {} ({}x{}): base colour {:?}, roughness {}, metallic {}, reflectivity {}",
+            material.name,
+            texture.width(),
+            texture.height(),
+            params.base_colour,
+            params.roughness,
+            params.metallic,
+            params.reflectivity
+        );
+    }
+    println!("Assets: {}", assets.summary());
+}
+
+// `config` with the scene's format, for things that draw into the HDR target.
+fn scene_config(config: &wgpu::SurfaceConfiguration) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        format: SCENE_FORMAT,
+        ..config.clone()
+    }
+}
+
+// Scene pipelines draw into the HDR target rather than the surface, but still
+// blend for the surface's alpha mode since the present pass copies alpha too.
+fn colour_target(config: &wgpu::SurfaceConfiguration) -> wgpu::ColorTargetState {
+    wgpu::ColorTargetState {
+        format: SCENE_FORMAT,
+        blend: Some(blend_state(config.alpha_mode)),
+        write_mask: wgpu::ColorWrites::ALL,
+    }
+}
+
+// The highest sample count no greater than `requested` that every format in
+// `formats` supports. Formats can support fewer counts than the adapter does
+// in general, and a pipeline with an unsupported count fails to build.
+fn supported_sample_count(
+    adapter: &wgpu::Adapter,
+    formats: &[wgpu::TextureFormat],
+    requested: u32,
+) -> u32 {
+    let sample_count = [16, 8, 4, 2, 1]
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .find(|&count| {
+            formats.iter().all(|&format| {
+                adapter
+                    .get_texture_format_features(format)
+                    .flags
+                    .sample_count_supported(count)
+            })
+        })
+        .unwrap_or(1);
+
+    if sample_count != requested {
+        eprintln!("{requested}x MSAA is not supported, using {sample_count}x");
+    }
+    sample_count
+}
+
+fn create_multisampled_framebuffer(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisampled Framebuffer"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+// What a pass does with the depth buffer before drawing, chosen separately
+// from colour so a pass can e.g. keep the colour already drawn into the frame
+// but start from a fresh depth buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthClearPolicy {
+    Clear(f32),
+    Load,
+}
+
+impl DepthClearPolicy {
+    fn load_op(self) -> wgpu::LoadOp<f32> {
+        match self {
+            Self::Clear(depth) => wgpu::LoadOp::Clear(depth),
+            Self::Load => wgpu::LoadOp::Load,
+        }
+    }
+}
+
+// Where `draw_scene` renders to. With MSAA the scene is drawn into
+// `multisampled` and resolved into `view`; otherwise it goes to `view`
+// directly. The depth view must match the sample count.
+struct SceneTarget<'a> {
+    view: &'a wgpu::TextureView,
+    multisampled: Option<&'a wgpu::TextureView>,
+    depth_view: &'a wgpu::TextureView,
+    clear_colour: bool,
+    depth_clear: DepthClearPolicy,
+    // Set on the deferred path, the same size as `view`.
+    gbuffer: Option<&'a GBuffer>,
+}
+
+struct PipelineOptions {
+    colour_target: wgpu::ColorTargetState,
+    sample_count: u32,
+    polygon_mode: wgpu::PolygonMode,
+    cull_mode: Option<wgpu::Face>,
+}
+
+impl PipelineOptions {
+    fn new(config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Self {
+        Self {
+            colour_target: colour_target(config),
+            sample_count,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            cull_mode: Some(wgpu::Face::Back),
+        }
+    }
+
+    fn wireframe(self) -> Self {
+        Self {
+            polygon_mode: wgpu::PolygonMode::Line,
+            ..self
+        }
+    }
+
+    fn double_sided(self) -> Self {
+        Self {
+            cull_mode: None,
+            ..self
+        }
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    options: PipelineOptions,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    label: &str,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(options.colour_target)],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: options.cull_mode,
+            polygon_mode: options.polygon_mode,
+            unclipped_depth: false,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: options.sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+// Zeroes `range` of `buffer` on the GPU, for counters that have to start
+// from nothing each frame without a write from the CPU. `clear_buffer` only
+// works in whole 4-byte words, so the range is widened to the words it
+// touches; a range reaching the end of the buffer clears to the end,
+// whatever its size. `buffer` needs `COPY_DST`.
+pub(crate) fn clear_gpu_buffer(
+    encoder: &mut wgpu::CommandEncoder,
+    buffer: &wgpu::Buffer,
+    range: Range<wgpu::BufferAddress>,
+) {
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    let start = range.start / align * align;
+    let end = range.end.div_ceil(align) * align;
+    if start >= end || start >= buffer.size() {
+        return;
+    }
+    let size = (end < buffer.size()).then(|| wgpu::BufferSize::new(end - start).unwrap());
+    encoder.clear_buffer(buffer, start, size);
+}
+
+// The instance buffer and the copy of it from the previous frame, kept for
+// motion vectors. Both start out holding `instances`.
+fn create_instance_buffers(
+    device: &wgpu::Device,
+    instances: &[Instance],
+) -> (wgpu::Buffer, wgpu::Buffer) {
+    let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Instance Buffer"),
+        contents: bytemuck::cast_slice(&instance_data),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_SRC,
+    });
+    let previous_instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Previous Instance Buffer"),
+        contents: bytemuck::cast_slice(&instance_data),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    (instance_buffer, previous_instance_buffer)
+}
+
+// Bind group reserved for `RunConfig::on_update`. It holds a single uniform
+// buffer of `USER_UNIFORM_SIZE` bytes at binding 0, visible to both the vertex
+// and fragment stages, which user shaders can declare as
+// `@group(0) @binding(0) var<uniform> user: MyData;`. Only the scene pass's
+// pipelines have it in their layout and get it bound by
+// `set_scene_bind_groups`: the forward and deferred mesh pipelines, along
+// with the challenge shader, light markers and bounding spheres that share
+// their layout, plus the skybox, grass and point sprites. Shadow, SSAO and
+// velocity passes, post effects and the overlays don't see it.
+pub const USER_UNIFORM_GROUP: u32 = 0;
+pub const USER_UNIFORM_SIZE: wgpu::BufferAddress = 256;
+
+const MATERIAL_GROUP: u32 = 1;
+const CAMERA_GROUP: u32 = 2;
+const LIGHT_GROUP: u32 = 3;
+
+pub struct UpdateContext<'a> {
+    pub queue: &'a wgpu::Queue,
+    pub elapsed: Duration,
+    // Point lights can be added, moved and removed here every frame; the
+    // changes are uploaded once the callback returns.
+    pub lights: &'a mut Lights,
+    user_uniform: &'a wgpu::Buffer,
+}
+
+pub type UpdateCallback = Box<dyn FnMut(&mut UpdateContext)>;
+
+impl UpdateContext<'_> {
+    pub fn write_user_uniform(&mut self, offset: wgpu::BufferAddress, data: &[u8]) {
+        let end = offset + data.len() as wgpu::BufferAddress;
+        if end > USER_UNIFORM_SIZE {
+            eprintln!("User uniform write of {end} bytes exceeds {USER_UNIFORM_SIZE} bytes");
+            return;
+        }
+        self.queue.write_buffer(self.user_uniform, offset, data);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct FrameStats {
+    draw_calls: u32,
+    // Vertices fed to non-indexed draws and indices fed to indexed ones. An
+    // indexed mesh's vertex count isn't what the GPU processes, so the two
+    // aren't summed.
+    vertices: u32,
+    indices: u32,
+    triangles: u32,
+    cull: CullStats,
+}
+
+impl FrameStats {
+    fn record_draw(&mut self, vertex_count: u32, instance_count: u32) {
+        self.draw_calls += 1;
+        self.vertices += vertex_count * instance_count;
+        self.triangles += (vertex_count / 3) * instance_count;
+    }
+
+    // Lines have no triangles to count.
+    fn record_line_draw(&mut self, vertex_count: u32, instance_count: u32) {
+        self.draw_calls += 1;
+        self.vertices += vertex_count * instance_count;
+    }
+
+    fn record_draw_indexed(&mut self, index_count: u32, instance_count: u32) {
+        self.draw_calls += 1;
+        self.indices += index_count * instance_count;
+        self.triangles += (index_count / 3) * instance_count;
+    }
+
+    // One draw of each mesh per run of instances.
+    fn record_instanced_draws(&mut self, meshes: &[Mesh], instances: &[Range<u32>]) {
+        self.record_instanced_index_counts(meshes.iter().map(|mesh| mesh.num_elements), instances);
+    }
+
+    // The same for meshes given by their index counts.
+    fn record_instanced_index_counts(
+        &mut self,
+        index_counts: impl IntoIterator<Item = u32>,
+        instances: &[Range<u32>],
+    ) {
+        for index_count in index_counts {
+            for range in instances {
+                self.record_draw_indexed(index_count, range.len() as u32);
+            }
+        }
+    }
+}
+
+// Largest viewport with the given aspect ratio that fits in `target`, centred so
+// the leftover space is split evenly between the bars.
+fn letterbox(target: PhysicalSize<u32>, aspect: f32) -> Viewport {
+    let (target_width, target_height) = (target.width as f32, target.height as f32);
+    let (width, height) = if target_width / target_height > aspect {
+        (target_height * aspect, target_height)
+    } else {
+        (target_width, target_width / aspect)
+    };
+
+    Viewport {
+        x: (target_width - width) / 2.0,
+        y: (target_height - height) / 2.0,
+        width,
+        height,
+    }
+}
+
+// Side-by-side panes, each letterboxed to `aspect` within its half.
+fn split_viewports(target: PhysicalSize<u32>, aspect: f32) -> [Viewport; 2] {
+    let half = PhysicalSize::new(target.width / 2, target.height);
+    let left = letterbox(half, aspect);
+    let right = Viewport {
+        x: left.x + half.width as f32,
+        ..left
+    };
+    [left, right]
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Viewport {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Viewport {
+    fn full(target: PhysicalSize<u32>) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: target.width as f32,
+            height: target.height as f32,
+        }
+    }
+
+    // Rounded inwards so the rectangle never reaches outside the target.
+    fn scissor_rect(self) -> (u32, u32, u32, u32) {
+        (
+            self.x.ceil() as u32,
+            self.y.ceil() as u32,
+            self.width.floor() as u32,
+            self.height.floor() as u32,
+        )
+    }
+
+    fn apply(self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_viewport(self.x, self.y, self.width, self.height, 0.0, 1.0);
+        let (x, y, width, height) = self.scissor_rect();
+        render_pass.set_scissor_rect(x, y, width, height);
+    }
+}
+
+// The cameras a viewport can be drawn from. The overview camera stays put,
+// looking down at the scene from above.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CameraId {
+    Main,
+    Overview,
+}
+
+// Float surfaces are presented as extended-range linear (scRGB) where the
+// platform supports it: shaders write linear values with no sRGB encode, 1.0
+// is SDR white and anything above it is brighter. wgpu doesn't expose the
+// surface colour space, so PQ output isn't available.
+const HDR_SURFACE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+const FIXED_CLEAR_COLOUR: wgpu::Color = wgpu::Color::BLACK;
+const DEFAULT_CLEAR_MODE: ClearMode = ClearMode::Cursor;
+// World units per second, and radians per pixel of mouse movement.
+const CAMERA_SPEED: f32 = 4.0;
+const CAMERA_SENSITIVITY: f32 = 0.003;
+const RAINBOW_SPEED: f64 = 0.1;
+// World units per scroll line, and how far the eye may be dollied from the
+// scene origin.
+const DOLLY_STEP: f32 = 0.5;
+const MAX_DOLLY_DISTANCE: f32 = 50.0;
+// Degrees per scroll line, and the range the field of view is kept in.
+const FOV_STEP: f32 = 2.0;
+const FOV_RANGE: (f32, f32) = (15.0, 90.0);
+const CAMERA_FLIGHT: Duration = Duration::from_millis(1500);
+// Eye and target of the viewpoints "y" flies between.
+const CAMERA_VIEWPOINTS: &[([f32; 3], [f32; 3])] = &[
+    ([0.0, 6.0, 12.0], [0.0, 0.0, 0.0]),
+    ([14.0, 3.0, 0.0], [0.0, 0.0, 0.0]),
+    ([0.0, 18.0, 2.0], [0.0, 0.0, 0.0]),
+    ([-6.0, 1.5, 8.0], [-3.0, 0.0, 0.0]),
+];
+// Position and colour of the point lights hovering over the instance grid.
+const DEFAULT_POINT_LIGHTS: &[([f32; 3], [f32; 3])] = &[
+    ([-4.0, 1.5, -4.0], [1.0, 0.3, 0.2]),
+    ([4.0, 1.5, -2.0], [0.2, 0.5, 1.0]),
+    ([0.0, 1.5, 4.0], [0.3, 1.0, 0.4]),
+];
+// Matches the `ambient_strength` default in shader.wgsl.
+const DEFAULT_AMBIENT_STRENGTH: f64 = 1.0;
+const DEFAULT_POINT_SIZE: f32 = 24.0;
+const DEFAULT_DISC_SEGMENTS: u16 = 4;
+// Asset name for whichever generated shape is currently on screen.
+const PROCEDURAL_MODEL: &str = "<procedural>";
+const LOD_SPHERE_MODEL: &str = "<lod sphere>";
+// Sectors and stacks of each of the LOD sphere's levels, finest first, and
+// the distances between them.
+const LOD_SPHERE_DETAIL: [(u16, u16); 3] = [(48, 24), (16, 8), (6, 4)];
+const LOD_SWITCH_DISTANCES: &[f32] = &[6.0, 14.0];
+const PARALLAX_WALL_MODEL: &str = "<parallax wall>";
+// The default of `parallax_steps` in shader.wgsl, and how far ArrowUp and
+// ArrowDown can take it.
+const DEFAULT_PARALLAX_STEPS: u32 = 16;
+const MAX_PARALLAX_STEPS: u32 = 128;
+
+// How far along the cursor's ray Insert spawns an instance when the ray
+// misses everything.
+const SPAWN_DISTANCE: f32 = 5.0;
+// Texture repeats per second that Home scrolls the model's materials by.
+const UV_SCROLL_SPEED: [f32; 2] = [0.1, 0.05];
+// F12's sprites fill a square grid this many across, each this many pixels
+// apart, drifting around their cells this many pixels at most.
+const SPRITE_GRID: u32 = 64;
+const SPRITE_SPACING: f32 = 12.0;
+const SPRITE_DRIFT: f32 = 4.0;
+
+// What F5 cycles through, one at a time so they can be compared on the same
+// scene. Launch settings can still combine MSAA with FXAA.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AntiAliasing {
+    Msaa,
+    Fxaa,
+    Taa,
+}
+
+impl AntiAliasing {
+    fn from_config(config: &RunConfig) -> Self {
+        if config.taa {
+            Self::Taa
+        } else if config.fxaa {
+            Self::Fxaa
+        } else {
+            Self::Msaa
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Self::Msaa => Self::Fxaa,
+            Self::Fxaa => Self::Taa,
+            Self::Taa => Self::Msaa,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ClearMode {
+    Fixed,
+    Cursor,
+    Rainbow,
+}
+
+impl ClearMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Fixed => Self::Cursor,
+            Self::Cursor => Self::Rainbow,
+            Self::Rainbow => Self::Fixed,
+        }
+    }
+}
+
+fn overview_camera_uniform(camera: &Camera) -> CameraUniform {
+    let mut uniform = CameraUniform::new();
+    uniform.update_view_proj(camera);
+    uniform
+}
+
+fn rainbow_colour(elapsed: Duration) -> wgpu::Color {
+    let angle = elapsed.as_secs_f64() * RAINBOW_SPEED * std::f64::consts::TAU;
+    let third = std::f64::consts::TAU / 3.0;
+    wgpu::Color {
+        r: 0.5 + 0.5 * angle.cos(),
+        g: 0.5 + 0.5 * (angle + third).cos(),
+        b: 0.5 + 0.5 * (angle + 2.0 * third).cos(),
+        a: 1.0,
+    }
+}
+
+// The cursor colour uses raw pixel coordinates, so channels are clamped
+// before blending or a transition would saturate almost immediately.
+fn lerp_colour(from: wgpu::Color, to: wgpu::Color, t: f64) -> wgpu::Color {
+    let lerp = |a: f64, b: f64| {
+        let (a, b) = (a.clamp(0.0, 1.0), b.clamp(0.0, 1.0));
+        a + (b - a) * t
+    };
+    wgpu::Color {
+        r: lerp(from.r, to.r),
+        g: lerp(from.g, to.g),
+        b: lerp(from.b, to.b),
+        a: lerp(from.a, to.a),
+    }
+}
+
+// Blends from the colour on screen when the mode changed towards whatever the
+// new mode is currently producing, so animated modes keep moving mid-blend.
+struct ClearTransition {
+    from: wgpu::Color,
+    start: Duration,
+    duration: Duration,
+}
+
+impl ClearTransition {
+    fn progress(&self, now: Duration) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (now.saturating_sub(self.start).as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+    }
+}
 
 struct State {
     surface: wgpu::Surface,
@@ -60,23 +776,144 @@ struct State {
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     clear_colour: wgpu::Color,
+    clear_mode: ClearMode,
+    cursor_colour: wgpu::Color,
+    clear_transition: Option<ClearTransition>,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    show_cursor_readout: bool,
+    // Reads the depth under the cursor back for the readout, which shows the
+    // world point from the last read to finish. `cursor_probe_position` is
+    // where that read was taken, and `cursor_world` is `None` over the
+    // background.
+    cursor_probe: DepthProbe,
+    cursor_probe_position: Option<PhysicalPosition<f64>>,
+    cursor_world: Option<cgmath::Point3<f32>>,
+    user_uniform_buffer: wgpu::Buffer,
+    user_uniform_bind_group_layout: wgpu::BindGroupLayout,
+    user_uniform_bind_group: wgpu::BindGroup,
+    depth_texture: Texture,
+    // The scene depth with one sample per pixel, when `depth_texture` has
+    // more. `readable_depth` picks whichever of the two depth of field, its
+    // focus probe and the cursor readout should read.
+    depth_resolve: DepthResolve,
+    resolved_depth: Option<ResolvedDepth>,
+    sample_count: u32,
+    // What MSAA uses when it's on; 1 on the deferred path.
+    msaa_sample_count: u32,
+    anti_aliasing: AntiAliasing,
+    multisampled_framebuffer: Option<wgpu::TextureView>,
+    hdr_presenter: HdrPresenter,
+    hdr_target: HdrTarget,
+    post_process: PostProcess,
+    post_targets: PostTargets,
+    taa: Option<Taa>,
+    // The main camera's view-projection last frame, without TAA's jitter.
+    previous_view_proj: cgmath::Matrix4<f32>,
+    // Renders the main view through the directional light's shadow camera,
+    // to check what the shadow map covers.
+    view_from_light: bool,
+    camera: Camera,
+    camera_mode: CameraMode,
+    camera_controller: Box<dyn CameraController>,
+    // Where the controllers and flights put the camera. The rendered camera
+    // follows it with `RunConfig::camera_smoothing`.
+    camera_goal: Camera,
+    pointer_locked: bool,
+    // What `update_cursor` last set the cursor to, or `None` if it hid it.
+    cursor: Option<CursorIcon>,
+    next_viewpoint: usize,
+    camera_uniform: CameraUniform,
+    camera_buffer: UniformBuffer<CameraUniform>,
+    overview_camera: Camera,
+    overview_camera_buffer: UniformBuffer<CameraUniform>,
+    lights: Lights,
+    light_marker_pipeline: wgpu::RenderPipeline,
+    bounding_spheres: BoundingSphereDebug,
+    show_bounding_spheres: bool,
+    light_marker_mesh: Mesh,
+    default_material: Material,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    shader_constants: HashMap<String, f64>,
     render_pipeline: wgpu::RenderPipeline,
     render_pipeline2: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    challenge_shader: Handle<wgpu::ShaderModule>,
+    wireframe_pipeline: Option<wgpu::RenderPipeline>,
+    // Meshes with double-sided materials are drawn with this instead.
+    double_sided_pipeline: wgpu::RenderPipeline,
+    wireframe: bool,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    // Materials whose textures are scrolling, advanced every update.
+    uv_scrolls: HashMap<Handle<Material>, UvScrollUniform>,
+    assets: Assets,
+    model: Handle<Model>,
+    // Drawn in place of the meshes of the model it's paired with, while that
+    // model is shown.
+    lod_sphere: Option<(Handle<Model>, LodMesh)>,
+    // The brick wall F11 swaps in to show off parallax.
+    parallax_wall: Option<Handle<Model>>,
+    parallax: bool,
+    // How many layers the parallax raymarch takes while it's on.
+    parallax_steps: u32,
+    // Index into `RunConfig::models` of the one being shown.
+    active_model: usize,
+    // Set when the camera should frame the model once it's finished loading.
+    frame_model: bool,
+    placeholder_model: Handle<Model>,
+    asset_loader: AssetLoader,
+    model_job: Option<u64>,
+    instances: Vec<Instance>,
+    // The instance last picked, which Delete shows or hides.
+    selected_instance: Option<usize>,
+    instance_buffer: wgpu::Buffer,
+    previous_instance_buffer: wgpu::Buffer,
+    // Rebuilt with the instance buffers. `None` when `instance_chunk_size`
+    // is, leaving every instance to be tested.
+    instance_chunks: Option<ChunkedInstances>,
     use_colour: bool,
+    crossfade: Crossfade,
+    shader_transition: Option<ShaderTransition>,
+    overdraw: OverdrawDebug,
+    overdraw_debug: bool,
+    mesh_workers: MeshWorkerPool,
+    mesh_job: Option<MeshJob>,
+    pending_uploads: VecDeque<FinishedMesh>,
+    disc_segments: u16,
+    show_quad: bool,
+    point_sprites: PointSpriteRenderer,
+    show_point_sprites: bool,
+    grass: GrassRenderer,
+    show_grass: bool,
+    // Which of `debug_targets` F10 saves.
+    dump_target_index: usize,
+    skybox: Skybox,
+    show_skybox: bool,
+    deferred: Option<Deferred>,
+    gbuffer: Option<GBuffer>,
+    split_screen: bool,
+    text: TextRenderer,
+    sprite_batch: SpriteBatch,
+    show_sprites: bool,
+    perf_overlay: PerfOverlay,
+    // Only runs while the overlay is shown. None without timestamp queries.
+    gpu_timer: Option<GpuTimer>,
+    event_log: EventLog,
+    input_recorder: Option<InputRecorder>,
+    input_playback: Option<InputPlayback>,
+    start_time: Instant,
+    last_update: Instant,
+    run_config: RunConfig,
     window: Window,
 }
 
 impl State {
-    async fn new(window: Window) -> Self {
+    async fn new(window: Window, run_config: RunConfig) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            flags: run_config.validation.instance_flags(),
             ..Default::default()
         });
+        println!("Validation level: {:?}", run_config.validation);
 
         let surface = unsafe { instance.create_surface(&window) }.unwrap();
 
@@ -89,11 +926,17 @@ impl State {
             .await
             .unwrap();
 
+        let limits = run_config.limits_profile.limits_for(&adapter.limits());
+        println!("Limits profile: {:?}", run_config.limits_profile);
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
+                    features: adapter.features()
+                        & (wgpu::Features::POLYGON_MODE_LINE
+                            | wgpu::Features::TIMESTAMP_QUERY
+                            | compressed_texture::FEATURES),
+                    limits,
                     label: None,
                 },
                 None,
@@ -103,123 +946,429 @@ impl State {
 
         let surface_caps = surface.get_capabilities(&adapter);
 
-        let surface_format = surface_caps
+        // An sRGB surface encodes the linear frame as it's written. Without
+        // one, `HdrPresenter` does it instead.
+        let sdr_format = surface_caps
             .formats
             .iter()
             .copied()
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
 
+        let surface_format = if run_config.hdr_output {
+            if surface_caps.formats.contains(&HDR_SURFACE_FORMAT) {
+                HDR_SURFACE_FORMAT
+            } else {
+                eprintln!("HDR output is not supported by the surface, using {sdr_format:?}");
+                sdr_format
+            }
+        } else {
+            sdr_format
+        };
+        println!("Surface format: {surface_format:?}");
+
+        let alpha_mode = match run_config.alpha_mode {
+            Some(mode) if surface_caps.alpha_modes.contains(&mode) => mode,
+            Some(mode) => {
+                eprintln!(
+                    "Alpha mode {mode:?} is not supported by the surface, using {:?}",
+                    surface_caps.alpha_modes[0]
+                );
+                surface_caps.alpha_modes[0]
+            }
+            None => surface_caps.alpha_modes[0],
+        };
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
             height: size.height,
             present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
+            alpha_mode,
             view_formats: vec![],
         };
 
         surface.configure(&device, &config);
 
-        let clear_colour = wgpu::Color::BLACK;
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-        let shader2 = device.create_shader_module(wgpu::include_wgsl!("challenge_shader.wgsl"));
+        // The G-buffer isn't multisampled, and what's drawn after the
+        // resolve has to match it. TAA reads the scene's depth, which it can
+        // only do when that isn't multisampled either.
+        let msaa_samples = match run_config.render_path {
+            RenderPath::Forward => run_config.msaa_samples,
+            RenderPath::Deferred => 1,
+        };
+        let msaa_sample_count = supported_sample_count(
+            &adapter,
+            &[SCENE_FORMAT, Texture::DEPTH_FORMAT],
+            msaa_samples,
+        );
+        let sample_count = if run_config.taa { 1 } else { msaa_sample_count };
+        println!("Render path: {:?}", run_config.render_path);
+        println!("MSAA samples: {sample_count}");
+        let multisampled_framebuffer = create_multisampled_framebuffer(
+            &device,
+            config.width,
+            config.height,
+            SCENE_FORMAT,
+            sample_count,
+        );
+        let hdr_presenter = HdrPresenter::new(&device, config.format);
+        let hdr_target = hdr_presenter.create_target(&device, config.width, config.height);
+        let bloom = run_config.bloom.map(|settings| {
+            let bloom = Bloom::new(&device, settings, config.width, config.height);
+            Box::new(bloom) as Box<dyn PostEffect>
+        });
+        let shader_effects =
+            run_config
+                .post_shaders
+                .iter()
+                .filter_map(|source| match source.load(&device) {
+                    Ok(shader) => {
+                        let label = source.path().display().to_string();
+                        let effect = ShaderEffect::new(&device, &label, &shader);
+                        Some(Box::new(effect) as Box<dyn PostEffect>)
+                    }
+                    Err(e) => {
+                        eprintln!("{e}, skipping the post effect");
+                        None
+                    }
+                });
+        let vignette = run_config
+            .vignette
+            .map(|settings| Box::new(Vignette::new(&device, settings)) as Box<dyn PostEffect>);
+        let film_grain = run_config
+            .film_grain
+            .map(|settings| Box::new(FilmGrain::new(&device, settings)) as Box<dyn PostEffect>);
+        let post_effects = bloom
+            .into_iter()
+            .chain(shader_effects)
+            .chain(vignette)
+            .chain(film_grain)
+            .collect();
+        let tonemap = Tonemap::new(&device, run_config.tonemapper, run_config.exposure);
+        let depth_of_field = run_config
+            .depth_of_field
+            .map(|settings| DepthOfField::new(&device, settings));
+        let motion_blur = run_config
+            .motion_blur
+            .map(|settings| MotionBlur::new(&device, settings, config.width, config.height));
+        let light_shafts = run_config
+            .light_shafts
+            .map(|settings| LightShafts::new(&device, settings));
+        let mut post_process = PostProcess::new(
+            depth_of_field,
+            motion_blur,
+            light_shafts,
+            post_effects,
+            tonemap,
+            ColorGrading::new(&device, &queue, &run_config.color_luts),
+            Fxaa::new(&device),
+        );
+        post_process.set_fxaa_enabled(run_config.fxaa);
+        let post_targets = PostTargets::new(&device, &hdr_presenter, config.width, config.height);
+
+        let clear_colour = FIXED_CLEAR_COLOUR;
+        let clear_mode = DEFAULT_CLEAR_MODE;
+        let mut assets = Assets::new(run_config.asset_cache_capacity);
+        let shader2 = match run_config
+            .challenge_shader
+            .as_ref()
+            .map(|s| assets.load_shader(&device, s))
+        {
+            Some(Ok(shader)) => shader,
+            Some(Err(e)) => {
+                eprintln!("{e}, using the error shader");
+                assets.error_shader(&device)
+            }
+            None => assets.insert_shader(
+                "challenge_shader.wgsl",
+                device.create_shader_module(wgpu::include_wgsl!("challenge_shader.wgsl")),
+            ),
+        };
+        // Pipeline 2 is rebuilt from it whenever the sample count changes.
+        assets.pin_shader(shader2);
+
+        let user_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("User Uniform Buffer"),
+            size: USER_UNIFORM_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let user_uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("User Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let user_uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("User Uniform Bind Group"),
+            layout: &user_uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: user_uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let depth_texture = Texture::create_depth_texture(
+            &device,
+            config.width,
+            config.height,
+            sample_count,
+            "Depth Texture",
+        );
+        let depth_resolve = DepthResolve::new(&device);
+        let resolved_depth = depth_resolve.target(&device, &depth_texture);
+
+        let camera = Camera::new(config.width as f32 / config.height as f32);
+        let camera_mode = CameraMode::FirstPerson;
+        let camera_controller = camera_mode.controller(&camera, CAMERA_SPEED, CAMERA_SENSITIVITY);
+        let camera_goal = Camera::new(camera.aspect);
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+
+        // The fragment stage needs the eye position for specular highlights.
+        let camera_buffer = UniformBuffer::new(
+            &device,
+            &camera_uniform,
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            "Camera",
+        );
+        let taa = run_config.taa.then(|| {
+            Taa::new(
+                &device,
+                &hdr_presenter,
+                camera_buffer.layout(),
+                config.width,
+                config.height,
+            )
+        });
+        let previous_view_proj = camera.build_view_projection_matrix();
+
+        let mut overview_camera = Camera::new(camera.aspect);
+        overview_camera.eye = (0.0, 20.0, 4.0).into();
+        let overview_camera_buffer = UniformBuffer::new(
+            &device,
+            &overview_camera_uniform(&overview_camera),
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            "Overview Camera",
+        );
+
+        let environment_path = run_config.environment.as_ref().map(model::resource_path);
+        let environment =
+            Environment::new(&device, &queue, &mut assets, environment_path.as_deref());
+        let skybox = Skybox::new(
+            &device,
+            &scene_config(&config),
+            &user_uniform_bind_group_layout,
+            camera_buffer.layout(),
+            sample_count,
+            &environment,
+        );
+        let ssao = Ssao::new(
+            &device,
+            &queue,
+            camera_buffer.layout(),
+            config.width,
+            config.height,
+        );
+        let mut lights = Lights::new(&device, LightUniform::default(), environment, ssao);
+        for &(position, colour) in DEFAULT_POINT_LIGHTS {
+            let mut light = PointLight::new(position, colour, 2.0, 6.0);
+            light.set_casts_shadows(true);
+            lights.add_point_light(light);
+        }
+        // A lamp over the middle of the grid.
+        lights.add_spot_light(SpotLight::new(
+            [0.0, 6.0, 0.0],
+            cgmath::Vector3::new(0.0, -1.0, 0.0),
+            [1.0, 0.95, 0.8],
+            4.0,
+            12.0,
+            cgmath::Deg(15.0),
+            cgmath::Deg(25.0),
+        ));
+        lights.upload(&device, &queue);
+
+        let material_bind_group_layout = Material::bind_group_layout(&device);
+        let diffuse_texture = Texture::from_bytes(
+            &device,
+            &queue,
+            include_bytes!("uv_grid.png"),
+            "uv_grid.png",
+            assets.sampler(&device, SamplerConfig::default()),
+        )
+        .unwrap();
+        println!(
+            "Loaded texture: {}x{}",
+            diffuse_texture.texture.width(),
+            diffuse_texture.texture.height()
+        );
+        let diffuse_texture =
+            assets.insert_texture(&device, &queue, "uv_grid.png", diffuse_texture);
+        let textures = MaterialTextures::new(&device, &queue, &mut assets, diffuse_texture);
+        let default_material = Material::new(
+            &device,
+            "Default",
+            &assets,
+            textures,
+            MaterialParams::default(),
+            &material_bind_group_layout,
+        );
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[],
+                bind_group_layouts: &[
+                    &user_uniform_bind_group_layout,
+                    &material_bind_group_layout,
+                    camera_buffer.layout(),
+                    lights.layout(),
+                ],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let shader_constants = HashMap::new();
+        let shader =
+            assets.specialised_shader(&device, "shader.wgsl", SHADER_SOURCE, &shader_constants);
+        let shader = assets.shader(shader);
 
-        let render_pipeline2 = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipline 2"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader2,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader2,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            multiview: None,
-        });
+        let render_pipeline = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            shader,
+            PipelineOptions::new(&config, sample_count),
+            &[Vertex::desc(), InstanceRaw::desc()],
+            "Render Pipeline",
+        );
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let num_vertices = VERTICES.len() as u32;
+        let double_sided_pipeline = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            shader,
+            PipelineOptions::new(&config, sample_count).double_sided(),
+            &[Vertex::desc(), InstanceRaw::desc()],
+            "Double-Sided Render Pipeline",
+        );
+
+        let wireframe_pipeline = device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| {
+                create_render_pipeline(
+                    &device,
+                    &render_pipeline_layout,
+                    shader,
+                    PipelineOptions::new(&config, sample_count).wireframe(),
+                    &[Vertex::desc(), InstanceRaw::desc()],
+                    "Wireframe Pipeline",
+                )
+            });
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
+        let deferred = (run_config.render_path == RenderPath::Deferred).then(|| {
+            let shader = assets.specialised_shader(
+                &device,
+                "deferred.wgsl",
+                &deferred_shader_source(),
+                &shader_constants,
+            );
+            Deferred::new(
+                &device,
+                &render_pipeline_layout,
+                &user_uniform_bind_group_layout,
+                camera_buffer.layout(),
+                lights.layout(),
+                assets.shader(shader),
+            )
         });
-        let num_indices = INDICES.len() as u32;
+        let gbuffer = deferred
+            .as_ref()
+            .map(|deferred| deferred.create_gbuffer(&device, config.width, config.height));
 
-        let use_colour = false;
+        let light_marker_pipeline = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &device.create_shader_module(wgpu::include_wgsl!("light_marker.wgsl")),
+            PipelineOptions::new(&config, sample_count),
+            &[Vertex::desc()],
+            "Light Marker Pipeline",
+        );
+        let light_marker_mesh = Mesh::new(&device, &MeshData::cube(), "Light Marker");
+        let bounding_spheres = BoundingSphereDebug::new(
+            &device,
+            &render_pipeline_layout,
+            colour_target(&config),
+            sample_count,
+        );
 
-        Self {
+        let render_pipeline2 = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            assets.shader(shader2),
+            PipelineOptions::new(&config, sample_count),
+            &[],
+            "Render Pipline 2",
+        );
+
+        let placeholder_model =
+            Model::placeholder(&device, &queue, &material_bind_group_layout, &mut assets);
+        let placeholder_model = assets.insert_fallback_model("<placeholder>", placeholder_model);
+
+        let instances = Instance::grid();
+        let (instance_buffer, previous_instance_buffer) =
+            create_instance_buffers(&device, &instances);
+        let instance_chunks = run_config
+            .instance_chunk_size
+            .map(|chunk_size| ChunkedInstances::new(&instances, chunk_size));
+
+        let use_colour = true;
+        let crossfade = Crossfade::new(&device, &scene_config(&config));
+        let overdraw = OverdrawDebug::new(&device, &config, camera_buffer.layout());
+
+        let point_sprites = PointSpriteRenderer::new(
+            &device,
+            &scene_config(&config),
+            &user_uniform_bind_group_layout,
+            camera_buffer.layout(),
+            sample_count,
+            POINT_SPRITES,
+            DEFAULT_POINT_SIZE,
+        );
+        let grass = GrassRenderer::new(
+            &device,
+            &queue,
+            &scene_config(&config),
+            &user_uniform_bind_group_layout,
+            camera_buffer.layout(),
+            sample_count,
+            assets.sampler(&device, SamplerConfig::default()),
+            run_config.grass,
+        );
+        let text = TextRenderer::new(&device, &config);
+        let cursor_probe = DepthProbe::new(&device);
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        let sprite_atlas = Texture::from_image(
+            &device,
+            &queue,
+            &image::DynamicImage::ImageRgba8(sprite_batch::shape_atlas()),
+            Some("Sprite Atlas"),
+            assets.sampler(&device, SamplerConfig::default()),
+        );
+        let sprite_batch = SpriteBatch::new(&device, &config, &sprite_atlas);
+
+        let asset_loader = AssetLoader::new(device.features());
+
+        let mut state = Self {
             window,
             surface,
             device,
@@ -227,14 +1376,108 @@ impl State {
             config,
             size,
             clear_colour,
+            clear_mode,
+            cursor_colour: FIXED_CLEAR_COLOUR,
+            clear_transition: None,
+            cursor_position: None,
+            show_cursor_readout: false,
+            cursor_probe,
+            cursor_probe_position: None,
+            cursor_world: None,
+            user_uniform_buffer,
+            user_uniform_bind_group_layout,
+            user_uniform_bind_group,
+            depth_texture,
+            depth_resolve,
+            resolved_depth,
+            sample_count,
+            msaa_sample_count,
+            anti_aliasing: AntiAliasing::from_config(&run_config),
+            multisampled_framebuffer,
+            hdr_presenter,
+            hdr_target,
+            post_process,
+            post_targets,
+            taa,
+            previous_view_proj,
+            view_from_light: false,
+            camera,
+            camera_mode,
+            camera_controller,
+            camera_goal,
+            pointer_locked: false,
+            cursor: Some(CursorIcon::Default),
+            next_viewpoint: 1,
+            camera_uniform,
+            camera_buffer,
+            overview_camera,
+            overview_camera_buffer,
+            lights,
+            light_marker_pipeline,
+            bounding_spheres,
+            show_bounding_spheres: false,
+            light_marker_mesh,
+            default_material,
+            render_pipeline_layout,
+            shader_constants,
             render_pipeline,
             render_pipeline2,
-            vertex_buffer,
-            num_vertices,
-            index_buffer,
-            num_indices,
+            challenge_shader: shader2,
+            wireframe_pipeline,
+            double_sided_pipeline,
+            wireframe: false,
+            material_bind_group_layout,
+            uv_scrolls: HashMap::new(),
+            assets,
+            model: placeholder_model,
+            lod_sphere: None,
+            parallax_wall: None,
+            parallax: true,
+            parallax_steps: DEFAULT_PARALLAX_STEPS,
+            active_model: 0,
+            frame_model: false,
+            placeholder_model,
+            asset_loader,
+            model_job: None,
+            instances,
+            selected_instance: None,
+            instance_buffer,
+            previous_instance_buffer,
+            instance_chunks,
             use_colour,
-        }
+            crossfade,
+            shader_transition: None,
+            overdraw,
+            overdraw_debug: false,
+            mesh_workers: MeshWorkerPool::new(2),
+            mesh_job: None,
+            pending_uploads: VecDeque::new(),
+            disc_segments: DEFAULT_DISC_SEGMENTS,
+            show_quad: false,
+            point_sprites,
+            show_point_sprites: false,
+            grass,
+            show_grass: false,
+            dump_target_index: 0,
+            skybox,
+            show_skybox: true,
+            deferred,
+            gbuffer,
+            split_screen: false,
+            text,
+            sprite_batch,
+            show_sprites: false,
+            perf_overlay: PerfOverlay::default(),
+            gpu_timer,
+            event_log: EventLog::default(),
+            input_recorder: None,
+            input_playback: None,
+            start_time: Instant::now(),
+            last_update: Instant::now(),
+            run_config,
+        };
+        state.request_scene_model();
+        state
     }
 
     pub fn window(&self) -> &Window {
@@ -247,97 +1490,2649 @@ impl State {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+            self.overview_camera.aspect = self.camera.aspect;
+            self.overview_camera_buffer
+                .write(&self.queue, &overview_camera_uniform(&self.overview_camera));
+            self.create_scene_attachments();
+            self.hdr_target =
+                self.hdr_presenter
+                    .create_target(&self.device, new_size.width, new_size.height);
+            self.post_targets = PostTargets::new(
+                &self.device,
+                &self.hdr_presenter,
+                new_size.width,
+                new_size.height,
+            );
+            self.post_process
+                .resize(&self.device, new_size.width, new_size.height);
+            if let Some(taa) = &mut self.taa {
+                taa.resize(
+                    &self.device,
+                    &self.hdr_presenter,
+                    new_size.width,
+                    new_size.height,
+                );
+            }
+            self.point_sprites
+                .resize(&self.queue, new_size.width, new_size.height);
+            self.crossfade
+                .resize(&self.device, &scene_config(&self.config));
+            self.overdraw.resize(&self.device, &self.config);
+            self.lights
+                .resize(&self.device, new_size.width, new_size.height);
+            self.text
+                .resize(&self.queue, new_size.width, new_size.height);
+            self.sprite_batch
+                .resize(&self.queue, new_size.width, new_size.height);
+            if let Some(deferred) = &self.deferred {
+                self.gbuffer =
+                    Some(deferred.create_gbuffer(&self.device, new_size.width, new_size.height));
+            }
 
             println!("{:?}", new_size);
         }
     }
 
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::CursorMoved { position, .. } => {
-                self.clear_colour = wgpu::Color {
-                    r: position.x,
-                    g: position.y,
-                    a: 1.0,
-                    b: 1.0,
-                };
-                true
-            }
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        state,
-                        logical_key: Key::Named(NamedKey::Space),
-                        ..
-                    },
-                ..
-            } => {
-                self.use_colour = *state == ElementState::Released;
-                true
-            }
-            _ => false,
-        }
+    // The depth and multisampled colour the scene pass draws into, which
+    // follow both the window size and the sample count.
+    fn create_scene_attachments(&mut self) {
+        self.depth_texture = Texture::create_depth_texture(
+            &self.device,
+            self.config.width,
+            self.config.height,
+            self.sample_count,
+            "Depth Texture",
+        );
+        self.resolved_depth = self.depth_resolve.target(&self.device, &self.depth_texture);
+        self.multisampled_framebuffer = create_multisampled_framebuffer(
+            &self.device,
+            self.config.width,
+            self.config.height,
+            SCENE_FORMAT,
+            self.sample_count,
+        );
     }
 
-    fn update(&mut self) {}
+    fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        self.configure_anti_aliasing(
+            anti_aliasing == AntiAliasing::Msaa,
+            anti_aliasing == AntiAliasing::Fxaa,
+            anti_aliasing == AntiAliasing::Taa,
+        );
+        println!(
+            "Anti-aliasing: {anti_aliasing:?}, {}x MSAA",
+            self.sample_count
+        );
+    }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+    // TAA reads the scene's depth, so it can't be combined with MSAA.
+    fn configure_anti_aliasing(&mut self, msaa: bool, fxaa: bool, taa: bool) {
+        self.post_process.set_fxaa_enabled(fxaa);
+        if taa && self.taa.is_none() {
+            self.taa = Some(Taa::new(
+                &self.device,
+                &self.hdr_presenter,
+                self.camera_buffer.layout(),
+                self.config.width,
+                self.config.height,
+            ));
+        } else if !taa {
+            self.taa = None;
+        }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_colour),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        let sample_count = if msaa && !taa {
+            self.msaa_sample_count
+        } else {
+            1
+        };
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.create_scene_attachments();
+            self.rebuild_render_pipeline();
+            self.rebuild_scene_pipelines();
+        }
+    }
+
+    // Everything else drawn in the scene pass, which has to match its sample
+    // count.
+    fn rebuild_scene_pipelines(&mut self) {
+        self.light_marker_pipeline = create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            &self
+                .device
+                .create_shader_module(wgpu::include_wgsl!("light_marker.wgsl")),
+            PipelineOptions::new(&self.config, self.sample_count),
+            &[Vertex::desc()],
+            "Light Marker Pipeline",
+        );
+        self.render_pipeline2 = create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            self.assets.shader(self.challenge_shader),
+            PipelineOptions::new(&self.config, self.sample_count),
+            &[],
+            "Render Pipline 2",
+        );
+        self.bounding_spheres = BoundingSphereDebug::new(
+            &self.device,
+            &self.render_pipeline_layout,
+            colour_target(&self.config),
+            self.sample_count,
+        );
+        let point_size = self.point_sprites.point_size();
+        self.point_sprites = PointSpriteRenderer::new(
+            &self.device,
+            &scene_config(&self.config),
+            &self.user_uniform_bind_group_layout,
+            self.camera_buffer.layout(),
+            self.sample_count,
+            POINT_SPRITES,
+            point_size,
+        );
+        self.grass = GrassRenderer::new(
+            &self.device,
+            &self.queue,
+            &scene_config(&self.config),
+            &self.user_uniform_bind_group_layout,
+            self.camera_buffer.layout(),
+            self.sample_count,
+            self.assets.sampler(&self.device, SamplerConfig::default()),
+            self.run_config.grass,
+        );
+        self.skybox = Skybox::new(
+            &self.device,
+            &scene_config(&self.config),
+            &self.user_uniform_bind_group_layout,
+            self.camera_buffer.layout(),
+            self.sample_count,
+            self.lights.environment(),
+        );
+    }
+
+    // Override constants are baked in at pipeline creation, so changing one
+    // costs a shader compile and pipeline rebuild. Use them for values that
+    // select a shading variant or rarely change; anything updated per frame
+    // belongs in a uniform instead.
+    pub fn set_shader_constant(&mut self, name: &str, value: f64) {
+        self.shader_constants.insert(name.to_string(), value);
+        self.rebuild_render_pipeline();
+    }
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    fn rebuild_render_pipeline(&mut self) {
+        let shader = self.assets.specialised_shader(
+            &self.device,
+            "shader.wgsl",
+            SHADER_SOURCE,
+            &self.shader_constants,
+        );
+        println!(
+            "Shader modules compiled: {}",
+            self.assets.shaders_compiled()
+        );
+        let shader = self.assets.shader(shader);
+        self.render_pipeline = create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            shader,
+            PipelineOptions::new(&self.config, self.sample_count),
+            &[Vertex::desc(), InstanceRaw::desc()],
+            "Render Pipeline",
+        );
+        self.double_sided_pipeline = create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            shader,
+            PipelineOptions::new(&self.config, self.sample_count).double_sided(),
+            &[Vertex::desc(), InstanceRaw::desc()],
+            "Double-Sided Render Pipeline",
+        );
+        if self.wireframe_pipeline.is_some() {
+            self.wireframe_pipeline = Some(create_render_pipeline(
+                &self.device,
+                &self.render_pipeline_layout,
+                shader,
+                PipelineOptions::new(&self.config, self.sample_count).wireframe(),
+                &[Vertex::desc(), InstanceRaw::desc()],
+                "Wireframe Pipeline",
+            ));
+        }
+        if let Some(deferred) = &mut self.deferred {
+            let shader = self.assets.specialised_shader(
+                &self.device,
+                "deferred.wgsl",
+                &deferred_shader_source(),
+                &self.shader_constants,
+            );
+            deferred.set_shader(
+                &self.device,
+                &self.render_pipeline_layout,
+                self.assets.shader(shader),
+            );
         }
+    }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+    fn adjust_ambient_strength(&mut self, delta: f64) {
+        let current = self
+            .shader_constants
+            .get("ambient_strength")
+            .copied()
+            .unwrap_or(DEFAULT_AMBIENT_STRENGTH);
+        let ambient_strength = (current + delta).clamp(0.0, 2.0);
+        self.set_shader_constant("ambient_strength", ambient_strength);
+        println!("ambient_strength: {ambient_strength}");
+    }
 
+    pub fn start_recording_input(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.input_recorder = Some(InputRecorder::create(path)?);
         Ok(())
     }
-}
 
-pub async fn run() -> Result<(), impl std::error::Error> {
-    SimpleLogger::new().init().unwrap();
-    let event_loop = EventLoop::new().unwrap();
+    pub fn stop_recording_input(&mut self) -> io::Result<()> {
+        match self.input_recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
 
-    let mut decorations = true;
-    let mut minimized = false;
-    let mut maximized = false;
-    let mut with_min_size = false;
-    let mut with_max_size = false;
+    pub fn play_input(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.input_playback = Some(InputPlayback::load(path)?);
+        Ok(())
+    }
 
-    let window = WindowBuilder::new()
-        .with_title("Window!")
-        .with_inner_size(winit::dpi::LogicalSize::new(128.0, 128.0))
-        .build(&event_loop)
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        match InputEvent::from_window_event(event) {
+            Some(input) => self.receive_input(input),
+            None => false,
+        }
+    }
+
+    fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        match InputEvent::from_device_event(event) {
+            Some(input) => self.receive_input(input),
+            None => false,
+        }
+    }
+
+    fn receive_input(&mut self, input: InputEvent) -> bool {
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(e) = recorder.record(&input) {
+                eprintln!("Failed to record input: {e}");
+                self.input_recorder = None;
+            }
+        }
+
+        // Live input is ignored while a recording plays back so the session
+        // stays reproducible. It still reaches the event loop for window keys.
+        if self.input_playback.is_some() {
+            return false;
+        }
+
+        self.handle_input(&input)
+    }
+
+    // Textures from the last frame that `dump_target` can save, by name. The
+    // post targets hold the output of alternate effects in the chain.
+    fn debug_targets(&self) -> Vec<(&'static str, &wgpu::Texture)> {
+        let mut targets = vec![
+            ("hdr", self.hdr_target.texture()),
+            (
+                "depth",
+                &readable_depth(&self.resolved_depth, &self.depth_texture).texture,
+            ),
+        ];
+        targets.extend(
+            ["post a", "post b"]
+                .into_iter()
+                .zip(self.post_targets.targets().iter().map(HdrTarget::texture)),
+        );
+        if let Some(gbuffer) = &self.gbuffer {
+            targets.extend(
+                ["albedo", "normal", "material", "emission"]
+                    .into_iter()
+                    .zip(gbuffer.textures()),
+            );
+        }
+        targets
+    }
+
+    // Saves one of `debug_targets` as a PNG, with depth and float formats
+    // squeezed into 8 bits.
+    pub fn dump_target(&self, name: &str, path: impl AsRef<Path>) -> Result<(), DumpTargetError> {
+        let path = path.as_ref();
+        let Some((_, texture)) = self
+            .debug_targets()
+            .into_iter()
+            .find(|(target, _)| *target == name)
+        else {
+            return Err(DumpTargetError::UnknownTarget(name.to_string()));
+        };
+        let pixels = capture::read_texture_rgba8(&self.device, &self.queue, texture);
+        image::save_buffer(
+            path,
+            &pixels,
+            texture.width(),
+            texture.height(),
+            image::ColorType::Rgba8,
+        )
+        .map_err(|e| DumpTargetError::Image(path.to_path_buf(), e))
+    }
+
+    fn cycle_dump_target(&mut self) {
+        let targets = self.debug_targets();
+        let index = (self.dump_target_index + 1) % targets.len();
+        println!("Dump target: {}", targets[index].0);
+        self.dump_target_index = index;
+    }
+
+    fn dump_selected_target(&self) {
+        let targets = self.debug_targets();
+        let name = targets[self.dump_target_index % targets.len()].0;
+        let path = format!("dump_{}.png", name.replace(' ', "_"));
+        match self.dump_target(name, &path) {
+            Ok(()) => println!("Saved {name} to {path}"),
+            Err(e) => eprintln!("Failed to dump {name}: {e}"),
+        }
+    }
+
+    fn save_thumbnail(&mut self) {
+        if !capture::can_read_rgba8(self.config.format) {
+            eprintln!(
+                "Can't save a thumbnail from a {:?} surface",
+                self.config.format
+            );
+            return;
+        }
+
+        let pixels = self.render_at(THUMBNAIL_SIZE.width, THUMBNAIL_SIZE.height);
+        match image::save_buffer(
+            THUMBNAIL_PATH,
+            &pixels,
+            THUMBNAIL_SIZE.width,
+            THUMBNAIL_SIZE.height,
+            image::ColorType::Rgba8,
+        ) {
+            Ok(()) => println!("Saved thumbnail to {THUMBNAIL_PATH}"),
+            Err(e) => eprintln!("Failed to save thumbnail: {e}"),
+        }
+    }
+
+    fn cursor_readout(&self) -> String {
+        let Some(position) = self.cursor_position else {
+            return "cursor: —".to_string();
+        };
+        let (ndc_x, ndc_y) = screen_to_ndc(position, self.size);
+        let world = match self.cursor_world {
+            Some(point) => format!("({:.2}, {:.2}, {:.2})", point.x, point.y, point.z),
+            None => "—".to_string(),
+        };
+
+        format!(
+            "screen: ({:.0}, {:.0}) | ndc: ({ndc_x:.3}, {ndc_y:.3}) | world: {world}",
+            position.x, position.y
+        )
+    }
+
+    // The scene object under the cursor, as seen by the main camera across
+    // the whole window, and the world position where the ray hits it.
+    fn pick_at_cursor(&self) -> Option<(picking::Hit, cgmath::Point3<f32>)> {
+        let ray = picking::Ray::from_screen(self.cursor_position?, self.size, &self.camera)?;
+        let meshes = &self.assets.model(self.model).meshes;
+        let hit = picking::pick(&ray, meshes, &self.instances)?;
+        Some((hit, ray.at(hit.distance)))
+    }
+
+    fn report_pick(&self, pick: Option<(picking::Hit, cgmath::Point3<f32>)>) {
+        let Some((hit, point)) = pick else {
+            println!("Picked nothing");
+            return;
+        };
+        println!(
+            "Picked instance {} mesh {} at ({:.2}, {:.2}, {:.2})",
+            hit.instance, hit.mesh, point.x, point.y, point.z
+        );
+
+        let origin = cgmath::Point3::from_vec(self.instances[hit.instance].position);
+        if let Some(screen) = self.camera.world_to_screen(origin, self.size) {
+            println!(
+                "Instance origin is on screen at ({:.0}, {:.0})",
+                screen.x, screen.y
+            );
+        }
+    }
+
+    fn log_event(&mut self, event: &Event<()>) {
+        self.event_log
+            .record_event(self.start_time.elapsed(), event);
+    }
+
+    fn update_title(&self) {
+        let mut title = WINDOW_TITLE.to_string();
+        if self.show_cursor_readout {
+            title.push_str(" | ");
+            title.push_str(&self.cursor_readout());
+        }
+        self.window.set_title(&title);
+    }
+
+    // Lasts until the camera or picking state next calls for a different
+    // cursor.
+    pub fn set_cursor_icon(&self, icon: CursorIcon) {
+        self.window.set_cursor_icon(icon);
+    }
+
+    // The cursor for what the mouse does right now: hidden while it steers
+    // the first-person camera, a grabbing hand while it drags the orbit
+    // camera, a crosshair while the readout picks what's under it, and an
+    // open hand over the orbit camera otherwise.
+    fn cursor_icon(&self) -> Option<CursorIcon> {
+        let dragging = self.camera_controller.is_dragging();
+        match self.camera_mode {
+            CameraMode::FirstPerson if dragging => None,
+            CameraMode::Orbit if dragging => Some(CursorIcon::Grabbing),
+            _ if self.show_cursor_readout => Some(CursorIcon::Crosshair),
+            CameraMode::Orbit => Some(CursorIcon::Grab),
+            CameraMode::FirstPerson => Some(CursorIcon::Default),
+        }
+    }
+
+    fn update_cursor(&mut self) {
+        let cursor = self.cursor_icon();
+        if cursor == self.cursor {
+            return;
+        }
+        if let Some(icon) = cursor {
+            self.set_cursor_icon(icon);
+        }
+        self.window.set_cursor_visible(cursor.is_some());
+        self.cursor = cursor;
+    }
+
+    fn mode_clear_colour(&self, now: Duration) -> wgpu::Color {
+        match self.clear_mode {
+            ClearMode::Fixed => FIXED_CLEAR_COLOUR,
+            ClearMode::Cursor => self.cursor_colour,
+            ClearMode::Rainbow => rainbow_colour(now),
+        }
+    }
+
+    fn update_clear_colour(&mut self) {
+        let now = self.start_time.elapsed();
+        let target = self.mode_clear_colour(now);
+        let blend = self
+            .clear_transition
+            .as_ref()
+            .map(|transition| (transition.from, transition.progress(now)));
+        self.clear_colour = match blend {
+            Some((from, t)) if t < 1.0 => lerp_colour(from, target, t),
+            _ => {
+                self.clear_transition = None;
+                target
+            }
+        };
+    }
+
+    fn set_clear_mode(&mut self, clear_mode: ClearMode) {
+        // Starting from the colour currently on screen means switching again
+        // mid-blend carries on smoothly rather than jumping.
+        self.clear_transition = Some(ClearTransition {
+            from: self.clear_colour,
+            start: self.start_time.elapsed(),
+            duration: self.run_config.clear_mode_transition,
+        });
+        self.clear_mode = clear_mode;
+        println!("Clear mode: {clear_mode:?}");
+    }
+
+    // Puts every runtime toggle back to the state a fresh launch starts in,
+    // including the scene geometry and any specialised shader constants.
+    pub fn reset_to_defaults(&mut self) {
+        self.mesh_job = None;
+        self.pending_uploads.clear();
+        self.disc_segments = DEFAULT_DISC_SEGMENTS;
+        self.show_quad = false;
+        self.active_model = 0;
+        self.frame_model = false;
+        self.selected_instance = None;
+        self.instances = Instance::grid();
+        self.rebuild_instance_buffers();
+        for material in self.uv_scrolls.keys().copied().collect::<Vec<_>>() {
+            self.set_uv_scroll(material, [0.0; 2]);
+        }
+        self.request_scene_model();
+
+        self.camera = Camera::new(self.camera.aspect);
+        self.camera_goal = Camera::new(self.camera.aspect);
+        self.next_viewpoint = 1;
+        self.camera_mode = CameraMode::FirstPerson;
+        self.set_pointer_lock(false);
+        self.rebuild_camera_controller();
+
+        self.parallax = true;
+        self.parallax_steps = DEFAULT_PARALLAX_STEPS;
+        if !self.shader_constants.is_empty() {
+            self.shader_constants.clear();
+            self.rebuild_render_pipeline();
+        }
+
+        self.use_colour = true;
+        self.shader_transition = None;
+        self.wireframe = false;
+        self.overdraw_debug = false;
+        self.lights.set_ssao_settings(SsaoSettings::default());
+        self.show_point_sprites = false;
+        self.show_grass = false;
+        self.show_bounding_spheres = false;
+        self.show_skybox = true;
+        self.view_from_light = false;
+        self.split_screen = false;
+        self.show_cursor_readout = false;
+        self.cursor_world = None;
+        self.show_sprites = false;
+        self.perf_overlay.set_visible(false);
+        self.event_log.set_visible(false);
+        self.event_log.set_filter(EventFilter::All);
+        self.point_sprites
+            .set_point_size(&self.queue, DEFAULT_POINT_SIZE);
+        let tonemap = self.post_process.tonemap_mut();
+        tonemap.set_operator(&self.queue, self.run_config.tonemapper);
+        tonemap.set_exposure(&self.queue, self.run_config.exposure);
+        self.post_process.color_grading_mut().reset();
+        self.anti_aliasing = AntiAliasing::from_config(&self.run_config);
+        self.configure_anti_aliasing(true, self.run_config.fxaa, self.run_config.taa);
+        if let (Some(depth_of_field), Some(settings)) = (
+            self.post_process.depth_of_field_mut(),
+            self.run_config.depth_of_field,
+        ) {
+            depth_of_field.set_settings(settings);
+        }
+        self.set_clear_mode(DEFAULT_CLEAR_MODE);
+        self.clear_transition = None;
+        self.cursor_colour = FIXED_CLEAR_COLOUR;
+        self.clear_colour = FIXED_CLEAR_COLOUR;
+        self.update_title();
+
+        println!("Reset to defaults");
+    }
+
+    fn toggle_wireframe(&mut self) {
+        if self.wireframe_pipeline.is_none() {
+            eprintln!(
+                "Wireframe rendering needs POLYGON_MODE_LINE, which the adapter doesn't support"
+            );
+            return;
+        }
+        self.wireframe = !self.wireframe;
+        println!("Wireframe: {}", self.wireframe);
+    }
+
+    fn toggle_camera_mode(&mut self) {
+        self.camera_mode = self.camera_mode.next();
+        self.rebuild_camera_controller();
+        println!("Camera: {:?}", self.camera_mode);
+    }
+
+    // Starts a controller for `camera_mode` from wherever the camera is now.
+    fn rebuild_camera_controller(&mut self) {
+        self.camera_controller =
+            self.camera_mode
+                .controller(&self.camera_goal, CAMERA_SPEED, CAMERA_SENSITIVITY);
+        self.camera_controller
+            .set_pointer_locked(self.pointer_locked);
+    }
+
+    // Locking hides the cursor and keeps it in the window, and hands all mouse
+    // motion to the first-person camera.
+    fn set_pointer_lock(&mut self, locked: bool) {
+        if locked == self.pointer_locked {
+            return;
+        }
+
+        if locked {
+            // Not every platform can lock the cursor in place; confining it
+            // to the window still works, since the camera reads raw motion.
+            let grab = self
+                .window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined));
+            if let Err(e) = grab {
+                eprintln!("Failed to grab the cursor: {e}");
+                return;
+            }
+        } else if let Err(e) = self.window.set_cursor_grab(CursorGrabMode::None) {
+            eprintln!("Failed to release the cursor: {e}");
+        }
+        self.pointer_locked = locked;
+
+        if locked && self.camera_mode != CameraMode::FirstPerson {
+            self.camera_mode = CameraMode::FirstPerson;
+            self.rebuild_camera_controller();
+            println!("Camera: {:?}", self.camera_mode);
+        } else {
+            self.camera_controller.set_pointer_locked(locked);
+        }
+        self.update_cursor();
+        println!("Pointer lock: {locked}");
+    }
+
+    // Changes only the goal camera, so the zoom eases in with the rest of the
+    // camera smoothing.
+    fn scroll_zoom(&mut self, lines: f32) {
+        let goal = &mut self.camera_goal;
+        match self.run_config.scroll_zoom {
+            ScrollZoom::Dolly => {
+                let step = (goal.target - goal.eye).normalize() * lines * DOLLY_STEP;
+                let eye = goal.eye + step;
+                if eye.to_vec().magnitude() <= MAX_DOLLY_DISTANCE {
+                    goal.eye = eye;
+                    goal.target += step;
+                }
+            }
+            ScrollZoom::FieldOfView => {
+                goal.fovy = (goal.fovy - lines * FOV_STEP).clamp(FOV_RANGE.0, FOV_RANGE.1);
+                println!("Field of view: {:.0}°", goal.fovy);
+            }
+        }
+    }
+
+    fn fly_to_next_viewpoint(&mut self) {
+        let (eye, target) = CAMERA_VIEWPOINTS[self.next_viewpoint];
+        self.next_viewpoint = (self.next_viewpoint + 1) % CAMERA_VIEWPOINTS.len();
+        let viewpoint = Viewpoint {
+            eye: eye.into(),
+            target: target.into(),
+        };
+        self.camera_goal.fly_to(viewpoint, CAMERA_FLIGHT);
+        println!("Flying to {viewpoint:?}");
+    }
+
+    // Flies the camera round to look at the whole scene from one side, the
+    // way 1 to 7 do.
+    pub fn set_view(&mut self, view: PresetView) {
+        self.frame_scene(view.direction());
+        println!("{view:?} view");
+    }
+
+    fn toggle_projection(&mut self) {
+        self.camera.projection = self.camera.projection.next();
+        println!("Projection: {:?}", self.camera.projection);
+    }
+
+    // Key repeat sends more presses while Space is held; those are no-ops.
+    fn set_use_colour(&mut self, use_colour: bool) {
+        if self.use_colour == use_colour {
+            return;
+        }
+        let now = self.start_time.elapsed();
+        self.use_colour = use_colour;
+        self.shader_transition = Some(match &self.shader_transition {
+            Some(transition) => transition.reversed(now),
+            None => ShaderTransition::new(self.use_colour, now, self.run_config.shader_crossfade),
+        });
+    }
+
+    fn handle_input(&mut self, input: &InputEvent) -> bool {
+        match input {
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Tab),
+                state: ElementState::Pressed,
+            } => {
+                self.set_pointer_lock(!self.pointer_locked);
+                return true;
+            }
+            // Escape only releases the pointer here; unlocked it quits.
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Escape),
+                state: ElementState::Pressed,
+            } if self.pointer_locked => {
+                self.set_pointer_lock(false);
+                return true;
+            }
+            InputEvent::Focused(false) => self.set_pointer_lock(false),
+            _ => (),
+        }
+
+        // Picking doesn't consume the click, so the orbit camera still sees it
+        // and rotates around whatever was hit, or its target over the
+        // background.
+        if let InputEvent::MouseInput {
+            button: MouseButton::Left,
+            state: ElementState::Pressed,
+        } = input
+        {
+            let pick = self.pick_at_cursor();
+            self.report_pick(pick);
+            if let Some((hit, _)) = pick {
+                self.selected_instance = Some(hit.instance);
+            }
+            self.camera_controller
+                .set_orbit_pivot(pick.map(|(_, point)| point));
+        }
+
+        if self.camera_controller.process_input(input) {
+            return true;
+        }
+
+        match input {
+            InputEvent::MouseWheel(delta) => {
+                self.scroll_zoom(camera_controller::scroll_lines(delta));
+                true
+            }
+            InputEvent::CursorMoved { x, y } => {
+                self.cursor_position = Some(PhysicalPosition::new(*x, *y));
+                self.cursor_colour = wgpu::Color {
+                    r: *x,
+                    g: *y,
+                    a: 1.0,
+                    b: 1.0,
+                };
+                if self.show_cursor_readout {
+                    self.update_title();
+                }
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Space),
+                state,
+            } => {
+                self.set_use_colour(*state == ElementState::Released);
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Backspace),
+                state: ElementState::Pressed,
+            } => {
+                self.reset_to_defaults();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F1),
+                state: ElementState::Pressed,
+            } => {
+                self.perf_overlay
+                    .set_visible(!self.perf_overlay.is_visible());
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F2),
+                state: ElementState::Pressed,
+            } => {
+                self.event_log.set_visible(!self.event_log.is_visible());
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F3),
+                state: ElementState::Pressed,
+            } => {
+                let filter = self.event_log.filter().next();
+                self.event_log.set_filter(filter);
+                println!("Event log filter: {filter:?}");
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F4),
+                state: ElementState::Pressed,
+            } => {
+                let mut settings = self.lights.ssao_settings();
+                settings.enabled = !settings.enabled;
+                self.lights.set_ssao_settings(settings);
+                println!("SSAO: {}", settings.enabled);
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F5),
+                state: ElementState::Pressed,
+            } => {
+                self.set_anti_aliasing(self.anti_aliasing.next());
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F6),
+                state: ElementState::Pressed,
+            } => {
+                self.view_from_light = !self.view_from_light;
+                println!("View from light: {}", self.view_from_light);
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(key @ (NamedKey::ArrowLeft | NamedKey::ArrowRight)),
+                state: ElementState::Pressed,
+            } => {
+                self.cycle_model(if *key == NamedKey::ArrowRight { 1 } else { -1 });
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F7),
+                state: ElementState::Pressed,
+            } => {
+                self.toggle_lod_sphere();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F8),
+                state: ElementState::Pressed,
+            } => {
+                self.show_grass = !self.show_grass;
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Home),
+                state: ElementState::Pressed,
+            } => {
+                self.toggle_uv_scroll();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F11),
+                state: ElementState::Pressed,
+            } => {
+                self.toggle_parallax_wall();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F12),
+                state: ElementState::Pressed,
+            } => {
+                self.show_sprites = !self.show_sprites;
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::End),
+                state: ElementState::Pressed,
+            } => {
+                self.set_parallax(!self.parallax, self.parallax_steps);
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(key @ (NamedKey::ArrowUp | NamedKey::ArrowDown)),
+                state: ElementState::Pressed,
+            } => {
+                let steps = if *key == NamedKey::ArrowUp {
+                    self.parallax_steps * 2
+                } else {
+                    self.parallax_steps / 2
+                };
+                self.set_parallax(true, steps);
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Insert),
+                state: ElementState::Pressed,
+            } => {
+                self.spawn_at_cursor();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::Delete),
+                state: ElementState::Pressed,
+            } => {
+                self.toggle_selected_visibility();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F9),
+                state: ElementState::Pressed,
+            } => {
+                self.cycle_dump_target();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(NamedKey::F10),
+                state: ElementState::Pressed,
+            } => {
+                self.dump_selected_target();
+                true
+            }
+            InputEvent::Key {
+                key: Key::Named(key @ (NamedKey::PageUp | NamedKey::PageDown)),
+                state: ElementState::Pressed,
+            } => {
+                let scale = if *key == NamedKey::PageUp { 1.25 } else { 0.8 };
+                if let Some(depth_of_field) = self.post_process.depth_of_field_mut() {
+                    let focus_distance = depth_of_field.adjust_focus(scale);
+                    println!("Focus distance: {focus_distance}");
+                }
+                true
+            }
+            InputEvent::Key {
+                key: Key::Character(ch),
+                state: ElementState::Pressed,
+            } => match ch.as_str() {
+                "[" => {
+                    self.adjust_ambient_strength(-0.1);
+                    true
+                }
+                "]" => {
+                    self.adjust_ambient_strength(0.1);
+                    true
+                }
+                "c" => {
+                    self.set_clear_mode(self.clear_mode.next());
+                    true
+                }
+                "g" => {
+                    self.request_disc_mesh();
+                    true
+                }
+                "n" => {
+                    self.toggle_quad();
+                    true
+                }
+                "k" => {
+                    self.show_cursor_readout = !self.show_cursor_readout;
+                    self.cursor_world = None;
+                    self.update_title();
+                    true
+                }
+                "t" => {
+                    self.save_thumbnail();
+                    true
+                }
+                "w" => {
+                    self.toggle_wireframe();
+                    true
+                }
+                "h" => {
+                    self.overdraw_debug = !self.overdraw_debug;
+                    true
+                }
+                "o" => {
+                    self.show_point_sprites = !self.show_point_sprites;
+                    true
+                }
+                "`" => {
+                    self.show_bounding_spheres = !self.show_bounding_spheres;
+                    true
+                }
+                // "x" is taken by maximize in the event loop.
+                "\\" => {
+                    self.show_skybox = !self.show_skybox;
+                    true
+                }
+                "v" => {
+                    self.split_screen = !self.split_screen;
+                    true
+                }
+                "l" => {
+                    self.toggle_camera_mode();
+                    true
+                }
+                "u" => {
+                    self.toggle_projection();
+                    true
+                }
+                "y" => {
+                    self.fly_to_next_viewpoint();
+                    true
+                }
+                "1" | "2" | "3" | "4" | "5" | "6" | "7" => {
+                    let index = ch.parse::<usize>().unwrap() - 1;
+                    self.set_view(PresetView::ALL[index]);
+                    true
+                }
+                "j" => {
+                    let tonemap = self.post_process.tonemap_mut();
+                    let operator = tonemap.operator().next();
+                    tonemap.set_operator(&self.queue, operator);
+                    println!("Tonemapper: {operator:?}");
+                    true
+                }
+                "," | "." => {
+                    let step = if ch.as_str() == "," { 0.8 } else { 1.25 };
+                    let tonemap = self.post_process.tonemap_mut();
+                    let exposure = (tonemap.exposure() * step).clamp(1.0 / 64.0, 64.0);
+                    tonemap.set_exposure(&self.queue, exposure);
+                    println!("Exposure: {exposure}");
+                    true
+                }
+                "'" => {
+                    match self.post_process.color_grading_mut().next_lut() {
+                        Some(name) => println!("LUT: {name}"),
+                        None => println!("LUT: none"),
+                    }
+                    true
+                }
+                ";" => {
+                    let enabled = !self.post_process.fxaa_enabled();
+                    self.post_process.set_fxaa_enabled(enabled);
+                    println!("FXAA: {enabled}");
+                    true
+                }
+                "/" => {
+                    if let Some(depth_of_field) = self.post_process.depth_of_field_mut() {
+                        let mut settings = depth_of_field.settings();
+                        settings.autofocus = !settings.autofocus;
+                        depth_of_field.set_settings(settings);
+                        println!("Autofocus: {}", settings.autofocus);
+                    }
+                    true
+                }
+                "-" | "=" => {
+                    let step = if ch.as_str() == "-" { 0.8 } else { 1.25 };
+                    let point_size = (self.point_sprites.point_size() * step).clamp(2.0, 256.0);
+                    self.point_sprites.set_point_size(&self.queue, point_size);
+                    println!("Point size: {point_size}");
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn replay_input(&mut self, dt: Duration) {
+        let Some(playback) = &mut self.input_playback else {
+            return;
+        };
+
+        let due_events = playback.advance(dt);
+        if playback.is_finished() {
+            self.input_playback = None;
+            println!("Input playback finished");
+        }
+
+        for input in due_events {
+            match input {
+                InputEvent::Resized { width, height } => {
+                    let _ = self
+                        .window
+                        .request_inner_size(PhysicalSize::new(width, height));
+                }
+                InputEvent::Focused(_) => {
+                    eprintln!("Skipping input that can't be replayed: {input:?}");
+                }
+                _ => {
+                    self.handle_input(&input);
+                }
+            }
+        }
+    }
+
+    // One sphere per mesh instance, the same meshes and instances the culler
+    // tests.
+    fn update_bounding_spheres(&mut self) {
+        let spheres = self
+            .assets
+            .model(self.model)
+            .meshes
+            .iter()
+            .filter_map(|mesh| mesh.pick.sphere())
+            .flat_map(|sphere| {
+                self.instances
+                    .iter()
+                    .filter(|instance| instance.visible)
+                    .map(move |instance| sphere.transformed(&instance.model_matrix()))
+            })
+            .collect::<Vec<_>>();
+        self.bounding_spheres
+            .update(&self.device, &self.queue, &spheres);
+    }
+
+    fn toggle_quad(&mut self) {
+        self.show_quad = !self.show_quad;
+        self.mesh_job = None;
+        self.model_job = None;
+        if self.show_quad {
+            let premultiplied = self.config.alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied;
+            let model = Model::alpha_test(
+                &self.device,
+                &self.queue,
+                &self.material_bind_group_layout,
+                &mut self.assets,
+                premultiplied,
+            );
+            self.model = self.assets.insert_model(PROCEDURAL_MODEL, model);
+        } else {
+            self.upload_mesh(&MeshData::pentagon());
+        }
+    }
+
+    // Swaps the scene for a sphere drawn at whichever level of detail suits
+    // each instance's distance. Its finest level stands in for it everywhere
+    // but the main pass.
+    fn toggle_lod_sphere(&mut self) {
+        if self.active_lod_sphere().is_some() {
+            self.request_scene_model();
+            return;
+        }
+
+        self.mesh_job = None;
+        self.model_job = None;
+        self.show_quad = false;
+        let levels =
+            LOD_SPHERE_DETAIL.map(|(sectors, stacks)| MeshData::uv_sphere(sectors, stacks));
+        let model = Model::from_mesh(Mesh::new(&self.device, &levels[0], "LOD Sphere"));
+        self.model = self.assets.insert_model(LOD_SPHERE_MODEL, model);
+        let lod = LodMesh::new(&self.device, &levels, LOD_SWITCH_DISTANCES);
+        self.lod_sphere = Some((self.model, lod));
+        println!("Showing the LOD sphere");
+    }
+
+    // Queues a grid of tinted shapes wandering around their cells, thousands
+    // of sprites that `render` flushes in one draw.
+    fn draw_sprite_demo(&mut self) {
+        let time = self.start_time.elapsed().as_secs_f32();
+        let size = SPRITE_SPACING - SPRITE_DRIFT;
+        for row in 0..SPRITE_GRID {
+            for column in 0..SPRITE_GRID {
+                let phase = (row * SPRITE_GRID + column) as f32 * 0.37;
+                let x = column as f32 * SPRITE_SPACING + (time + phase).cos() * SPRITE_DRIFT;
+                let y = row as f32 * SPRITE_SPACING + (time * 1.3 + phase).sin() * SPRITE_DRIFT;
+                let colour = [
+                    column as f32 / SPRITE_GRID as f32,
+                    row as f32 / SPRITE_GRID as f32,
+                    0.5 + 0.5 * (time + phase).sin(),
+                    0.9,
+                ];
+                self.sprite_batch.draw(
+                    [x + SPRITE_DRIFT, y + SPRITE_DRIFT, size, size],
+                    sprite_batch::shape_uv_rect(row + column),
+                    colour,
+                );
+            }
+        }
+    }
+
+    // Swaps the scene for a wall of bricks whose mortar parallax sinks into
+    // the surface.
+    fn toggle_parallax_wall(&mut self) {
+        if self.parallax_wall == Some(self.model) {
+            self.request_scene_model();
+            return;
+        }
+
+        self.mesh_job = None;
+        self.model_job = None;
+        self.show_quad = false;
+        let model = Model::parallax_test(
+            &self.device,
+            &self.queue,
+            &self.material_bind_group_layout,
+            &mut self.assets,
+        );
+        self.model = self.assets.insert_model(PARALLAX_WALL_MODEL, model);
+        self.parallax_wall = Some(self.model);
+        println!("Showing the parallax wall");
+    }
+
+    fn set_parallax(&mut self, parallax: bool, steps: u32) {
+        self.parallax = parallax;
+        self.parallax_steps = steps.clamp(1, MAX_PARALLAX_STEPS);
+        let steps = if parallax { self.parallax_steps } else { 0 };
+        self.set_shader_constant("parallax_steps", steps as f64);
+        println!("Parallax steps: {steps}");
+    }
+
+    fn active_lod_sphere(&self) -> Option<&LodMesh> {
+        self.lod_sphere
+            .as_ref()
+            .filter(|(model, _)| *model == self.model)
+            .map(|(_, lod)| lod)
+    }
+
+    fn request_disc_mesh(&mut self) {
+        self.disc_segments = match self.disc_segments.checked_mul(4) {
+            Some(segments) if segments <= 16384 => segments,
+            _ => 16,
+        };
+        let segments = self.disc_segments;
+        println!("Generating disc with {segments} segments");
+
+        // Replacing the handle cancels any job still in flight for the
+        // previous segment count.
+        self.model_job = None;
+        self.mesh_job = Some(self.mesh_workers.submit(move || MeshData::disc(segments)));
+    }
+
+    fn upload_finished_meshes(&mut self) {
+        self.pending_uploads.extend(self.mesh_workers.poll());
+
+        // One upload per frame keeps large meshes from stalling a single frame.
+        let Some(finished) = self.pending_uploads.pop_front() else {
+            return;
+        };
+        if self.mesh_job.as_ref().map(MeshJob::id) != Some(finished.job_id) {
+            return;
+        }
+        self.mesh_job = None;
+        self.upload_mesh(&finished.data);
+    }
+
+    // Shows the active model, loading it in the background behind the
+    // placeholder if it isn't already in `assets`. With no models configured
+    // the placeholder stays.
+    fn request_scene_model(&mut self) {
+        self.mesh_job = None;
+        self.model_job = None;
+        let Some(file_name) = self.run_config.models.get(self.active_model) else {
+            self.model = self.placeholder_model;
+            return;
+        };
+
+        let path = model::resource_path(file_name);
+        if let Some(model) = self.assets.find_model(&path.to_string_lossy()) {
+            self.model = model;
+            return;
+        }
+
+        println!("Loading {} in the background", path.display());
+        self.model = self.placeholder_model;
+        self.model_job = Some(self.asset_loader.load_model(path));
+    }
+
+    fn upload_loaded_model(&mut self, loaded: LoadedModel) {
+        let is_current = self.model_job == Some(loaded.job_id);
+        if is_current {
+            self.model_job = None;
+        }
+
+        match loaded.result {
+            Ok(data) => {
+                let model = data.upload(
+                    &loaded.path.to_string_lossy(),
+                    &self.device,
+                    &self.queue,
+                    &self.material_bind_group_layout,
+                    &mut self.assets,
+                );
+                let model = self
+                    .assets
+                    .insert_model(&loaded.path.to_string_lossy(), model);
+                log_model(&self.assets, model, &loaded.path);
+                if is_current {
+                    self.model = model;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to load {}: {e}", loaded.path.display());
+                if is_current {
+                    self.upload_mesh(&MeshData::pentagon());
+                }
+            }
+        }
+        if is_current {
+            self.frame_loaded_model();
+        }
+    }
+
+    // Steps forwards or backwards through `RunConfig::models`.
+    fn cycle_model(&mut self, step: isize) {
+        let count = self.run_config.models.len();
+        if count == 0 {
+            println!("No models to cycle through");
+            return;
+        }
+        self.active_model = (self.active_model as isize + step).rem_euclid(count as isize) as usize;
+        self.show_quad = false;
+        self.frame_model = true;
+        self.request_scene_model();
+        self.frame_loaded_model();
+    }
+
+    fn frame_loaded_model(&mut self) {
+        if !self.frame_model || self.model_job.is_some() {
+            return;
+        }
+        self.frame_model = false;
+        self.frame_scene(self.camera_goal.target - self.camera_goal.eye);
+    }
+
+    // The box around every instance of every mesh being shown.
+    fn scene_bounds(&self) -> Option<picking::Aabb> {
+        self.assets
+            .model(self.model)
+            .meshes
+            .iter()
+            .filter_map(|mesh| mesh.pick.bounds())
+            .flat_map(|bounds| {
+                self.instances
+                    .iter()
+                    .filter(|instance| instance.visible)
+                    .map(move |instance| bounds.transformed(&instance.model_matrix()))
+            })
+            .reduce(|a, b| a.union(&b))
+    }
+
+    // Flies the camera to look along `direction` at the whole scene.
+    fn frame_scene(&mut self, direction: cgmath::Vector3<f32>) {
+        let Some(bounds) = self.scene_bounds() else {
+            return;
+        };
+        let centre = bounds.min.midpoint(bounds.max);
+        let radius = bounds.min.distance(bounds.max) / 2.0;
+        let viewpoint = self.camera_goal.framing(centre, radius, direction);
+        self.camera_goal.fly_to(viewpoint, CAMERA_FLIGHT);
+    }
+
+    fn upload_loaded_models(&mut self) {
+        for loaded in self.asset_loader.poll() {
+            self.upload_loaded_model(loaded);
+        }
+    }
+
+    // Blocks until the model being loaded in the background is on screen.
+    fn wait_for_model(&mut self) {
+        while self.model_job.is_some() {
+            let Some(loaded) = self.asset_loader.wait() else {
+                break;
+            };
+            self.upload_loaded_model(loaded);
+        }
+    }
+
+    fn upload_mesh(&mut self, mesh: &MeshData) {
+        let model = Model::from_mesh(Mesh::new(&self.device, mesh, "Scene Mesh"));
+        self.model = self.assets.insert_model(PROCEDURAL_MODEL, model);
+    }
+
+    fn scene_target<'a>(&'a self, view: &'a wgpu::TextureView) -> SceneTarget<'a> {
+        SceneTarget {
+            view,
+            multisampled: self.multisampled_framebuffer.as_ref(),
+            depth_view: &self.depth_texture.view,
+            clear_colour: true,
+            depth_clear: DepthClearPolicy::Clear(1.0),
+            gbuffer: self.gbuffer.as_ref(),
+        }
+    }
+
+    // Runs of neighbouring instances that aren't hidden, for passes that
+    // draw every instance without culling.
+    fn shown_instances(&self) -> Vec<Range<u32>> {
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        for (index, instance) in self.instances.iter().enumerate() {
+            if !instance.visible {
+                continue;
+            }
+            let index = index as u32;
+            match ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        ranges
+    }
+
+    // Hidden instances are left out of every pass, shadows included, rather
+    // than drawn transparent.
+    pub fn set_visible(&mut self, index: usize, visible: bool) {
+        if let Some(instance) = self.instances.get_mut(index) {
+            instance.visible = visible;
+        }
+    }
+
+    // Adds an instance of the current model at `world_pos`. The instance
+    // buffers are rebuilt to fit it; the new instance starts with no motion.
+    pub fn spawn_at(&mut self, world_pos: cgmath::Point3<f32>) {
+        self.instances.push(Instance {
+            position: world_pos.to_vec(),
+            rotation: cgmath::Quaternion::one(),
+            visible: true,
+        });
+        self.rebuild_instance_buffers();
+        println!(
+            "Spawned instance {} at ({:.2}, {:.2}, {:.2})",
+            self.instances.len() - 1,
+            world_pos.x,
+            world_pos.y,
+            world_pos.z
+        );
+    }
+
+    // Spawns where the cursor's pick ray hits the scene, or `SPAWN_DISTANCE`
+    // along it over the background.
+    fn spawn_at_cursor(&mut self) {
+        let Some(position) = self.cursor_position else {
+            return;
+        };
+        let Some(ray) = picking::Ray::from_screen(position, self.size, &self.camera) else {
+            return;
+        };
+        let point = match self.pick_at_cursor() {
+            Some((_, point)) => point,
+            None => ray.at(SPAWN_DISTANCE),
+        };
+        self.spawn_at(point);
+    }
+
+    // Scrolls `material`'s textures by `speed` repeats per second. A speed of
+    // zero stops the scroll and puts the textures back where they started.
+    pub fn set_uv_scroll(&mut self, material: Handle<Material>, speed: [f32; 2]) {
+        if speed == [0.0; 2] {
+            self.uv_scrolls.remove(&material);
+            if let Some(material) = self.assets.material(material) {
+                material.write_uv_scroll(&self.queue, &UvScrollUniform::default());
+            }
+            return;
+        }
+        self.uv_scrolls.entry(material).or_default().speed = speed;
+    }
+
+    fn toggle_uv_scroll(&mut self) {
+        let materials = self.assets.model(self.model).materials.clone();
+        if materials.is_empty() {
+            println!("The model has no materials to scroll");
+            return;
+        }
+        let scrolling = materials
+            .iter()
+            .any(|material| self.uv_scrolls.contains_key(material));
+        let speed = if scrolling { [0.0; 2] } else { UV_SCROLL_SPEED };
+        for material in materials {
+            self.set_uv_scroll(material, speed);
+        }
+        println!("UV scroll: {}", !scrolling);
+    }
+
+    fn update_uv_scrolls(&mut self, dt: Duration) {
+        for (&material, scroll) in &mut self.uv_scrolls {
+            scroll.advance(dt);
+            if let Some(material) = self.assets.material(material) {
+                material.write_uv_scroll(&self.queue, scroll);
+            }
+        }
+    }
+
+    fn rebuild_instance_buffers(&mut self) {
+        (self.instance_buffer, self.previous_instance_buffer) =
+            create_instance_buffers(&self.device, &self.instances);
+        self.instance_chunks = self
+            .run_config
+            .instance_chunk_size
+            .map(|chunk_size| ChunkedInstances::new(&self.instances, chunk_size));
+    }
+
+    fn toggle_selected_visibility(&mut self) {
+        let Some(index) = self.selected_instance else {
+            println!("Pick an instance to hide first");
+            return;
+        };
+        let visible = !self.instances[index].visible;
+        self.set_visible(index, visible);
+        println!("Instance {index} visible: {visible}");
+    }
+
+    // What the main view is drawn with, without TAA's jitter.
+    fn main_view_proj(&self) -> cgmath::Matrix4<f32> {
+        if self.view_from_light {
+            self.lights.shadow_map().light_view_proj()
+        } else {
+            self.camera.build_view_projection_matrix()
+        }
+    }
+
+    fn update_camera(&mut self) {
+        let mut camera_uniform = self.camera_uniform;
+        if self.view_from_light {
+            camera_uniform.set_view_proj(self.main_view_proj());
+        } else {
+            camera_uniform.update_view_proj(&self.camera);
+        }
+        if let Some(taa) = &self.taa {
+            let jitter = taa.jitter();
+            camera_uniform.jitter(cgmath::Vector2::new(
+                jitter.x * 2.0 / self.size.width as f32,
+                jitter.y * 2.0 / self.size.height as f32,
+            ));
+        }
+        if camera_uniform != self.camera_uniform {
+            self.camera_uniform = camera_uniform;
+            self.camera_buffer.write(&self.queue, &self.camera_uniform);
+        }
+    }
+
+    fn update(&mut self) {
+        let now = Instant::now();
+        let live_dt = now - self.last_update;
+        self.last_update = now;
+        self.perf_overlay.record_frame(live_dt);
+        // Playback steps by the recorded frames, so it matches what was
+        // recorded however fast this run renders.
+        let dt = match &mut self.input_playback {
+            Some(playback) => playback.next_delta(live_dt),
+            None => live_dt,
+        };
+
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(e) = recorder.advance(dt) {
+                eprintln!("Failed to record input: {e}");
+                self.input_recorder = None;
+            }
+        }
+        self.replay_input(dt);
+        self.upload_finished_meshes();
+        self.upload_loaded_models();
+        self.update_clear_colour();
+        self.update_uv_scrolls(dt);
+        self.update_cursor_world();
+
+        // Controllers sit out flights, then pick up from where the flight
+        // ended.
+        if self.camera_goal.is_flying() {
+            if self.camera_goal.update_flight(dt) {
+                self.rebuild_camera_controller();
+            }
+        } else {
+            self.camera_controller
+                .update_camera(&mut self.camera_goal, dt);
+        }
+        self.camera
+            .smooth_towards(&self.camera_goal, self.run_config.camera_smoothing, dt);
+        self.update_cursor();
+
+        let now = self.start_time.elapsed();
+        if self
+            .shader_transition
+            .as_ref()
+            .is_some_and(|transition| transition.is_finished(now))
+        {
+            self.shader_transition = None;
+        }
+
+        if let Some(on_update) = &mut self.run_config.on_update {
+            on_update(&mut UpdateContext {
+                queue: &self.queue,
+                elapsed: self.start_time.elapsed(),
+                lights: &mut self.lights,
+                user_uniform: &self.user_uniform_buffer,
+            });
+        }
+        self.lights.upload(&self.device, &self.queue);
+        self.lights.follow_camera(&self.queue, &self.camera);
+        // After the shadow cascades, which the light view looks through.
+        self.update_camera();
+        let view_proj = self.main_view_proj();
+        let direction = self.lights.directional().direction();
+        if let Some(light_shafts) = self.post_process.light_shafts_mut() {
+            light_shafts.update(&self.queue, view_proj, direction);
+        }
+        if self.show_bounding_spheres {
+            self.update_bounding_spheres();
+        }
+        if self.show_grass {
+            self.grass.update(&self.queue, self.start_time.elapsed());
+        }
+        if let Some((model, lod)) = &mut self.lod_sphere {
+            if *model == self.model {
+                lod.update(self.camera.eye, &self.instances);
+            }
+        }
+        self.post_process
+            .update(&self.queue, self.start_time.elapsed());
+        if let Some(depth_of_field) = self.post_process.depth_of_field_mut() {
+            // Lets an autofocus read that's finished call back.
+            self.device.poll(wgpu::Maintain::Poll);
+            depth_of_field.update(&self.queue, &self.camera);
+        }
+    }
+
+    // Casters are the same meshes and instances the main pass draws, before
+    // any culling: something off screen can still shadow what's on it.
+    fn draw_shadows(&self, encoder: &mut wgpu::CommandEncoder, frame_stats: &mut FrameStats) {
+        let meshes = &self.assets.model(self.model).meshes;
+        let instances = self.shown_instances();
+        let geometry = ShadowGeometry {
+            meshes,
+            instance_buffer: &self.instance_buffer,
+            instances: &instances,
+        };
+        self.lights.shadow_map().draw(encoder, geometry);
+        let point_shadows = self.lights.point_shadow_maps();
+        point_shadows.draw(encoder, geometry);
+
+        for _ in 0..CASCADE_COUNT + point_shadows.face_count() {
+            frame_stats.record_instanced_draws(meshes, &instances);
+        }
+    }
+
+    // Ambient occlusion is worked out for the main camera only; other views
+    // look up what it saw at the same point.
+    fn draw_ssao(&self, encoder: &mut wgpu::CommandEncoder, frame_stats: &mut FrameStats) {
+        let meshes = &self.assets.model(self.model).meshes;
+        let instances = self.shown_instances();
+        self.lights.ssao().draw(
+            encoder,
+            SsaoGeometry {
+                meshes,
+                instance_buffer: &self.instance_buffer,
+                instances: &instances,
+                camera_bind_group: self.camera_buffer.bind_group(),
+            },
+        );
+
+        frame_stats.record_instanced_draws(meshes, &instances);
+        frame_stats.record_draw(3, 1);
+        frame_stats.record_draw(3, 1);
+    }
+
+    // Motion is measured for the main camera, against where it and the
+    // instances were last frame.
+    fn draw_velocity(&self, encoder: &mut wgpu::CommandEncoder, frame_stats: &mut FrameStats) {
+        let Some(motion_blur) = self.post_process.motion_blur() else {
+            return;
+        };
+        motion_blur.update(&self.queue, self.main_view_proj(), self.previous_view_proj);
+        let meshes = &self.assets.model(self.model).meshes;
+        let instances = self.shown_instances();
+        motion_blur.draw_velocity(
+            encoder,
+            MotionBlurGeometry {
+                meshes,
+                instance_buffer: &self.instance_buffer,
+                previous_instance_buffer: &self.previous_instance_buffer,
+                instances: &instances,
+            },
+        );
+
+        frame_stats.record_instanced_draws(meshes, &instances);
+        frame_stats.record_draw(3, 1);
+    }
+
+    fn draw_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: SceneTarget,
+        use_colour: bool,
+        views: &[(Viewport, CameraId)],
+        frame_stats: &mut FrameStats,
+    ) {
+        // The G-buffer only has room for lit, filled meshes.
+        if let (Some(deferred), Some(gbuffer), true) = (
+            &self.deferred,
+            target.gbuffer,
+            use_colour && !self.wireframe,
+        ) {
+            self.draw_deferred(encoder, deferred, gbuffer, target, views, frame_stats);
+            return;
+        }
+
+        let mut render_pass = self.begin_scene_pass(encoder, &target);
+
+        // Every view draws into the same pass; the scissor keeps each one
+        // inside its own rectangle.
+        for &(viewport, camera) in views {
+            viewport.apply(&mut render_pass);
+            self.draw_view(&mut render_pass, use_colour, camera, frame_stats);
+        }
+    }
+
+    // Meshes go into the G-buffer for every view first, then each view is lit
+    // in one fullscreen draw. Light markers, the skybox and point sprites
+    // aren't lit, so they're drawn forward on top.
+    fn draw_deferred(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        deferred: &Deferred,
+        gbuffer: &GBuffer,
+        target: SceneTarget,
+        views: &[(Viewport, CameraId)],
+        frame_stats: &mut FrameStats,
+    ) {
+        {
+            let mut render_pass = deferred.begin_geometry_pass(encoder, gbuffer);
+            for &(viewport, camera) in views {
+                viewport.apply(&mut render_pass);
+                self.set_scene_bind_groups(&mut render_pass, camera);
+                self.draw_meshes(
+                    &mut render_pass,
+                    camera,
+                    Some(deferred.double_sided_geometry_pipeline()),
+                    frame_stats,
+                );
+            }
+        }
+
+        let mut render_pass = self.begin_scene_pass(encoder, &target);
+        for &(viewport, camera) in views {
+            viewport.apply(&mut render_pass);
+            self.set_scene_bind_groups(&mut render_pass, camera);
+            deferred.resolve(&mut render_pass, gbuffer);
+            frame_stats.record_draw(3, 1);
+            render_pass.set_bind_group(MATERIAL_GROUP, &self.default_material.bind_group, &[]);
+            self.draw_unlit(&mut render_pass, true, camera, frame_stats);
+        }
+    }
+
+    fn begin_scene_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        target: &SceneTarget<'a>,
+    ) -> wgpu::RenderPass<'a> {
+        let colour_load = if target.clear_colour {
+            wgpu::LoadOp::Clear(self.clear_colour)
+        } else {
+            wgpu::LoadOp::Load
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(match target.multisampled {
+                // The multisampled buffer is kept so a later pass can load it
+                // and draw on top; the resolve only covers what's stored.
+                Some(multisampled) => wgpu::RenderPassColorAttachment {
+                    view: multisampled,
+                    resolve_target: Some(target.view),
+                    ops: wgpu::Operations {
+                        load: colour_load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                },
+                None => wgpu::RenderPassColorAttachment {
+                    view: target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: colour_load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: target.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: target.depth_clear.load_op(),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+
+    fn view_proj(&self, camera: CameraId) -> cgmath::Matrix4<f32> {
+        match camera {
+            CameraId::Main => self.main_view_proj(),
+            CameraId::Overview => self.overview_camera.build_view_projection_matrix(),
+        }
+    }
+
+    fn camera_bind_group(&self, camera: CameraId) -> &wgpu::BindGroup {
+        match camera {
+            CameraId::Main => self.camera_buffer.bind_group(),
+            CameraId::Overview => self.overview_camera_buffer.bind_group(),
+        }
+    }
+
+    fn set_scene_bind_groups<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera: CameraId,
+    ) {
+        render_pass.set_bind_group(USER_UNIFORM_GROUP, &self.user_uniform_bind_group, &[]);
+        render_pass.set_bind_group(MATERIAL_GROUP, &self.default_material.bind_group, &[]);
+        render_pass.set_bind_group(CAMERA_GROUP, self.camera_bind_group(camera), &[]);
+        render_pass.set_bind_group(LIGHT_GROUP, self.lights.bind_group(), &[]);
+    }
+
+    fn draw_view<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        use_colour: bool,
+        camera: CameraId,
+        frame_stats: &mut FrameStats,
+    ) {
+        self.set_scene_bind_groups(render_pass, camera);
+        if use_colour {
+            // Wireframes show every mesh's triangles as they are, culling
+            // included.
+            let double_sided_pipeline = match &self.wireframe_pipeline {
+                Some(wireframe_pipeline) if self.wireframe => {
+                    render_pass.set_pipeline(wireframe_pipeline);
+                    None
+                }
+                _ => {
+                    render_pass.set_pipeline(&self.render_pipeline);
+                    Some(&self.double_sided_pipeline)
+                }
+            };
+            self.draw_meshes(render_pass, camera, double_sided_pipeline, frame_stats);
+        } else {
+            render_pass.set_pipeline(&self.render_pipeline2);
+            render_pass.draw(0..3, 0..1);
+            frame_stats.record_draw(3, 1);
+        }
+        self.draw_unlit(render_pass, use_colour, camera, frame_stats);
+    }
+
+    // The scene's meshes seen by `camera`, with whichever pipeline is set.
+    // Those with double-sided materials switch to `double_sided_pipeline` if
+    // there is one.
+    fn draw_meshes<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera: CameraId,
+        double_sided_pipeline: Option<&'a wgpu::RenderPipeline>,
+        frame_stats: &mut FrameStats,
+    ) {
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let frustum = Frustum::from_matrix(self.view_proj(camera));
+        if let Some(lod) = self.active_lod_sphere() {
+            let visible = culling::visible_instances(
+                &frustum,
+                &lod.levels()[0].pick,
+                &self.instances,
+                self.instance_chunks.as_ref(),
+                &mut frame_stats.cull,
+            );
+            render_pass.set_bind_group(MATERIAL_GROUP, &self.default_material.bind_group, &[]);
+            for (level, mesh) in lod.levels().iter().enumerate() {
+                for instances in lod.instances_at(level, &visible) {
+                    let instance_count = instances.len() as u32;
+                    render_pass.draw_mesh_instanced(mesh, instances);
+                    frame_stats.record_draw_indexed(mesh.num_elements, instance_count);
+                }
+            }
+            return;
+        }
+
+        let material = |mesh: &Mesh| {
+            mesh.material
+                .and_then(|material| self.assets.material(material))
+                .unwrap_or(&self.default_material)
+        };
+        let mut meshes = self
+            .assets
+            .model(self.model)
+            .meshes
+            .iter()
+            .collect::<Vec<_>>();
+        // Double-sided meshes go last, so their pipeline is only set once.
+        meshes.sort_by_key(|mesh| material(mesh).double_sided);
+        let mut double_sided_pipeline = double_sided_pipeline;
+        for mesh in meshes {
+            let material = material(mesh);
+            if material.double_sided {
+                if let Some(pipeline) = double_sided_pipeline.take() {
+                    render_pass.set_pipeline(pipeline);
+                }
+            }
+            let visible = culling::visible_instances(
+                &frustum,
+                &mesh.pick,
+                &self.instances,
+                self.instance_chunks.as_ref(),
+                &mut frame_stats.cull,
+            );
+            if visible.is_empty() {
+                continue;
+            }
+
+            render_pass.set_bind_group(MATERIAL_GROUP, &material.bind_group, &[]);
+            for instances in visible {
+                let instance_count = instances.len() as u32;
+                render_pass.draw_mesh_instanced(mesh, instances);
+                frame_stats.record_draw_indexed(mesh.num_elements, instance_count);
+            }
+        }
+    }
+
+    // Everything after the lit meshes: grass, light markers and the skybox
+    // with colour, then point sprites either way.
+    fn draw_unlit<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        use_colour: bool,
+        camera: CameraId,
+        frame_stats: &mut FrameStats,
+    ) {
+        if use_colour {
+            if self.show_grass {
+                self.grass.draw(render_pass, self.camera_bind_group(camera));
+                frame_stats.record_draw(
+                    GrassRenderer::VERTICES_PER_BLADE,
+                    self.grass.num_instances(),
+                );
+            }
+
+            let light_count = self.lights.point_light_count() + self.lights.spot_light_count();
+            if light_count > 0 {
+                render_pass.set_pipeline(&self.light_marker_pipeline);
+                render_pass.draw_mesh_instanced(&self.light_marker_mesh, 0..light_count);
+                frame_stats.record_draw_indexed(self.light_marker_mesh.num_elements, light_count);
+            }
+
+            if self.show_bounding_spheres {
+                self.bounding_spheres.draw(render_pass);
+                frame_stats.record_line_draw(
+                    self.bounding_spheres.ring_vertex_count(),
+                    self.bounding_spheres.sphere_count(),
+                );
+            }
+
+            // Drawn after the opaque geometry so the depth test skips every
+            // pixel it covers.
+            if self.show_skybox {
+                self.skybox
+                    .draw(render_pass, self.camera_bind_group(camera));
+                frame_stats.record_draw(3, 1);
+            }
+        }
+
+        if self.show_point_sprites {
+            self.point_sprites
+                .draw(render_pass, self.camera_bind_group(camera));
+            frame_stats.record_draw(
+                PointSpriteRenderer::VERTICES_PER_SPRITE,
+                self.point_sprites.num_instances(),
+            );
+        }
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let mut frame_stats = FrameStats::default();
+
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        let timing_gpu = self.perf_overlay.is_visible();
+        if let Some(gpu_timer) = self.gpu_timer.as_mut().filter(|_| timing_gpu) {
+            gpu_timer.begin(&mut encoder);
+        }
+        self.draw_shadows(&mut encoder, &mut frame_stats);
+        self.draw_ssao(&mut encoder, &mut frame_stats);
+        self.draw_velocity(&mut encoder, &mut frame_stats);
+        self.lights.cull(&mut encoder);
+
+        let scene_view = self.hdr_target.view();
+        match &self.shader_transition {
+            _ if self.overdraw_debug => {
+                let meshes = &self.assets.model(self.model).meshes;
+                let instances = self.shown_instances();
+                self.overdraw.draw(
+                    &mut encoder,
+                    &view,
+                    OverdrawGeometry {
+                        meshes,
+                        instance_buffer: &self.instance_buffer,
+                        instances: &instances,
+                        camera_bind_group: self.camera_buffer.bind_group(),
+                    },
+                );
+                frame_stats.record_instanced_draws(meshes, &instances);
+                frame_stats.record_draw(3, 1);
+            }
+            Some(transition) => {
+                let to_colour = transition.to_colour;
+                let factor = transition.progress(self.start_time.elapsed());
+                self.draw_scene(
+                    &mut encoder,
+                    self.scene_target(self.crossfade.outgoing_view()),
+                    !to_colour,
+                    &[(Viewport::full(self.size), CameraId::Main)],
+                    &mut frame_stats,
+                );
+                self.draw_scene(
+                    &mut encoder,
+                    self.scene_target(self.crossfade.incoming_view()),
+                    to_colour,
+                    &[(Viewport::full(self.size), CameraId::Main)],
+                    &mut frame_stats,
+                );
+                self.crossfade
+                    .draw(&self.queue, &mut encoder, scene_view, factor);
+                frame_stats.record_draw(3, 1);
+            }
+            None if self.split_screen => {
+                // The right pane shows the overview camera. It keeps the left
+                // pane's colour but starts from a fresh depth buffer.
+                let aspect = self.size.width as f32 / self.size.height as f32;
+                let [left, right] = split_viewports(self.size, aspect);
+                self.draw_scene(
+                    &mut encoder,
+                    self.scene_target(scene_view),
+                    self.use_colour,
+                    &[(left, CameraId::Main)],
+                    &mut frame_stats,
+                );
+                self.draw_scene(
+                    &mut encoder,
+                    SceneTarget {
+                        clear_colour: false,
+                        depth_clear: DepthClearPolicy::Clear(1.0),
+                        ..self.scene_target(scene_view)
+                    },
+                    !self.use_colour,
+                    &[(right, CameraId::Overview)],
+                    &mut frame_stats,
+                );
+            }
+            None => self.draw_scene(
+                &mut encoder,
+                self.scene_target(scene_view),
+                self.use_colour,
+                &[(Viewport::full(self.size), CameraId::Main)],
+                &mut frame_stats,
+            ),
+        }
+        if let Some(resolved_depth) = &self.resolved_depth {
+            self.depth_resolve.resolve(&mut encoder, resolved_depth);
+            frame_stats.record_draw(3, 1);
+        }
+        // The overdraw view is drawn straight to the surface.
+        if !self.overdraw_debug {
+            let scene = match &mut self.taa {
+                Some(taa) => {
+                    taa.update(&self.queue, self.previous_view_proj);
+                    frame_stats.record_draw(3, 1);
+                    taa.resolve(
+                        &self.device,
+                        &mut encoder,
+                        &self.hdr_target,
+                        &self.depth_texture.view,
+                        self.camera_buffer.bind_group(),
+                    )
+                }
+                None => &self.hdr_target,
+            };
+            let result = self.post_process.run(
+                &self.device,
+                &mut encoder,
+                scene,
+                &readable_depth(&self.resolved_depth, &self.depth_texture).view,
+                &self.post_targets,
+            );
+            self.hdr_presenter.draw(&mut encoder, result, &view);
+            for _ in 0..=self.post_process.effect_count() {
+                frame_stats.record_draw(3, 1);
+            }
+        }
+        if self.show_sprites {
+            self.draw_sprite_demo();
+            let count = self.sprite_batch.len() as u32;
+            self.sprite_batch
+                .flush(&self.device, &self.queue, &mut encoder, &view);
+            frame_stats.record_draw_indexed(count * SpriteBatch::INDICES_PER_SPRITE, 1);
+        }
+        // Over everything else, and left out of the stats it shows.
+        let mut overlay = TextBatch::default();
+        let gpu_stats = self.gpu_stats();
+        self.perf_overlay
+            .build(&mut overlay, &frame_stats, &gpu_stats);
+        self.event_log
+            .build(&mut overlay, self.config.height as f32);
+        self.text
+            .draw(&self.device, &self.queue, &mut encoder, &view, &overlay);
+        self.probe_focus(&mut encoder);
+        self.probe_cursor(&mut encoder);
+        if self.post_process.motion_blur().is_some() {
+            encoder.copy_buffer_to_buffer(
+                &self.instance_buffer,
+                0,
+                &self.previous_instance_buffer,
+                0,
+                self.instance_buffer.size(),
+            );
+        }
+        if let Some(gpu_timer) = self.gpu_timer.as_mut().filter(|_| timing_gpu) {
+            gpu_timer.end(&mut encoder);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        if let Some(depth_of_field) = self.post_process.depth_of_field_mut() {
+            depth_of_field.after_submit();
+        }
+        self.cursor_probe.map();
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.map();
+        }
+        self.previous_view_proj = self.main_view_proj();
+
+        Ok(())
+    }
+
+    // Picks up the latest GPU frame time and adds up the video memory
+    // estimate, but only while the perf overlay is there to show them.
+    fn gpu_stats(&mut self) -> GpuStats {
+        if !self.perf_overlay.is_visible() {
+            return GpuStats::default();
+        }
+        let frame_time = self.gpu_timer.as_mut().and_then(|gpu_timer| {
+            self.device.poll(wgpu::Maintain::Poll);
+            gpu_timer.poll()
+        });
+        GpuStats {
+            frame_time,
+            vram_bytes: self.vram_estimate(),
+        }
+    }
+
+    // Cached assets plus the scene's render targets and instance buffers.
+    // Shadow maps, the post effects' own targets, pipelines and uniform
+    // buffers are left out, so the real figure is somewhat higher.
+    fn vram_estimate(&self) -> u64 {
+        let (width, height) = (u64::from(self.config.width), u64::from(self.config.height));
+        let multisampled = self.multisampled_framebuffer.as_ref().map_or(0, |_| {
+            let texel = u64::from(SCENE_FORMAT.block_size(None).unwrap_or(4));
+            width * height * texel * u64::from(self.sample_count)
+        });
+        let targets = [&self.depth_texture.texture, self.hdr_target.texture()]
+            .into_iter()
+            .chain(
+                self.resolved_depth
+                    .as_ref()
+                    .map(|resolved| &resolved.depth().texture),
+            )
+            .chain(self.post_targets.targets().iter().map(HdrTarget::texture))
+            .chain(self.gbuffer.iter().flat_map(GBuffer::textures))
+            .map(texture_bytes)
+            .sum::<u64>();
+        self.assets.gpu_bytes()
+            + multisampled
+            + targets
+            + self.instance_buffer.size()
+            + self.previous_instance_buffer.size()
+    }
+
+    // With autofocus on, reads back the depth under the cursor so depth of
+    // field can focus there.
+    fn probe_focus(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(texel) = self.cursor_texel() else {
+            return;
+        };
+        let Some(depth_of_field) = self.post_process.depth_of_field_mut() else {
+            return;
+        };
+        let scene_depth = readable_depth(&self.resolved_depth, &self.depth_texture);
+        depth_of_field.probe_focus(encoder, scene_depth, texel);
+    }
+
+    // Records a read of the depth under the cursor for the readout, from the
+    // same scene depth as `probe_focus`.
+    fn probe_cursor(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.show_cursor_readout {
+            return;
+        }
+        let Some(texel) = self.cursor_texel() else {
+            return;
+        };
+        if self.cursor_probe.copy(
+            encoder,
+            readable_depth(&self.resolved_depth, &self.depth_texture),
+            texel.0,
+            texel.1,
+        ) {
+            self.cursor_probe_position = self.cursor_position;
+        }
+    }
+
+    // Picks up a finished read of the depth under the cursor and turns it
+    // back into a world position. Nothing was drawn where the depth is still
+    // the cleared 1.0.
+    fn update_cursor_world(&mut self) {
+        let Some(depth) = self.cursor_probe.poll() else {
+            return;
+        };
+        let Some(position) = self.cursor_probe_position else {
+            return;
+        };
+        self.cursor_world = if depth < 1.0 {
+            self.camera.screen_to_world(position, depth, self.size)
+        } else {
+            None
+        };
+        self.update_title();
+    }
+
+    // The depth texel under the cursor, kept inside the window.
+    fn cursor_texel(&self) -> Option<(u32, u32)> {
+        let position = self.cursor_position?;
+        Some((
+            (position.x.max(0.0) as u32).min(self.size.width.saturating_sub(1)),
+            (position.y.max(0.0) as u32).min(self.size.height.saturating_sub(1)),
+        ))
+    }
+
+    // Renders one frame into an offscreen target of the given size and returns
+    // its RGBA bytes. The camera takes on the target's aspect ratio for the
+    // capture, so a differently shaped target shows more or less of the scene
+    // rather than stretching it, and the ambient occlusion prepass and
+    // post-process targets are drawn at its size. All of them are put back for
+    // the window afterwards.
+    pub fn render_at(&mut self, width: u32, height: u32) -> Vec<u8> {
+        self.fit_main_view(PhysicalSize::new(width, height));
+        let pixels = self.draw_capture(width, height);
+        self.fit_main_view(self.size);
+        self.camera_buffer.write(&self.queue, &self.camera_uniform);
+        pixels
+    }
+
+    // Sizes everything drawn from the main camera for a target of `size`.
+    // The camera uniform is written without TAA's jitter.
+    fn fit_main_view(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.lights.resize(&self.device, size.width, size.height);
+        self.lights.follow_camera(&self.queue, &self.camera);
+        self.post_process
+            .resize(&self.device, size.width, size.height);
+        let mut camera_uniform = self.camera_uniform;
+        if self.view_from_light {
+            camera_uniform.set_view_proj(self.main_view_proj());
+        } else {
+            camera_uniform.update_view_proj(&self.camera);
+        }
+        self.camera_buffer.write(&self.queue, &camera_uniform);
+    }
+
+    fn draw_capture(&self, width: u32, height: u32) -> Vec<u8> {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_texture = Texture::create_depth_texture(
+            &self.device,
+            width,
+            height,
+            self.sample_count,
+            "Capture Depth Texture",
+        );
+        let resolved_depth = self.depth_resolve.target(&self.device, &depth_texture);
+        let multisampled_framebuffer = create_multisampled_framebuffer(
+            &self.device,
+            width,
+            height,
+            SCENE_FORMAT,
+            self.sample_count,
+        );
+        let hdr_target = self
+            .hdr_presenter
+            .create_target(&self.device, width, height);
+        let gbuffer = self
+            .deferred
+            .as_ref()
+            .map(|deferred| deferred.create_gbuffer(&self.device, width, height));
+        let post_targets = PostTargets::new(&self.device, &self.hdr_presenter, width, height);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Render Encoder"),
+            });
+        self.draw_shadows(&mut encoder, &mut FrameStats::default());
+        self.draw_ssao(&mut encoder, &mut FrameStats::default());
+        self.draw_velocity(&mut encoder, &mut FrameStats::default());
+        self.lights.cull(&mut encoder);
+        self.draw_scene(
+            &mut encoder,
+            SceneTarget {
+                view: hdr_target.view(),
+                multisampled: multisampled_framebuffer.as_ref(),
+                depth_view: &depth_texture.view,
+                clear_colour: true,
+                depth_clear: DepthClearPolicy::Clear(1.0),
+                gbuffer: gbuffer.as_ref(),
+            },
+            self.use_colour,
+            &[(
+                Viewport::full(PhysicalSize::new(width, height)),
+                CameraId::Main,
+            )],
+            &mut FrameStats::default(),
+        );
+        if let Some(resolved_depth) = &resolved_depth {
+            self.depth_resolve.resolve(&mut encoder, resolved_depth);
+        }
+        // A single frame has no history, so TAA is skipped.
+        let result = self.post_process.run(
+            &self.device,
+            &mut encoder,
+            &hdr_target,
+            &readable_depth(&resolved_depth, &depth_texture).view,
+            &post_targets,
+        );
+        self.hdr_presenter.draw(&mut encoder, result, &view);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        capture::read_texture_rgba8(&self.device, &self.queue, &texture)
+    }
+}
+
+// Backend validation layers catch API misuse with detailed messages but can
+// cost a large fraction of frame time, so they're on by default only in debug
+// builds. wgpu's own checks and naga's shader validation always run; this only
+// controls what is requested from the backend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationLevel {
+    Full,
+    Minimal,
+}
+
+impl Default for ValidationLevel {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            Self::Full
+        } else {
+            Self::Minimal
+        }
+    }
+}
+
+impl ValidationLevel {
+    fn instance_flags(self) -> wgpu::InstanceFlags {
+        match self {
+            Self::Full => wgpu::InstanceFlags::DEBUG | wgpu::InstanceFlags::VALIDATION,
+            Self::Minimal => wgpu::InstanceFlags::empty(),
+        }
+    }
+}
+
+// What the scroll wheel does when the camera controller doesn't use it
+// itself, as the orbit controller does for zooming.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScrollZoom {
+    // Moves the camera along its view direction.
+    #[default]
+    Dolly,
+    // Narrows or widens the field of view, leaving the camera in place.
+    FieldOfView,
+}
+
+// How lit meshes reach the scene. Deferred writes their materials to a
+// G-buffer and lights each pixel once afterwards, which scales better with
+// many lights and overlapping meshes but drops MSAA and transparency.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RenderPath {
+    #[default]
+    Forward,
+    Deferred,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LimitsProfile {
+    #[default]
+    Default,
+    Downlevel,
+    WebGl2,
+}
+
+impl LimitsProfile {
+    fn requested_limits(self) -> wgpu::Limits {
+        match self {
+            Self::Default => wgpu::Limits::default(),
+            Self::Downlevel => wgpu::Limits::downlevel_defaults(),
+            Self::WebGl2 => wgpu::Limits::downlevel_webgl2_defaults(),
+        }
+    }
+
+    // Requesting more than the adapter supports fails device creation, so the
+    // profile is clamped to what the adapter can actually provide.
+    fn limits_for(self, supported: &wgpu::Limits) -> wgpu::Limits {
+        let requested = self.requested_limits();
+        if requested.check_limits(supported) {
+            return requested;
+        }
+
+        eprintln!("Adapter can't meet the {self:?} limits profile, clamping to adapter limits");
+        wgpu::Limits {
+            max_texture_dimension_1d: requested
+                .max_texture_dimension_1d
+                .min(supported.max_texture_dimension_1d),
+            max_texture_dimension_2d: requested
+                .max_texture_dimension_2d
+                .min(supported.max_texture_dimension_2d),
+            max_texture_dimension_3d: requested
+                .max_texture_dimension_3d
+                .min(supported.max_texture_dimension_3d),
+            max_texture_array_layers: requested
+                .max_texture_array_layers
+                .min(supported.max_texture_array_layers),
+            max_bind_groups: requested.max_bind_groups.min(supported.max_bind_groups),
+            max_bindings_per_bind_group: requested
+                .max_bindings_per_bind_group
+                .min(supported.max_bindings_per_bind_group),
+            max_dynamic_uniform_buffers_per_pipeline_layout: requested
+                .max_dynamic_uniform_buffers_per_pipeline_layout
+                .min(supported.max_dynamic_uniform_buffers_per_pipeline_layout),
+            max_dynamic_storage_buffers_per_pipeline_layout: requested
+                .max_dynamic_storage_buffers_per_pipeline_layout
+                .min(supported.max_dynamic_storage_buffers_per_pipeline_layout),
+            max_sampled_textures_per_shader_stage: requested
+                .max_sampled_textures_per_shader_stage
+                .min(supported.max_sampled_textures_per_shader_stage),
+            max_samplers_per_shader_stage: requested
+                .max_samplers_per_shader_stage
+                .min(supported.max_samplers_per_shader_stage),
+            max_storage_buffers_per_shader_stage: requested
+                .max_storage_buffers_per_shader_stage
+                .min(supported.max_storage_buffers_per_shader_stage),
+            max_storage_textures_per_shader_stage: requested
+                .max_storage_textures_per_shader_stage
+                .min(supported.max_storage_textures_per_shader_stage),
+            max_uniform_buffers_per_shader_stage: requested
+                .max_uniform_buffers_per_shader_stage
+                .min(supported.max_uniform_buffers_per_shader_stage),
+            max_uniform_buffer_binding_size: requested
+                .max_uniform_buffer_binding_size
+                .min(supported.max_uniform_buffer_binding_size),
+            max_storage_buffer_binding_size: requested
+                .max_storage_buffer_binding_size
+                .min(supported.max_storage_buffer_binding_size),
+            max_vertex_buffers: requested
+                .max_vertex_buffers
+                .min(supported.max_vertex_buffers),
+            max_buffer_size: requested.max_buffer_size.min(supported.max_buffer_size),
+            max_vertex_attributes: requested
+                .max_vertex_attributes
+                .min(supported.max_vertex_attributes),
+            max_vertex_buffer_array_stride: requested
+                .max_vertex_buffer_array_stride
+                .min(supported.max_vertex_buffer_array_stride),
+            min_uniform_buffer_offset_alignment: requested
+                .min_uniform_buffer_offset_alignment
+                .max(supported.min_uniform_buffer_offset_alignment),
+            min_storage_buffer_offset_alignment: requested
+                .min_storage_buffer_offset_alignment
+                .max(supported.min_storage_buffer_offset_alignment),
+            max_inter_stage_shader_components: requested
+                .max_inter_stage_shader_components
+                .min(supported.max_inter_stage_shader_components),
+            max_compute_workgroup_storage_size: requested
+                .max_compute_workgroup_storage_size
+                .min(supported.max_compute_workgroup_storage_size),
+            max_compute_invocations_per_workgroup: requested
+                .max_compute_invocations_per_workgroup
+                .min(supported.max_compute_invocations_per_workgroup),
+            max_compute_workgroup_size_x: requested
+                .max_compute_workgroup_size_x
+                .min(supported.max_compute_workgroup_size_x),
+            max_compute_workgroup_size_y: requested
+                .max_compute_workgroup_size_y
+                .min(supported.max_compute_workgroup_size_y),
+            max_compute_workgroup_size_z: requested
+                .max_compute_workgroup_size_z
+                .min(supported.max_compute_workgroup_size_z),
+            max_compute_workgroups_per_dimension: requested
+                .max_compute_workgroups_per_dimension
+                .min(supported.max_compute_workgroups_per_dimension),
+            max_push_constant_size: requested
+                .max_push_constant_size
+                .min(supported.max_push_constant_size),
+            ..requested
+        }
+    }
+}
+
+pub struct RunConfig {
+    // How the compositor treats the window's alpha, if the surface supports
+    // it, otherwise the surface's first. Only PreMultiplied and
+    // PostMultiplied let anything behind the window show through; the rest
+    // draw it opaque whatever the alpha.
+    pub alpha_mode: Option<wgpu::CompositeAlphaMode>,
+    pub asset_cache_capacity: usize,
+    pub on_update: Option<UpdateCallback>,
+    pub headless: bool,
+    pub limits_profile: LimitsProfile,
+    pub validation: ValidationLevel,
+    pub hdr_output: bool,
+    pub msaa_samples: u32,
+    // Cycled with the left and right arrows, starting from the first. The
+    // placeholder is shown without any.
+    pub models: Vec<PathBuf>,
+    pub shader_crossfade: Duration,
+    pub clear_mode_transition: Duration,
+    // Time constant of the camera's damping. Zero makes it follow input
+    // exactly.
+    pub camera_smoothing: Duration,
+    pub scroll_zoom: ScrollZoom,
+    pub challenge_shader: Option<ShaderSource>,
+    // An equirectangular .hdr or .exr image to light the scene with. A plain
+    // sky is used without one.
+    pub environment: Option<PathBuf>,
+    pub render_path: RenderPath,
+    // WGSL fragment shaders run in order on the finished frame, each reading
+    // the last one's output. `fs_main` gets the uv at location 0 and the frame
+    // as `texture_2d<f32>` at group 0 binding 0 with a sampler at binding 1.
+    pub post_shaders: Vec<ShaderSource>,
+    // Glow around bright pixels, added before `post_shaders` run. `None`
+    // turns it off.
+    pub bloom: Option<BloomSettings>,
+    // Blur away from a focus distance, before bloom. Off by default.
+    pub depth_of_field: Option<DepthOfFieldSettings>,
+    // Blur along camera and object motion, after depth of field. Off by
+    // default.
+    pub motion_blur: Option<MotionBlurSettings>,
+    // Shafts of the directional light past whatever is in front of the sky,
+    // after motion blur.
+    pub light_shafts: Option<LightShaftSettings>,
+    // The billboard grass field shown with F8.
+    pub grass: GrassSettings,
+    // Darkening towards the edges and animated grain, after `post_shaders`.
+    // Both off by default. vignette.rs and film_grain.rs are small examples
+    // to start from when writing an effect in Rust.
+    pub vignette: Option<VignetteSettings>,
+    pub film_grain: Option<FilmGrainSettings>,
+    // Applied after `post_shaders`, right before the frame is presented.
+    pub tonemapper: Tonemapper,
+    pub exposure: f32,
+    // Colour grading LUTs, as .cube files or PNG strips, applied after
+    // tonemapping. The first is used to start with and the rest can be
+    // switched to at runtime to compare looks.
+    pub color_luts: Vec<PathBuf>,
+    // Smooths edges after tonemapping. Cheaper than MSAA, and the only
+    // anti-aliasing the deferred path gets.
+    pub fxaa: bool,
+    // Temporal anti-aliasing, from jittering the main camera and blending
+    // each frame into the last. Turns MSAA off.
+    pub taa: bool,
+    // Instances are grouped into cubes this wide, and a cube out of view is
+    // culled whole before its instances are tested. `None` tests every
+    // instance.
+    pub instance_chunk_size: Option<f32>,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            alpha_mode: None,
+            asset_cache_capacity: 32,
+            on_update: None,
+            headless: false,
+            limits_profile: LimitsProfile::default(),
+            validation: ValidationLevel::default(),
+            hdr_output: false,
+            msaa_samples: 4,
+            models: vec![PathBuf::from("cube.obj")],
+            shader_crossfade: Duration::from_millis(500),
+            clear_mode_transition: Duration::from_millis(300),
+            camera_smoothing: Duration::from_millis(80),
+            scroll_zoom: ScrollZoom::default(),
+            challenge_shader: None,
+            environment: None,
+            render_path: RenderPath::default(),
+            post_shaders: Vec::new(),
+            bloom: Some(BloomSettings::default()),
+            depth_of_field: None,
+            motion_blur: None,
+            light_shafts: Some(LightShaftSettings::default()),
+            grass: GrassSettings::default(),
+            vignette: None,
+            film_grain: None,
+            tonemapper: Tonemapper::default(),
+            exposure: 1.0,
+            color_luts: Vec::new(),
+            fxaa: false,
+            taa: false,
+            instance_chunk_size: Some(6.0),
+        }
+    }
+}
+
+// Renders a single frame at `size` and saves it as a PNG without entering the
+// event loop. With `headless` set the window is created hidden and never shown.
+pub async fn run_screenshot(
+    config: RunConfig,
+    size: PhysicalSize<u32>,
+    output_path: impl AsRef<Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    SimpleLogger::new().init().unwrap();
+    let event_loop = EventLoop::new()?;
+
+    let window = WindowBuilder::new()
+        .with_title(WINDOW_TITLE)
+        .with_inner_size(size)
+        .with_visible(!config.headless)
+        .build(&event_loop)?;
+
+    let mut state = State::new(window, config).await;
+    // The capture is rendered in the surface's format, so e.g. an HDR surface
+    // can't be saved as an 8-bit PNG.
+    if !capture::can_read_rgba8(state.config.format) {
+        return Err(format!(
+            "can't save a screenshot from a {:?} surface",
+            state.config.format
+        )
+        .into());
+    }
+    state.wait_for_model();
+    state.update();
+    let pixels = state.render_at(size.width, size.height);
+
+    image::save_buffer(
+        output_path,
+        &pixels,
+        size.width,
+        size.height,
+        image::ColorType::Rgba8,
+    )?;
+
+    Ok(())
+}
+
+pub async fn run(config: RunConfig) -> Result<(), impl std::error::Error> {
+    SimpleLogger::new().init().unwrap();
+    let event_loop = EventLoop::new().unwrap();
+
+    let mut decorations = true;
+    let mut minimized = false;
+    let mut maximized = false;
+    let mut with_min_size = false;
+    let mut with_max_size = false;
+
+    let window = WindowBuilder::new()
+        .with_title(WINDOW_TITLE)
+        .with_inner_size(winit::dpi::LogicalSize::new(128.0, 128.0))
+        .build(&event_loop)
         .unwrap();
 
-    let mut monitor = event_loop
+    let monitor = event_loop
         .available_monitors()
         .next()
         .expect("No monitor found!");
@@ -350,121 +4145,134 @@ pub async fn run() -> Result<(), impl std::error::Error> {
         .expect("No fullscreen mode found");
     println!("Mode: {mode}");
 
-    let mut state = State::new(window).await;
+    let mut state = State::new(window, config).await;
 
     event_loop.run(move |event, elwt| {
-        println!("{event:?}");
+        state.log_event(&event);
 
         match event {
             Event::WindowEvent {
                 window_id,
                 ref event,
-            } if window_id == state.window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested => elwt.exit(),
-
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    logical_key: key,
-                                    ..
-                                },
+            } if window_id == state.window().id() && !state.input(event) => match event {
+                WindowEvent::CloseRequested => elwt.exit(),
+
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            state: ElementState::Pressed,
+                            logical_key: key,
                             ..
-                        } => match key {
-                            Key::Named(NamedKey::Escape) => elwt.exit(),
-                            Key::Character(ch) => match ch.to_lowercase().as_str() {
-                                "f" | "b" if state.window.fullscreen().is_some() => {
-                                    state.window.set_fullscreen(None);
-                                }
-                                "f" => {
-                                    let fullscreen = Some(Fullscreen::Exclusive(mode.clone()));
-                                    println!("Setting mode: {fullscreen:?}");
-                                    state.window.set_fullscreen(fullscreen);
-                                }
-                                "b" => {
-                                    let fullscreen =
-                                        Some(Fullscreen::Borderless(Some(monitor.clone())));
-                                    println!("Setting mode: {fullscreen:?}");
-                                    state.window.set_fullscreen(fullscreen);
-                                }
-                                "m" => {
-                                    mode_index += 1;
-                                    if let Some(m) = monitor.video_modes().nth(mode_index) {
-                                        mode = m;
-                                    } else {
-                                        mode_index = 0;
-                                        mode = monitor
-                                            .video_modes()
-                                            .next()
-                                            .expect("No fullscreen mode found");
-                                    }
-                                    println!("Mode: {mode}");
-                                }
-                                "d" => {
-                                    decorations = !decorations;
-                                    state.window.set_decorations(decorations);
-                                }
-                                "x" => {
-                                    maximized = !maximized;
-                                    state.window.set_maximized(maximized);
-                                }
-                                "z" => {
-                                    minimized = !minimized;
-                                    state.window.set_minimized(minimized);
-                                }
-                                "i" => {
-                                    with_min_size = !with_min_size;
-                                    let min_size = if with_min_size {
-                                        Some(PhysicalSize::new(100, 100))
-                                    } else {
-                                        None
-                                    };
-
-                                    state.window.set_min_inner_size(min_size);
-                                    eprintln!(
-                                        "Min: {with_min_size}: {min_size:?} => {:?}",
-                                        state.window.inner_size()
-                                    );
-                                }
-                                "a" => {
-                                    with_max_size = !with_max_size;
-                                    let max_size = if with_max_size {
-                                        Some(PhysicalSize::new(200, 200))
-                                    } else {
-                                        None
-                                    };
-
-                                    state.window.set_max_inner_size(max_size);
-                                    eprintln!(
-                                        "Max: {with_max_size}: {max_size:?} => {:?}",
-                                        state.window.inner_size()
-                                    );
-                                }
-                                _ => (),
-                            },
-                            _ => (),
                         },
-
-                        WindowEvent::RedrawRequested => {
-                            state.update();
-                            match state.render() {
-                                Ok(_) => {}
-                                Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
-                                Err(e) => eprintln!("{:?}", e),
+                    ..
+                } => match key {
+                    Key::Named(NamedKey::Escape) => elwt.exit(),
+                    Key::Character(ch) => match ch.to_lowercase().as_str() {
+                        "f" | "b" if state.window.fullscreen().is_some() => {
+                            state.window.set_fullscreen(None);
+                        }
+                        "f" => {
+                            let fullscreen = Some(Fullscreen::Exclusive(mode.clone()));
+                            println!("Setting mode: {fullscreen:?}");
+                            state.window.set_fullscreen(fullscreen);
+                        }
+                        "b" => {
+                            let fullscreen = Some(Fullscreen::Borderless(Some(monitor.clone())));
+                            println!("Setting mode: {fullscreen:?}");
+                            state.window.set_fullscreen(fullscreen);
+                        }
+                        "m" => {
+                            mode_index += 1;
+                            if let Some(m) = monitor.video_modes().nth(mode_index) {
+                                mode = m;
+                            } else {
+                                mode_index = 0;
+                                mode = monitor
+                                    .video_modes()
+                                    .next()
+                                    .expect("No fullscreen mode found");
+                            }
+                            println!("Mode: {mode}");
+                        }
+                        "d" => {
+                            decorations = !decorations;
+                            state.window.set_decorations(decorations);
+                        }
+                        "x" => {
+                            maximized = !maximized;
+                            state.window.set_maximized(maximized);
+                        }
+                        "z" => {
+                            minimized = !minimized;
+                            state.window.set_minimized(minimized);
+                        }
+                        "r" if state.input_recorder.is_some() => {
+                            match state.stop_recording_input() {
+                                Ok(()) => println!("Saved input to {INPUT_RECORDING_PATH}"),
+                                Err(e) => eprintln!("Failed to save input: {e}"),
                             }
-                            state.window.pre_present_notify();
                         }
+                        "r" => match state.start_recording_input(INPUT_RECORDING_PATH) {
+                            Ok(()) => println!("Recording input to {INPUT_RECORDING_PATH}"),
+                            Err(e) => eprintln!("Failed to start recording: {e}"),
+                        },
+                        "p" => match state.play_input(INPUT_RECORDING_PATH) {
+                            Ok(()) => println!("Playing input from {INPUT_RECORDING_PATH}"),
+                            Err(e) => eprintln!("Failed to play input: {e}"),
+                        },
+                        "i" => {
+                            with_min_size = !with_min_size;
+                            let min_size = if with_min_size {
+                                Some(PhysicalSize::new(100, 100))
+                            } else {
+                                None
+                            };
 
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
+                            state.window.set_min_inner_size(min_size);
+                            eprintln!(
+                                "Min: {with_min_size}: {min_size:?} => {:?}",
+                                state.window.inner_size()
+                            );
                         }
+                        "a" => {
+                            with_max_size = !with_max_size;
+                            let max_size = if with_max_size {
+                                Some(PhysicalSize::new(200, 200))
+                            } else {
+                                None
+                            };
 
+                            state.window.set_max_inner_size(max_size);
+                            eprintln!(
+                                "Max: {with_max_size}: {max_size:?} => {:?}",
+                                state.window.inner_size()
+                            );
+                        }
                         _ => (),
+                    },
+                    _ => (),
+                },
+
+                WindowEvent::RedrawRequested => {
+                    state.update();
+                    match state.render() {
+                        Ok(_) => {}
+                        Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
+                        Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
+                        Err(e) => eprintln!("{:?}", e),
                     }
+                    state.window.pre_present_notify();
                 }
+
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                }
+
+                _ => (),
+            },
+
+            Event::DeviceEvent { ref event, .. } => {
+                state.device_input(event);
             }
 
             Event::AboutToWait => {
@@ -475,3 +4283,51 @@ pub async fn run() -> Result<(), impl std::error::Error> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_vertices_and_triangles_per_draw() {
+        let mut stats = FrameStats::default();
+        stats.record_draw(3, 1);
+        stats.record_draw(6, 4);
+
+        assert_eq!(stats.draw_calls, 2);
+        assert_eq!(stats.vertices, 3 + 24);
+        assert_eq!(stats.indices, 0);
+        assert_eq!(stats.triangles, 1 + 8);
+    }
+
+    #[test]
+    fn counts_indexed_instanced_triangles_per_instance() {
+        // A cube of 36 indices, drawn as runs of 10 and 5 instances with a
+        // culled gap between them, alongside a 6-index quad.
+        let mut stats = FrameStats::default();
+        stats.record_instanced_index_counts([36, 6], &[0..10, 12..17]);
+
+        assert_eq!(stats.draw_calls, 4);
+        assert_eq!(stats.indices, 36 * 15 + 6 * 15);
+        assert_eq!(stats.triangles, (36 / 3) * 15 + (6 / 3) * 15);
+        assert_eq!(stats.vertices, 0);
+    }
+
+    #[test]
+    fn each_frame_starts_from_zero() {
+        // `render` starts every frame from a fresh `FrameStats`, so nothing
+        // carries over from the frame before.
+        let mut last_frame = FrameStats::default();
+        last_frame.record_draw(3, 1);
+        last_frame.record_line_draw(2, 1);
+        last_frame.record_instanced_index_counts([36], &[0..4, 6..8]);
+        assert_ne!(last_frame, FrameStats::default());
+
+        let mut frame = FrameStats::default();
+        frame.record_draw_indexed(6, 1);
+        assert_eq!(frame.draw_calls, 1);
+        assert_eq!(frame.indices, 6);
+        assert_eq!(frame.vertices, 0);
+        assert_eq!(frame.triangles, 2);
+    }
+}
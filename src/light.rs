@@ -0,0 +1,731 @@
+use cgmath::InnerSpace;
+
+use crate::{
+    camera::Camera,
+    environment::Environment,
+    light_clusters::LightClusters,
+    shadow::{PointShadowMaps, ShadowMap, MAX_SHADOWED_POINT_LIGHTS},
+    ssao::{Ssao, SsaoSettings},
+};
+
+// Matches `Light` in shader.wgsl: a directional light, like the sun, shining
+// along `direction` everywhere in the scene, plus how many point and spot
+// lights are in use.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    direction: [f32; 3],
+    intensity: f32,
+    colour: [f32; 3],
+    point_light_count: u32,
+    spot_light_count: u32,
+    _padding: [u32; 3],
+}
+
+impl LightUniform {
+    pub fn new(direction: cgmath::Vector3<f32>, colour: [f32; 3], intensity: f32) -> Self {
+        Self {
+            direction: direction.normalize().into(),
+            intensity,
+            colour,
+            point_light_count: 0,
+            spot_light_count: 0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+impl LightUniform {
+    pub fn direction(&self) -> cgmath::Vector3<f32> {
+        self.direction.into()
+    }
+}
+
+impl Default for LightUniform {
+    // Warm white, from above and a little to the front right.
+    fn default() -> Self {
+        Self::new(
+            cgmath::Vector3::new(-0.4, -1.0, -0.6),
+            [1.0, 0.96, 0.9],
+            1.0,
+        )
+    }
+}
+
+// Falloff of 1 / (1 + linear * d + quadratic * d²) suits lights a few units
+// across.
+const DEFAULT_ATTENUATION: [f32; 2] = [0.35, 0.44];
+
+// Matches `PointLight` in shader.wgsl. Light falls off as
+// 1 / (1 + linear * d + quadratic * d²), and is faded out to nothing at
+// `radius` so lights only need to be considered within it. Shadows are off by
+// default, as each shadowed light renders the scene six more times a frame.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    pub radius: f32,
+    pub colour: [f32; 3],
+    pub intensity: f32,
+    // (linear, quadratic)
+    pub attenuation: [f32; 2],
+    casts_shadows: u32,
+    // Which set of faces in the point shadow maps is this light's, or -1.
+    // Assigned by `Lights::upload`.
+    shadow_index: i32,
+}
+
+impl PointLight {
+    pub fn new(position: [f32; 3], colour: [f32; 3], intensity: f32, radius: f32) -> Self {
+        Self {
+            position,
+            radius,
+            colour,
+            intensity,
+            attenuation: DEFAULT_ATTENUATION,
+            casts_shadows: 0,
+            shadow_index: -1,
+        }
+    }
+
+    pub fn casts_shadows(&self) -> bool {
+        self.casts_shadows != 0
+    }
+
+    // Only the first `MAX_SHADOWED_POINT_LIGHTS` lights to ask for shadows
+    // get them.
+    pub fn set_casts_shadows(&mut self, casts_shadows: bool) {
+        self.casts_shadows = casts_shadows.into();
+    }
+}
+
+// Matches `SpotLight` in shader.wgsl. A point light limited to a cone around
+// `direction`: full strength inside the inner angle, fading to nothing at the
+// outer angle. Both angles are measured from the cone's axis.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpotLight {
+    pub position: [f32; 3],
+    pub radius: f32,
+    direction: [f32; 3],
+    pub intensity: f32,
+    pub colour: [f32; 3],
+    // The shader compares against cosines, so they're stored that way.
+    inner_cos: f32,
+    // (linear, quadratic)
+    pub attenuation: [f32; 2],
+    outer_cos: f32,
+    _padding: f32,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: [f32; 3],
+        direction: cgmath::Vector3<f32>,
+        colour: [f32; 3],
+        intensity: f32,
+        radius: f32,
+        inner_angle: cgmath::Deg<f32>,
+        outer_angle: cgmath::Deg<f32>,
+    ) -> Self {
+        let mut light = Self {
+            position,
+            radius,
+            direction: [0.0, -1.0, 0.0],
+            intensity,
+            colour,
+            inner_cos: 1.0,
+            attenuation: DEFAULT_ATTENUATION,
+            outer_cos: 1.0,
+            _padding: 0.0,
+        };
+        light.set_direction(direction);
+        light.set_cone(inner_angle, outer_angle);
+        light
+    }
+
+    pub fn set_direction(&mut self, direction: cgmath::Vector3<f32>) {
+        self.direction = direction.normalize().into();
+    }
+
+    // The outer angle is kept at least as wide as the inner one.
+    pub fn set_cone(&mut self, inner_angle: cgmath::Deg<f32>, outer_angle: cgmath::Deg<f32>) {
+        let inner = cgmath::Rad::from(inner_angle).0;
+        let outer = cgmath::Rad::from(outer_angle).0.max(inner);
+        self.inner_cos = inner.cos();
+        self.outer_cos = outer.cos();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PointLightId(u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpotLightId(u64);
+
+// The buffers every light is uploaded into.
+#[derive(Clone, Copy)]
+pub struct LightBuffers<'a> {
+    pub uniform: &'a wgpu::Buffer,
+    pub point_lights: &'a wgpu::Buffer,
+    pub spot_lights: &'a wgpu::Buffer,
+}
+
+// Everything the lighting pass samples besides the light buffers.
+struct LightTextures<'a> {
+    shadow_map: &'a ShadowMap,
+    point_shadow_maps: &'a PointShadowMaps,
+    environment: &'a Environment,
+    ssao: &'a Ssao,
+}
+
+// Lights of one kind and the storage buffer they're uploaded into.
+struct LightList<T> {
+    lights: Vec<(u64, T)>,
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    label: &'static str,
+}
+
+impl<T: bytemuck::Pod> LightList<T> {
+    // The storage buffer can't be empty, and starting with room for a few
+    // lights avoids regrowing it for small scenes.
+    const INITIAL_CAPACITY: usize = 8;
+
+    fn new(device: &wgpu::Device, label: &'static str) -> Self {
+        Self {
+            lights: Vec::new(),
+            buffer: create_storage_buffer::<T>(device, Self::INITIAL_CAPACITY, label),
+            capacity: Self::INITIAL_CAPACITY,
+            label,
+        }
+    }
+
+    fn add(&mut self, id: u64, light: T) {
+        self.lights.push((id, light));
+    }
+
+    fn remove(&mut self, id: u64) -> Option<T> {
+        let index = self.lights.iter().position(|(i, _)| *i == id)?;
+        Some(self.lights.remove(index).1)
+    }
+
+    fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+        self.lights
+            .iter_mut()
+            .find(|(i, _)| *i == id)
+            .map(|(_, light)| light)
+    }
+
+    fn count(&self) -> u32 {
+        self.lights.len() as u32
+    }
+
+    // Returns true when the buffer had to be replaced with a bigger one.
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let grew = self.lights.len() > self.capacity;
+        if grew {
+            self.capacity = self.lights.len().next_power_of_two();
+            self.buffer = create_storage_buffer::<T>(device, self.capacity, self.label);
+        }
+
+        let lights: Vec<T> = self.lights.iter().map(|(_, light)| *light).collect();
+        if !lights.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&lights));
+        }
+        grew
+    }
+}
+
+// Every light in the scene, bound at the light group: the directional light
+// uniform at binding 0, and the point and spot lights in storage buffers at
+// bindings 1 and 2. The directional light's shadow cascades, their comparison
+// sampler and a uniform describing them follow at bindings 3 to 5, then the
+// point light shadow maps and their matrices at 6 and 7. The light clusters
+// (see `LightClusters`) take bindings 8 to 10, and the environment's
+// irradiance map, prefiltered map, BRDF lookup table and sampler 11 to 14,
+// and the screen-space ambient occlusion 15. Changes are only sent to the GPU
+// by `upload`, once per frame.
+pub struct Lights {
+    directional: LightUniform,
+    point_lights: LightList<PointLight>,
+    spot_lights: LightList<SpotLight>,
+    next_id: u64,
+    dirty: bool,
+    uniform_buffer: wgpu::Buffer,
+    shadow_map: ShadowMap,
+    point_shadow_maps: PointShadowMaps,
+    clusters: LightClusters,
+    environment: Environment,
+    ssao: Ssao,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Lights {
+    pub fn new(
+        device: &wgpu::Device,
+        directional: LightUniform,
+        environment: Environment,
+        ssao: Ssao,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: std::mem::size_of::<LightUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let point_lights = LightList::new(device, "Point Light Buffer");
+        let spot_lights = LightList::new(device, "Spot Light Buffer");
+        let shadow_map = ShadowMap::new(device);
+        let point_shadow_maps = PointShadowMaps::new(device);
+
+        // Everything is visible to the vertex stage too, which places the
+        // marker cubes drawn at each light.
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 15,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let buffers = LightBuffers {
+            uniform: &uniform_buffer,
+            point_lights: &point_lights.buffer,
+            spot_lights: &spot_lights.buffer,
+        };
+        let clusters = LightClusters::new(device, buffers);
+        let bind_group = create_bind_group(
+            device,
+            &layout,
+            buffers,
+            &clusters,
+            LightTextures {
+                shadow_map: &shadow_map,
+                point_shadow_maps: &point_shadow_maps,
+                environment: &environment,
+                ssao: &ssao,
+            },
+        );
+
+        Self {
+            directional,
+            point_lights,
+            spot_lights,
+            next_id: 0,
+            dirty: true,
+            uniform_buffer,
+            shadow_map,
+            point_shadow_maps,
+            clusters,
+            environment,
+            ssao,
+            layout,
+            bind_group,
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.dirty = true;
+        self.next_id
+    }
+
+    pub fn add_point_light(&mut self, light: PointLight) -> PointLightId {
+        let id = self.next_id();
+        self.point_lights.add(id, light);
+        PointLightId(id)
+    }
+
+    pub fn remove_point_light(&mut self, id: PointLightId) -> Option<PointLight> {
+        self.dirty = true;
+        self.point_lights.remove(id.0)
+    }
+
+    pub fn point_light_mut(&mut self, id: PointLightId) -> Option<&mut PointLight> {
+        // Assume the caller changes it.
+        self.dirty = true;
+        self.point_lights.get_mut(id.0)
+    }
+
+    pub fn point_light_count(&self) -> u32 {
+        self.point_lights.count()
+    }
+
+    pub fn add_spot_light(&mut self, light: SpotLight) -> SpotLightId {
+        let id = self.next_id();
+        self.spot_lights.add(id, light);
+        SpotLightId(id)
+    }
+
+    pub fn remove_spot_light(&mut self, id: SpotLightId) -> Option<SpotLight> {
+        self.dirty = true;
+        self.spot_lights.remove(id.0)
+    }
+
+    pub fn spot_light_mut(&mut self, id: SpotLightId) -> Option<&mut SpotLight> {
+        self.dirty = true;
+        self.spot_lights.get_mut(id.0)
+    }
+
+    pub fn spot_light_count(&self) -> u32 {
+        self.spot_lights.count()
+    }
+
+    pub fn clear(&mut self) {
+        self.point_lights.lights.clear();
+        self.spot_lights.lights.clear();
+        self.dirty = true;
+    }
+
+    // Writes any changes since the last upload. A storage buffer that had to
+    // grow also means rebuilding the bind group.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let mut shadowed = Vec::new();
+        for (_, light) in &mut self.point_lights.lights {
+            light.shadow_index = -1;
+            if light.casts_shadows() && shadowed.len() < MAX_SHADOWED_POINT_LIGHTS {
+                light.shadow_index = shadowed.len() as i32;
+                shadowed.push((light.position, light.radius));
+            }
+        }
+        self.point_shadow_maps.update(queue, &shadowed);
+
+        let points_grew = self.point_lights.upload(device, queue);
+        let spots_grew = self.spot_lights.upload(device, queue);
+        if points_grew || spots_grew {
+            let buffers = LightBuffers {
+                uniform: &self.uniform_buffer,
+                point_lights: &self.point_lights.buffer,
+                spot_lights: &self.spot_lights.buffer,
+            };
+            self.clusters.set_lights(device, buffers);
+            self.rebuild_bind_group(device);
+        }
+
+        let uniform = LightUniform {
+            point_light_count: self.point_light_count(),
+            spot_light_count: self.spot_light_count(),
+            ..self.directional
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    // The shadow cascades, light clusters and ambient occlusion follow the
+    // camera, so this runs every frame.
+    pub fn follow_camera(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        self.shadow_map
+            .update(queue, self.directional.direction.into(), camera);
+        self.clusters.update(queue, camera);
+        self.ssao.update(queue, camera);
+    }
+
+    // The ambient occlusion targets match the window's size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.ssao.resize(device, width, height);
+        self.rebuild_bind_group(device);
+    }
+
+    fn rebuild_bind_group(&mut self, device: &wgpu::Device) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.layout,
+            LightBuffers {
+                uniform: &self.uniform_buffer,
+                point_lights: &self.point_lights.buffer,
+                spot_lights: &self.spot_lights.buffer,
+            },
+            &self.clusters,
+            LightTextures {
+                shadow_map: &self.shadow_map,
+                point_shadow_maps: &self.point_shadow_maps,
+                environment: &self.environment,
+                ssao: &self.ssao,
+            },
+        );
+    }
+
+    // Bins the lights into clusters. Must run after `upload` and before the
+    // scene is drawn.
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.clusters.dispatch(encoder);
+    }
+
+    pub fn directional(&self) -> &LightUniform {
+        &self.directional
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    pub fn shadow_map(&self) -> &ShadowMap {
+        &self.shadow_map
+    }
+
+    pub fn point_shadow_maps(&self) -> &PointShadowMaps {
+        &self.point_shadow_maps
+    }
+
+    pub fn ssao(&self) -> &Ssao {
+        &self.ssao
+    }
+
+    pub fn ssao_settings(&self) -> SsaoSettings {
+        self.ssao.settings()
+    }
+
+    pub fn set_ssao_settings(&mut self, settings: SsaoSettings) {
+        self.ssao.set_settings(settings);
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+fn create_storage_buffer<T>(device: &wgpu::Device, capacity: usize, label: &str) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: LightBuffers,
+    clusters: &LightClusters,
+    textures: LightTextures,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Light Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffers.uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.point_lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffers.spot_lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(textures.shadow_map.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(textures.shadow_map.sampler()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: textures.shadow_map.uniform_buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(textures.point_shadow_maps.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: textures
+                    .point_shadow_maps
+                    .uniform_buffer()
+                    .as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: clusters.uniform_buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: clusters.cluster_buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: clusters.index_buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: wgpu::BindingResource::TextureView(
+                    textures.environment.irradiance_view(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::TextureView(
+                    textures.environment.prefiltered_view(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::TextureView(textures.environment.brdf_lut_view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: wgpu::BindingResource::Sampler(textures.environment.sampler()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: wgpu::BindingResource::TextureView(textures.ssao.view()),
+            },
+        ],
+    })
+}
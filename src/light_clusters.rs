@@ -0,0 +1,219 @@
+use cgmath::SquareMatrix;
+
+use crate::{camera::Camera, clear_gpu_buffer, light::LightBuffers};
+
+// The view is cut into CLUSTER_X x CLUSTER_Y tiles across the screen and
+// CLUSTER_Z slices in depth. These and `MAX_LIGHTS_PER_CLUSTER` match the
+// constants in cluster.wgsl and shader.wgsl.
+const CLUSTER_X: u32 = 16;
+const CLUSTER_Y: u32 = 9;
+const CLUSTER_Z: u32 = 24;
+const CLUSTER_COUNT: u32 = CLUSTER_X * CLUSTER_Y * CLUSTER_Z;
+// Lights past this many touching one cluster are left out of it.
+const MAX_LIGHTS_PER_CLUSTER: u32 = 32;
+const WORKGROUP_SIZE: u32 = 64;
+
+// Matches `ClusterView` in cluster.wgsl and shader.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClusterUniform {
+    view: [[f32; 4]; 4],
+    view_proj: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    znear: f32,
+    zfar: f32,
+    _padding: [f32; 2],
+}
+
+// Clustered light culling. Each frame a compute pass splits the camera's view
+// into froxels (frustum-shaped voxels) and lists the point and spot lights
+// whose range reaches each one. Fragments then only loop over their own
+// froxel's lists instead of every light in the scene. Slices get deeper
+// further from the camera so froxels stay roughly cube-shaped.
+pub struct LightClusters {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    // Point and spot light counts per cluster.
+    cluster_buffer: wgpu::Buffer,
+    // `MAX_LIGHTS_PER_CLUSTER` slots per cluster: point light indices, then
+    // spot light indices.
+    index_buffer: wgpu::Buffer,
+}
+
+impl LightClusters {
+    pub fn new(device: &wgpu::Device, lights: LightBuffers) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Buffer"),
+            size: std::mem::size_of::<ClusterUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let cluster_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Count Buffer"),
+            size: (CLUSTER_COUNT as usize * std::mem::size_of::<[u32; 2]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Index Buffer"),
+            size: (CLUSTER_COUNT as usize
+                * MAX_LIGHTS_PER_CLUSTER as usize
+                * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cluster Bind Group Layout"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                buffer_entry(1, read_only),
+                buffer_entry(2, read_only),
+                buffer_entry(3, wgpu::BufferBindingType::Uniform),
+                buffer_entry(4, read_write),
+                buffer_entry(5, read_write),
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("cluster.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cluster Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cluster Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        let bind_group = create_bind_group(
+            device,
+            &layout,
+            lights,
+            &uniform_buffer,
+            &cluster_buffer,
+            &index_buffer,
+        );
+
+        Self {
+            pipeline,
+            layout,
+            bind_group,
+            uniform_buffer,
+            cluster_buffer,
+            index_buffer,
+        }
+    }
+
+    // The light buffers are replaced when they grow.
+    pub fn set_lights(&mut self, device: &wgpu::Device, lights: LightBuffers) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.layout,
+            lights,
+            &self.uniform_buffer,
+            &self.cluster_buffer,
+            &self.index_buffer,
+        );
+    }
+
+    // Fits the clusters to `camera`. Other cameras' fragments fall outside
+    // them and loop over every light instead.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let view = camera.view_matrix();
+        let proj = camera.projection_matrix();
+        let uniform = ClusterUniform {
+            view: view.into(),
+            view_proj: (proj * view).into(),
+            inverse_proj: proj
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity)
+                .into(),
+            znear: camera.znear,
+            zfar: camera.zfar,
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    // The counts are zeroed first, so a cluster the pass leaves alone lists
+    // no lights rather than last frame's.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        clear_gpu_buffer(encoder, &self.cluster_buffer, 0..self.cluster_buffer.size());
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light Culling Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(CLUSTER_COUNT.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    pub fn cluster_buffer(&self) -> &wgpu::Buffer {
+        &self.cluster_buffer
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        &self.index_buffer
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    lights: LightBuffers,
+    uniform_buffer: &wgpu::Buffer,
+    cluster_buffer: &wgpu::Buffer,
+    index_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Cluster Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: lights.uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: lights.point_lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: lights.spot_lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: cluster_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: index_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
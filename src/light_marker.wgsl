@@ -0,0 +1,81 @@
+// Draws a small unlit cube at each point and spot light, in the light's
+// colour. The cube is instanced once per light, point lights first, and reads
+// its position straight from the light buffers.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Light {
+    direction: vec3<f32>,
+    intensity: f32,
+    colour: vec3<f32>,
+    point_light_count: u32,
+    spot_light_count: u32,
+};
+@group(3) @binding(0)
+var<uniform> light: Light;
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    colour: vec3<f32>,
+    intensity: f32,
+    attenuation: vec2<f32>,
+    casts_shadows: u32,
+    shadow_index: i32,
+};
+@group(3) @binding(1)
+var<storage, read> point_lights: array<PointLight>;
+
+struct SpotLight {
+    position: vec3<f32>,
+    radius: f32,
+    direction: vec3<f32>,
+    intensity: f32,
+    colour: vec3<f32>,
+    inner_cos: f32,
+    attenuation: vec2<f32>,
+    outer_cos: f32,
+};
+@group(3) @binding(2)
+var<storage, read> spot_lights: array<SpotLight>;
+
+const MARKER_SIZE: f32 = 0.2;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) colour: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var centre: vec3<f32>;
+    var colour: vec3<f32>;
+    if instance_index < light.point_light_count {
+        let point = point_lights[instance_index];
+        centre = point.position;
+        colour = point.colour * point.intensity;
+    } else {
+        let spot = spot_lights[instance_index - light.point_light_count];
+        centre = spot.position;
+        colour = spot.colour * spot.intensity;
+    }
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(centre + position * MARKER_SIZE, 1.0);
+    // Markers glow as bright as their light, which can go past 1.0.
+    out.colour = colour;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.colour, 1.0);
+}
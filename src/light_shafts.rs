@@ -0,0 +1,148 @@
+use crate::{
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    uniform::UniformBuffer,
+};
+
+// Matches `LightShaftUniform` in light_shafts.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightShaftUniform {
+    // Where the light is on screen, in uv coordinates.
+    light_screen_pos: [f32; 2],
+    density: f32,
+    decay: f32,
+    weight: f32,
+    _padding: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightShaftSettings {
+    // How much of the way to the light each pixel's samples reach.
+    pub density: f32,
+    // How much each sample counts for relative to the one before it, from
+    // the pixel towards the light.
+    pub decay: f32,
+    // How bright the shafts are.
+    pub weight: f32,
+}
+
+impl Default for LightShaftSettings {
+    fn default() -> Self {
+        Self {
+            density: 0.8,
+            decay: 0.97,
+            weight: 0.5,
+        }
+    }
+}
+
+// Shafts of the directional light streaming past whatever is in front of
+// the sky. Each pixel samples along the line to where the light is on screen,
+// adding the sky it finds there and leaving out anything the depth shows in
+// the way, so the sky's light spreads out from the sun past the occluders'
+// edges (Mitchell, GPU Gems 3, ch. 13). Skipped while the light is off
+// screen or behind the camera.
+pub struct LightShafts {
+    pass: FullscreenPass,
+    uniform_buffer: UniformBuffer<LightShaftUniform>,
+    depth_layout: wgpu::BindGroupLayout,
+    settings: LightShaftSettings,
+    visible: bool,
+}
+
+impl LightShafts {
+    pub fn new(device: &wgpu::Device, settings: LightShaftSettings) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &LightShaftUniform {
+                light_screen_pos: [0.5, 0.5],
+                density: settings.density,
+                decay: settings.decay,
+                weight: settings.weight,
+                _padding: 0,
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "Light Shaft Uniform",
+        );
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Shaft Depth Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("light_shafts.wgsl"));
+        let pass = FullscreenPass::new(
+            device,
+            "Light Shafts",
+            &shader,
+            "fs_main",
+            &[uniform_buffer.layout(), &depth_layout],
+        );
+
+        Self {
+            pass,
+            uniform_buffer,
+            depth_layout,
+            settings,
+            visible: false,
+        }
+    }
+
+    // Whether the light was on screen at the last `update`.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    // Finds the directional light, shining along `direction`, on screen
+    // through `view_proj`.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        view_proj: cgmath::Matrix4<f32>,
+        direction: cgmath::Vector3<f32>,
+    ) {
+        // The light is infinitely far away, back along its direction.
+        let clip = view_proj * (-direction).extend(0.0);
+        let ndc = clip.truncate().truncate() / clip.w;
+        self.visible = clip.w > 0.0 && ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0;
+        if !self.visible {
+            return;
+        }
+
+        self.uniform_buffer.write(
+            queue,
+            &LightShaftUniform {
+                light_screen_pos: [ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5],
+                density: self.settings.density,
+                decay: self.settings.decay,
+                weight: self.settings.weight,
+                _padding: 0,
+            },
+        );
+    }
+}
+
+impl PostEffect for LightShafts {
+    fn draw(&self, frame: PostFrame) {
+        let depth_bind_group = frame.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Shaft Depth Bind Group"),
+            layout: &self.depth_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(frame.depth),
+            }],
+        });
+        self.pass.draw(
+            frame,
+            &[self.uniform_buffer.bind_group(), &depth_bind_group],
+        );
+    }
+}
@@ -0,0 +1,50 @@
+// Light shafts from the directional light, drawn with post_process.wgsl's
+// vertex stage. See light_shafts.rs.
+
+const SAMPLE_COUNT: u32 = 64u;
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct LightShaftUniform {
+    light_screen_pos: vec2<f32>,
+    density: f32,
+    decay: f32,
+    weight: f32,
+};
+@group(1) @binding(0)
+var<uniform> shafts: LightShaftUniform;
+
+// The main camera's depth, which may be a different size to the frame.
+@group(2) @binding(0)
+var t_depth: texture_depth_2d;
+
+// The frame at `uv` where it shows the sky, and black where something is in
+// front of it.
+fn sky_at(uv: vec2<f32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let texel = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    if textureLoad(t_depth, texel, 0) < 1.0 {
+        return vec3<f32>(0.0);
+    }
+    return textureSampleLevel(t_input, s_input, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let colour = textureSampleLevel(t_input, s_input, uv, 0.0);
+
+    let step = (uv - shafts.light_screen_pos) * shafts.density / f32(SAMPLE_COUNT);
+    var sample_uv = uv;
+    var illumination = 1.0;
+    var shaft = vec3<f32>(0.0);
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        sample_uv -= step;
+        shaft += sky_at(sample_uv) * illumination;
+        illumination *= shafts.decay;
+    }
+
+    return vec4<f32>(colour.rgb + shaft * shafts.weight / f32(SAMPLE_COUNT), colour.a);
+}
@@ -0,0 +1,112 @@
+use std::ops::Range;
+
+use cgmath::MetricSpace;
+
+use crate::{
+    instance::Instance,
+    mesh::{Mesh, MeshData},
+};
+
+// How far past a switch distance, as a fraction of it, an instance has to
+// move before it changes level. Without it, an instance sitting on the
+// boundary flickers between the two levels as the camera jitters.
+const HYSTERESIS: f32 = 0.1;
+
+// The same shape at several levels of detail, finest first. Each instance
+// gets a level from how far its bounding sphere is from the eye, so distant
+// copies draw fewer triangles.
+pub struct LodMesh {
+    levels: Vec<Mesh>,
+    // Where level `i` gives way to `i + 1`; one fewer than `levels`.
+    switch_distances: Vec<f32>,
+    // The level each instance was last drawn at.
+    instance_levels: Vec<usize>,
+}
+
+impl LodMesh {
+    pub fn new(device: &wgpu::Device, levels: &[MeshData], switch_distances: &[f32]) -> Self {
+        assert_eq!(levels.len(), switch_distances.len() + 1);
+        Self {
+            levels: levels
+                .iter()
+                .enumerate()
+                .map(|(i, data)| Mesh::new(device, data, &format!("LOD {i}")))
+                .collect(),
+            switch_distances: switch_distances.to_vec(),
+            instance_levels: Vec::new(),
+        }
+    }
+
+    pub fn levels(&self) -> &[Mesh] {
+        &self.levels
+    }
+
+    // Moves each instance to the level for its distance from `eye`.
+    pub fn update(&mut self, eye: cgmath::Point3<f32>, instances: &[Instance]) {
+        // Every level is the same shape, so the finest one's sphere does.
+        let Some(sphere) = self.levels[0].pick.sphere() else {
+            return;
+        };
+        self.instance_levels.resize(instances.len(), 0);
+        for (level, instance) in self.instance_levels.iter_mut().zip(instances) {
+            let sphere = sphere.transformed(&instance.model_matrix());
+            let distance = (sphere.centre.distance(eye) - sphere.radius).max(0.0);
+            *level = select_level(&self.switch_distances, *level, distance);
+        }
+    }
+
+    // The parts of `visible` whose instances are at `level`.
+    pub fn instances_at(&self, level: usize, visible: &[Range<u32>]) -> Vec<Range<u32>> {
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        for index in visible.iter().flat_map(|range| range.clone()) {
+            // Instances added since the last `update` start at the finest.
+            let instance_level = self.instance_levels.get(index as usize).copied();
+            if instance_level.unwrap_or(0) != level {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        ranges
+    }
+}
+
+// The level for something `distance` away that was last at `current`. It
+// only moves once the distance is clear of the switch distance either way.
+fn select_level(switch_distances: &[f32], current: usize, distance: f32) -> usize {
+    let coarser = |level: usize| switch_distances[level] * (1.0 + HYSTERESIS);
+    let finer = |level: usize| switch_distances[level - 1] * (1.0 - HYSTERESIS);
+    let mut level = current.min(switch_distances.len());
+    while level < switch_distances.len() && distance > coarser(level) {
+        level += 1;
+    }
+    while level > 0 && distance < finer(level) {
+        level -= 1;
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWITCH_DISTANCES: &[f32] = &[10.0, 20.0];
+
+    #[test]
+    fn picks_level_by_distance() {
+        assert_eq!(select_level(SWITCH_DISTANCES, 0, 5.0), 0);
+        assert_eq!(select_level(SWITCH_DISTANCES, 0, 15.0), 1);
+        assert_eq!(select_level(SWITCH_DISTANCES, 0, 50.0), 2);
+        assert_eq!(select_level(SWITCH_DISTANCES, 2, 5.0), 0);
+    }
+
+    #[test]
+    fn holds_level_near_a_switch_distance() {
+        assert_eq!(select_level(SWITCH_DISTANCES, 0, 10.5), 0);
+        assert_eq!(select_level(SWITCH_DISTANCES, 1, 10.5), 1);
+        assert_eq!(select_level(SWITCH_DISTANCES, 1, 9.5), 1);
+        assert_eq!(select_level(SWITCH_DISTANCES, 1, 8.5), 0);
+    }
+}
@@ -1,5 +1,25 @@
-use learning_wgpu::run;
+use learning_wgpu::{run, run_screenshot, RunConfig};
+use winit::dpi::PhysicalSize;
 
 fn main() {
-   pollster::block_on(run()).unwrap();
+    let args: Vec<String> = std::env::args().collect();
+
+    if let Some(index) = args.iter().position(|arg| arg == "--screenshot") {
+        let output_path = args
+            .get(index + 1)
+            .expect("--screenshot needs an output path");
+        let config = RunConfig {
+            headless: true,
+            ..Default::default()
+        };
+        pollster::block_on(run_screenshot(
+            config,
+            PhysicalSize::new(512, 512),
+            output_path,
+        ))
+        .unwrap();
+        return;
+    }
+
+    pollster::block_on(run(RunConfig::default())).unwrap();
 }
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::{Assets, Handle},
+    texture::Texture,
+};
+
+// Matches `MaterialUniform` in shader.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialParams {
+    pub base_colour: [f32; 4],
+    // Linear light given off regardless of lighting. Components can go above
+    // 1.0 for surfaces brighter than white.
+    pub emissive: [f32; 3],
+    pub roughness: f32,
+    pub metallic: f32,
+    // Scales how much of the environment the surface mirrors. 1.0 is
+    // physically based; 0.0 turns reflections off and values above 1.0
+    // exaggerate them.
+    pub reflectivity: f32,
+    pub uv_scroll: UvScrollUniform,
+    // How deep the height map's black is below its white, in texture
+    // coordinates. 0 leaves the surface flat.
+    pub height_scale: f32,
+    _padding: f32,
+}
+
+impl MaterialParams {
+    pub fn new(base_colour: [f32; 4], roughness: f32, metallic: f32) -> Self {
+        Self {
+            base_colour,
+            emissive: [0.0; 3],
+            roughness,
+            metallic,
+            reflectivity: 1.0,
+            uv_scroll: UvScrollUniform::default(),
+            height_scale: 0.0,
+            _padding: 0.0,
+        }
+    }
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self::new([1.0, 1.0, 1.0, 1.0], 1.0, 0.0)
+    }
+}
+
+// Matches `UvScrollUniform` in shader.wgsl. The offset is added to the
+// texture coordinates before every map is sampled, so scrolling only looks
+// continuous with a sampler that repeats.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct UvScrollUniform {
+    pub offset: [f32; 2],
+    // In texture repeats per second.
+    pub speed: [f32; 2],
+}
+
+impl UvScrollUniform {
+    // Moves the offset on by `dt` at `speed`. It's wrapped into 0..1, which
+    // the repeating texture can't tell apart from the unwrapped offset, so it
+    // keeps its precision however long the scroll runs.
+    pub fn advance(&mut self, dt: Duration) {
+        for (offset, speed) in self.offset.iter_mut().zip(self.speed) {
+            *offset = (*offset + speed * dt.as_secs_f32()).rem_euclid(1.0);
+        }
+    }
+}
+
+// The maps a material samples, following glTF's metallic-roughness model.
+// Each one is multiplied with the matching factor in `MaterialParams`, so a
+// white map leaves the factor as it is.
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialTextures {
+    // sRGB colour, with alpha.
+    pub base_colour: Handle<Texture>,
+    // Linear, tangent space.
+    pub normal: Handle<Texture>,
+    // Linear; roughness in green and metallic in blue.
+    pub metallic_roughness: Handle<Texture>,
+    // Linear; ambient occlusion in red.
+    pub occlusion: Handle<Texture>,
+    // sRGB colour.
+    pub emissive: Handle<Texture>,
+    // Linear; height in red, white at the surface. Offsets where the other
+    // maps are sampled to fake depth, by `MaterialParams::height_scale`.
+    pub height: Handle<Texture>,
+}
+
+impl MaterialTextures {
+    // `base_colour` with neutral maps for everything else.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut Assets,
+        base_colour: Handle<Texture>,
+    ) -> Self {
+        let white = assets.solid_texture(device, queue, [255, 255, 255, 255]);
+        Self {
+            base_colour,
+            normal: assets.flat_normal_texture(device, queue),
+            metallic_roughness: white,
+            occlusion: white,
+            emissive: white,
+            height: white,
+        }
+    }
+}
+
+// Everything a mesh needs bound at `MATERIAL_GROUP` to be drawn with the
+// shared render pipeline: its textures, a sampler shared by all of them, and
+// a parameter uniform.
+pub struct Material {
+    pub name: String,
+    pub textures: MaterialTextures,
+    pub params: MaterialParams,
+    // Drawn without back-face culling, for thin surfaces such as leaves and
+    // cloth that are seen from both sides. Back faces are shaded with their
+    // normal flipped. Depth-only passes still cull them, so such a surface
+    // only casts shadows and occludes from its front.
+    pub double_sided: bool,
+    params_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        assets: &Assets,
+        textures: MaterialTextures,
+        params: MaterialParams,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let base_colour = assets.texture(textures.base_colour);
+        let view = |handle| wgpu::BindingResource::TextureView(&assets.texture(handle).view);
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Material Buffer")),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{name} Material Bind Group")),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&base_colour.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&base_colour.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: view(textures.normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: view(textures.metallic_roughness),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: view(textures.occlusion),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: view(textures.emissive),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: view(textures.height),
+                },
+            ],
+        });
+
+        Self {
+            name: name.to_string(),
+            textures,
+            params,
+            double_sided: false,
+            params_buffer,
+            bind_group,
+        }
+    }
+
+    // Writes only the scroll part of the parameters, leaving `params` as the
+    // material was made.
+    pub fn write_uv_scroll(&self, queue: &wgpu::Queue, uv_scroll: &UvScrollUniform) {
+        let offset = std::mem::offset_of!(MaterialParams, uv_scroll) as wgpu::BufferAddress;
+        queue.write_buffer(&self.params_buffer, offset, bytemuck::bytes_of(uv_scroll));
+    }
+
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[
+                texture_layout_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_layout_entry(3),
+                texture_layout_entry(4),
+                texture_layout_entry(5),
+                texture_layout_entry(6),
+                texture_layout_entry(7),
+            ],
+        })
+    }
+}
+
+fn texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_the_scroll_offset() {
+        let mut scroll = UvScrollUniform {
+            offset: [0.0; 2],
+            speed: [0.25, -0.5],
+        };
+        scroll.advance(Duration::from_secs(3));
+        assert_eq!(scroll.offset, [0.75, 0.5]);
+        // Hours in, the offset is as precise as it was at the start.
+        scroll.advance(Duration::from_secs(36_000));
+        assert_eq!(scroll.offset, [0.75, 0.5]);
+    }
+}
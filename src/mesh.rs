@@ -0,0 +1,307 @@
+use std::ops::Range;
+
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+use crate::{assets::Handle, material::Material, picking::PickMesh, Vertex};
+
+const PENTAGON_VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-0.0868241, 0.49240386, 0.0],
+        colour: [0.5, 0.0, 0.5],
+        tex_coords: [0.4131759, 0.00759614],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [-0.49513406, 0.06958647, 0.0],
+        colour: [0.5, 0.0, 0.5],
+        tex_coords: [0.0048659444, 0.43041354],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [-0.21918549, -0.44939706, 0.0],
+        colour: [0.5, 0.0, 0.5],
+        tex_coords: [0.28081453, 0.949397],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.35966998, -0.3473291, 0.0],
+        colour: [0.5, 0.0, 0.5],
+        tex_coords: [0.85967, 0.84732914],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.44147372, 0.2347359, 0.0],
+        colour: [0.5, 0.0, 0.5],
+        tex_coords: [0.9414737, 0.2652641],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+    },
+];
+
+const PENTAGON_INDICES: &[u32] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
+
+// CPU-side geometry, ready to be uploaded into vertex and index buffers.
+pub struct MeshData {
+    pub(crate) vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn pentagon() -> Self {
+        Self {
+            vertices: PENTAGON_VERTICES.to_vec(),
+            indices: PENTAGON_INDICES.to_vec(),
+        }
+    }
+
+    pub fn quad() -> Self {
+        let corner = |x: f32, y: f32, colour: [f32; 3]| Vertex {
+            position: [x, y, 0.0],
+            colour,
+            tex_coords: [x + 0.5, 0.5 - y],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+        };
+
+        Self {
+            vertices: vec![
+                corner(-0.5, -0.5, [1.0, 0.0, 0.0]),
+                corner(0.5, -0.5, [0.0, 1.0, 0.0]),
+                corner(0.5, 0.5, [0.0, 0.0, 1.0]),
+                corner(-0.5, 0.5, [1.0, 1.0, 0.0]),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    // A unit cube with each face mapping the whole texture.
+    pub fn cube() -> Self {
+        // (normal, u axis, v axis) with u x v = normal, so faces wind
+        // counter-clockwise seen from outside.
+        const FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ];
+
+        let vertices = FACES
+            .iter()
+            .flat_map(|&(normal, u, v)| {
+                [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(s, t): (f32, f32)| {
+                    Vertex {
+                        position: [0, 1, 2].map(|i| 0.5 * (normal[i] + s * u[i] + t * v[i])),
+                        colour: [1.0, 1.0, 1.0],
+                        tex_coords: [(s + 1.0) / 2.0, (1.0 - t) / 2.0],
+                        normal,
+                        tangent: [u[0], u[1], u[2], 1.0],
+                    }
+                })
+            })
+            .collect();
+        let indices = (0..FACES.len() as u32)
+            .flat_map(|face| [0, 1, 2, 0, 2, 3].map(|i| face * 4 + i))
+            .collect();
+
+        Self { vertices, indices }
+    }
+
+    pub fn disc(segments: u16) -> Self {
+        let segments = segments.clamp(3, u16::MAX - 1);
+        let radius = 0.5;
+
+        let mut vertices = vec![Vertex {
+            position: [0.0, 0.0, 0.0],
+            colour: [1.0, 1.0, 1.0],
+            tex_coords: [0.5, 0.5],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+        }];
+        vertices.extend((0..segments).map(|i| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            let third = std::f32::consts::TAU / 3.0;
+            let (x, y) = (radius * angle.cos(), radius * angle.sin());
+            Vertex {
+                position: [x, y, 0.0],
+                colour: [
+                    0.5 + 0.5 * angle.cos(),
+                    0.5 + 0.5 * (angle + third).cos(),
+                    0.5 + 0.5 * (angle + 2.0 * third).cos(),
+                ],
+                tex_coords: [x + 0.5, 0.5 - y],
+                normal: [0.0, 0.0, 1.0],
+                tangent: [1.0, 0.0, 0.0, 1.0],
+            }
+        }));
+
+        let indices = (0..segments)
+            .flat_map(|i| [0, i + 1, (i + 1) % segments + 1])
+            .map(u32::from)
+            .collect();
+
+        Self { vertices, indices }
+    }
+
+    // A sphere of diameter 1 cut into `sectors` slices around Y and `stacks`
+    // bands from pole to pole. The seam repeats its vertices so the texture
+    // wraps around once.
+    pub fn uv_sphere(sectors: u16, stacks: u16) -> Self {
+        let sectors = u32::from(sectors.max(3));
+        let stacks = u32::from(stacks.max(2));
+        let radius = 0.5;
+
+        let vertices = (0..=stacks)
+            .flat_map(|stack| {
+                (0..=sectors).map(move |sector| {
+                    let (u, v) = (sector as f32 / sectors as f32, stack as f32 / stacks as f32);
+                    let polar = v * std::f32::consts::PI;
+                    let azimuth = u * std::f32::consts::TAU;
+                    let normal = [
+                        polar.sin() * azimuth.cos(),
+                        polar.cos(),
+                        -polar.sin() * azimuth.sin(),
+                    ];
+                    Vertex {
+                        position: normal.map(|n| n * radius),
+                        colour: [1.0, 1.0, 1.0],
+                        tex_coords: [u, v],
+                        normal,
+                        tangent: [-azimuth.sin(), 0.0, -azimuth.cos(), 1.0],
+                    }
+                })
+            })
+            .collect();
+
+        let row = sectors + 1;
+        let indices = (0..stacks)
+            .flat_map(|stack| {
+                (0..sectors).flat_map(move |sector| {
+                    let top = stack * row + sector;
+                    let bottom = top + row;
+                    [bottom, bottom + 1, top + 1, bottom, top + 1, top]
+                })
+            })
+            .collect();
+
+        Self { vertices, indices }
+    }
+
+    // Smooth vertex normals for geometry that doesn't come with any. Each
+    // triangle adds its face normal, weighted by its area, to its corners.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![cgmath::Vector3::new(0.0f32, 0.0, 0.0); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2]
+                .map(|i| cgmath::Vector3::from(self.vertices[triangle[i] as usize].position));
+            let face_normal = (b - a).cross(c - a);
+            for &i in triangle {
+                normals[i as usize] += face_normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            if normal.magnitude2() > 0.0 {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+
+    // Per-vertex tangents from the texture coordinates, for normal mapping.
+    // Each triangle adds the directions its u and image-up axes run in world
+    // space; the sums are then made perpendicular to the vertex normal.
+    pub fn compute_tangents(&mut self) {
+        let zero = cgmath::Vector3::new(0.0f32, 0.0, 0.0);
+        let mut tangents = vec![zero; self.vertices.len()];
+        let mut bitangents = vec![zero; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &self.vertices[triangle[i] as usize]);
+            let edge1 = cgmath::Vector3::from(b.position) - cgmath::Vector3::from(a.position);
+            let edge2 = cgmath::Vector3::from(c.position) - cgmath::Vector3::from(a.position);
+            let uv1 = cgmath::Vector2::from(b.tex_coords) - cgmath::Vector2::from(a.tex_coords);
+            let uv2 = cgmath::Vector2::from(c.tex_coords) - cgmath::Vector2::from(a.tex_coords);
+            let determinant = uv1.x * uv2.y - uv2.x * uv1.y;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+
+            let tangent = (edge1 * uv2.y - edge2 * uv1.y) / determinant;
+            // `tex_coords` v runs down the image, so up is along -v.
+            let bitangent = (edge1 * uv2.x - edge2 * uv1.x) / determinant;
+            for &i in triangle {
+                tangents[i as usize] += tangent;
+                bitangents[i as usize] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = cgmath::Vector3::from(vertex.normal);
+            let tangent = tangent - normal * normal.dot(tangent);
+            if tangent.magnitude2() <= f32::EPSILON {
+                continue;
+            }
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            let tangent = tangent.normalize();
+            vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+        }
+    }
+}
+
+// Geometry uploaded to the GPU and ready to draw.
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_elements: u32,
+    pub material: Option<Handle<Material>>,
+    pub pick: PickMesh,
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, data: &MeshData, name: &str) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Vertex Buffer")),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Index Buffer")),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_elements: data.indices.len() as u32,
+            material: None,
+            pick: PickMesh::new(&data.vertices, &data.indices),
+        }
+    }
+}
+
+// Instance data, when used, is expected in vertex buffer slot 1.
+pub trait DrawMesh<'a> {
+    fn draw_mesh_instanced(&mut self, mesh: &'a Mesh, instances: Range<u32>);
+}
+
+impl<'a, 'b> DrawMesh<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_mesh_instanced(&mut self, mesh: &'b Mesh, instances: Range<u32>) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::mesh::MeshData;
+
+pub struct FinishedMesh {
+    pub job_id: u64,
+    pub data: MeshData,
+}
+
+type Generator = Box<dyn FnOnce() -> MeshData + Send>;
+
+struct QueuedJob {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    generate: Generator,
+}
+
+// Handle to a submitted job. Dropping it cancels the job, so a result that is
+// no longer wanted is either never generated or discarded before upload.
+pub struct MeshJob {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl MeshJob {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Drop for MeshJob {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct MeshWorkerPool {
+    job_sender: Option<Sender<QueuedJob>>,
+    result_receiver: Receiver<(FinishedMesh, Arc<AtomicBool>)>,
+    workers: Vec<JoinHandle<()>>,
+    next_id: u64,
+}
+
+impl MeshWorkerPool {
+    pub fn new(threads: usize) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<QueuedJob>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                std::thread::spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    let Ok(job) = job else {
+                        break;
+                    };
+
+                    if job.cancelled.load(Ordering::Relaxed) {
+                        continue;
+                    }
+
+                    let data = (job.generate)();
+                    let finished = FinishedMesh {
+                        job_id: job.id,
+                        data,
+                    };
+                    if result_sender.send((finished, job.cancelled)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            job_sender: Some(job_sender),
+            result_receiver,
+            workers,
+            next_id: 0,
+        }
+    }
+
+    pub fn submit(&mut self, generate: impl FnOnce() -> MeshData + Send + 'static) -> MeshJob {
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        if let Some(sender) = &self.job_sender {
+            let _ = sender.send(QueuedJob {
+                id: self.next_id,
+                cancelled: cancelled.clone(),
+                generate: Box::new(generate),
+            });
+        }
+
+        MeshJob {
+            id: self.next_id,
+            cancelled,
+        }
+    }
+
+    pub fn poll(&self) -> Vec<FinishedMesh> {
+        self.result_receiver
+            .try_iter()
+            .filter(|(_, cancelled)| !cancelled.load(Ordering::Relaxed))
+            .map(|(finished, _)| finished)
+            .collect()
+    }
+}
+
+impl Drop for MeshWorkerPool {
+    fn drop(&mut self) {
+        self.job_sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
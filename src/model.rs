@@ -0,0 +1,624 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assets::{Assets, Handle},
+    compressed_texture::{compressed_variant, CompressedImage},
+    material::{Material, MaterialParams, MaterialTextures},
+    mesh::{Mesh, MeshData},
+    texture::{SamplerConfig, Texture, TextureKind},
+    Vertex,
+};
+
+// Meshes reference the model's materials by handle into `Assets`.
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Handle<Material>>,
+}
+
+impl Model {
+    // Procedural shapes have no materials; their meshes fall back to the
+    // renderer's default material.
+    pub fn from_mesh(mesh: Mesh) -> Self {
+        Self {
+            meshes: vec![mesh],
+            materials: Vec::new(),
+        }
+    }
+
+    // Shown while a model loads in the background: a bright pink cube that's
+    // hard to mistake for real content.
+    pub fn placeholder(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut Assets,
+    ) -> Self {
+        let texture = assets.solid_texture(device, queue, [255, 0, 255, 255]);
+        let textures = MaterialTextures::new(device, queue, assets, texture);
+        let material = Material::new(
+            device,
+            "Placeholder",
+            assets,
+            textures,
+            MaterialParams::default(),
+            material_layout,
+        );
+        let material = assets.insert_material("<placeholder>", material);
+        let mut mesh = Mesh::new(device, &MeshData::cube(), "Placeholder");
+        mesh.material = Some(material);
+
+        Self {
+            meshes: vec![mesh],
+            materials: vec![material],
+        }
+    }
+
+    // A quad textured with a checkerboard inside a semi-transparent border, for
+    // seeing how blending treats partial alpha. With a premultiplied surface
+    // the texels are premultiplied too, so the shader's colour already carries
+    // its alpha and blending adds it as-is; with straight alpha the blend
+    // scales it by alpha instead. Either way the border should look like the
+    // background seen through orange glass, and a dark or bright fringe there
+    // means the texture and blend state disagree. Under an opaque or
+    // inherited alpha mode nothing blends and the border is drawn solid.
+    pub fn alpha_test(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut Assets,
+        premultiplied: bool,
+    ) -> Self {
+        let name = if premultiplied {
+            "<alpha test, premultiplied>"
+        } else {
+            "<alpha test>"
+        };
+        let sampler = assets.sampler(device, SamplerConfig::default());
+        let texture = assets.texture_or_insert_with(device, queue, name, || {
+            let img = image::DynamicImage::ImageRgba8(alpha_test_image(premultiplied));
+            Texture::from_image(device, queue, &img, Some(name), sampler)
+        });
+        let textures = MaterialTextures::new(device, queue, assets, texture);
+        let mut material = Material::new(
+            device,
+            "Alpha Test",
+            assets,
+            textures,
+            MaterialParams::default(),
+            material_layout,
+        );
+        // A lone quad, which should still show when seen from behind.
+        material.double_sided = true;
+        let material = assets.insert_material(name, material);
+        let mut mesh = Mesh::new(device, &MeshData::quad(), "Alpha Test");
+        mesh.material = Some(material);
+
+        Self {
+            meshes: vec![mesh],
+            materials: vec![material],
+        }
+    }
+
+    // A flat quad of generated bricks with a height map and the normal map
+    // that goes with it, for seeing parallax occlusion mapping sink the
+    // mortar between the bricks.
+    pub fn parallax_test(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut Assets,
+    ) -> Self {
+        let sampler = assets.sampler(
+            device,
+            SamplerConfig {
+                address_mode: wgpu::AddressMode::Repeat,
+                ..Default::default()
+            },
+        );
+        let heights = brick_heights();
+        let mut upload = |name: &str, img: image::RgbaImage, kind: TextureKind| {
+            assets.texture_or_insert_with(device, queue, &kind.cache_key(name), || {
+                let img = image::DynamicImage::ImageRgba8(img);
+                Texture::from_image_of_kind(device, queue, &img, kind, Some(name), sampler.clone())
+            })
+        };
+        let base_colour = upload("<bricks>", brick_colours(&heights), TextureKind::Color);
+        let normal = upload(
+            "<bricks normal>",
+            height_normals(&heights),
+            TextureKind::Data,
+        );
+        let height = upload("<bricks height>", heights, TextureKind::Data);
+
+        let mut textures = MaterialTextures::new(device, queue, assets, base_colour);
+        textures.normal = normal;
+        textures.height = height;
+        let mut params = MaterialParams::new([1.0; 4], 0.9, 0.0);
+        params.height_scale = DEFAULT_HEIGHT_SCALE;
+        let material = Material::new(device, "Bricks", assets, textures, params, material_layout);
+        let material = assets.insert_material("<bricks>", material);
+        let mut mesh = Mesh::new(device, &MeshData::quad(), "Bricks");
+        mesh.material = Some(material);
+
+        Self {
+            meshes: vec![mesh],
+            materials: vec![material],
+        }
+    }
+}
+
+// How deep a height map reaches when its material doesn't say, in texture
+// coordinates.
+const DEFAULT_HEIGHT_SCALE: f32 = 0.05;
+// The brick wall's layout: bricks across and down the texture, and the
+// mortar between them, in texels.
+const BRICK_TEXTURE_SIZE: u32 = 256;
+const BRICK_COLUMNS: u32 = 4;
+const BRICK_ROWS: u32 = 8;
+const MORTAR_WIDTH: u32 = 4;
+
+// Premultiplying the sRGB-encoded values rather than linear ones is slightly
+// off, but close enough to tell the two modes apart.
+fn alpha_test_image(premultiplied: bool) -> image::RgbaImage {
+    const SIZE: u32 = 64;
+    const BORDER: u32 = 8;
+
+    image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let edge = x.min(y).min(SIZE - 1 - x).min(SIZE - 1 - y);
+        let [r, g, b, a] = if edge < BORDER {
+            [255, 128, 0, 128]
+        } else if (x / BORDER + y / BORDER).is_multiple_of(2) {
+            [230, 230, 230, 255]
+        } else {
+            [40, 40, 40, 255]
+        };
+        let scale = |channel: u8| {
+            if premultiplied {
+                (channel as u32 * a as u32 / 255) as u8
+            } else {
+                channel
+            }
+        };
+        image::Rgba([scale(r), scale(g), scale(b), a])
+    })
+}
+
+// Rows of bricks, every other one offset by half a brick. Bricks are white
+// with bevelled edges falling to the black mortar between them.
+fn brick_heights() -> image::RgbaImage {
+    let (width, height) = (
+        BRICK_TEXTURE_SIZE / BRICK_COLUMNS,
+        BRICK_TEXTURE_SIZE / BRICK_ROWS,
+    );
+    image::RgbaImage::from_fn(BRICK_TEXTURE_SIZE, BRICK_TEXTURE_SIZE, |x, y| {
+        let row = y / height;
+        let x = (x + (row % 2) * width / 2) % BRICK_TEXTURE_SIZE;
+        let edge = (x % width)
+            .min(width - 1 - x % width)
+            .min(y % height)
+            .min(height - 1 - y % height);
+        let level = (edge.saturating_sub(MORTAR_WIDTH / 2) * 255 / MORTAR_WIDTH).min(255) as u8;
+        image::Rgba([level, level, level, 255])
+    })
+}
+
+// Brick red, with the recessed mortar a pale grey.
+fn brick_colours(heights: &image::RgbaImage) -> image::RgbaImage {
+    image::RgbaImage::from_fn(heights.width(), heights.height(), |x, y| {
+        let t = heights.get_pixel(x, y)[0] as f32 / 255.0;
+        let mix = |mortar: f32, brick: f32| (mortar + (brick - mortar) * t) as u8;
+        image::Rgba([mix(170.0, 150.0), mix(165.0, 60.0), mix(155.0, 45.0), 255])
+    })
+}
+
+// Tangent-space normals from the slope of the height map, which wraps at the
+// edges like the texture does. A texel is taken as one `MORTAR_WIDTH`th of
+// the height range across, so the bevels lean by 45 degrees.
+fn height_normals(heights: &image::RgbaImage) -> image::RgbaImage {
+    let size = heights.width();
+    let height = |x: u32, y: u32| {
+        heights.get_pixel(x % size, y % size)[0] as f32 / 255.0 * MORTAR_WIDTH as f32
+    };
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let dx = (height(x + 1, y) - height(x + size - 1, y)) / 2.0;
+        // Up the image is +y in tangent space.
+        let dy = (height(x, y + size - 1) - height(x, y + 1)) / 2.0;
+        let length = (dx * dx + dy * dy + 1.0).sqrt();
+        let encode = |n: f32| ((n / length * 0.5 + 0.5) * 255.0).round() as u8;
+        image::Rgba([encode(-dx), encode(-dy), encode(1.0), 255])
+    })
+}
+
+// Resources are looked up in a `res` folder next to the executable so a
+// packaged build can ship its assets alongside it. `cargo run` builds into
+// `target/`, so the crate's own `res` folder is used when that doesn't exist.
+pub fn resource_path(file_name: impl AsRef<Path>) -> PathBuf {
+    let exe_res = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("res")));
+
+    match exe_res {
+        Some(dir) if dir.is_dir() => dir.join(file_name),
+        _ => Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("res")
+            .join(file_name),
+    }
+}
+
+pub type LoadError = Box<dyn Error + Send + Sync>;
+
+pub enum TextureData {
+    Image(image::DynamicImage),
+    Compressed(CompressedImage),
+    // Uploaded as the embedded checkerboard.
+    Missing,
+}
+
+impl TextureData {
+    // Prefers a compressed KTX2 variant the device can sample over decoding
+    // the image itself.
+    fn read(path: &Path, features: wgpu::Features) -> Result<(String, Self), LoadError> {
+        let ktx2_path = match path.extension() {
+            Some(extension) if extension == "ktx2" => Some(path.to_path_buf()),
+            _ => compressed_variant(path, features),
+        };
+
+        match ktx2_path {
+            Some(ktx2_path) => {
+                let bytes = std::fs::read(&ktx2_path)?;
+                let image = CompressedImage::from_ktx2(&bytes, features)?;
+                Ok((
+                    ktx2_path.to_string_lossy().into_owned(),
+                    Self::Compressed(image),
+                ))
+            }
+            None => Ok((
+                path.to_string_lossy().into_owned(),
+                Self::Image(image::open(path)?),
+            )),
+        }
+    }
+}
+
+pub struct MaterialData {
+    pub name: String,
+    // Keyed by path so `upload` can reuse a texture already in `Assets`.
+    pub diffuse_texture: Option<(String, TextureData)>,
+    pub normal_texture: Option<(String, TextureData)>,
+    pub metallic_roughness_texture: Option<(String, TextureData)>,
+    pub occlusion_texture: Option<(String, TextureData)>,
+    pub emissive_texture: Option<(String, TextureData)>,
+    pub height_texture: Option<(String, TextureData)>,
+    pub params: MaterialParams,
+}
+
+pub struct MeshEntry {
+    pub name: String,
+    pub data: MeshData,
+    pub material: Option<usize>,
+}
+
+// A model parsed and decoded on the CPU, not yet uploaded. Reading one does no
+// GPU work, so it can happen off the main thread.
+pub struct ModelData {
+    pub materials: Vec<MaterialData>,
+    pub meshes: Vec<MeshEntry>,
+}
+
+impl ModelData {
+    // `features` decides which compressed texture formats may be used.
+    pub fn read(path: &Path, features: wgpu::Features) -> Result<Self, LoadError> {
+        let (obj_models, obj_materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        )?;
+
+        let obj_materials = obj_materials.unwrap_or_else(|e| {
+            eprintln!("Failed to load materials for {}: {e}", path.display());
+            Vec::new()
+        });
+
+        let material_dir = path.parent().unwrap_or(Path::new("."));
+        let materials = obj_materials
+            .into_iter()
+            .map(|m| {
+                let read_texture = |texture_name: &String| {
+                    let texture_path = material_dir.join(texture_name);
+                    TextureData::read(&texture_path, features).unwrap_or_else(|e| {
+                        eprintln!(
+                            "Failed to load {}: {e}, using the checkerboard",
+                            texture_path.display()
+                        );
+                        (String::new(), TextureData::Missing)
+                    })
+                };
+                let diffuse_texture = m.diffuse_texture.as_ref().map(read_texture);
+                let normal_texture = m.normal_texture.as_ref().map(read_texture);
+                // MTL has no occlusion map, but exporters commonly put it in
+                // the ambient map.
+                let occlusion_texture = m.ambient_texture.as_ref().map(read_texture);
+                let metallic_roughness_texture = read_metallic_roughness(&m, material_dir);
+                let emissive_texture = m
+                    .unknown_param
+                    .get("map_Ke")
+                    .map(|name| read_texture(&name.trim().to_string()));
+                // MTL's displacement map, used as a height map for parallax.
+                let height_texture = m
+                    .unknown_param
+                    .get("disp")
+                    .map(|name| read_texture(&name.trim().to_string()));
+                let [r, g, b] = m.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+                let mut params = MaterialParams::new(
+                    [r, g, b, m.dissolve.unwrap_or(1.0)],
+                    roughness(&m),
+                    param(&m, "Pm").unwrap_or(0.0),
+                );
+                params.emissive = emissive(&m);
+                // Not part of MTL; lets a material override how strongly it
+                // reflects the environment.
+                params.reflectivity = param(&m, "reflectivity").unwrap_or(1.0);
+                // Also not MTL. Without a height map there's nothing to scale.
+                if height_texture.is_some() {
+                    params.height_scale = param(&m, "height_scale").unwrap_or(DEFAULT_HEIGHT_SCALE);
+                }
+
+                MaterialData {
+                    name: m.name,
+                    diffuse_texture,
+                    normal_texture,
+                    metallic_roughness_texture,
+                    occlusion_texture,
+                    emissive_texture,
+                    height_texture,
+                    params,
+                }
+            })
+            .collect();
+
+        let meshes = obj_models
+            .into_iter()
+            .map(|m| {
+                let colour = |i: usize| match m.mesh.vertex_color.get(i * 3..i * 3 + 3) {
+                    Some(c) => [c[0], c[1], c[2]],
+                    None => [1.0, 1.0, 1.0],
+                };
+                let tex_coords = |i: usize| match m.mesh.texcoords.get(i * 2..i * 2 + 2) {
+                    // OBJ puts the texture origin at the bottom left, wgpu at the top left.
+                    Some(uv) => [uv[0], 1.0 - uv[1]],
+                    None => [0.0, 0.0],
+                };
+
+                let normal = |i: usize| match m.mesh.normals.get(i * 3..i * 3 + 3) {
+                    Some(n) => [n[0], n[1], n[2]],
+                    None => [0.0, 0.0, 1.0],
+                };
+
+                let vertices = (0..m.mesh.positions.len() / 3)
+                    .map(|i| Vertex {
+                        position: [
+                            m.mesh.positions[i * 3],
+                            m.mesh.positions[i * 3 + 1],
+                            m.mesh.positions[i * 3 + 2],
+                        ],
+                        colour: colour(i),
+                        tex_coords: tex_coords(i),
+                        normal: normal(i),
+                        // Filled in by `compute_tangents` below.
+                        tangent: [1.0, 0.0, 0.0, 1.0],
+                    })
+                    .collect();
+
+                let mut data = MeshData {
+                    vertices,
+                    indices: m.mesh.indices,
+                };
+                if m.mesh.normals.is_empty() {
+                    data.compute_normals();
+                }
+                data.compute_tangents();
+
+                MeshEntry {
+                    name: m.name,
+                    data,
+                    material: m.mesh.material_id,
+                }
+            })
+            .collect();
+
+        Ok(Self { materials, meshes })
+    }
+
+    // Textures go through `assets` so materials that share a map share the
+    // GPU texture. Materials are registered under `name`, the model's path,
+    // followed by their own name.
+    pub fn upload(
+        self,
+        name: &str,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &wgpu::BindGroupLayout,
+        assets: &mut Assets,
+    ) -> Model {
+        // OBJ texture coordinates commonly run outside 0..1 to tile a map.
+        let sampler_config = SamplerConfig {
+            address_mode: wgpu::AddressMode::Repeat,
+            ..Default::default()
+        };
+
+        let materials = self
+            .materials
+            .into_iter()
+            .map(|m| {
+                let mut upload = |texture: &Option<(String, TextureData)>, kind| {
+                    texture.as_ref().map(|(name, data)| {
+                        upload_texture(device, queue, assets, sampler_config, name, data, kind)
+                    })
+                };
+                let base_colour = upload(&m.diffuse_texture, TextureKind::Color);
+                let normal = upload(&m.normal_texture, TextureKind::Data);
+                let metallic_roughness = upload(&m.metallic_roughness_texture, TextureKind::Data);
+                let occlusion = upload(&m.occlusion_texture, TextureKind::Data);
+                let emissive = upload(&m.emissive_texture, TextureKind::Color);
+                let height = upload(&m.height_texture, TextureKind::Data);
+
+                let base_colour = base_colour
+                    .unwrap_or_else(|| assets.solid_texture(device, queue, [255, 255, 255, 255]));
+                let mut textures = MaterialTextures::new(device, queue, assets, base_colour);
+                textures.normal = normal.unwrap_or(textures.normal);
+                textures.metallic_roughness =
+                    metallic_roughness.unwrap_or(textures.metallic_roughness);
+                textures.occlusion = occlusion.unwrap_or(textures.occlusion);
+                textures.emissive = emissive.unwrap_or(textures.emissive);
+                textures.height = height.unwrap_or(textures.height);
+                let material =
+                    Material::new(device, &m.name, assets, textures, m.params, material_layout);
+                assets.insert_material(&format!("{name}: {}", m.name), material)
+            })
+            .collect::<Vec<_>>();
+
+        let meshes = self
+            .meshes
+            .into_iter()
+            .map(|entry| {
+                let mut mesh = Mesh::new(device, &entry.data, &entry.name);
+                mesh.material = entry
+                    .material
+                    .and_then(|index| materials.get(index).copied());
+                mesh
+            })
+            .collect();
+
+        Model { meshes, materials }
+    }
+}
+
+// `kind` picks the format for images; compressed textures carry their own.
+// A missing one still shows the checkerboard, which makes the broken path
+// obvious.
+fn upload_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    assets: &mut Assets,
+    sampler_config: SamplerConfig,
+    name: &str,
+    data: &TextureData,
+    kind: TextureKind,
+) -> Handle<Texture> {
+    if let TextureData::Missing = data {
+        return assets.checkerboard_texture(device, queue);
+    }
+
+    let sampler = assets.sampler(device, sampler_config);
+    let key = kind.cache_key(name);
+    assets.texture_or_insert_with(device, queue, &key, || match data {
+        TextureData::Image(img) => {
+            Texture::from_image_of_kind(device, queue, img, kind, Some(name), sampler)
+        }
+        TextureData::Compressed(image) => {
+            Texture::from_compressed(device, queue, image, name, sampler)
+        }
+        TextureData::Missing => unreachable!(),
+    })
+}
+
+// MTL's PBR extension keeps roughness (`map_Pr`) and metallic (`map_Pm`) in
+// separate greyscale maps. They're packed into one image the way glTF stores
+// them, roughness in green and metallic in blue, with white standing in for
+// whichever is missing.
+fn read_metallic_roughness(
+    material: &tobj::Material,
+    material_dir: &Path,
+) -> Option<(String, TextureData)> {
+    let read = |key: &str| {
+        let path = material_dir.join(material.unknown_param.get(key)?.trim());
+        match image::open(&path) {
+            Ok(img) => Some((path, img.to_luma8())),
+            Err(e) => {
+                eprintln!("Failed to load {}: {e}", path.display());
+                None
+            }
+        }
+    };
+    let roughness = read("map_Pr");
+    let metallic = read("map_Pm");
+
+    let (width, height) = match (&roughness, &metallic) {
+        (Some((_, img)), _) | (None, Some((_, img))) => img.dimensions(),
+        (None, None) => return None,
+    };
+    // The maps may differ in size; both are sampled at the first one's.
+    let channel = |map: &Option<(PathBuf, image::GrayImage)>| {
+        map.as_ref().map(|(_, img)| {
+            image::imageops::resize(img, width, height, image::imageops::FilterType::Triangle)
+        })
+    };
+    let roughness_channel = channel(&roughness);
+    let metallic_channel = channel(&metallic);
+    let packed = image::RgbaImage::from_fn(width, height, |x, y| {
+        let value = |img: &Option<image::GrayImage>| match img {
+            Some(img) => img.get_pixel(x, y)[0],
+            None => 255,
+        };
+        image::Rgba([
+            255,
+            value(&roughness_channel),
+            value(&metallic_channel),
+            255,
+        ])
+    });
+
+    let name = |map: &Option<(PathBuf, image::GrayImage)>| match map {
+        Some((path, _)) => path.to_string_lossy().into_owned(),
+        None => String::new(),
+    };
+    Some((
+        format!(
+            "<metallic-roughness {}+{}>",
+            name(&roughness),
+            name(&metallic)
+        ),
+        TextureData::Image(image::DynamicImage::ImageRgba8(packed)),
+    ))
+}
+
+// PBR extensions to MTL are stored by tobj as unknown parameters.
+fn param(material: &tobj::Material, name: &str) -> Option<f32> {
+    material.unknown_param.get(name)?.trim().parse().ok()
+}
+
+// `Ke` from the PBR extension. An emissive map with no `Ke` is shown at full
+// strength rather than multiplied away to black.
+fn emissive(material: &tobj::Material) -> [f32; 3] {
+    let ke = material.unknown_param.get("Ke").and_then(|value| {
+        let components: Vec<f32> = value
+            .split_whitespace()
+            .filter_map(|c| c.parse().ok())
+            .collect();
+        match components[..] {
+            [r, g, b] => Some([r, g, b]),
+            [value] => Some([value; 3]),
+            _ => None,
+        }
+    });
+    match ke {
+        Some(ke) => ke,
+        None if material.unknown_param.contains_key("map_Ke") => [1.0; 3],
+        None => [0.0; 3],
+    }
+}
+
+// Prefers an explicit `Pr`, otherwise approximates roughness from the Phong
+// specular exponent.
+fn roughness(material: &tobj::Material) -> f32 {
+    param(material, "Pr").unwrap_or_else(|| match material.shininess {
+        Some(shininess) => (2.0 / (shininess.max(0.0) + 2.0)).sqrt(),
+        None => 1.0,
+    })
+}
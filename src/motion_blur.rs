@@ -0,0 +1,324 @@
+use std::ops::Range;
+
+use cgmath::SquareMatrix;
+
+use crate::{
+    instance::InstanceRaw,
+    mesh::{DrawMesh, Mesh},
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    texture::Texture,
+    uniform::UniformBuffer,
+    Vertex,
+};
+
+const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+// Last frame's instance transforms, after the vertex and instance attributes.
+const PREVIOUS_INSTANCE_ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+    9 => Float32x4,
+    10 => Float32x4,
+    11 => Float32x4,
+    12 => Float32x4,
+];
+
+// Matches `VelocityUniform` in velocity.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VelocityUniform {
+    view_proj: [[f32; 4]; 4],
+    previous_view_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+}
+
+// Matches `MotionBlurUniform` in motion_blur.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniform {
+    sample_count: u32,
+    shutter_scale: f32,
+    _padding: [u32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionBlurSettings {
+    // How many taps each pixel takes along its motion.
+    pub sample_count: u32,
+    // How much of a frame's motion is blurred over, like the fraction of the
+    // frame a camera's shutter is open for.
+    pub shutter_scale: f32,
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        Self {
+            sample_count: 8,
+            shutter_scale: 0.5,
+        }
+    }
+}
+
+pub struct MotionBlurGeometry<'a> {
+    pub meshes: &'a [Mesh],
+    pub instance_buffer: &'a wgpu::Buffer,
+    // The same instances as they were last frame.
+    pub previous_instance_buffer: &'a wgpu::Buffer,
+    // Runs of instances to draw, leaving out hidden ones.
+    pub instances: &'a [Range<u32>],
+}
+
+// The velocity target and its depth, rebuilt when the window is resized.
+struct VelocityTargets {
+    depth: Texture,
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// Blurs along each pixel's motion since the last frame. A velocity pass
+// draws the meshes with this frame's and last frame's camera and instance
+// transforms, so both camera and object motion are caught, and fills the
+// sky with the camera's motion alone. The blur then gathers along it.
+pub struct MotionBlur {
+    velocity_pipeline: wgpu::RenderPipeline,
+    background_pipeline: wgpu::RenderPipeline,
+    velocity_uniform: UniformBuffer<VelocityUniform>,
+    gather: FullscreenPass,
+    uniform_buffer: UniformBuffer<MotionBlurUniform>,
+    velocity_layout: wgpu::BindGroupLayout,
+    targets: VelocityTargets,
+}
+
+impl MotionBlur {
+    pub fn new(
+        device: &wgpu::Device,
+        settings: MotionBlurSettings,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let identity: [[f32; 4]; 4] = cgmath::Matrix4::identity().into();
+        let velocity_uniform = UniformBuffer::new(
+            device,
+            &VelocityUniform {
+                view_proj: identity,
+                previous_view_proj: identity,
+                inverse_view_proj: identity,
+            },
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            "Velocity Uniform",
+        );
+        let velocity_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Velocity Pipeline Layout"),
+                bind_group_layouts: &[velocity_uniform.layout()],
+                push_constant_ranges: &[],
+            });
+        let velocity_shader = device.create_shader_module(wgpu::include_wgsl!("velocity.wgsl"));
+        let velocity_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Velocity Pipeline"),
+            layout: Some(&velocity_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &velocity_shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    Vertex::desc(),
+                    InstanceRaw::desc(),
+                    wgpu::VertexBufferLayout {
+                        attributes: &PREVIOUS_INSTANCE_ATTRIBS,
+                        ..InstanceRaw::desc()
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &velocity_shader,
+                entry_point: "fs_main",
+                targets: &[Some(VELOCITY_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let background_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Velocity Background Pipeline"),
+            layout: Some(&velocity_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &velocity_shader,
+                entry_point: "vs_background",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &velocity_shader,
+                entry_point: "fs_background",
+                targets: &[Some(VELOCITY_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Only where no mesh was drawn.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &MotionBlurUniform {
+                sample_count: settings.sample_count,
+                shutter_scale: settings.shutter_scale,
+                _padding: [0; 2],
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "Motion Blur Uniform",
+        );
+        let velocity_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Motion Blur Velocity Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            }],
+        });
+        let gather_shader = device.create_shader_module(wgpu::include_wgsl!("motion_blur.wgsl"));
+        let gather = FullscreenPass::new(
+            device,
+            "Motion Blur",
+            &gather_shader,
+            "fs_main",
+            &[uniform_buffer.layout(), &velocity_layout],
+        );
+
+        let targets = VelocityTargets::new(device, &velocity_layout, width, height);
+        Self {
+            velocity_pipeline,
+            background_pipeline,
+            velocity_uniform,
+            gather,
+            uniform_buffer,
+            velocity_layout,
+            targets,
+        }
+    }
+
+    // Both matrices are the main camera's, without TAA's jitter.
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        view_proj: cgmath::Matrix4<f32>,
+        previous_view_proj: cgmath::Matrix4<f32>,
+    ) {
+        self.velocity_uniform.write(
+            queue,
+            &VelocityUniform {
+                view_proj: view_proj.into(),
+                previous_view_proj: previous_view_proj.into(),
+                inverse_view_proj: view_proj
+                    .invert()
+                    .unwrap_or_else(cgmath::Matrix4::identity)
+                    .into(),
+            },
+        );
+    }
+
+    pub fn draw_velocity(&self, encoder: &mut wgpu::CommandEncoder, geometry: MotionBlurGeometry) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Velocity Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.targets.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.targets.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.velocity_pipeline);
+        render_pass.set_bind_group(0, self.velocity_uniform.bind_group(), &[]);
+        render_pass.set_vertex_buffer(1, geometry.instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, geometry.previous_instance_buffer.slice(..));
+        for mesh in geometry.meshes {
+            for instances in geometry.instances {
+                render_pass.draw_mesh_instanced(mesh, instances.clone());
+            }
+        }
+
+        render_pass.set_pipeline(&self.background_pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl PostEffect for MotionBlur {
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.targets = VelocityTargets::new(device, &self.velocity_layout, width, height);
+    }
+
+    fn draw(&self, frame: PostFrame) {
+        self.gather.draw(
+            frame,
+            &[self.uniform_buffer.bind_group(), &self.targets.bind_group],
+        );
+    }
+}
+
+impl VelocityTargets {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, width: u32, height: u32) -> Self {
+        let depth = Texture::create_depth_texture(device, width, height, 1, "Velocity Depth");
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Velocity Texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: VELOCITY_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Velocity Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+
+        Self {
+            depth,
+            _texture: texture,
+            view,
+            bind_group,
+        }
+    }
+}
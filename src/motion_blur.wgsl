@@ -0,0 +1,41 @@
+// Blurs each pixel along its motion, drawn with post_process.wgsl's vertex
+// stage. See motion_blur.rs.
+
+// The longest blur, in uv, so a sudden jump of the camera doesn't smear the
+// whole screen.
+const MAX_BLUR: f32 = 0.05;
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct MotionBlurUniform {
+    sample_count: u32,
+    shutter_scale: f32,
+};
+@group(1) @binding(0)
+var<uniform> motion_blur: MotionBlurUniform;
+
+// From velocity.wgsl; may be a different size to the frame.
+@group(2) @binding(0)
+var t_velocity: texture_2d<f32>;
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let centre = textureSampleLevel(t_input, s_input, uv, 0.0);
+    var motion = textureSampleLevel(t_velocity, s_input, uv, 0.0).xy * motion_blur.shutter_scale;
+    let speed = length(motion);
+    if speed > MAX_BLUR {
+        motion *= MAX_BLUR / speed;
+    }
+
+    // Spread evenly along the motion, centred on this pixel.
+    let count = max(motion_blur.sample_count, 2u);
+    var colour = vec3<f32>(0.0);
+    for (var i = 0u; i < count; i++) {
+        let t = f32(i) / f32(count - 1u) - 0.5;
+        colour += textureSampleLevel(t_input, s_input, uv + motion * t, 0.0).rgb;
+    }
+    return vec4<f32>(colour / f32(count), centre.a);
+}
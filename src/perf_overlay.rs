@@ -0,0 +1,183 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{text::TextBatch, FrameStats};
+
+// How many frames the graph and the averages cover.
+const HISTORY: usize = 120;
+// Where the overlay sits, from the top left of the window.
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const BAR_WIDTH: f32 = 2.0;
+// Pixels of bar per millisecond, and the tallest a bar gets.
+const GRAPH_SCALE: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 80.0;
+// Frames that fit in a 60Hz and a 30Hz refresh.
+const GOOD_FRAME_MS: f32 = 1000.0 / 60.0;
+const SLOW_FRAME_MS: f32 = 1000.0 / 30.0;
+
+const PANEL_COLOUR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const TEXT_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const GOOD_COLOUR: [f32; 4] = [0.2, 0.9, 0.3, 1.0];
+const SLOW_COLOUR: [f32; 4] = [0.95, 0.8, 0.2, 1.0];
+const BAD_COLOUR: [f32; 4] = [0.95, 0.25, 0.2, 1.0];
+const TARGET_LINE_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 0.4];
+
+// What the overlay shows of the GPU, which the caller only needs to gather
+// while it's visible.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GpuStats {
+    // None without timestamp queries, or until the first read comes back.
+    pub frame_time: Option<Duration>,
+    // An estimate of the video memory the renderer has allocated.
+    pub vram_bytes: u64,
+}
+
+// Frame timing and the last frame's counts in one corner of the window, with
+// a graph of recent frame times. Nothing is recorded or drawn while it's
+// hidden, and the history starts afresh each time it's shown.
+#[derive(Default)]
+pub struct PerfOverlay {
+    visible: bool,
+    // The most recent frame last.
+    frame_times: VecDeque<Duration>,
+}
+
+impl PerfOverlay {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.frame_times.clear();
+    }
+
+    pub fn record_frame(&mut self, dt: Duration) {
+        if !self.visible {
+            return;
+        }
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+    }
+
+    fn average(&self) -> Option<Duration> {
+        let count = self.frame_times.len() as u32;
+        (count > 0).then(|| self.frame_times.iter().sum::<Duration>() / count)
+    }
+
+    fn worst(&self) -> Option<Duration> {
+        self.frame_times.iter().max().copied()
+    }
+
+    // Adds the overlay to `batch`, if it's shown.
+    pub fn build(&self, batch: &mut TextBatch, stats: &FrameStats, gpu: &GpuStats) {
+        if !self.visible {
+            return;
+        }
+        let timing = match (self.average(), self.worst()) {
+            (Some(average), Some(worst)) => format!(
+                "FPS {:.1}  AVG {:.2}MS  MAX {:.2}MS",
+                1.0 / average.as_secs_f64(),
+                average.as_secs_f64() * 1000.0,
+                worst.as_secs_f64() * 1000.0
+            ),
+            _ => "FPS -".to_string(),
+        };
+        let gpu_time = match gpu.frame_time {
+            Some(time) => format!("GPU {:.2}MS", time.as_secs_f64() * 1000.0),
+            None => "GPU N/A".to_string(),
+        };
+        let lines = [
+            timing,
+            format!(
+                "{gpu_time}  VRAM EST {:.1}MB",
+                gpu.vram_bytes as f64 / (1024.0 * 1024.0)
+            ),
+            format!("DRAWS {}  TRIS {}", stats.draw_calls, stats.triangles),
+            format!("VERTS {}  INDICES {}", stats.vertices, stats.indices),
+            format!(
+                "CULLED {}/{}  CHUNKS {}/{}",
+                stats.cull.culled,
+                stats.cull.tested,
+                stats.cull.chunks_culled,
+                stats.cull.chunks_tested
+            ),
+        ];
+
+        let graph_width = HISTORY as f32 * BAR_WIDTH;
+        let text_width = lines
+            .iter()
+            .map(|line| line.chars().count() as f32 * TextBatch::CHAR_WIDTH)
+            .fold(graph_width, f32::max);
+        let text_height = lines.len() as f32 * TextBatch::LINE_HEIGHT;
+        batch.rect(
+            [
+                MARGIN,
+                MARGIN,
+                text_width + PADDING * 2.0,
+                text_height + GRAPH_HEIGHT + PADDING * 3.0,
+            ],
+            PANEL_COLOUR,
+        );
+
+        let x = MARGIN + PADDING;
+        let mut y = MARGIN + PADDING;
+        for line in &lines {
+            batch.text(x, y, line, TEXT_COLOUR);
+            y += TextBatch::LINE_HEIGHT;
+        }
+
+        // Bars grow up from the bottom of the graph, oldest on the left.
+        let bottom = y + PADDING + GRAPH_HEIGHT;
+        for (i, dt) in self.frame_times.iter().enumerate() {
+            let ms = dt.as_secs_f32() * 1000.0;
+            let height = (ms * GRAPH_SCALE).clamp(1.0, GRAPH_HEIGHT);
+            let colour = if ms <= GOOD_FRAME_MS {
+                GOOD_COLOUR
+            } else if ms <= SLOW_FRAME_MS {
+                SLOW_COLOUR
+            } else {
+                BAD_COLOUR
+            };
+            batch.rect(
+                [x + i as f32 * BAR_WIDTH, bottom - height, BAR_WIDTH, height],
+                colour,
+            );
+        }
+        batch.rect(
+            [x, bottom - GOOD_FRAME_MS * GRAPH_SCALE, graph_width, 1.0],
+            TARGET_LINE_COLOUR,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_most_recent_frames() {
+        let mut overlay = PerfOverlay::default();
+        overlay.set_visible(true);
+        for ms in 0..HISTORY as u64 + 10 {
+            overlay.record_frame(Duration::from_millis(ms));
+        }
+        assert_eq!(overlay.frame_times.len(), HISTORY);
+        assert_eq!(overlay.frame_times[0], Duration::from_millis(10));
+        assert_eq!(overlay.worst(), Some(Duration::from_millis(129)));
+        assert_eq!(overlay.average(), Some(Duration::from_micros(69_500)));
+    }
+
+    #[test]
+    fn records_nothing_while_hidden() {
+        let mut overlay = PerfOverlay::default();
+        overlay.record_frame(Duration::from_millis(16));
+        assert_eq!(overlay.average(), None);
+
+        let mut batch = TextBatch::default();
+        overlay.build(&mut batch, &FrameStats::default(), &GpuStats::default());
+        assert!(batch.is_empty());
+    }
+}
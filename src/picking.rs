@@ -0,0 +1,255 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rotation, Transform, Vector3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{camera::Camera, instance::Instance, mesh::Mesh, Vertex};
+
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    // The ray through a point on a surface of `size`, running from the near
+    // plane towards the far plane.
+    pub fn from_screen(
+        position: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+        camera: &Camera,
+    ) -> Option<Self> {
+        let near = camera.screen_to_world(position, 0.0, size)?;
+        let far = camera.screen_to_world(position, 1.0, size)?;
+        Some(Self {
+            origin: near,
+            direction: (far - near).normalize(),
+        })
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    // The box around this one after `transform`, which is at least as large.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        let centre = self.min.midpoint(self.max);
+        let extent = (self.max - self.min) / 2.0;
+
+        // Each world axis gathers the absolute contribution of every local
+        // axis (Arvo's method).
+        let centre = transform.transform_point(centre);
+        let extent = Vector3::new(
+            transform.x.x.abs() * extent.x
+                + transform.y.x.abs() * extent.y
+                + transform.z.x.abs() * extent.z,
+            transform.x.y.abs() * extent.x
+                + transform.y.y.abs() * extent.y
+                + transform.z.y.abs() * extent.z,
+            transform.x.z.abs() * extent.x
+                + transform.y.z.abs() * extent.y
+                + transform.z.z.abs() * extent.z,
+        );
+
+        Self {
+            min: centre - extent,
+            max: centre + extent,
+        }
+    }
+
+    // Distance along `ray` to where it enters the box, or 0 when it starts
+    // inside.
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let a = (self.min[axis] - ray.origin[axis]) * inverse;
+            let b = (self.max[axis] - ray.origin[axis]) * inverse;
+            // A ray parallel to a slab gives NaN here when it starts on its
+            // edge; `min`/`max` ignore the NaN, keeping the ray in the slab.
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then_some(near)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub centre: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Centred on the box around `positions`, reaching the furthest of them.
+    // Not always the smallest sphere, but it touches at least one vertex.
+    fn around(bounds: &Aabb, positions: &[Point3<f32>]) -> Self {
+        let centre = bounds.min.midpoint(bounds.max);
+        let radius = positions
+            .iter()
+            .map(|p| (p - centre).magnitude())
+            .fold(0.0, f32::max);
+        Self { centre, radius }
+    }
+
+    // Instances only rotate and translate, so the radius carries over.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        Self {
+            centre: transform.transform_point(self.centre),
+            radius: self.radius,
+        }
+    }
+}
+
+// The CPU copy of a mesh's triangles that rays are tested against.
+pub struct PickMesh {
+    bounds: Option<Aabb>,
+    sphere: Option<BoundingSphere>,
+    positions: Vec<Point3<f32>>,
+    indices: Vec<u32>,
+}
+
+impl PickMesh {
+    pub(crate) fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+        let positions: Vec<Point3<f32>> = vertices.iter().map(|v| v.position.into()).collect();
+        let bounds = positions.split_first().map(|(first, rest)| {
+            rest.iter().fold(
+                Aabb {
+                    min: *first,
+                    max: *first,
+                },
+                |bounds, p| Aabb {
+                    min: Point3::new(
+                        bounds.min.x.min(p.x),
+                        bounds.min.y.min(p.y),
+                        bounds.min.z.min(p.z),
+                    ),
+                    max: Point3::new(
+                        bounds.max.x.max(p.x),
+                        bounds.max.y.max(p.y),
+                        bounds.max.z.max(p.z),
+                    ),
+                },
+            )
+        });
+
+        let sphere = bounds.map(|bounds| BoundingSphere::around(&bounds, &positions));
+
+        Self {
+            bounds,
+            sphere,
+            positions,
+            indices: indices.to_vec(),
+        }
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    pub fn sphere(&self) -> Option<BoundingSphere> {
+        self.sphere
+    }
+
+    // Nearest triangle hit, from either side.
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        self.bounds?.intersect(ray)?;
+
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| self.positions[triangle[i] as usize]);
+                intersect_triangle(ray, a, b, c)
+            })
+            .min_by(f32::total_cmp)
+    }
+}
+
+// Möller–Trumbore.
+fn intersect_triangle(ray: &Ray, a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let to_origin = ray.origin - a;
+    let u = to_origin.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(edge1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge2.dot(q) * inverse;
+    (distance >= 0.0).then_some(distance)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub instance: usize,
+    pub mesh: usize,
+    pub distance: f32,
+}
+
+// Finds the closest visible mesh instance along `ray`. Instances only rotate
+// and translate, so distances in their local space match world distances.
+pub fn pick(ray: &Ray, meshes: &[Mesh], instances: &[Instance]) -> Option<Hit> {
+    let mut closest: Option<Hit> = None;
+
+    for (instance_index, instance) in instances.iter().enumerate() {
+        if !instance.visible {
+            continue;
+        }
+        let inverse_rotation = instance.rotation.invert();
+        let local_ray = Ray {
+            origin: Point3::from_vec(
+                inverse_rotation.rotate_vector(ray.origin.to_vec() - instance.position),
+            ),
+            direction: inverse_rotation.rotate_vector(ray.direction),
+        };
+
+        for (mesh_index, mesh) in meshes.iter().enumerate() {
+            let Some(distance) = mesh.pick.intersect(&local_ray) else {
+                continue;
+            };
+            if closest.is_some_and(|hit| hit.distance <= distance) {
+                continue;
+            }
+            closest = Some(Hit {
+                instance: instance_index,
+                mesh: mesh_index,
+                distance,
+            });
+        }
+    }
+
+    closest
+}
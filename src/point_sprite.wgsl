@@ -0,0 +1,61 @@
+struct PointSpriteUniform {
+    viewport_size: vec2<f32>,
+    point_size: f32,
+};
+@group(1) @binding(0)
+var<uniform> sprites: PointSpriteUniform;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct InstanceInput {
+    @location(0) position: vec3<f32>,
+    @location(1) colour: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[in_vertex_index];
+
+    var out: VertexOutput;
+    out.corner = corner;
+    out.colour = instance.colour;
+    // The quad is point_size pixels across, so each corner sits half of
+    // that away from the centre, converted from pixels to NDC. Scaling by w
+    // keeps the size constant after the perspective divide.
+    let centre = camera.view_proj * vec4<f32>(instance.position, 1.0);
+    let offset = corner * sprites.point_size / sprites.viewport_size;
+    out.clip_position = vec4<f32>(centre.xy + offset * centre.w, centre.zw);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = length(in.corner);
+    let feather = fwidth(distance);
+    let alpha = in.colour.a * (1.0 - smoothstep(1.0 - feather, 1.0, distance));
+    if alpha <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.colour.rgb * alpha, alpha);
+}
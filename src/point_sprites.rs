@@ -0,0 +1,162 @@
+use wgpu::util::DeviceExt;
+
+use crate::{texture::Texture, uniform::UniformBuffer, CAMERA_GROUP};
+
+// Sprites are drawn as instanced quads rather than `PrimitiveTopology::PointList`
+// because point primitives are always one pixel wide in WebGPU and their size
+// support varies by backend. A quad lets the fragment shader cut out a disc and
+// feather its edge the same way everywhere.
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+pub struct PointSprite {
+    pub position: [f32; 3],
+    pub colour: [f32; 4],
+}
+
+impl PointSprite {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<PointSprite>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+struct PointSpriteUniform {
+    viewport_size: [f32; 2],
+    point_size: f32,
+    _padding: f32,
+}
+
+pub struct PointSpriteRenderer {
+    pipeline: wgpu::RenderPipeline,
+    instance_buffer: wgpu::Buffer,
+    num_instances: u32,
+    uniform: PointSpriteUniform,
+    uniform_buffer: UniformBuffer<PointSpriteUniform>,
+}
+
+impl PointSpriteRenderer {
+    pub const VERTICES_PER_SPRITE: u32 = 6;
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        user_uniform_layout: &wgpu::BindGroupLayout,
+        camera_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+        sprites: &[PointSprite],
+        point_size: f32,
+    ) -> Self {
+        let uniform = PointSpriteUniform {
+            viewport_size: [config.width as f32, config.height as f32],
+            point_size,
+            _padding: 0.0,
+        };
+
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &uniform,
+            wgpu::ShaderStages::VERTEX,
+            "Point Sprite Uniform",
+        );
+
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Point Sprite Instance Buffer"),
+            contents: bytemuck::cast_slice(sprites),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("point_sprite.wgsl"));
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Point Sprite Pipeline Layout"),
+            bind_group_layouts: &[user_uniform_layout, uniform_buffer.layout(), camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Point Sprite Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[PointSprite::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            // Sprites are blended, so they test against the scene's depth but
+            // don't write to it. LessEqual keeps sprites lying on a surface.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            instance_buffer,
+            num_instances: sprites.len() as u32,
+            uniform,
+            uniform_buffer,
+        }
+    }
+
+    pub fn num_instances(&self) -> u32 {
+        self.num_instances
+    }
+
+    pub fn point_size(&self) -> f32 {
+        self.uniform.point_size
+    }
+
+    pub fn set_point_size(&mut self, queue: &wgpu::Queue, point_size: f32) {
+        self.uniform.point_size = point_size;
+        self.uniform_buffer.write(queue, &self.uniform);
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.uniform.viewport_size = [width as f32, height as f32];
+        self.uniform_buffer.write(queue, &self.uniform);
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, self.uniform_buffer.bind_group(), &[]);
+        render_pass.set_bind_group(CAMERA_GROUP, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..Self::VERTICES_PER_SPRITE, 0..self.num_instances);
+    }
+}
@@ -0,0 +1,345 @@
+use std::time::Duration;
+
+use crate::{
+    color_grading::ColorGrading,
+    depth_of_field::DepthOfField,
+    fxaa::Fxaa,
+    hdr::{HdrPresenter, HdrTarget, SCENE_FORMAT},
+    light_shafts::LightShafts,
+    motion_blur::MotionBlur,
+    tonemap::Tonemap,
+};
+
+// What an effect gets to draw with. `input` and `output` are both
+// `SCENE_FORMAT` and the size of the frame. `depth` is the main camera's
+// single-sampled depth, which may be a different size.
+pub struct PostFrame<'a> {
+    pub device: &'a wgpu::Device,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub input: &'a wgpu::TextureView,
+    pub output: &'a wgpu::TextureView,
+    pub depth: &'a wgpu::TextureView,
+}
+
+// One step of the post-processing chain.
+pub trait PostEffect {
+    // Called when the window changes size, before the next `draw`.
+    fn resize(&mut self, _device: &wgpu::Device, _width: u32, _height: u32) {}
+
+    // Called once a frame before drawing, for effects that animate.
+    fn update(&self, _queue: &wgpu::Queue, _elapsed: Duration) {}
+
+    // Reads the frame so far from `frame.input` and writes every pixel of
+    // `frame.output`.
+    fn draw(&self, frame: PostFrame);
+}
+
+// The two targets the chain alternates between, so each effect reads what
+// the previous one wrote.
+pub struct PostTargets {
+    targets: [HdrTarget; 2],
+}
+
+impl PostTargets {
+    pub fn new(device: &wgpu::Device, presenter: &HdrPresenter, width: u32, height: u32) -> Self {
+        Self {
+            targets: [(); 2].map(|_| presenter.create_target(device, width, height)),
+        }
+    }
+
+    // The effects take turns writing to these, so each holds the output of
+    // every other effect in the last frame's chain.
+    pub fn targets(&self) -> &[HdrTarget] {
+        &self.targets
+    }
+}
+
+// Fullscreen passes run in order on the scene after it's drawn and before
+// it's presented: depth of field and motion blur if they're on, light shafts
+// if they're on and the light is on screen, then `effects`, tonemapping,
+// colour grading if a LUT is in use and FXAA if it's on.
+pub struct PostProcess {
+    depth_of_field: Option<DepthOfField>,
+    motion_blur: Option<MotionBlur>,
+    light_shafts: Option<LightShafts>,
+    effects: Vec<Box<dyn PostEffect>>,
+    tonemap: Tonemap,
+    color_grading: ColorGrading,
+    fxaa: Fxaa,
+    fxaa_enabled: bool,
+}
+
+impl PostProcess {
+    pub fn new(
+        depth_of_field: Option<DepthOfField>,
+        motion_blur: Option<MotionBlur>,
+        light_shafts: Option<LightShafts>,
+        effects: Vec<Box<dyn PostEffect>>,
+        tonemap: Tonemap,
+        color_grading: ColorGrading,
+        fxaa: Fxaa,
+    ) -> Self {
+        Self {
+            depth_of_field,
+            motion_blur,
+            light_shafts,
+            effects,
+            tonemap,
+            color_grading,
+            fxaa,
+            fxaa_enabled: false,
+        }
+    }
+
+    // Includes depth of field, motion blur, light shafts, tonemapping,
+    // grading and FXAA.
+    pub fn effect_count(&self) -> usize {
+        usize::from(self.depth_of_field.is_some())
+            + usize::from(self.motion_blur.is_some())
+            + usize::from(self.light_shafts().is_some())
+            + self.effects.len()
+            + 1
+            + usize::from(self.color_grading.is_active())
+            + usize::from(self.fxaa_enabled)
+    }
+
+    pub fn depth_of_field_mut(&mut self) -> Option<&mut DepthOfField> {
+        self.depth_of_field.as_mut()
+    }
+
+    pub fn motion_blur(&self) -> Option<&MotionBlur> {
+        self.motion_blur.as_ref()
+    }
+
+    // Only while the light is on screen.
+    fn light_shafts(&self) -> Option<&LightShafts> {
+        self.light_shafts
+            .as_ref()
+            .filter(|light_shafts| light_shafts.is_visible())
+    }
+
+    pub fn light_shafts_mut(&mut self) -> Option<&mut LightShafts> {
+        self.light_shafts.as_mut()
+    }
+
+    pub fn tonemap_mut(&mut self) -> &mut Tonemap {
+        &mut self.tonemap
+    }
+
+    pub fn color_grading_mut(&mut self) -> &mut ColorGrading {
+        &mut self.color_grading
+    }
+
+    pub fn fxaa_enabled(&self) -> bool {
+        self.fxaa_enabled
+    }
+
+    pub fn set_fxaa_enabled(&mut self, enabled: bool) {
+        self.fxaa_enabled = enabled;
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.resize(device, width, height);
+        }
+        for effect in &mut self.effects {
+            effect.resize(device, width, height);
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, elapsed: Duration) {
+        for effect in &self.effects {
+            effect.update(queue, elapsed);
+        }
+    }
+
+    // Runs every effect starting from `scene` and returns whichever target
+    // holds the result. `targets` must be the same size as `scene`.
+    pub fn run<'a>(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &'a HdrTarget,
+        depth: &wgpu::TextureView,
+        targets: &'a PostTargets,
+    ) -> &'a HdrTarget {
+        let mut input = scene;
+        let effects = self
+            .depth_of_field
+            .iter()
+            .map(|effect| effect as &dyn PostEffect)
+            .chain(
+                self.motion_blur
+                    .iter()
+                    .map(|effect| effect as &dyn PostEffect),
+            )
+            .chain(self.light_shafts().map(|effect| effect as &dyn PostEffect))
+            .chain(self.effects.iter().map(|effect| effect.as_ref()))
+            .chain(std::iter::once(&self.tonemap as &dyn PostEffect))
+            .chain(
+                self.color_grading
+                    .is_active()
+                    .then_some(&self.color_grading as &dyn PostEffect),
+            )
+            .chain(self.fxaa_enabled.then_some(&self.fxaa as &dyn PostEffect));
+        for (effect, output) in effects.zip(targets.targets.iter().cycle()) {
+            effect.draw(PostFrame {
+                device,
+                encoder,
+                input: input.view(),
+                output: output.view(),
+                depth,
+            });
+            input = output;
+        }
+        input
+    }
+}
+
+// A pipeline that draws a fullscreen triangle with post_process.wgsl's vertex
+// stage and `fragment`'s `entry_point`, which reads the input frame from group
+// 0: the texture at binding 0 and a filtering sampler at binding 1. Any
+// `extra_layouts` follow from group 1.
+pub struct FullscreenPass {
+    pipeline: wgpu::RenderPipeline,
+    input_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl FullscreenPass {
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        fragment: &wgpu::ShaderModule,
+        entry_point: &str,
+        extra_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Input Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group_layouts = std::iter::once(&input_layout)
+            .chain(extra_layouts.iter().copied())
+            .collect::<Vec<_>>();
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{label} Pipeline Layout")),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let vertex = device.create_shader_module(wgpu::include_wgsl!("post_process.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: fragment,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: SCENE_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Input Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            input_layout,
+            sampler,
+        }
+    }
+
+    // `bind_groups` match the `extra_layouts` given to `new`.
+    pub fn draw(&self, frame: PostFrame, bind_groups: &[&wgpu::BindGroup]) {
+        let bind_group = frame.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Input Bind Group"),
+            layout: &self.input_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(frame.input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let mut render_pass = frame
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: frame.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        for (index, bind_group) in (1..).zip(bind_groups) {
+            render_pass.set_bind_group(index, bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// A post effect written by the user, from `RunConfig::post_shaders`. Its
+// `fs_main` follows `FullscreenPass`'s interface.
+pub struct ShaderEffect {
+    pass: FullscreenPass,
+}
+
+impl ShaderEffect {
+    pub fn new(device: &wgpu::Device, label: &str, fragment: &wgpu::ShaderModule) -> Self {
+        Self {
+            pass: FullscreenPass::new(device, label, fragment, "fs_main", &[]),
+        }
+    }
+}
+
+impl PostEffect for ShaderEffect {
+    fn draw(&self, frame: PostFrame) {
+        self.pass.draw(frame, &[]);
+    }
+}
@@ -1,23 +1,623 @@
+// Scales the light from the environment.
+override ambient_strength: f32 = 1.0;
+// Layers the parallax raymarch steps through the height map; 0 turns it off.
+override parallax_steps: u32 = 16u;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Light {
+    direction: vec3<f32>,
+    intensity: f32,
+    colour: vec3<f32>,
+    point_light_count: u32,
+    spot_light_count: u32,
+};
+@group(3) @binding(0)
+var<uniform> light: Light;
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    colour: vec3<f32>,
+    intensity: f32,
+    attenuation: vec2<f32>,
+    casts_shadows: u32,
+    // Which set of six faces in `t_point_shadow` is this light's, or -1.
+    shadow_index: i32,
+};
+@group(3) @binding(1)
+var<storage, read> point_lights: array<PointLight>;
+
+struct SpotLight {
+    position: vec3<f32>,
+    radius: f32,
+    direction: vec3<f32>,
+    intensity: f32,
+    colour: vec3<f32>,
+    inner_cos: f32,
+    attenuation: vec2<f32>,
+    outer_cos: f32,
+};
+@group(3) @binding(2)
+var<storage, read> spot_lights: array<SpotLight>;
+
+// Depth of the scene seen from the directional light, one layer per cascade.
+@group(3) @binding(3)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(4)
+var s_shadow: sampler_comparison;
+struct Shadow {
+    view_proj: array<mat4x4<f32>, 4>,
+    // How far from the camera each cascade reaches.
+    splits: vec4<f32>,
+    // World size of one shadow map texel in each cascade.
+    texel_sizes: vec4<f32>,
+};
+@group(3) @binding(5)
+var<uniform> shadow: Shadow;
+
+// Depth seen from each shadowed point light, six faces per light in the
+// order +x, -x, +y, -y, +z, -z.
+const POINT_SHADOW_COUNT: u32 = 4u;
+@group(3) @binding(6)
+var t_point_shadow: texture_depth_2d_array;
+@group(3) @binding(7)
+var<uniform> point_shadow_view_proj: array<mat4x4<f32>, 24>;
+
+// Which lights reach each froxel of the main camera's view, filled in by
+// cluster.wgsl. The constants match light_clusters.rs.
+const CLUSTER_X: u32 = 16u;
+const CLUSTER_Y: u32 = 9u;
+const CLUSTER_Z: u32 = 24u;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 32u;
+struct ClusterView {
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inverse_proj: mat4x4<f32>,
+    znear: f32,
+    zfar: f32,
+};
+@group(3) @binding(8)
+var<uniform> cluster_view: ClusterView;
+struct Cluster {
+    point_count: u32,
+    spot_count: u32,
+};
+@group(3) @binding(9)
+var<storage, read> clusters: array<Cluster>;
+// Point light indices, then spot light indices, `MAX_LIGHTS_PER_CLUSTER`
+// slots per cluster.
+@group(3) @binding(10)
+var<storage, read> light_indices: array<u32>;
+
+// Image-based lighting baked from the environment by environment.wgsl: the
+// cosine-weighted average of the light around each normal, the environment
+// blurred for roughness 0 to 1 down `PREFILTERED_MIP_COUNT` mip levels, and
+// the split-sum scale and bias for F0 by n.v and roughness.
+const PREFILTERED_MIP_COUNT: u32 = 5u;
+@group(3) @binding(11)
+var t_irradiance: texture_cube<f32>;
+@group(3) @binding(12)
+var t_prefiltered: texture_cube<f32>;
+@group(3) @binding(13)
+var t_brdf_lut: texture_2d<f32>;
+@group(3) @binding(14)
+var s_environment: sampler;
+
+// Screen-space ambient occlusion seen from the main camera, at half its
+// resolution. 1 is unoccluded.
+@group(3) @binding(15)
+var t_ssao: texture_2d<f32>;
+
 struct VertexInput {
 	@location(0) position: vec3<f32>,
 	@location(1) colour: vec3<f32>,
+	@location(2) tex_coords: vec2<f32>,
+	@location(3) normal: vec3<f32>,
+	@location(4) tangent: vec4<f32>,
 }
+struct InstanceInput {
+	@location(5) model_matrix_0: vec4<f32>,
+	@location(6) model_matrix_1: vec4<f32>,
+	@location(7) model_matrix_2: vec4<f32>,
+	@location(8) model_matrix_3: vec4<f32>,
+};
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
 	@location(0) colour: vec3<f32>,
+	@location(1) tex_coords: vec2<f32>,
+	@location(2) world_normal: vec3<f32>,
+	@location(3) world_position: vec3<f32>,
+	@location(4) world_tangent: vec4<f32>,
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
     var out: VertexOutput;
     out.colour = model.colour;
-    out.clip_position = vec4<f32>(model.position, 1.0);
+    out.tex_coords = model.tex_coords;
+    // Instances only rotate and translate, so the model matrix can transform
+    // normals as it is.
+    out.world_normal = (model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
+    let world_tangent = model_matrix * vec4<f32>(model.tangent.xyz, 0.0);
+    out.world_tangent = vec4<f32>(world_tangent.xyz, model.tangent.w);
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+@group(1) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(1) @binding(1)
+var s_diffuse: sampler;
+
+// Added to the texture coordinates; see material.rs.
+struct UvScrollUniform {
+    offset: vec2<f32>,
+    speed: vec2<f32>,
+};
+struct MaterialUniform {
+    base_colour: vec4<f32>,
+    emissive: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+    reflectivity: f32,
+    uv_scroll: UvScrollUniform,
+    height_scale: f32,
+};
+@group(1) @binding(2)
+var<uniform> material: MaterialUniform;
+@group(1) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(4)
+var t_metallic_roughness: texture_2d<f32>;
+@group(1) @binding(5)
+var t_occlusion: texture_2d<f32>;
+@group(1) @binding(6)
+var t_emissive: texture_2d<f32>;
+@group(1) @binding(7)
+var t_height: texture_2d<f32>;
+
+const PI: f32 = 3.14159265;
+
+// Bends the interpolated vertex normal by the normal map, which stores
+// directions in tangent space: x along u, y up the image, z out of the surface.
+fn mapped_normal(in: VertexOutput) -> vec3<f32> {
+    let normal = normalize(in.world_normal);
+    // Re-orthogonalised, as interpolation skews the tangent away from the normal.
+    let tangent = normalize(in.world_tangent.xyz - normal * dot(normal, in.world_tangent.xyz));
+    let bitangent = cross(normal, tangent) * in.world_tangent.w;
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    let sampled = textureSample(t_normal, s_diffuse, in.tex_coords).xyz * 2.0 - 1.0;
+    return normalize(tbn * sampled);
+}
+
+// Looking through the surface at a shallower angle than this would push the
+// lookup arbitrarily far across the texture, so the view is held at it.
+const MIN_PARALLAX_VIEW_Z: f32 = 0.2;
+
+fn surface_depth(uv: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> f32 {
+    return 1.0 - textureSampleGrad(t_height, s_diffuse, uv, ddx, ddy).r;
+}
+
+// Where the view ray that enters the surface at `uv` first meets the height
+// map (parallax occlusion mapping). The ray steps down through
+// `parallax_steps` layers of depth in tangent space until it's below the
+// height map, then interpolates between the last two steps for where it
+// crossed.
+fn parallax_uv(in: VertexOutput, uv: vec2<f32>) -> vec2<f32> {
+    // Taken before anything varies per fragment, for the lookups in the loop.
+    let ddx = dpdx(uv);
+    let ddy = dpdy(uv);
+    if parallax_steps == 0u || material.height_scale <= 0.0 {
+        return uv;
+    }
+
+    let normal = normalize(in.world_normal);
+    let tangent = normalize(in.world_tangent.xyz - normal * dot(normal, in.world_tangent.xyz));
+    let bitangent = cross(normal, tangent) * in.world_tangent.w;
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+    let view = vec3<f32>(dot(view_dir, tangent), dot(view_dir, bitangent), dot(view_dir, normal));
+
+    // Away from the eye for each layer deeper. The bitangent points up the
+    // image, against v.
+    let layer_depth = 1.0 / f32(parallax_steps);
+    let step = vec2<f32>(-view.x, view.y) / max(view.z, MIN_PARALLAX_VIEW_Z)
+        * material.height_scale * layer_depth;
+
+    var current_uv = uv;
+    var depth = 0.0;
+    var height_depth = surface_depth(current_uv, ddx, ddy);
+    for (var i = 0u; i < parallax_steps && depth < height_depth; i++) {
+        current_uv += step;
+        depth += layer_depth;
+        height_depth = surface_depth(current_uv, ddx, ddy);
+    }
+
+    let previous_uv = current_uv - step;
+    let below = height_depth - depth;
+    let above = surface_depth(previous_uv, ddx, ddy) - (depth - layer_depth);
+    if abs(below - above) < 1e-6 {
+        return current_uv;
+    }
+    return mix(current_uv, previous_uv, below / (below - above));
+}
+
+// Back faces are only drawn for double-sided materials. They face away from
+// the normal they were given, so it's flipped, and the bitangent with it, to
+// light them from the side that's showing.
+fn facing_viewer(in: VertexOutput, front_facing: bool) -> VertexOutput {
+    var out = in;
+    if !front_facing {
+        out.world_normal = -in.world_normal;
+    }
     return out;
 }
 
+// The surface as the BRDF sees it, after the material's maps are applied.
+struct Surface {
+    normal: vec3<f32>,
+    diffuse_colour: vec3<f32>,
+    // Reflectance at normal incidence.
+    f0: vec3<f32>,
+    // GGX alpha, the square of perceptual roughness.
+    alpha: f32,
+};
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals.
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Height-correlated Smith masking-shadowing, folded together with the
+// 1 / (4 n.l n.v) of the specular BRDF.
+fn visibility_smith_ggx(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(ggx_v + ggx_l, 1e-5);
+}
+
+fn fresnel_schlick(v_dot_h: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// Light from every direction at once, so rough surfaces don't brighten all
+// the way to white at grazing angles.
+fn fresnel_schlick_roughness(n_dot_v: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+}
+
+// Light reflected towards `view_dir` from the whole environment. The mirrored
+// part is scaled by `reflectivity`, which is 1 for a physically based result.
+fn environment_light(
+    surface: Surface,
+    view_dir: vec3<f32>,
+    roughness: f32,
+    reflectivity: f32,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(surface.normal, view_dir), 0.0001);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, surface.f0, roughness);
+
+    // The irradiance map and lookup table have a single level each, so naming
+    // it changes nothing but lets lighting run after a branch or discard.
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, surface.normal, 0.0).rgb;
+    let diffuse = irradiance * surface.diffuse_colour * (1.0 - fresnel);
+
+    let reflected = reflect(-view_dir, surface.normal);
+    let lod = roughness * f32(PREFILTERED_MIP_COUNT - 1u);
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflected, lod).rgb;
+    let env_brdf = textureSampleLevel(
+        t_brdf_lut,
+        s_environment,
+        vec2<f32>(n_dot_v, roughness),
+        0.0,
+    ).rg;
+    let specular = prefiltered * (fresnel * env_brdf.x + env_brdf.y) * reflectivity;
+
+    return diffuse + specular;
+}
+
+// Light reflected towards `view_dir` per unit of light arriving from
+// `light_dir`, including the cosine term: GGX specular plus Lambert diffuse.
+fn brdf(surface: Surface, light_dir: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = dot(surface.normal, light_dir);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+
+    let half_dir = normalize(view_dir + light_dir);
+    let n_dot_v = max(dot(surface.normal, view_dir), 1e-4);
+    let n_dot_h = max(dot(surface.normal, half_dir), 0.0);
+    let fresnel = fresnel_schlick(max(dot(view_dir, half_dir), 0.0), surface.f0);
+
+    let specular = fresnel
+        * distribution_ggx(n_dot_h, surface.alpha)
+        * visibility_smith_ggx(n_dot_v, n_dot_l, surface.alpha);
+    let diffuse = (1.0 - fresnel) * surface.diffuse_colour / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// How much of the directional light reaches `world_position` according to
+// one cascade, from 0 in full shadow to 1 fully lit. A 3x3 grid of
+// comparisons (percentage-closer filtering) softens the edges.
+fn cascade_shadow(cascade: u32, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // Nudging the point along its normal keeps it from shadowing itself at
+    // grazing angles, where the depth bias alone isn't enough. Texels cover
+    // more ground in further cascades, so the nudge grows with them.
+    let offset = normal * shadow.texel_sizes[cascade] * 1.5;
+    let clip = shadow.view_proj[cascade] * vec4<f32>(world_position + offset, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let sample_uv = uv + vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, sample_uv, cascade, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// How much of the directional light reaches `world_position`, using the
+// nearest cascade that covers it. Over the last part of each cascade the
+// result fades into the next one's so the change in detail doesn't show as a
+// seam. Anything beyond the last cascade is lit.
+fn shadow_factor(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let distance = length(world_position - camera.view_position.xyz);
+    var cascade = 0u;
+    loop {
+        if cascade >= 4u {
+            return 1.0;
+        }
+        if distance < shadow.splits[cascade] {
+            break;
+        }
+        cascade++;
+    }
+
+    let lit = cascade_shadow(cascade, world_position, normal);
+    var start = 0.0;
+    if cascade > 0u {
+        start = shadow.splits[cascade - 1u];
+    }
+    let end = shadow.splits[cascade];
+    let blend = smoothstep(end - (end - start) * 0.1, end, distance);
+    if blend <= 0.0 || cascade == 3u {
+        return mix(lit, 1.0, select(0.0, blend, cascade == 3u));
+    }
+    return mix(lit, cascade_shadow(cascade + 1u, world_position, normal), blend);
+}
+
+// How much of a point light reaches `world_position`, 0 to 1, from the face
+// of its shadow cube that the point lies in.
+fn point_shadow_factor(
+    shadow_index: i32,
+    light_position: vec3<f32>,
+    world_position: vec3<f32>,
+    normal: vec3<f32>,
+) -> f32 {
+    if shadow_index < 0 || u32(shadow_index) >= POINT_SHADOW_COUNT {
+        return 1.0;
+    }
+
+    let position = world_position + normal * 0.02;
+    let from_light = position - light_position;
+    let a = abs(from_light);
+    var face = 0u;
+    if a.x >= a.y && a.x >= a.z {
+        face = select(1u, 0u, from_light.x > 0.0);
+    } else if a.y >= a.z {
+        face = select(3u, 2u, from_light.y > 0.0);
+    } else {
+        face = select(5u, 4u, from_light.z > 0.0);
+    }
+    let layer = u32(shadow_index) * 6u + face;
+
+    let clip = point_shadow_view_proj[layer] * vec4<f32>(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    // The sampler's linear filtering gives a little softening; a full PCF
+    // kernel per point light per fragment would cost too much.
+    return textureSampleCompareLevel(t_point_shadow, s_shadow, uv, layer, ndc.z);
+}
+
+// Distance falloff shared by point and spot lights, faded to exactly zero at
+// `radius`.
+fn distance_attenuation(distance: f32, radius: f32, attenuation: vec2<f32>) -> f32 {
+    let window = pow(saturate(1.0 - pow(distance / radius, 4.0)), 2.0);
+    return window / (1.0 + attenuation.x * distance + attenuation.y * distance * distance);
+}
+
+// The cluster `world_position` falls in, or -1 outside the main camera's
+// view.
+fn cluster_index(world_position: vec3<f32>) -> i32 {
+    let position = vec4<f32>(world_position, 1.0);
+    let depth = -(cluster_view.view * position).z;
+    if depth < cluster_view.znear || depth >= cluster_view.zfar {
+        return -1;
+    }
+    let clip = cluster_view.view_proj * position;
+    let ndc = clip.xy / clip.w;
+    if any(abs(ndc) > vec2<f32>(1.0)) {
+        return -1;
+    }
+
+    let tile = min(
+        vec2<u32>((ndc * 0.5 + 0.5) * vec2<f32>(f32(CLUSTER_X), f32(CLUSTER_Y))),
+        vec2<u32>(CLUSTER_X - 1u, CLUSTER_Y - 1u),
+    );
+    // The inverse of the exponential slicing in cluster.wgsl.
+    let slice = log(depth / cluster_view.znear) / log(cluster_view.zfar / cluster_view.znear);
+    let z = min(u32(slice * f32(CLUSTER_Z)), CLUSTER_Z - 1u);
+    return i32(tile.x + tile.y * CLUSTER_X + z * CLUSTER_X * CLUSTER_Y);
+}
+
+fn point_light(
+    i: u32,
+    surface: Surface,
+    world_position: vec3<f32>,
+    view_dir: vec3<f32>,
+    normal: vec3<f32>,
+) -> vec3<f32> {
+    let point = point_lights[i];
+    let to_light = point.position - world_position;
+    let distance = length(to_light);
+    if distance >= point.radius {
+        return vec3<f32>(0.0);
+    }
+
+    let attenuation = distance_attenuation(distance, point.radius, point.attenuation);
+    let shadowed = point_shadow_factor(point.shadow_index, point.position, world_position, normal);
+    let radiance = point.colour * point.intensity * attenuation * shadowed * PI;
+    return brdf(surface, to_light / distance, view_dir) * radiance;
+}
+
+fn spot_light(
+    i: u32,
+    surface: Surface,
+    world_position: vec3<f32>,
+    view_dir: vec3<f32>,
+) -> vec3<f32> {
+    let spot = spot_lights[i];
+    let to_light = spot.position - world_position;
+    let distance = length(to_light);
+    if distance >= spot.radius {
+        return vec3<f32>(0.0);
+    }
+
+    let light_dir = to_light / distance;
+    // Full strength inside the inner cone, fading out towards the outer.
+    let cone = smoothstep(spot.outer_cos, spot.inner_cos, dot(-light_dir, spot.direction));
+    if cone <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+
+    let attenuation = distance_attenuation(distance, spot.radius, spot.attenuation) * cone;
+    let radiance = spot.colour * spot.intensity * attenuation * PI;
+    return brdf(surface, light_dir, view_dir) * radiance;
+}
+
+// The screen-space occlusion at `world_position`, looked up where the main
+// camera sees it so other cameras' views can use it too.
+fn screen_occlusion(world_position: vec3<f32>) -> f32 {
+    let clip = cluster_view.view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xy / clip.w;
+    if clip.w <= 0.0 || any(abs(ndc) > vec2<f32>(1.0)) {
+        return 1.0;
+    }
+    return textureSampleLevel(t_ssao, s_environment, ndc * vec2<f32>(0.5, -0.5) + 0.5, 0.0).r;
+}
+
+// What the material's maps give for one point of a mesh, before lighting.
+struct MaterialSample {
+    albedo: vec3<f32>,
+    opacity: f32,
+    normal: vec3<f32>,
+    metallic: f32,
+    emission: vec3<f32>,
+    roughness: f32,
+    occlusion: f32,
+    reflectivity: f32,
+};
+
+fn sample_material(vertex: VertexOutput) -> MaterialSample {
+    var in = vertex;
+    in.tex_coords = parallax_uv(in, in.tex_coords + material.uv_scroll.offset);
+    let texel = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let base_colour = texel * material.base_colour;
+    // glTF packs roughness into green and metallic into blue.
+    let metallic_roughness = textureSample(t_metallic_roughness, s_diffuse, in.tex_coords);
+
+    var sampled: MaterialSample;
+    sampled.albedo = base_colour.rgb * in.colour;
+    sampled.opacity = base_colour.a;
+    sampled.normal = mapped_normal(in);
+    sampled.metallic = saturate(material.metallic * metallic_roughness.b);
+    // Clamped so a perfectly smooth surface keeps a visible highlight.
+    sampled.roughness = clamp(material.roughness * metallic_roughness.g, 0.045, 1.0);
+    sampled.occlusion = textureSample(t_occlusion, s_diffuse, in.tex_coords).r;
+    sampled.emission = textureSample(t_emissive, s_diffuse, in.tex_coords).rgb * material.emissive;
+    sampled.reflectivity = material.reflectivity;
+    return sampled;
+}
+
+// The light leaving `world_position` towards the camera. `normal` is the
+// surface's normal before normal mapping, which shadow lookups are offset
+// along.
+fn shade(sampled: MaterialSample, world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var surface: Surface;
+    surface.normal = sampled.normal;
+    surface.diffuse_colour = sampled.albedo * (1.0 - sampled.metallic);
+    surface.f0 = mix(vec3<f32>(0.04), sampled.albedo, sampled.metallic);
+    surface.alpha = sampled.roughness * sampled.roughness;
+
+    let view_dir = normalize(camera.view_position.xyz - world_position);
+    let occlusion = sampled.occlusion * screen_occlusion(world_position);
+
+    // Light intensities are scaled by pi so a white surface facing a light of
+    // intensity 1 shows the light's full colour.
+    let sun_radiance = light.colour * light.intensity * PI
+        * shadow_factor(world_position, normal);
+    let ambient = environment_light(surface, view_dir, sampled.roughness, sampled.reflectivity);
+    var lit = sampled.emission + ambient * ambient_strength * occlusion
+        + brdf(surface, -normalize(light.direction), view_dir) * sun_radiance;
+
+    let cluster = cluster_index(world_position);
+    if cluster < 0 {
+        // Outside the clusters, e.g. seen from another camera.
+        for (var i = 0u; i < light.point_light_count; i++) {
+            lit += point_light(i, surface, world_position, view_dir, normal);
+        }
+        for (var i = 0u; i < light.spot_light_count; i++) {
+            lit += spot_light(i, surface, world_position, view_dir);
+        }
+    } else {
+        let counts = clusters[cluster];
+        let first = u32(cluster) * MAX_LIGHTS_PER_CLUSTER;
+        for (var j = 0u; j < counts.point_count; j++) {
+            lit += point_light(light_indices[first + j], surface, world_position, view_dir, normal);
+        }
+        let first_spot = first + counts.point_count;
+        for (var j = 0u; j < counts.spot_count; j++) {
+            lit += spot_light(light_indices[first_spot + j], surface, world_position, view_dir);
+        }
+    }
+
+    return lit;
+}
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.colour, 1.0);
+fn fs_main(
+    vertex: VertexOutput,
+    @builtin(front_facing) front_facing: bool,
+) -> @location(0) vec4<f32> {
+    let in = facing_viewer(vertex, front_facing);
+    let sampled = sample_material(in);
+    let lit = shade(sampled, in.world_position, normalize(in.world_normal));
+    return vec4<f32>(lit, sampled.opacity);
 }
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+pub enum ShaderSource {
+    Wgsl(PathBuf),
+    SpirV(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum ShaderLoadError {
+    Io(PathBuf, io::Error),
+    SpirVUnsupported(PathBuf),
+    InvalidSpirV(PathBuf),
+}
+
+impl fmt::Display for ShaderLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, error) => write!(f, "failed to read {}: {error}", path.display()),
+            Self::SpirVUnsupported(path) => write!(
+                f,
+                "cannot load {}: built without the `spirv` feature",
+                path.display()
+            ),
+            Self::InvalidSpirV(path) => write!(f, "{} is not a SPIR-V module", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ShaderLoadError {}
+
+impl ShaderSource {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Wgsl(path) | Self::SpirV(path) => path,
+        }
+    }
+
+    pub fn load(&self, device: &wgpu::Device) -> Result<wgpu::ShaderModule, ShaderLoadError> {
+        let path = self.path();
+        let label = path.to_str();
+
+        match self {
+            Self::Wgsl(_) => {
+                let source = fs::read_to_string(path)
+                    .map_err(|e| ShaderLoadError::Io(path.to_path_buf(), e))?;
+                Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label,
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                }))
+            }
+            Self::SpirV(_) => {
+                let bytes =
+                    fs::read(path).map_err(|e| ShaderLoadError::Io(path.to_path_buf(), e))?;
+                load_spirv(device, label, path, &bytes)
+            }
+        }
+    }
+}
+
+// The module is translated by naga rather than passed through, so any backend
+// can consume it once the frontend is compiled in. `make_spirv` panics on bad
+// input, so the header is checked here first.
+#[cfg(feature = "spirv")]
+fn load_spirv(
+    device: &wgpu::Device,
+    label: Option<&str>,
+    path: &Path,
+    bytes: &[u8],
+) -> Result<wgpu::ShaderModule, ShaderLoadError> {
+    const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+    let magic = bytes
+        .get(..4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+    if !bytes.len().is_multiple_of(4) || magic != Some(SPIRV_MAGIC) {
+        return Err(ShaderLoadError::InvalidSpirV(path.to_path_buf()));
+    }
+
+    Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label,
+        source: wgpu::util::make_spirv(bytes),
+    }))
+}
+
+#[cfg(not(feature = "spirv"))]
+fn load_spirv(
+    _device: &wgpu::Device,
+    _label: Option<&str>,
+    path: &Path,
+    _bytes: &[u8],
+) -> Result<wgpu::ShaderModule, ShaderLoadError> {
+    Err(ShaderLoadError::SpirVUnsupported(path.to_path_buf()))
+}
+
+// wgpu 0.18 has no `PipelineCompilationOptions::constants`, so `override`
+// declarations are specialised into plain `const`s before the module is built.
+// Overrides without a value or a default are left for naga to reject.
+pub fn specialise_wgsl(source: &str, constants: &HashMap<String, f64>) -> String {
+    let mut specialised = String::with_capacity(source.len());
+
+    for line in source.lines() {
+        match parse_override(line) {
+            Some(decl) => {
+                let ty = decl.ty.or_else(|| decl.default.map(literal_type));
+                let value = constants
+                    .get(decl.name)
+                    .zip(ty)
+                    .and_then(|(&value, ty)| format_value(value, ty))
+                    .or_else(|| decl.default.map(str::to_string));
+                match (value, decl.ty) {
+                    (Some(value), Some(ty)) => {
+                        specialised.push_str(&format!("const {}: {ty} = {value};", decl.name))
+                    }
+                    (Some(value), None) => {
+                        specialised.push_str(&format!("const {} = {value};", decl.name))
+                    }
+                    (None, _) => specialised.push_str(line),
+                }
+            }
+            None => specialised.push_str(line),
+        }
+        specialised.push('\n');
+    }
+
+    specialised
+}
+
+struct OverrideDecl<'a> {
+    name: &'a str,
+    ty: Option<&'a str>,
+    default: Option<&'a str>,
+}
+
+// Reads `[@id(n)] override name[: ty][ = default];`, ignoring any trailing
+// comment. The name is taken up to the end of the identifier, so it has to be
+// followed by the type or the default rather than merely start the line.
+fn parse_override(line: &str) -> Option<OverrideDecl<'_>> {
+    let code = line.split("//").next().unwrap_or(line).trim();
+    let mut decl = code;
+    while let Some(attribute) = decl.strip_prefix('@') {
+        decl = attribute.split_once(')')?.1.trim_start();
+    }
+    let decl = decl.strip_prefix("override")?;
+    if !decl.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let decl = decl.trim_start().strip_suffix(';')?.trim_end();
+
+    let name_end = decl
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(decl.len());
+    let (name, rest) = decl.split_at(name_end);
+    if name.is_empty() {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let (ty, default) = if let Some(typed) = rest.strip_prefix(':') {
+        match typed.split_once('=') {
+            Some((ty, default)) => (ty.trim(), Some(default.trim())),
+            None => (typed.trim(), None),
+        }
+    } else if let Some(default) = rest.strip_prefix('=') {
+        ("", Some(default.trim()))
+    } else {
+        return None;
+    };
+
+    Some(OverrideDecl {
+        name,
+        ty: (!ty.is_empty()).then_some(ty),
+        default: default.filter(|default| !default.is_empty()),
+    })
+}
+
+// The type an untyped override takes from its default. Abstract ints and
+// floats concretise to i32 and f32.
+fn literal_type(literal: &str) -> &'static str {
+    match literal {
+        "true" | "false" => "bool",
+        _ if literal.ends_with('u') => "u32",
+        _ if literal.ends_with('i') => "i32",
+        _ if literal.starts_with("0x") => "i32",
+        _ if literal.contains(['.', 'e', 'f']) => "f32",
+        _ => "i32",
+    }
+}
+
+fn format_value(value: f64, ty: &str) -> Option<String> {
+    match ty {
+        "f32" => Some(format!("{:?}", value as f32)),
+        "i32" => Some(format!("{}i", value as i32)),
+        "u32" => Some(format!("{}u", value as u32)),
+        "bool" => Some((value != 0.0).to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specialise(source: &str, constants: &[(&str, f64)]) -> String {
+        let constants = constants
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect();
+        specialise_wgsl(source, &constants)
+    }
+
+    #[test]
+    fn replaces_typed_override() {
+        assert_eq!(
+            specialise("override strength: f32 = 1.0;", &[("strength", 0.25)]),
+            "const strength: f32 = 0.25;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_default_without_value() {
+        assert_eq!(
+            specialise("override count: u32 = 4u;", &[]),
+            "const count: u32 = 4u;\n"
+        );
+    }
+
+    #[test]
+    fn infers_type_of_untyped_override() {
+        assert_eq!(
+            specialise("override scale = 2.0;", &[("scale", 3.0)]),
+            "const scale = 3.0;\n"
+        );
+        assert_eq!(
+            specialise("override steps = 8u;", &[("steps", 16.0)]),
+            "const steps = 16u;\n"
+        );
+        assert_eq!(
+            specialise("override enabled = true;", &[("enabled", 0.0)]),
+            "const enabled = false;\n"
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_comment() {
+        assert_eq!(
+            specialise("override bias: f32 = 0.1; // in: view units", &[]),
+            "const bias: f32 = 0.1;\n"
+        );
+        assert_eq!(
+            specialise("override bias: f32 = 0.1; // = 2", &[("bias", 0.5)]),
+            "const bias: f32 = 0.5;\n"
+        );
+    }
+
+    #[test]
+    fn matches_whole_identifier() {
+        let source = "override strength_scale: f32 = 1.0;\noverride strength: f32 = 1.0;";
+        assert_eq!(
+            specialise(source, &[("strength", 2.0)]),
+            "const strength_scale: f32 = 1.0;\nconst strength: f32 = 2.0;\n"
+        );
+    }
+
+    #[test]
+    fn handles_id_attribute() {
+        assert_eq!(
+            specialise("@id(0) override radius: f32 = 1.0;", &[("radius", 4.0)]),
+            "const radius: f32 = 4.0;\n"
+        );
+    }
+
+    #[test]
+    fn leaves_other_lines_alone() {
+        let source = "let overrides: f32 = 1.0;\nvar<private> override_x: f32 = 1.0;";
+        assert_eq!(
+            specialise(source, &[("overrides", 2.0), ("override_x", 2.0)]),
+            format!("{source}\n")
+        );
+    }
+
+    #[test]
+    fn leaves_override_without_value_or_default() {
+        assert_eq!(
+            specialise("override size: u32;", &[]),
+            "override size: u32;\n"
+        );
+        assert_eq!(
+            specialise("override size: u32;", &[("size", 3.0)]),
+            "const size: u32 = 3u;\n"
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use std::ops::Range;
+
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, SquareMatrix, Transform};
+
+use crate::{
+    camera::{Camera, OPENGL_TO_WGPU_MATRIX},
+    instance::InstanceRaw,
+    mesh::{DrawMesh, Mesh},
+    Vertex,
+};
+
+// Matches the array sizes in `Shadow` in shader.wgsl.
+pub const CASCADE_COUNT: usize = 4;
+const SHADOW_MAP_SIZE: u32 = 2048;
+const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// Shadows end this far from the camera, or at its far plane if that's nearer.
+const MAX_SHADOW_DISTANCE: f32 = 60.0;
+// Blend between evenly spaced cascades (0) and logarithmically spaced ones
+// (1). Logarithmic spacing gives the most detail near the camera but leaves
+// the last cascades huge.
+const SPLIT_LAMBDA: f32 = 0.75;
+// How far towards the light beyond a cascade's bounds casters are still
+// drawn, so something off to the side can shadow into view.
+const CASTER_DISTANCE: f32 = 40.0;
+
+// Matches `POINT_SHADOW_COUNT` in shader.wgsl. Point lights past this many
+// that ask for shadows go without.
+pub const MAX_SHADOWED_POINT_LIGHTS: usize = 4;
+const POINT_SHADOW_MAP_SIZE: u32 = 512;
+const POINT_SHADOW_NEAR: f32 = 0.05;
+// Directions and up vectors of the six cube faces, in the order shader.wgsl
+// picks them: +x, -x, +y, -y, +z, -z.
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+// Matches `Shadow` in shader.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    view_proj: [[[f32; 4]; 4]; CASCADE_COUNT],
+    // How far from the camera each cascade reaches.
+    splits: [f32; CASCADE_COUNT],
+    // The width of one shadow map texel in world units, per cascade.
+    texel_sizes: [f32; CASCADE_COUNT],
+}
+
+#[derive(Clone, Copy)]
+pub struct ShadowGeometry<'a> {
+    pub meshes: &'a [Mesh],
+    pub instance_buffer: &'a wgpu::Buffer,
+    // Runs of instances to draw, leaving out hidden ones.
+    pub instances: &'a [Range<u32>],
+}
+
+// A depth texture array whose layers are each rendered from their own
+// view-projection matrix, with the depth-only pipeline that draws them.
+struct ShadowLayers {
+    pipeline: wgpu::RenderPipeline,
+    _texture: wgpu::Texture,
+    // Every layer, for sampling.
+    view: wgpu::TextureView,
+    // One per layer, for rendering into.
+    layer_views: Vec<wgpu::TextureView>,
+    // Each layer's view-projection matrix, read by its depth pass.
+    layer_buffers: Vec<wgpu::Buffer>,
+    layer_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl ShadowLayers {
+    fn new(device: &wgpu::Device, label: &str, size: u32, layer_count: usize) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layer_count as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..layer_count as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some(&format!("{label} Layer {layer}")),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let layer_buffers: Vec<wgpu::Buffer> = (0..layer_count)
+            .map(|layer| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{label} Layer {layer} Buffer")),
+                    size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layer_bind_groups = layer_buffers
+            .iter()
+            .enumerate()
+            .map(|(layer, buffer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(&format!("{label} Layer {layer} Bind Group")),
+                    layout: &layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                })
+            })
+            .collect();
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        // Depth only, so there's no fragment stage.
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Pushes stored depths back so surfaces don't shadow
+                // themselves (shadow acne), more so on slopes facing away
+                // from the light.
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            _texture: texture,
+            view,
+            layer_views,
+            layer_buffers,
+            layer_bind_groups,
+        }
+    }
+
+    fn set_view_proj(&self, queue: &wgpu::Queue, layer: usize, view_proj: [[f32; 4]; 4]) {
+        queue.write_buffer(
+            &self.layer_buffers[layer],
+            0,
+            bytemuck::cast_slice(&view_proj),
+        );
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        geometry: ShadowGeometry,
+        layers: Range<usize>,
+    ) {
+        for layer in layers {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            shadow_pass.set_pipeline(&self.pipeline);
+            shadow_pass.set_bind_group(0, &self.layer_bind_groups[layer], &[]);
+            shadow_pass.set_vertex_buffer(1, geometry.instance_buffer.slice(..));
+            for mesh in geometry.meshes {
+                for instances in geometry.instances {
+                    shadow_pass.draw_mesh_instanced(mesh, instances.clone());
+                }
+            }
+        }
+    }
+}
+
+// Depth of the scene seen from the directional light, rendered each frame
+// before the main pass. The camera's view is cut into `CASCADE_COUNT` slices
+// by distance, each with its own layer of the shadow map, so nearby shadows
+// get more texels than distant ones. The main pass compares against the
+// layer covering each fragment through a comparison sampler to find what the
+// light can't reach.
+pub struct ShadowMap {
+    cascades: ShadowLayers,
+    // Shared with the point light shadows.
+    sampler: wgpu::Sampler,
+    // Every cascade's matrix and range, read by the main pass.
+    uniform_buffer: wgpu::Buffer,
+    light_view_proj: cgmath::Matrix4<f32>,
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device) -> Self {
+        // Linear filtering compares against four texels and blends the
+        // results, which softens shadow edges on top of the shader's PCF.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            cascades: ShadowLayers::new(device, "Shadow Map", SHADOW_MAP_SIZE, CASCADE_COUNT),
+            sampler,
+            uniform_buffer,
+            light_view_proj: cgmath::Matrix4::identity(),
+        }
+    }
+
+    // The outermost cascade's view-projection from the last `update`. It
+    // covers the most of the camera's view.
+    pub fn light_view_proj(&self) -> cgmath::Matrix4<f32> {
+        self.light_view_proj
+    }
+
+    // Fits each cascade around its slice of `camera`'s view, looking along
+    // `direction`, the way the light shines.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        direction: cgmath::Vector3<f32>,
+        camera: &Camera,
+    ) {
+        let direction = direction.normalize();
+        // Any up vector works as long as it isn't parallel to the light.
+        let up = if direction.y.abs() > 0.99 {
+            cgmath::Vector3::unit_z()
+        } else {
+            cgmath::Vector3::unit_y()
+        };
+        let light_view = cgmath::Matrix4::look_at_rh(
+            cgmath::Point3::from_vec(-direction),
+            cgmath::Point3::new(0.0, 0.0, 0.0),
+            up,
+        );
+
+        let near = camera.znear;
+        let far = camera.zfar.min(MAX_SHADOW_DISTANCE);
+        // Fragments choose a cascade by their distance from the eye, not
+        // their depth, and towards the edges of the view a point is
+        // shallower than it is distant. Each cascade is fitted from this
+        // much closer in so it covers every point it's chosen for.
+        let forward = (camera.target - camera.eye).normalize();
+        let far_corner = camera.frustum_corners(near, far)[7];
+        let edge_cos = forward.dot((far_corner - camera.eye).normalize()).max(0.1);
+        let mut uniform = ShadowUniform {
+            view_proj: [[[0.0; 4]; 4]; CASCADE_COUNT],
+            splits: [0.0; CASCADE_COUNT],
+            texel_sizes: [0.0; CASCADE_COUNT],
+        };
+        let mut slice_near = near;
+        for i in 0..CASCADE_COUNT {
+            let t = (i + 1) as f32 / CASCADE_COUNT as f32;
+            let linear = near + (far - near) * t;
+            let logarithmic = near * (far / near).powf(t);
+            let slice_far = linear + (logarithmic - linear) * SPLIT_LAMBDA;
+
+            // A sphere around the slice keeps the cascade the same size as
+            // the camera turns, so shadow edges don't swim.
+            let corners = camera.frustum_corners(slice_near * edge_cos, slice_far);
+            let centre = cgmath::Point3::centroid(&corners);
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(centre))
+                .fold(0.0, f32::max);
+            let texel_size = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+
+            // Moving the cascade in whole texels keeps the same texels
+            // covering the same ground as the camera moves.
+            let centre = light_view.transform_point(centre);
+            let snap = |value: f32| (value / texel_size).round() * texel_size;
+            let (x, y) = (snap(centre.x), snap(centre.y));
+            // The light view looks down -z.
+            let proj = cgmath::ortho(
+                x - radius,
+                x + radius,
+                y - radius,
+                y + radius,
+                -centre.z - radius - CASTER_DISTANCE,
+                -centre.z + radius,
+            );
+            let view_proj = OPENGL_TO_WGPU_MATRIX * proj * light_view;
+
+            uniform.view_proj[i] = view_proj.into();
+            uniform.splits[i] = slice_far;
+            uniform.texel_sizes[i] = texel_size;
+            self.cascades.set_view_proj(queue, i, view_proj.into());
+            self.light_view_proj = view_proj;
+            slice_near = slice_far;
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, geometry: ShadowGeometry) {
+        self.cascades.draw(encoder, geometry, 0..CASCADE_COUNT);
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.cascades.view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+}
+
+// Depth seen from each shadow-casting point light, as six layers per light
+// facing along each axis, together covering every direction like a cubemap.
+// A plain texture array is used rather than a cube array, which not every
+// device supports; the main pass picks the face itself.
+pub struct PointShadowMaps {
+    faces: ShadowLayers,
+    // Every face's view-projection matrix, read by the main pass.
+    uniform_buffer: wgpu::Buffer,
+    // How many lights' faces are in use.
+    light_count: usize,
+}
+
+impl PointShadowMaps {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let face_count = MAX_SHADOWED_POINT_LIGHTS * CUBE_FACES.len();
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Point Shadow Buffer"),
+            size: (face_count * std::mem::size_of::<[[f32; 4]; 4]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            faces: ShadowLayers::new(
+                device,
+                "Point Shadow Map",
+                POINT_SHADOW_MAP_SIZE,
+                face_count,
+            ),
+            uniform_buffer,
+            light_count: 0,
+        }
+    }
+
+    // Points the faces of each light in `lights`, given as (position,
+    // radius), out from it. Depth only needs to reach as far as the light
+    // does.
+    pub fn update(&mut self, queue: &wgpu::Queue, lights: &[([f32; 3], f32)]) {
+        let lights = &lights[..lights.len().min(MAX_SHADOWED_POINT_LIGHTS)];
+        let mut view_projs = Vec::with_capacity(lights.len() * CUBE_FACES.len());
+        for &(position, radius) in lights {
+            let eye = cgmath::Point3::from(position);
+            let proj = cgmath::perspective(
+                cgmath::Deg(90.0),
+                1.0,
+                POINT_SHADOW_NEAR,
+                radius.max(POINT_SHADOW_NEAR * 2.0),
+            );
+            for (direction, up) in CUBE_FACES {
+                let view = cgmath::Matrix4::look_at_rh(
+                    eye,
+                    eye + cgmath::Vector3::from(direction),
+                    up.into(),
+                );
+                let view_proj: [[f32; 4]; 4] = (OPENGL_TO_WGPU_MATRIX * proj * view).into();
+                self.faces.set_view_proj(queue, view_projs.len(), view_proj);
+                view_projs.push(view_proj);
+            }
+        }
+
+        if !view_projs.is_empty() {
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&view_projs));
+        }
+        self.light_count = lights.len();
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, geometry: ShadowGeometry) {
+        self.faces.draw(encoder, geometry, 0..self.face_count());
+    }
+
+    // Faces drawn by `draw`, six for each shadowed light.
+    pub fn face_count(&self) -> usize {
+        self.light_count * CUBE_FACES.len()
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.faces.view
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+}
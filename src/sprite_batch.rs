@@ -0,0 +1,289 @@
+use wgpu::util::DeviceExt;
+
+use crate::{texture::Texture, uniform::UniformBuffer};
+
+// The shape atlas is a row of cells this many texels square.
+const SHAPE_CELL: u32 = 32;
+pub const SHAPE_COUNT: u32 = 4;
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug, PartialEq)]
+struct SpriteVertex {
+    // In pixels from the top left of the window.
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    colour: [f32; 4],
+}
+
+impl SpriteVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// Matches `SpriteUniform` in sprite_batch.wgsl.
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+struct SpriteUniform {
+    viewport_size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+// Textured quads from one atlas, gathered up with `draw` and drawn over the
+// frame by `flush` in a single indexed draw, however many there are. The
+// vertex and index buffers only grow, to the next power of two that fits,
+// and the batch starts empty again after each flush.
+pub struct SpriteBatch {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: UniformBuffer<SpriteUniform>,
+    atlas_bind_group: wgpu::BindGroup,
+    vertices: Vec<SpriteVertex>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // How many sprites the buffers have room for.
+    capacity: usize,
+}
+
+impl SpriteBatch {
+    pub const INDICES_PER_SPRITE: u32 = 6;
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        atlas: &Texture,
+    ) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &SpriteUniform {
+                viewport_size: [config.width as f32, config.height as f32],
+                _padding: [0.0; 2],
+            },
+            wgpu::ShaderStages::VERTEX,
+            "Sprite Uniform",
+        );
+
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Atlas Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Atlas Bind Group"),
+            layout: &atlas_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("sprite_batch.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[uniform_buffer.layout(), &atlas_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SpriteVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let (vertex_buffer, index_buffer) = create_sprite_buffers(device, 1);
+        Self {
+            pipeline,
+            uniform_buffer,
+            atlas_bind_group,
+            vertices: Vec::new(),
+            vertex_buffer,
+            index_buffer,
+            capacity: 1,
+        }
+    }
+
+    pub fn resize(&self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.uniform_buffer.write(
+            queue,
+            &SpriteUniform {
+                viewport_size: [width as f32, height as f32],
+                _padding: [0.0; 2],
+            },
+        );
+    }
+
+    // How many sprites the next flush will draw.
+    pub fn len(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    // Queues a sprite covering `rect`, left, top, width and height in pixels,
+    // showing `uv_rect` of the atlas in the same order, tinted by `colour`.
+    pub fn draw(&mut self, rect: [f32; 4], uv_rect: [f32; 4], colour: [f32; 4]) {
+        let [x, y, width, height] = rect;
+        let [u, v, uv_width, uv_height] = uv_rect;
+        for (dx, dy) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            self.vertices.push(SpriteVertex {
+                position: [x + dx * width, y + dy * height],
+                tex_coords: [u + dx * uv_width, v + dy * uv_height],
+                colour,
+            });
+        }
+    }
+
+    // Uploads the queued sprites and draws them over whatever is already in
+    // `view`, then empties the batch.
+    pub fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        let count = self.len();
+        if count == 0 {
+            return;
+        }
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            (self.vertex_buffer, self.index_buffer) = create_sprite_buffers(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.vertices.clear();
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sprite Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, self.uniform_buffer.bind_group(), &[]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..count as u32 * Self::INDICES_PER_SPRITE, 0, 0..1);
+    }
+}
+
+// Two triangles for each sprite's four corners, which `draw` adds clockwise
+// from the top left.
+fn sprite_indices(capacity: usize) -> Vec<u32> {
+    (0..capacity as u32)
+        .flat_map(|sprite| [0, 1, 2, 0, 2, 3].map(|corner| sprite * 4 + corner))
+        .collect()
+}
+
+// The index buffer never changes for a given capacity, so it's filled in
+// here rather than on each flush.
+fn create_sprite_buffers(device: &wgpu::Device, capacity: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+    let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Sprite Vertex Buffer"),
+        size: (capacity * 4 * std::mem::size_of::<SpriteVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Sprite Index Buffer"),
+        contents: bytemuck::cast_slice(&sprite_indices(capacity)),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+    (vertex_buffer, index_buffer)
+}
+
+// The corner of the shape atlas that `shape` occupies, as a uv rect for
+// `SpriteBatch::draw`.
+pub fn shape_uv_rect(shape: u32) -> [f32; 4] {
+    let width = 1.0 / SHAPE_COUNT as f32;
+    [(shape % SHAPE_COUNT) as f32 * width, 0.0, width, 1.0]
+}
+
+// White shapes on transparent black, side by side: a disc, a square, a
+// diamond and a ring. Sprites tint them with their colour.
+pub fn shape_atlas() -> image::RgbaImage {
+    image::RgbaImage::from_fn(SHAPE_CELL * SHAPE_COUNT, SHAPE_CELL, |x, y| {
+        let half = SHAPE_CELL as f32 / 2.0;
+        // -1..1 across the cell, leaving a texel of margin so filtering
+        // doesn't bleed between neighbours.
+        let scale = half - 1.0;
+        let px = ((x % SHAPE_CELL) as f32 + 0.5 - half) / scale;
+        let py = (y as f32 + 0.5 - half) / scale;
+        let radius = (px * px + py * py).sqrt();
+        let inside = match x / SHAPE_CELL {
+            0 => radius <= 1.0,
+            1 => px.abs().max(py.abs()) <= 0.8,
+            2 => px.abs() + py.abs() <= 1.0,
+            _ => (0.6..=1.0).contains(&radius),
+        };
+        if inside {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_two_triangles_per_sprite() {
+        assert_eq!(sprite_indices(2), [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7]);
+    }
+}
@@ -0,0 +1,516 @@
+use std::ops::Range;
+
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    instance::InstanceRaw,
+    mesh::{DrawMesh, Mesh},
+    texture::Texture,
+    Vertex,
+};
+
+// Matches `KERNEL_SIZE` in ssao.wgsl.
+const KERNEL_SIZE: usize = 16;
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+// Matches `NOISE_SIZE` in ssao.wgsl, and the width of the blur so the blur
+// averages each rotation exactly once.
+const NOISE_SIZE: u32 = 4;
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+// Matches `Ssao` in ssao.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    proj: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    kernel: [[f32; 4]; KERNEL_SIZE],
+    radius: f32,
+    bias: f32,
+    strength: f32,
+    _padding: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsaoSettings {
+    // How far from a point, in world units, other surfaces can occlude it.
+    pub radius: f32,
+    // How much closer a surface must be than a sample to count, which keeps
+    // flat surfaces from shadowing themselves.
+    pub bias: f32,
+    // 0 turns the effect off; 1 lets a fully enclosed point go black.
+    pub strength: f32,
+    // Off leaves the ambient term unoccluded, for comparison. The depth
+    // prepass still runs for the other effects that read it.
+    pub enabled: bool,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            bias: 0.025,
+            strength: 1.0,
+            enabled: true,
+        }
+    }
+}
+
+pub struct SsaoGeometry<'a> {
+    pub meshes: &'a [Mesh],
+    pub instance_buffer: &'a wgpu::Buffer,
+    // Runs of instances to draw, leaving out hidden ones.
+    pub instances: &'a [Range<u32>],
+    pub camera_bind_group: &'a wgpu::BindGroup,
+}
+
+// The render targets, rebuilt when the window is resized. Occlusion is
+// worked out at half resolution and then blurred to hide the sampling noise.
+struct SsaoTargets {
+    // The main camera's depth from the prepass, the main view's size and
+    // single-sampled whatever the scene's sample count. The occlusion pass
+    // needs it before the scene is drawn, so it can't use the resolved scene
+    // depth that depth of field reads. Each pixel has the depth at its
+    // centre rather than the nearest of its samples, which the blur hides.
+    // The prepass only draws the model, so grass, point sprites and light
+    // markers don't occlude anything.
+    depth: Texture,
+    _raw: wgpu::Texture,
+    raw_view: wgpu::TextureView,
+    _blurred: wgpu::Texture,
+    blurred_view: wgpu::TextureView,
+    ao_bind_group: wgpu::BindGroup,
+    blur_bind_group: wgpu::BindGroup,
+}
+
+// Screen-space ambient occlusion for the main camera. A depth prepass gives
+// the view-space position of every pixel, with normals reconstructed from
+// neighbouring depths; points are then sampled in a hemisphere around each
+// one, and the more of them that end up behind other surfaces the less
+// ambient light reaches it. The result is read back by the main pass,
+// which only applies it to the ambient term.
+pub struct Ssao {
+    depth_pipeline: wgpu::RenderPipeline,
+    ao_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    ao_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    _noise: wgpu::Texture,
+    noise_view: wgpu::TextureView,
+    settings: SsaoSettings,
+    targets: SsaoTargets,
+}
+
+impl Ssao {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // The camera uniform starts with its view-projection matrix, which is
+        // all the shadow depth shader reads.
+        let depth_shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+        let depth_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("SSAO Depth Pipeline Layout"),
+                bind_group_layouts: &[camera_layout],
+                push_constant_ranges: &[],
+            });
+        let depth_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("SSAO Depth Pipeline"),
+            layout: Some(&depth_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &depth_shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let ao_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Blur Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("ssao.wgsl"));
+        let fullscreen_pipeline = |label: &str, layout: &wgpu::BindGroupLayout, entry_point| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!("{label} Layout")),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(AO_FORMAT.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let ao_pipeline = fullscreen_pipeline("SSAO Pipeline", &ao_layout, "fs_ao");
+        let blur_pipeline = fullscreen_pipeline("SSAO Blur Pipeline", &blur_layout, "fs_blur");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Buffer"),
+            size: std::mem::size_of::<SsaoUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let noise = create_noise_texture(device, queue);
+        let noise_view = noise.create_view(&wgpu::TextureViewDescriptor::default());
+        let targets = SsaoTargets::new(
+            device,
+            &ao_layout,
+            &blur_layout,
+            &uniform_buffer,
+            &noise_view,
+            width,
+            height,
+        );
+
+        Self {
+            depth_pipeline,
+            ao_pipeline,
+            blur_pipeline,
+            ao_layout,
+            blur_layout,
+            uniform_buffer,
+            _noise: noise,
+            noise_view,
+            settings: SsaoSettings::default(),
+            targets,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.targets = SsaoTargets::new(
+            device,
+            &self.ao_layout,
+            &self.blur_layout,
+            &self.uniform_buffer,
+            &self.noise_view,
+            width,
+            height,
+        );
+    }
+
+    pub fn settings(&self) -> SsaoSettings {
+        self.settings
+    }
+
+    // Takes effect at the next `update`.
+    pub fn set_settings(&mut self, settings: SsaoSettings) {
+        self.settings = settings;
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let proj = camera.projection_matrix();
+        let uniform = SsaoUniform {
+            proj: proj.into(),
+            inverse_proj: proj
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity)
+                .into(),
+            kernel: hemisphere_kernel(),
+            radius: self.settings.radius,
+            bias: self.settings.bias,
+            strength: self.settings.strength,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    // Draws the depth prepass with the main camera, then the occlusion and
+    // blur passes. Must run before the scene is drawn.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, geometry: SsaoGeometry) {
+        {
+            let mut depth_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Depth Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.targets.depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            depth_pass.set_pipeline(&self.depth_pipeline);
+            depth_pass.set_bind_group(0, geometry.camera_bind_group, &[]);
+            depth_pass.set_vertex_buffer(1, geometry.instance_buffer.slice(..));
+            for mesh in geometry.meshes {
+                for instances in geometry.instances {
+                    depth_pass.draw_mesh_instanced(mesh, instances.clone());
+                }
+            }
+        }
+
+        if !self.settings.enabled {
+            clear_pass(encoder, &self.targets.blurred_view);
+            return;
+        }
+
+        fullscreen_pass(
+            encoder,
+            "SSAO Pass",
+            &self.targets.raw_view,
+            &self.ao_pipeline,
+            &self.targets.ao_bind_group,
+        );
+        fullscreen_pass(
+            encoder,
+            "SSAO Blur Pass",
+            &self.targets.blurred_view,
+            &self.blur_pipeline,
+            &self.targets.blur_bind_group,
+        );
+    }
+
+    // Half the window's size; 1 is unoccluded.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.targets.blurred_view
+    }
+}
+
+impl SsaoTargets {
+    fn new(
+        device: &wgpu::Device,
+        ao_layout: &wgpu::BindGroupLayout,
+        blur_layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        noise_view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let depth = Texture::create_depth_texture(device, width, height, 1, "SSAO Depth Texture");
+        let (raw, raw_view) = create_ao_texture(device, "SSAO Texture", width, height);
+        let (blurred, blurred_view) =
+            create_ao_texture(device, "SSAO Blurred Texture", width, height);
+
+        let ao_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Bind Group"),
+            layout: ao_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(noise_view),
+                },
+            ],
+        });
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Blur Bind Group"),
+            layout: blur_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&raw_view),
+            }],
+        });
+
+        Self {
+            depth,
+            _raw: raw,
+            raw_view,
+            _blurred: blurred,
+            blurred_view,
+            ao_bind_group,
+            blur_bind_group,
+        }
+    }
+}
+
+// A half-size target for `width` x `height`.
+fn create_ao_texture(
+    device: &wgpu::Device,
+    label: &str,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: width.div_ceil(2),
+            height: height.div_ceil(2),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: AO_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+// A tile of kernel rotations, repeated across the screen. Each texel turns
+// the kernel about the surface normal by a different golden-angle step, so
+// neighbouring pixels sample in different directions and the blur, which
+// covers exactly one tile, averages the banding away.
+fn create_noise_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+    let rotations = (0..NOISE_SIZE * NOISE_SIZE)
+        .map(|i| {
+            let (sin, cos) = (i as f32 * GOLDEN_ANGLE).sin_cos();
+            [cos, sin]
+        })
+        .collect::<Vec<_>>();
+    device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("SSAO Noise Texture"),
+            size: wgpu::Extent3d {
+                width: NOISE_SIZE,
+                height: NOISE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rg32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        bytemuck::cast_slice(&rotations),
+    )
+}
+
+// Leaves `view` unoccluded.
+fn clear_pass(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("SSAO Clear Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+}
+
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    view: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+// Directions spread evenly over the +z hemisphere along a golden-angle
+// spiral, scaled so more of them fall close to the centre point, where
+// occlusion matters most.
+fn hemisphere_kernel() -> [[f32; 4]; KERNEL_SIZE] {
+    std::array::from_fn(|i| {
+        let t = (i as f32 + 0.5) / KERNEL_SIZE as f32;
+        let z = 1.0 - t;
+        let r = (1.0 - z * z).sqrt();
+        let phi = i as f32 * GOLDEN_ANGLE;
+        let scale = 0.1 + 0.9 * t * t;
+        [r * phi.cos() * scale, r * phi.sin() * scale, z * scale, 0.0]
+    })
+}
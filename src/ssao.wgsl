@@ -0,0 +1,128 @@
+// Screen-space ambient occlusion from the main camera's depth. See ssao.rs.
+
+const KERNEL_SIZE: u32 = 16u;
+const NOISE_SIZE: i32 = 4;
+// Half the width of the square of texels the blur averages.
+const BLUR_RADIUS: i32 = 2;
+
+struct Ssao {
+    proj: mat4x4<f32>,
+    inverse_proj: mat4x4<f32>,
+    // Offsets in a hemisphere around +z, no longer than 1.
+    kernel: array<vec4<f32>, 16>,
+    radius: f32,
+    bias: f32,
+    strength: f32,
+};
+
+@group(0) @binding(0)
+var t_depth: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> ssao: Ssao;
+// Only used by the blur pass.
+@group(0) @binding(2)
+var t_ao: texture_2d<f32>;
+// Kernel rotations as (cos, sin), tiled over the screen.
+@group(0) @binding(3)
+var t_noise: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle that covers the whole target.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn depth_at(uv: vec2<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let texel = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    return textureLoad(t_depth, texel, 0);
+}
+
+fn view_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = ssao.inverse_proj * ndc;
+    return position.xyz / position.w;
+}
+
+fn view_position_at(uv: vec2<f32>) -> vec3<f32> {
+    return view_position(uv, depth_at(uv));
+}
+
+// Towards the camera, from whichever neighbour on each axis is closer in
+// depth, so edges don't blend the normals of two surfaces.
+fn reconstruct_normal(uv: vec2<f32>, position: vec3<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_depth));
+    let right = view_position_at(uv + vec2<f32>(texel.x, 0.0));
+    let left = view_position_at(uv - vec2<f32>(texel.x, 0.0));
+    let down = view_position_at(uv + vec2<f32>(0.0, texel.y));
+    let up = view_position_at(uv - vec2<f32>(0.0, texel.y));
+    let dx = select(position - left, right - position, abs(right.z - position.z) < abs(position.z - left.z));
+    let dy = select(position - up, down - position, abs(down.z - position.z) < abs(position.z - up.z));
+    return normalize(cross(dy, dx));
+}
+
+@fragment
+fn fs_ao(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth = depth_at(in.uv);
+    if depth >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+
+    let position = view_position(in.uv, depth);
+    let normal = reconstruct_normal(in.uv, position);
+    // Varies the kernel's rotation from pixel to pixel so the banding from
+    // only a few samples turns into noise the blur can remove.
+    let noise_texel = vec2<i32>(in.clip_position.xy) % NOISE_SIZE;
+    let random = vec3<f32>(textureLoad(t_noise, noise_texel, 0).xy, 0.0);
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < KERNEL_SIZE; i++) {
+        let sample_position = position + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let clip = ssao.proj * vec4<f32>(sample_position, 1.0);
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+            continue;
+        }
+
+        // View space looks down -z, so a larger z is nearer the camera.
+        let scene_z = view_position_at(uv).z;
+        // Surfaces far in front of the sample are something else entirely
+        // and shouldn't darken it.
+        let in_range = smoothstep(0.0, 1.0, ssao.radius / abs(position.z - scene_z));
+        if scene_z >= sample_position.z + ssao.bias {
+            occlusion += in_range;
+        }
+    }
+
+    let ao = 1.0 - ssao.strength * occlusion / f32(KERNEL_SIZE);
+    return vec4<f32>(saturate(ao), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_ao));
+    let centre = vec2<i32>(in.clip_position.xy);
+    var sum = 0.0;
+    for (var y = -BLUR_RADIUS; y < BLUR_RADIUS; y++) {
+        for (var x = -BLUR_RADIUS; x < BLUR_RADIUS; x++) {
+            let texel = clamp(centre + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            sum += textureLoad(t_ao, texel, 0).r;
+        }
+    }
+    let count = f32(4 * BLUR_RADIUS * BLUR_RADIUS);
+    return vec4<f32>(sum / count, 0.0, 0.0, 1.0);
+}
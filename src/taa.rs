@@ -0,0 +1,201 @@
+use cgmath::SquareMatrix;
+
+use crate::{
+    hdr::{HdrPresenter, HdrTarget},
+    post_process::{FullscreenPass, PostFrame},
+    uniform::UniformBuffer,
+};
+
+// How many frames the jitter pattern takes before repeating.
+const JITTER_PHASES: u32 = 8;
+// How much of each new frame goes into the history once it's built up.
+const CURRENT_FRAME_WEIGHT: f32 = 0.1;
+
+// Matches `TaaUniform` in taa.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    previous_view_proj: [[f32; 4]; 4],
+    current_weight: f32,
+    _padding: [f32; 3],
+}
+
+// Temporal anti-aliasing. The camera is nudged by a different fraction of a
+// pixel each frame, and each frame is blended into a running history, so
+// over a few frames every pixel averages several positions like MSAA would.
+// The history is reprojected through the previous frame's camera so it
+// follows camera motion, and clamped to the colours around each pixel in the
+// new frame so what's moved or been uncovered doesn't smear.
+pub struct Taa {
+    pass: FullscreenPass,
+    uniform_buffer: UniformBuffer<TaaUniform>,
+    history_layout: wgpu::BindGroupLayout,
+    history: [HdrTarget; 2],
+    // The history this frame writes; the other holds last frame's.
+    current: usize,
+    frame: u32,
+    // Cleared when the history is lost, e.g. on resize, so the next frame
+    // starts it again instead of blending in whatever was there.
+    history_valid: bool,
+}
+
+impl Taa {
+    pub fn new(
+        device: &wgpu::Device,
+        presenter: &HdrPresenter,
+        camera_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &TaaUniform {
+                previous_view_proj: cgmath::Matrix4::identity().into(),
+                current_weight: 1.0,
+                _padding: [0.0; 3],
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "TAA Uniform",
+        );
+        let history_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA History Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("taa.wgsl"));
+        let pass = FullscreenPass::new(
+            device,
+            "TAA Resolve",
+            &shader,
+            "fs_main",
+            &[uniform_buffer.layout(), &history_layout, camera_layout],
+        );
+
+        Self {
+            pass,
+            uniform_buffer,
+            history_layout,
+            history: [(); 2].map(|_| presenter.create_target(device, width, height)),
+            current: 0,
+            frame: 0,
+            history_valid: false,
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        presenter: &HdrPresenter,
+        width: u32,
+        height: u32,
+    ) {
+        self.history = [(); 2].map(|_| presenter.create_target(device, width, height));
+        self.history_valid = false;
+    }
+
+    // This frame's offset in pixels, each axis in -0.5..0.5. Follows the
+    // Halton (2, 3) sequence, which covers the pixel evenly in a few frames.
+    pub fn jitter(&self) -> cgmath::Vector2<f32> {
+        let index = self.frame % JITTER_PHASES + 1;
+        cgmath::Vector2::new(halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+    }
+
+    // `previous_view_proj` is the main camera's last frame, without jitter.
+    pub fn update(&self, queue: &wgpu::Queue, previous_view_proj: cgmath::Matrix4<f32>) {
+        let current_weight = if self.history_valid {
+            CURRENT_FRAME_WEIGHT
+        } else {
+            1.0
+        };
+        self.uniform_buffer.write(
+            queue,
+            &TaaUniform {
+                previous_view_proj: previous_view_proj.into(),
+                current_weight,
+                _padding: [0.0; 3],
+            },
+        );
+    }
+
+    // Blends `scene` into the history and returns the result, which the rest
+    // of the frame should use in its place. `depth` is the scene's
+    // single-sampled depth and `camera_bind_group` the main camera's, with
+    // this frame's jitter.
+    pub fn resolve(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &HdrTarget,
+        depth: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) -> &HdrTarget {
+        let current = self.current;
+        let previous = &self.history[1 - current];
+        let history_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA History Bind Group"),
+            layout: &self.history_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(previous.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+            ],
+        });
+        self.pass.draw(
+            PostFrame {
+                device,
+                encoder,
+                input: scene.view(),
+                output: self.history[current].view(),
+                depth,
+            },
+            &[
+                self.uniform_buffer.bind_group(),
+                &history_bind_group,
+                camera_bind_group,
+            ],
+        );
+
+        self.current = 1 - current;
+        self.frame = self.frame.wrapping_add(1);
+        self.history_valid = true;
+        &self.history[current]
+    }
+}
+
+// The `index`th point of the van der Corput sequence in `base`, in 0..1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
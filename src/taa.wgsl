@@ -0,0 +1,69 @@
+// The TAA resolve, drawn with post_process.wgsl's vertex stage. See taa.rs.
+
+// This frame's scene, jittered.
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct TaaUniform {
+    previous_view_proj: mat4x4<f32>,
+    // 1 when there's no history yet.
+    current_weight: f32,
+};
+@group(1) @binding(0)
+var<uniform> taa: TaaUniform;
+
+@group(2) @binding(0)
+var t_history: texture_2d<f32>;
+@group(2) @binding(1)
+var t_depth: texture_depth_2d;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+};
+@group(3) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@fragment
+fn fs_main(
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    let size = vec2<i32>(textureDimensions(t_input));
+    let current = textureLoad(t_input, texel, 0);
+
+    // Where this pixel's surface was on screen last frame. Sky pixels sit on
+    // the far plane, which still follows the camera turning.
+    let depth = textureLoad(t_depth, texel, 0);
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let world = camera.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    let previous_clip = taa.previous_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    let previous_ndc = previous_clip.xy / previous_clip.w;
+    let previous_uv = vec2<f32>(previous_ndc.x * 0.5 + 0.5, 0.5 - previous_ndc.y * 0.5);
+    let history = textureSample(t_history, s_input, previous_uv).rgb;
+
+    // History outside the range of this pixel's neighbours is from something
+    // that's no longer here.
+    var low = current.rgb;
+    var high = current.rgb;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbour = clamp(texel + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let colour = textureLoad(t_input, neighbour, 0).rgb;
+            low = min(low, colour);
+            high = max(high, colour);
+        }
+    }
+    let clamped = clamp(history, low, high);
+
+    var current_weight = taa.current_weight;
+    // Just come into view, so there's no history for it.
+    if any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0)) {
+        current_weight = 1.0;
+    }
+    return vec4<f32>(mix(clamped, current.rgb, current_weight), current.a);
+}
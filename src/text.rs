@@ -0,0 +1,309 @@
+use crate::uniform::UniformBuffer;
+
+// Each glyph is a 3x5 grid, drawn this many pixels to a cell.
+const GLYPH_SCALE: f32 = 2.0;
+// A glyph with every cell set, drawn as a solid rectangle.
+const SOLID: u32 = 0x7fff;
+
+// Lowercase is drawn as uppercase, and anything missing as '?'.
+#[rustfmt::skip]
+const FONT: &[(char, [&str; 5])] = &[
+    (' ', ["...", "...", "...", "...", "..."]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["###", "..#", "###", "#..", "###"]),
+    ('3', ["###", "..#", ".##", "..#", "###"]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "###", "..#", "###"]),
+    ('6', ["###", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", "..#", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "###"]),
+    ('A', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', [".##", "#..", "#..", "#..", ".##"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('Q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', [".##", "#..", ".#.", "..#", "##."]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    (',', ["...", "...", "...", ".#.", "#.."]),
+    (':', ["...", ".#.", "...", ".#.", "..."]),
+    (';', ["...", ".#.", "...", ".#.", "#.."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('—', ["...", "...", "###", "...", "..."]),
+    ('+', ["...", ".#.", "###", ".#.", "..."]),
+    ('=', ["...", "###", "...", "###", "..."]),
+    ('_', ["...", "...", "...", "...", "###"]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+    ('\\', ["#..", "#..", ".#.", "..#", "..#"]),
+    ('|', [".#.", ".#.", ".#.", ".#.", ".#."]),
+    ('(', ["..#", ".#.", ".#.", ".#.", "..#"]),
+    (')', ["#..", ".#.", ".#.", ".#.", "#.."]),
+    ('[', [".##", ".#.", ".#.", ".#.", ".##"]),
+    (']', ["##.", ".#.", ".#.", ".#.", "##."]),
+    ('{', [".##", ".#.", "##.", ".#.", ".##"]),
+    ('}', ["##.", ".#.", ".##", ".#.", "##."]),
+    ('<', ["..#", ".#.", "#..", ".#.", "..#"]),
+    ('>', ["#..", ".#.", "..#", ".#.", "#.."]),
+    ('%', ["#.#", "..#", ".#.", "#..", "#.#"]),
+    ('"', ["#.#", "#.#", "...", "...", "..."]),
+    ('\'', [".#.", ".#.", "...", "...", "..."]),
+    ('!', [".#.", ".#.", ".#.", "...", ".#."]),
+    ('?', ["###", "..#", ".##", "...", ".#."]),
+    ('#', ["#.#", "###", "#.#", "###", "#.#"]),
+    ('*', ["...", "#.#", ".#.", "#.#", "..."]),
+];
+
+// The cells of `ch` set in a 15-bit mask, row by row from the top and left
+// to right within a row, as text.wgsl reads them.
+fn glyph_mask(ch: char) -> u32 {
+    let ch = ch.to_ascii_uppercase();
+    let rows = FONT
+        .iter()
+        .find(|(glyph, _)| *glyph == ch)
+        .or_else(|| FONT.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| rows)
+        .expect("the font has '?'");
+    rows.iter()
+        .flat_map(|row| row.chars())
+        .enumerate()
+        .filter(|(_, cell)| *cell == '#')
+        .fold(0, |mask, (bit, _)| mask | 1 << bit)
+}
+
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+struct TextQuad {
+    // Left, top, width and height in pixels.
+    rect: [f32; 4],
+    colour: [f32; 4],
+    glyph: u32,
+}
+
+impl TextQuad {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Uint32];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TextQuad>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// Lines of text and solid rectangles to draw over a frame, positioned in
+// pixels from the top left of the window.
+#[derive(Default)]
+pub struct TextBatch {
+    quads: Vec<TextQuad>,
+}
+
+impl TextBatch {
+    // How far apart characters and lines are, in pixels.
+    pub const CHAR_WIDTH: f32 = 4.0 * GLYPH_SCALE;
+    pub const LINE_HEIGHT: f32 = 7.0 * GLYPH_SCALE;
+
+    pub fn is_empty(&self) -> bool {
+        self.quads.is_empty()
+    }
+
+    pub fn rect(&mut self, rect: [f32; 4], colour: [f32; 4]) {
+        self.quads.push(TextQuad {
+            rect,
+            colour,
+            glyph: SOLID,
+        });
+    }
+
+    // Spaces are skipped rather than drawn as empty quads.
+    pub fn text(&mut self, x: f32, y: f32, text: &str, colour: [f32; 4]) {
+        for (i, ch) in text.chars().enumerate() {
+            if ch == ' ' {
+                continue;
+            }
+            self.quads.push(TextQuad {
+                rect: [
+                    x + i as f32 * Self::CHAR_WIDTH,
+                    y,
+                    3.0 * GLYPH_SCALE,
+                    5.0 * GLYPH_SCALE,
+                ],
+                colour,
+                glyph: glyph_mask(ch),
+            });
+        }
+    }
+}
+
+// Matches `TextUniform` in text.wgsl.
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Copy, Clone, Debug)]
+struct TextUniform {
+    viewport_size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+// Draws a `TextBatch` over the finished frame in one instanced draw. Glyphs
+// come from a tiny built-in bitmap font, tested bit by bit in the fragment
+// shader, so there's no font texture to load.
+pub struct TextRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: UniformBuffer<TextUniform>,
+    quad_buffer: wgpu::Buffer,
+    quad_capacity: usize,
+}
+
+impl TextRenderer {
+    pub const VERTICES_PER_QUAD: u32 = 6;
+
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &TextUniform {
+                viewport_size: [config.width as f32, config.height as f32],
+                _padding: [0.0; 2],
+            },
+            wgpu::ShaderStages::VERTEX,
+            "Text Uniform",
+        );
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[uniform_buffer.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[TextQuad::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            uniform_buffer,
+            quad_buffer: create_quad_buffer(device, 1),
+            quad_capacity: 1,
+        }
+    }
+
+    pub fn resize(&self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.uniform_buffer.write(
+            queue,
+            &TextUniform {
+                viewport_size: [width as f32, height as f32],
+                _padding: [0.0; 2],
+            },
+        );
+    }
+
+    // Draws over whatever is already in `view`. The buffer only grows, to
+    // the next power of two that fits.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        batch: &TextBatch,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+        if batch.quads.len() > self.quad_capacity {
+            self.quad_capacity = batch.quads.len().next_power_of_two();
+            self.quad_buffer = create_quad_buffer(device, self.quad_capacity);
+        }
+        queue.write_buffer(&self.quad_buffer, 0, bytemuck::cast_slice(&batch.quads));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Text Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, self.uniform_buffer.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.quad_buffer.slice(..));
+        render_pass.draw(0..Self::VERTICES_PER_QUAD, 0..batch.quads.len() as u32);
+    }
+}
+
+fn create_quad_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Text Quad Buffer"),
+        size: (capacity * std::mem::size_of::<TextQuad>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_glyph_is_three_by_five() {
+        for (ch, rows) in FONT {
+            assert!(rows.iter().all(|row| row.len() == 3), "{ch:?}");
+        }
+    }
+
+    #[test]
+    fn packs_cells_from_the_top_left() {
+        assert_eq!(glyph_mask('_'), 0b111 << 12);
+        assert_eq!(glyph_mask('\''), 1 << 1 | 1 << 4);
+        assert_eq!(glyph_mask('a'), glyph_mask('A'));
+        assert_eq!(glyph_mask('~'), glyph_mask('?'));
+    }
+}
@@ -0,0 +1,61 @@
+// Overlay text and panels, drawn in pixels over the finished frame. See
+// text.rs.
+
+struct TextUniform {
+    viewport_size: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> text: TextUniform;
+
+struct InstanceInput {
+    // Left, top, width and height in pixels.
+    @location(0) rect: vec4<f32>,
+    @location(1) colour: vec4<f32>,
+    @location(2) glyph: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 0..1 across the quad, with y down like the glyph rows.
+    @location(0) uv: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+    @location(2) @interpolate(flat) glyph: u32,
+};
+
+const GLYPH_COLUMNS: u32 = 3u;
+const GLYPH_ROWS: u32 = 5u;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[in_vertex_index];
+    let pixel = instance.rect.xy + corner * instance.rect.zw;
+    let ndc = pixel / text.viewport_size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.uv = corner;
+    out.colour = instance.colour;
+    out.glyph = instance.glyph;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let column = min(u32(in.uv.x * f32(GLYPH_COLUMNS)), GLYPH_COLUMNS - 1u);
+    let row = min(u32(in.uv.y * f32(GLYPH_ROWS)), GLYPH_ROWS - 1u);
+    if ((in.glyph >> (row * GLYPH_COLUMNS + column)) & 1u) == 0u {
+        discard;
+    }
+    return in.colour;
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use image::GenericImageView;
+use wgpu::util::DeviceExt;
+
+use crate::{compressed_texture::CompressedImage, mipmap};
+
+// How a texture is sampled. Textures created with equal configs share one
+// sampler through `SamplerCache`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerConfig {
+    pub address_mode: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // Only applied when all three filters are linear, as wgpu requires.
+    pub anisotropy: u16,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+        }
+    }
+}
+
+impl Eq for SamplerConfig {}
+
+impl Hash for SamplerConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address_mode.hash(state);
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_filter.hash(state);
+        self.anisotropy.hash(state);
+        self.lod_min_clamp.to_bits().hash(state);
+        self.lod_max_clamp.to_bits().hash(state);
+    }
+}
+
+impl SamplerConfig {
+    fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == wgpu::FilterMode::Linear);
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{self:?}")),
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            anisotropy_clamp: if linear { self.anisotropy.max(1) } else { 1 },
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerConfig, Arc<wgpu::Sampler>>,
+}
+
+impl SamplerCache {
+    pub fn get(&mut self, device: &wgpu::Device, config: SamplerConfig) -> Arc<wgpu::Sampler> {
+        self.samplers
+            .entry(config)
+            .or_insert_with(|| Arc::new(config.create_sampler(device)))
+            .clone()
+    }
+
+    pub fn count(&self) -> usize {
+        self.samplers.len()
+    }
+}
+
+// What an image's texels hold, which decides its format. Colour is stored
+// sRGB-encoded and decoded as it's sampled; data such as normals, roughness
+// and occlusion has to come back exactly as stored, so it's linear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    Color,
+    Data,
+}
+
+impl TextureKind {
+    // The same file loaded as both kinds makes two different textures, so
+    // each is cached under its own name.
+    pub fn cache_key(self, name: &str) -> String {
+        match self {
+            Self::Color => name.to_string(),
+            Self::Data => format!("{name} <data>"),
+        }
+    }
+}
+
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: Arc<wgpu::Sampler>,
+}
+
+impl Texture {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            // Copies let the CPU read the depth under the cursor.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Arc::new(device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            ..Default::default()
+        }));
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> image::ImageResult<Self> {
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(device, queue, &img, Some(label), sampler))
+    }
+
+    pub fn from_colour(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        colour: [u8; 4],
+        label: &str,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(colour));
+        Self::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(img),
+            Some(label),
+            sampler,
+        )
+    }
+
+    pub fn from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &CompressedImage,
+        label: &str,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: image.mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: image.format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &image.data,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    // Float images (.hdr, .exr) keep their range as `Rgba16Float`; anything
+    // else is treated as sRGB colour.
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        if is_hdr(img) {
+            return Self::from_hdr_image(
+                device,
+                queue,
+                img,
+                label,
+                sampler,
+                wgpu::TextureFormat::Rgba16Float,
+            );
+        }
+
+        let (width, height) = img.dimensions();
+        Self::from_pixels(
+            device,
+            queue,
+            extent(width, height),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &img.to_rgba8(),
+            label,
+            sampler,
+        )
+    }
+
+    // Normal, metallic-roughness and occlusion maps store data rather than
+    // colour, so they're uploaded as linear `Rgba8Unorm` and sampled without
+    // sRGB decoding.
+    pub fn from_linear_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let (width, height) = img.dimensions();
+        Self::from_pixels(
+            device,
+            queue,
+            extent(width, height),
+            wgpu::TextureFormat::Rgba8Unorm,
+            &img.to_rgba8(),
+            label,
+            sampler,
+        )
+    }
+
+    pub fn from_image_of_kind(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        kind: TextureKind,
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        match kind {
+            TextureKind::Color => Self::from_image(device, queue, img, label, sampler),
+            TextureKind::Data => Self::from_linear_image(device, queue, img, label, sampler),
+        }
+    }
+
+    // `format` must be `Rgba16Float` or `Rgba32Float`. The latter is only
+    // filterable with `Features::FLOAT32_FILTERABLE`, so pair it with a
+    // non-filtering sampler otherwise.
+    pub fn from_hdr_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let rgba = img.to_rgba32f();
+        let data = match format {
+            wgpu::TextureFormat::Rgba16Float => {
+                let halves: Vec<u16> = rgba
+                    .iter()
+                    .map(|&value| half::f16::from_f32(value).to_bits())
+                    .collect();
+                bytemuck::cast_slice(&halves).to_vec()
+            }
+            wgpu::TextureFormat::Rgba32Float => bytemuck::cast_slice(rgba.as_raw()).to_vec(),
+            _ => panic!("{format:?} is not an HDR texture format"),
+        };
+
+        let (width, height) = img.dimensions();
+        Self::from_pixels(
+            device,
+            queue,
+            extent(width, height),
+            format,
+            &data,
+            label,
+            sampler,
+        )
+    }
+
+    // `data` holds tightly packed rows for mip level 0. The rest of the mip
+    // chain is allocated but left for `MipmapGenerator` to fill.
+    fn from_pixels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+        data: &[u8],
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let mip_level_count = mipmap::mip_level_count(size, format, device.features());
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(data.len() as u32 / size.height),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+}
+
+// Roughly what `texture` takes up in video memory: every mip, layer and
+// sample at its format's block size, counting formats without one (packed
+// depth-stencil) as 4 bytes a texel. Drivers pad and compress as they see
+// fit, so it's an estimate.
+pub fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = u64::from(format.block_size(None).unwrap_or(4));
+    let mips = (0..texture.mip_level_count())
+        .map(|mip| {
+            let width = (texture.width() >> mip).max(1).div_ceil(block_width);
+            let height = (texture.height() >> mip).max(1).div_ceil(block_height);
+            u64::from(width) * u64::from(height) * block_size
+        })
+        .sum::<u64>();
+    mips * u64::from(texture.depth_or_array_layers()) * u64::from(texture.sample_count())
+}
+
+fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+fn is_hdr(img: &image::DynamicImage) -> bool {
+    matches!(
+        img,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    )
+}
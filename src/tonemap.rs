@@ -0,0 +1,100 @@
+use crate::{
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    uniform::UniformBuffer,
+};
+
+// How the scene's unbounded HDR colour is squeezed into the 0..1 the display
+// takes. The order matches `tonemapper` in tonemap.wgsl.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Tonemapper {
+    // Cuts off everything above 1, as if there were no tonemapping.
+    LinearClamp,
+    Reinhard,
+    #[default]
+    Aces,
+    Uncharted2,
+}
+
+impl Tonemapper {
+    pub fn next(self) -> Self {
+        match self {
+            Self::LinearClamp => Self::Reinhard,
+            Self::Reinhard => Self::Aces,
+            Self::Aces => Self::Uncharted2,
+            Self::Uncharted2 => Self::LinearClamp,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    exposure: f32,
+    tonemapper: u32,
+    _padding: [u32; 2],
+}
+
+// The last post-process step: scales the frame by `exposure` and maps it to
+// display range with the chosen operator.
+pub struct Tonemap {
+    pass: FullscreenPass,
+    uniform: TonemapUniform,
+    uniform_buffer: UniformBuffer<TonemapUniform>,
+    operator: Tonemapper,
+}
+
+impl Tonemap {
+    pub fn new(device: &wgpu::Device, operator: Tonemapper, exposure: f32) -> Self {
+        let uniform = TonemapUniform {
+            exposure,
+            tonemapper: operator as u32,
+            _padding: [0; 2],
+        };
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &uniform,
+            wgpu::ShaderStages::FRAGMENT,
+            "Tonemap Uniform",
+        );
+        let shader = device.create_shader_module(wgpu::include_wgsl!("tonemap.wgsl"));
+        let pass = FullscreenPass::new(
+            device,
+            "Tonemap",
+            &shader,
+            "fs_main",
+            &[uniform_buffer.layout()],
+        );
+
+        Self {
+            pass,
+            uniform,
+            uniform_buffer,
+            operator,
+        }
+    }
+
+    pub fn operator(&self) -> Tonemapper {
+        self.operator
+    }
+
+    pub fn set_operator(&mut self, queue: &wgpu::Queue, operator: Tonemapper) {
+        self.operator = operator;
+        self.uniform.tonemapper = operator as u32;
+        self.uniform_buffer.write(queue, &self.uniform);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.uniform.exposure
+    }
+
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, exposure: f32) {
+        self.uniform.exposure = exposure;
+        self.uniform_buffer.write(queue, &self.uniform);
+    }
+}
+
+impl PostEffect for Tonemap {
+    fn draw(&self, frame: PostFrame) {
+        self.pass.draw(frame, &[self.uniform_buffer.bind_group()]);
+    }
+}
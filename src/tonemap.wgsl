@@ -0,0 +1,66 @@
+// Maps the HDR frame to display range. Drawn with post_process.wgsl's vertex
+// stage; see tonemap.rs.
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct TonemapUniform {
+    exposure: f32,
+    // `Tonemapper`'s variants in order: linear clamp, Reinhard, ACES,
+    // Uncharted 2.
+    tonemapper: u32,
+};
+@group(1) @binding(0)
+var<uniform> tonemap: TonemapUniform;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+fn aces(c: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let d = 2.43;
+    let e = 0.59;
+    let f = 0.14;
+    return (c * (a * c + b)) / (c * (d * c + e) + f);
+}
+
+// John Hable's filmic curve from Uncharted 2, before white point scaling.
+fn uncharted2_partial(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+fn uncharted2(c: vec3<f32>) -> vec3<f32> {
+    let white = vec3<f32>(11.2);
+    // Hable's exposure bias, so the default exposure looks about as bright as
+    // the other operators.
+    return uncharted2_partial(c * 2.0) / uncharted2_partial(white);
+}
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let input = textureSample(t_input, s_input, uv);
+    let c = max(input.rgb * tonemap.exposure, vec3<f32>(0.0));
+    var mapped: vec3<f32>;
+    switch tonemap.tonemapper {
+        case 1u: {
+            mapped = c / (1.0 + c);
+        }
+        case 2u: {
+            mapped = aces(c);
+        }
+        case 3u: {
+            mapped = uncharted2(c);
+        }
+        default: {
+            mapped = c;
+        }
+    }
+    return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), input.a);
+}
@@ -0,0 +1,99 @@
+// Per-pixel screen-space motion since the last frame. See motion_blur.rs.
+
+// Neither view-projection has TAA's jitter, so still things read as still.
+struct VelocityUniform {
+    view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> velocity: VelocityUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+// The same instance's transform last frame.
+struct PreviousInstanceInput {
+    @location(9) model_matrix_0: vec4<f32>,
+    @location(10) model_matrix_1: vec4<f32>,
+    @location(11) model_matrix_2: vec4<f32>,
+    @location(12) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) current: vec4<f32>,
+    @location(1) previous: vec4<f32>,
+};
+
+// How far the point has moved across the screen since last frame, in uv.
+fn uv_motion(current: vec4<f32>, previous: vec4<f32>) -> vec2<f32> {
+    let delta = current.xy / current.w - previous.xy / previous.w;
+    return delta * vec2<f32>(0.5, -0.5);
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+    previous_instance: PreviousInstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let previous_model_matrix = mat4x4<f32>(
+        previous_instance.model_matrix_0,
+        previous_instance.model_matrix_1,
+        previous_instance.model_matrix_2,
+        previous_instance.model_matrix_3,
+    );
+    let position = vec4<f32>(model.position, 1.0);
+    var out: VertexOutput;
+    out.clip_position = velocity.view_proj * model_matrix * position;
+    out.current = out.clip_position;
+    out.previous = velocity.previous_view_proj * previous_model_matrix * position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec2<f32> {
+    return uv_motion(in.current, in.previous);
+}
+
+struct BackgroundOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// A single triangle on the far plane, drawn after the meshes so it only
+// covers the sky, which moves with the camera alone.
+@vertex
+fn vs_background(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> BackgroundOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: BackgroundOutput;
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_background(in: BackgroundOutput) -> @location(0) vec2<f32> {
+    let current = vec4<f32>(in.ndc, 1.0, 1.0);
+    let world = velocity.inverse_view_proj * current;
+    let previous = velocity.previous_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    return uv_motion(current, previous);
+}
@@ -0,0 +1,72 @@
+use crate::{
+    post_process::{FullscreenPass, PostEffect, PostFrame},
+    uniform::UniformBuffer,
+};
+
+// Matches `VignetteUniform` in vignette.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniform {
+    strength: f32,
+    radius: f32,
+    _padding: [u32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VignetteSettings {
+    // How dark the corners get, from 0 for not at all to 1 for black.
+    pub strength: f32,
+    // Where the darkening starts, as a fraction of the way from the centre to
+    // a corner.
+    pub radius: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            strength: 0.4,
+            radius: 0.5,
+        }
+    }
+}
+
+// Darkens the frame towards its edges. About as small as an effect with
+// settings gets: one uniform at group 1 and a fragment shader.
+pub struct Vignette {
+    pass: FullscreenPass,
+    uniform_buffer: UniformBuffer<VignetteUniform>,
+}
+
+impl Vignette {
+    pub fn new(device: &wgpu::Device, settings: VignetteSettings) -> Self {
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            &VignetteUniform {
+                strength: settings.strength,
+                radius: settings.radius,
+                _padding: [0; 2],
+            },
+            wgpu::ShaderStages::FRAGMENT,
+            "Vignette Uniform",
+        );
+        let shader = device.create_shader_module(wgpu::include_wgsl!("vignette.wgsl"));
+        let pass = FullscreenPass::new(
+            device,
+            "Vignette",
+            &shader,
+            "fs_main",
+            &[uniform_buffer.layout()],
+        );
+
+        Self {
+            pass,
+            uniform_buffer,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn draw(&self, frame: PostFrame) {
+        self.pass.draw(frame, &[self.uniform_buffer.bind_group()]);
+    }
+}
@@ -0,0 +1,23 @@
+// Darkens towards the edges of the frame. Drawn with post_process.wgsl's
+// vertex stage; see vignette.rs.
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct VignetteUniform {
+    strength: f32,
+    radius: f32,
+};
+@group(1) @binding(0)
+var<uniform> vignette: VignetteUniform;
+
+@fragment
+fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    let colour = textureSampleLevel(t_input, s_input, uv, 0.0);
+    // 0 at the centre and 1 in the corners.
+    let offset = length(uv - 0.5) * sqrt(2.0);
+    let shade = smoothstep(vignette.radius, 1.0, offset);
+    return vec4<f32>(colour.rgb * (1.0 - vignette.strength * shade), colour.a);
+}